@@ -453,3 +453,24 @@ describe("anvil_setStorageAt", function () {
     expect(BigNumber.from(after).toNumber()).to.eq(16);
   });
 });
+
+describe("anvil_dealERC20 & anvil_setERC20Allowance", function () {
+  it("Should set the balance and allowance of a token", async function () {
+    // Arrange
+    const wallet = new Wallet(RichAccounts[0].PrivateKey);
+    const deployer = new Deployer(hre, wallet);
+    const token = await deployContract(deployer, "MyERC20", ["MyToken", "MyToken", 18]);
+    const owner = Wallet.createRandom().address;
+    const spender = Wallet.createRandom().address;
+    const amount = ethers.utils.parseEther("1000");
+
+    // Act
+    await provider.send("anvil_dealERC20", [token.address, owner, amount.toHexString()]);
+    await provider.send("anvil_setERC20Allowance", [token.address, owner, spender, amount.toHexString()]);
+
+    // Assert
+    expect(await token.balanceOf(owner)).to.eq(amount);
+    expect(await token.allowance(owner, spender)).to.eq(amount);
+    expect(await token.balanceOf(spender)).to.eq(0);
+  });
+});
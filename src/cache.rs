@@ -2,15 +2,21 @@ use rustc_hash::FxHashMap;
 use serde::Serialize;
 use std::fs;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
 use zksync_types::api::{Block, BridgeAddresses, Transaction, TransactionVariant};
+use zksync_types::web3::{keccak256, Bytes};
 use zksync_types::Transaction as RawTransaction;
-use zksync_types::H256;
+use zksync_types::{Address, H256};
 
+use crate::cache_file::CacheFile;
 use crate::config::cache::CacheConfig;
+use crate::metrics::{CacheKind, METRICS};
 
 /// Caches full blocks by their hashes
 const CACHE_TYPE_BLOCKS_FULL: &str = "blocks_full";
@@ -24,10 +30,56 @@ const CACHE_TYPE_TRANSACTIONS: &str = "transactions";
 const CACHE_TYPE_RESOLVER_SELECTORS: &str = "resolver_selectors";
 /// Caches arbitrary values by their keys
 const CACHE_TYPE_KEY_VALUE: &str = "key_value";
+/// Caches storage values by their fork block, address and key
+const CACHE_TYPE_STORAGE_VALUES: &str = "storage_values";
+/// Caches factory deps (bytecodes) by their hashes
+const CACHE_TYPE_FACTORY_DEPS: &str = "factory_deps";
+
+/// All cache types, each stored in its own directory for the disk cache.
+const CACHE_TYPES: [&str; 8] = [
+    CACHE_TYPE_BLOCKS_FULL,
+    CACHE_TYPE_BLOCKS_MIN,
+    CACHE_TYPE_BLOCK_RAW_TRANSACTIONS,
+    CACHE_TYPE_TRANSACTIONS,
+    CACHE_TYPE_RESOLVER_SELECTORS,
+    CACHE_TYPE_KEY_VALUE,
+    CACHE_TYPE_STORAGE_VALUES,
+    CACHE_TYPE_FACTORY_DEPS,
+];
 
 /// Caching key for bridge addresses
 const CACHE_KEY_BRIDGE_ADDRESSES: &str = "bridge_addresses";
 
+/// Cache files opened by this process, so that all caches using the same file share a single handle.
+static CACHE_FILES: Lazy<Mutex<FxHashMap<PathBuf, Arc<Mutex<CacheFile>>>>> =
+    Lazy::new(Default::default);
+
+/// Opens the cache file at `path`, or returns the handle if it's already open.
+fn open_cache_file(path: &str, max_size: u64) -> io::Result<Arc<Mutex<CacheFile>>> {
+    let mut files = CACHE_FILES.lock().expect("cache files lock is poisoned");
+    if let Some(file) = files.get(Path::new(path)) {
+        return Ok(file.clone());
+    }
+    let file = Arc::new(Mutex::new(CacheFile::open(path, max_size)?));
+    files.insert(PathBuf::from(path), file.clone());
+    Ok(file)
+}
+
+/// Returns the directory of the disk cache entries of `namespace`, so that caches of different networks sharing
+/// the same cache directory never mix up their entries. Caches without a namespace use `dir` itself.
+fn namespace_dir(dir: &str, namespace: &str) -> PathBuf {
+    if namespace.is_empty() {
+        return PathBuf::from(dir);
+    }
+    let hash = keccak256(namespace.as_bytes());
+    Path::new(dir).join(hex::encode(&hash[..8]))
+}
+
+/// Returns the key of an entry in the cache file, the equivalent of its path in the disk cache.
+fn cache_file_key(cache_type: &str, key: &str) -> String {
+    format!("{}/{}", cache_type, key)
+}
+
 /// A general purpose cache.
 #[derive(Default, Debug, Clone)]
 pub(crate) struct Cache {
@@ -40,55 +92,82 @@ pub(crate) struct Cache {
     resolver_selectors: FxHashMap<String, String>,
     bridge_addresses: Option<BridgeAddresses>,
     confirmed_tokens: FxHashMap<(u32, u8), Vec<zksync_web3_decl::types::Token>>,
+    storage_values: FxHashMap<(u64, Address, H256), H256>,
+    factory_deps: FxHashMap<H256, Vec<u8>>,
+    /// Namespace of the entries in a shared cache file or directory, usually the fork URL. In-memory entries
+    /// need none since every fork source has its own cache.
+    namespace: String,
+    file: Option<Arc<Mutex<CacheFile>>>,
 }
 
 impl Cache {
     /// Creates a new cache with the provided config.
     pub(crate) fn new(config: CacheConfig) -> Self {
+        Self::with_namespace(config, String::new())
+    }
+
+    /// Creates a new cache with the provided config, keeping its entries apart from other namespaces
+    /// when they share a cache file.
+    pub(crate) fn with_namespace(config: CacheConfig, namespace: String) -> Self {
         let mut cache = Cache {
             config: config.clone(),
+            namespace,
             ..Default::default()
         };
 
-        if let CacheConfig::Disk { dir, reset } = &config {
-            if *reset {
-                for cache_type in [
-                    CACHE_TYPE_BLOCKS_FULL,
-                    CACHE_TYPE_BLOCKS_MIN,
-                    CACHE_TYPE_BLOCK_RAW_TRANSACTIONS,
-                    CACHE_TYPE_TRANSACTIONS,
-                    CACHE_TYPE_RESOLVER_SELECTORS,
-                    CACHE_TYPE_KEY_VALUE,
-                ] {
-                    fs::remove_dir_all(Path::new(dir).join(cache_type)).unwrap_or_else(|err| {
-                        tracing::warn!(
-                            "failed removing directory {:?}: {:?}",
-                            Path::new(dir).join(cache_type),
-                            err
-                        )
+        match &config {
+            CacheConfig::Disk { dir, reset } => {
+                let dir = &namespace_dir(dir, &cache.namespace);
+                if *reset {
+                    for cache_type in CACHE_TYPES {
+                        fs::remove_dir_all(dir.join(cache_type)).unwrap_or_else(|err| {
+                            tracing::warn!(
+                                "failed removing directory {:?}: {:?}",
+                                dir.join(cache_type),
+                                err
+                            )
+                        });
+                    }
+
+                    fs::remove_dir(dir).unwrap_or_else(|err| {
+                        tracing::warn!("failed removing cache directory: {:?}", err)
                     });
                 }
 
-                fs::remove_dir(Path::new(dir)).unwrap_or_else(|err| {
-                    tracing::warn!("failed removing cache directory: {:?}", err)
-                });
-            }
-
-            for cache_type in [
-                CACHE_TYPE_BLOCKS_FULL,
-                CACHE_TYPE_BLOCKS_MIN,
-                CACHE_TYPE_BLOCK_RAW_TRANSACTIONS,
-                CACHE_TYPE_TRANSACTIONS,
-                CACHE_TYPE_RESOLVER_SELECTORS,
-                CACHE_TYPE_KEY_VALUE,
-            ] {
-                fs::create_dir_all(Path::new(dir).join(cache_type)).unwrap_or_else(|err| {
-                    panic!("failed creating directory {}: {:?}", cache_type, err)
+                for cache_type in CACHE_TYPES {
+                    fs::create_dir_all(dir.join(cache_type)).unwrap_or_else(|err| {
+                        panic!("failed creating directory {}: {:?}", cache_type, err)
+                    });
+                }
+                cache.read_all_from_disk(dir).unwrap_or_else(|err| {
+                    tracing::error!("failed reading cache from disk: {:?}", err)
                 });
             }
-            cache
-                .read_all_from_disk(dir)
-                .unwrap_or_else(|err| tracing::error!("failed reading cache from disk: {:?}", err));
+            CacheConfig::File {
+                path,
+                max_size,
+                reset,
+            } => match open_cache_file(path, *max_size) {
+                Ok(file) => {
+                    cache.file = Some(file);
+                    if *reset {
+                        cache.reset_file().unwrap_or_else(|err| {
+                            tracing::warn!("failed resetting cache file {:?}: {:?}", path, err)
+                        });
+                    }
+                    cache.read_all_from_file().unwrap_or_else(|err| {
+                        tracing::error!("failed reading cache from file: {:?}", err)
+                    });
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "failed opening cache file {:?}, falling back to memory: {:?}",
+                        path,
+                        err
+                    )
+                }
+            },
+            CacheConfig::None | CacheConfig::Memory => {}
         }
 
         cache
@@ -104,11 +183,15 @@ impl Cache {
             return None;
         }
 
-        if full_transactions {
-            self.blocks_full.get(hash)
+        let (cache_type, block) = if full_transactions {
+            (CACHE_TYPE_BLOCKS_FULL, self.blocks_full.get(hash))
         } else {
-            self.blocks_min.get(hash)
+            (CACHE_TYPE_BLOCKS_MIN, self.blocks_min.get(hash))
+        };
+        if block.is_some() {
+            self.touch_file_entry(cache_type, || format!("{:#x}", hash));
         }
+        METRICS.observe_cache_lookup(CacheKind::Blocks, block)
     }
 
     /// Cache a full/minimal block for the provided hash.
@@ -138,7 +221,7 @@ impl Cache {
             return None;
         }
 
-        self.block_hashes.get(number)
+        METRICS.observe_cache_lookup(CacheKind::BlockHashes, self.block_hashes.get(number))
     }
 
     /// Returns the cached raw transactions for the provided block number.
@@ -147,7 +230,11 @@ impl Cache {
             return None;
         }
 
-        self.block_raw_transactions.get(number)
+        let transactions = self.block_raw_transactions.get(number);
+        if transactions.is_some() {
+            self.touch_file_entry(CACHE_TYPE_BLOCK_RAW_TRANSACTIONS, || number.to_string());
+        }
+        METRICS.observe_cache_lookup(CacheKind::BlockRawTransactions, transactions)
     }
 
     /// Returns the cached confirmed tokens.
@@ -159,7 +246,10 @@ impl Cache {
         if matches!(self.config, CacheConfig::None) {
             return None;
         }
-        self.confirmed_tokens.get(&(from, limit))
+        METRICS.observe_cache_lookup(
+            CacheKind::ConfirmedTokens,
+            self.confirmed_tokens.get(&(from, limit)),
+        )
     }
 
     /// Cache confirmed tokens
@@ -200,7 +290,11 @@ impl Cache {
             return None;
         }
 
-        self.transactions.get(hash)
+        let transaction = self.transactions.get(hash);
+        if transaction.is_some() {
+            self.touch_file_entry(CACHE_TYPE_TRANSACTIONS, || format!("{:#x}", hash));
+        }
+        METRICS.observe_cache_lookup(CacheKind::Transactions, transaction)
     }
 
     /// Returns the cached resolved function/event selector for the provided selector.
@@ -209,7 +303,11 @@ impl Cache {
             return None;
         }
 
-        self.resolver_selectors.get(selector)
+        let resolved = self.resolver_selectors.get(selector);
+        if resolved.is_some() {
+            self.touch_file_entry(CACHE_TYPE_RESOLVER_SELECTORS, || selector.clone());
+        }
+        METRICS.observe_cache_lookup(CacheKind::ResolverSelectors, resolved)
     }
 
     /// Cache a transaction for the provided hash.
@@ -246,7 +344,12 @@ impl Cache {
             return None;
         }
 
-        self.bridge_addresses.as_ref()
+        if self.bridge_addresses.is_some() {
+            self.touch_file_entry(CACHE_TYPE_KEY_VALUE, || {
+                String::from(CACHE_KEY_BRIDGE_ADDRESSES)
+            });
+        }
+        METRICS.observe_cache_lookup(CacheKind::BridgeAddresses, self.bridge_addresses.as_ref())
     }
 
     /// Cache default bridge addresses.
@@ -263,17 +366,78 @@ impl Cache {
         self.bridge_addresses = Some(bridge_addresses);
     }
 
+    /// Returns the cached storage value for the provided fork block, address and key.
+    pub(crate) fn get_storage_value(
+        &self,
+        block_number: u64,
+        address: &Address,
+        key: &H256,
+    ) -> Option<&H256> {
+        if matches!(self.config, CacheConfig::None) {
+            return None;
+        }
+
+        let value = self.storage_values.get(&(block_number, *address, *key));
+        if value.is_some() {
+            self.touch_file_entry(CACHE_TYPE_STORAGE_VALUES, || {
+                format!("{}_{:#x}_{:#x}", block_number, address, key)
+            });
+        }
+        METRICS.observe_cache_lookup(CacheKind::BlockStorageValues, value)
+    }
+
+    /// Cache a storage value for the provided fork block, address and key.
+    pub(crate) fn insert_storage_value(
+        &mut self,
+        block_number: u64,
+        address: Address,
+        key: H256,
+        value: H256,
+    ) {
+        if matches!(self.config, CacheConfig::None) {
+            return;
+        }
+
+        self.write_to_disk(
+            CACHE_TYPE_STORAGE_VALUES,
+            format!("{}_{:#x}_{:#x}", block_number, address, key),
+            &value,
+        );
+        self.storage_values
+            .insert((block_number, address, key), value);
+    }
+
+    /// Returns the cached factory dep (bytecode) for the provided hash.
+    pub(crate) fn get_factory_dep(&self, hash: &H256) -> Option<&Vec<u8>> {
+        if matches!(self.config, CacheConfig::None) {
+            return None;
+        }
+
+        let bytecode = self.factory_deps.get(hash);
+        if bytecode.is_some() {
+            self.touch_file_entry(CACHE_TYPE_FACTORY_DEPS, || format!("{:#x}", hash));
+        }
+        METRICS.observe_cache_lookup(CacheKind::Bytecodes, bytecode)
+    }
+
+    /// Cache a factory dep (bytecode) for the provided hash.
+    pub(crate) fn insert_factory_dep(&mut self, hash: H256, bytecode: Vec<u8>) {
+        if matches!(self.config, CacheConfig::None) {
+            return;
+        }
+
+        self.write_to_disk(
+            CACHE_TYPE_FACTORY_DEPS,
+            format!("{:#x}", hash),
+            &Bytes(bytecode.clone()),
+        );
+        self.factory_deps.insert(hash, bytecode);
+    }
+
     /// Reads the cache contents from the disk, if available.
-    fn read_all_from_disk(&mut self, dir: &str) -> Result<(), String> {
-        for cache_type in [
-            CACHE_TYPE_BLOCKS_FULL,
-            CACHE_TYPE_BLOCKS_MIN,
-            CACHE_TYPE_BLOCK_RAW_TRANSACTIONS,
-            CACHE_TYPE_TRANSACTIONS,
-            CACHE_TYPE_RESOLVER_SELECTORS,
-            CACHE_TYPE_KEY_VALUE,
-        ] {
-            let cache_dir = Path::new(dir).join(cache_type);
+    fn read_all_from_disk(&mut self, dir: &Path) -> Result<(), String> {
+        for cache_type in CACHE_TYPES {
+            let cache_dir = dir.join(cache_type);
             let dir_listing = fs::read_dir(cache_dir.clone())
                 .map_err(|err| format!("failed reading dir '{:?}': {:?}", cache_dir, err))?
                 .flatten();
@@ -287,80 +451,175 @@ impl Cache {
                 let cache_file = File::open(file.path()).map_err(|err| {
                     format!("failed reading file: '{:?}': {:?}", file.path(), err)
                 })?;
-                let reader = BufReader::new(cache_file);
-                match cache_type {
-                    CACHE_TYPE_BLOCKS_FULL => {
-                        let key = H256::from_str(&key).map_err(|err| {
-                            format!("invalid key for cache file '{:?}': {:?}", key, err)
-                        })?;
-                        let block: Block<TransactionVariant> = serde_json::from_reader(reader)
-                            .map_err(|err| {
-                                format!("failed parsing json for cache file '{:?}': {:?}", key, err)
-                            })?;
-                        self.block_hashes.insert(block.number.as_u64(), block.hash);
-                        self.blocks_full.insert(key, block);
-                    }
-                    CACHE_TYPE_BLOCKS_MIN => {
-                        let key = H256::from_str(&key).map_err(|err| {
-                            format!("invalid key for cache file '{:?}': {:?}", key, err)
-                        })?;
-                        let block: Block<TransactionVariant> = serde_json::from_reader(reader)
-                            .map_err(|err| {
-                                format!("failed parsing json for cache file '{:?}': {:?}", key, err)
-                            })?;
-                        self.block_hashes.insert(block.number.as_u64(), block.hash);
-                        self.blocks_min.insert(key, block);
-                    }
-                    CACHE_TYPE_BLOCK_RAW_TRANSACTIONS => {
-                        let key = key.parse::<u64>().map_err(|err| {
-                            format!("invalid key for cache file '{:?}': {:?}", key, err)
-                        })?;
-                        let transactions: Vec<RawTransaction> = serde_json::from_reader(reader)
-                            .map_err(|err| {
-                                format!("failed parsing json for cache file '{:?}': {:?}", key, err)
-                            })?;
-                        self.block_raw_transactions.insert(key, transactions);
-                    }
-                    CACHE_TYPE_TRANSACTIONS => {
-                        let key = H256::from_str(&key).map_err(|err| {
-                            format!("invalid key for cache file '{:?}': {:?}", key, err)
-                        })?;
-                        let transaction: Transaction =
-                            serde_json::from_reader(reader).map_err(|err| {
-                                format!("failed parsing json for cache file '{:?}': {:?}", key, err)
-                            })?;
-                        self.transactions.insert(key, transaction);
-                    }
-                    CACHE_TYPE_RESOLVER_SELECTORS => {
-                        let selector: String = serde_json::from_reader(reader).map_err(|err| {
+                self.load_entry(cache_type, key, BufReader::new(cache_file))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the entries of this cache's namespace from the cache file. Loading doesn't count as a use, so the
+    /// entries keep their recency. Entries that can't be parsed are dropped.
+    fn read_all_from_file(&mut self) -> Result<(), String> {
+        let Some(file) = self.file.clone() else {
+            return Ok(());
+        };
+        let mut file = file.lock().expect("cache file lock is poisoned");
+        for file_key in file.keys(&self.namespace) {
+            let value = file
+                .peek(&self.namespace, &file_key)
+                .map_err(|err| format!("failed reading cache entry '{}': {:?}", file_key, err))?;
+            let Some(value) = value else {
+                continue;
+            };
+            let result = match file_key.split_once('/') {
+                Some((cache_type, key)) => {
+                    self.load_entry(cache_type, key.to_string(), value.as_slice())
+                }
+                None => Err(format!("invalid cache file key '{}'", file_key)),
+            };
+            if let Err(err) = result {
+                tracing::warn!("dropping invalid cache entry '{}': {}", file_key, err);
+                file.remove(&self.namespace, &file_key);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes all entries of this cache's namespace from the cache file.
+    fn reset_file(&self) -> io::Result<()> {
+        if let Some(file) = &self.file {
+            let mut file = file.lock().expect("cache file lock is poisoned");
+            file.remove_namespace(&self.namespace)?;
+        }
+        Ok(())
+    }
+
+    /// Parses a single cache entry and stores it in memory.
+    fn load_entry(
+        &mut self,
+        cache_type: &str,
+        key: String,
+        reader: impl Read,
+    ) -> Result<(), String> {
+        match cache_type {
+            CACHE_TYPE_BLOCKS_FULL => {
+                let key = H256::from_str(&key)
+                    .map_err(|err| format!("invalid key for cache file '{:?}': {:?}", key, err))?;
+                let block: Block<TransactionVariant> =
+                    serde_json::from_reader(reader).map_err(|err| {
+                        format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                    })?;
+                self.block_hashes.insert(block.number.as_u64(), block.hash);
+                self.blocks_full.insert(key, block);
+            }
+            CACHE_TYPE_BLOCKS_MIN => {
+                let key = H256::from_str(&key)
+                    .map_err(|err| format!("invalid key for cache file '{:?}': {:?}", key, err))?;
+                let block: Block<TransactionVariant> =
+                    serde_json::from_reader(reader).map_err(|err| {
+                        format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                    })?;
+                self.block_hashes.insert(block.number.as_u64(), block.hash);
+                self.blocks_min.insert(key, block);
+            }
+            CACHE_TYPE_BLOCK_RAW_TRANSACTIONS => {
+                let key = key
+                    .parse::<u64>()
+                    .map_err(|err| format!("invalid key for cache file '{:?}': {:?}", key, err))?;
+                let transactions: Vec<RawTransaction> =
+                    serde_json::from_reader(reader).map_err(|err| {
+                        format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                    })?;
+                self.block_raw_transactions.insert(key, transactions);
+            }
+            CACHE_TYPE_TRANSACTIONS => {
+                let key = H256::from_str(&key)
+                    .map_err(|err| format!("invalid key for cache file '{:?}': {:?}", key, err))?;
+                let transaction: Transaction = serde_json::from_reader(reader).map_err(|err| {
+                    format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                })?;
+                self.transactions.insert(key, transaction);
+            }
+            CACHE_TYPE_RESOLVER_SELECTORS => {
+                let selector: String = serde_json::from_reader(reader).map_err(|err| {
+                    format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                })?;
+                self.resolver_selectors.insert(key, selector);
+            }
+            CACHE_TYPE_KEY_VALUE => match key.as_str() {
+                CACHE_KEY_BRIDGE_ADDRESSES => {
+                    self.bridge_addresses =
+                        Some(serde_json::from_reader(reader).map_err(|err| {
                             format!("failed parsing json for cache file '{:?}': {:?}", key, err)
-                        })?;
-                        self.resolver_selectors.insert(key, selector);
-                    }
-                    CACHE_TYPE_KEY_VALUE => match key.as_str() {
-                        CACHE_KEY_BRIDGE_ADDRESSES => {
-                            self.bridge_addresses =
-                                Some(serde_json::from_reader(reader).map_err(|err| {
-                                    format!(
-                                        "failed parsing json for cache file '{:?}': {:?}",
-                                        key, err
-                                    )
-                                })?);
-                        }
-                        _ => return Err(format!("invalid cache_type_value key {}", cache_type)),
-                    },
-                    _ => return Err(format!("invalid cache_type {}", cache_type)),
+                        })?);
                 }
+                _ => return Err(format!("invalid cache_type_value key {}", cache_type)),
+            },
+            CACHE_TYPE_STORAGE_VALUES => {
+                let parsed_key = match key.split('_').collect::<Vec<_>>().as_slice() {
+                    [block_number, address, storage_key] => block_number
+                        .parse::<u64>()
+                        .ok()
+                        .zip(Address::from_str(address).ok())
+                        .zip(H256::from_str(storage_key).ok())
+                        .map(|((block_number, address), storage_key)| {
+                            (block_number, address, storage_key)
+                        }),
+                    _ => None,
+                };
+                let parsed_key =
+                    parsed_key.ok_or_else(|| format!("invalid key for cache file '{:?}'", key))?;
+                let value: H256 = serde_json::from_reader(reader).map_err(|err| {
+                    format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                })?;
+                self.storage_values.insert(parsed_key, value);
             }
+            CACHE_TYPE_FACTORY_DEPS => {
+                let key = H256::from_str(&key)
+                    .map_err(|err| format!("invalid key for cache file '{:?}': {:?}", key, err))?;
+                let bytecode: Bytes = serde_json::from_reader(reader).map_err(|err| {
+                    format!("failed parsing json for cache file '{:?}': {:?}", key, err)
+                })?;
+                self.factory_deps.insert(key, bytecode.0);
+            }
+            _ => return Err(format!("invalid cache_type {}", cache_type)),
         }
 
         Ok(())
     }
 
+    /// Marks an entry of the cache file as recently used, so that it's evicted last.
+    fn touch_file_entry(&self, cache_type: &str, key: impl FnOnce() -> String) {
+        if let Some(file) = &self.file {
+            file.lock()
+                .expect("cache file lock is poisoned")
+                .touch(&self.namespace, &cache_file_key(cache_type, &key()));
+        }
+    }
+
     /// Writes the cache contents to disk, if supported.
     fn write_to_disk<T: Serialize>(&self, cache_type: &'static str, key: String, data: &T) {
-        if let CacheConfig::Disk { dir, .. } = &self.config {
-            let file = Path::new(&dir).join(cache_type).join(key);
+        if let Some(file) = &self.file {
+            let file_key = cache_file_key(cache_type, &key);
+            tracing::debug!("writing cache entry '{}'", file_key);
+            let result = serde_json::to_vec(data)
+                .map_err(io::Error::from)
+                .and_then(|value| {
+                    file.lock().expect("cache file lock is poisoned").insert(
+                        &self.namespace,
+                        &file_key,
+                        &value,
+                    )
+                });
+            if let Err(err) = result {
+                tracing::error!("failed writing to cache '{}': {:?}", file_key, err);
+            }
+        } else if let CacheConfig::Disk { dir, .. } = &self.config {
+            let file = namespace_dir(dir, &self.namespace)
+                .join(cache_type)
+                .join(key);
 
             tracing::debug!("writing cache {:?}", file);
             match File::create(file.clone()) {
@@ -572,6 +831,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cache_config_disk_preserves_storage_values_and_factory_deps() {
+        let address = H160::repeat_byte(0x1);
+        let key = H256::repeat_byte(0x2);
+        let value = H256::repeat_byte(0x3);
+        let bytecode = vec![0x4; 32];
+
+        let cache_dir = TempDir::new("cache-test").expect("failed creating temporary dir");
+        let cache_dir_path = cache_dir
+            .path()
+            .to_str()
+            .expect("invalid dir name")
+            .to_string();
+        let mut cache = Cache::new(CacheConfig::Disk {
+            dir: cache_dir_path.clone(),
+            reset: true,
+        });
+
+        cache.insert_storage_value(10, address, key, value);
+        assert_eq!(Some(&value), cache.get_storage_value(10, &address, &key));
+        assert_eq!(None, cache.get_storage_value(11, &address, &key));
+
+        cache.insert_factory_dep(H256::repeat_byte(0x5), bytecode.clone());
+        assert_eq!(
+            Some(&bytecode),
+            cache.get_factory_dep(&H256::repeat_byte(0x5))
+        );
+
+        let new_cache = Cache::new(CacheConfig::Disk {
+            dir: cache_dir_path,
+            reset: false,
+        });
+        assert_eq!(
+            Some(&value),
+            new_cache.get_storage_value(10, &address, &key)
+        );
+        assert_eq!(None, new_cache.get_storage_value(11, &address, &key));
+        assert_eq!(
+            Some(&bytecode),
+            new_cache.get_factory_dep(&H256::repeat_byte(0x5))
+        );
+    }
+
     #[test]
     fn test_cache_config_disk_enables_cache_and_can_reset_data_on_disk() {
         let block_full = Block::<TransactionVariant> {
@@ -696,4 +998,189 @@ mod tests {
         );
         assert!(random_file_path.exists(), "random file was reset from disk");
     }
+
+    #[test]
+    fn test_cache_config_disk_keeps_namespaces_apart_and_can_reset_them() {
+        let cache_dir = TempDir::new("cache-test").expect("failed creating temporary dir");
+        let config = |reset| CacheConfig::Disk {
+            dir: cache_dir
+                .path()
+                .to_str()
+                .expect("invalid dir name")
+                .to_string(),
+            reset,
+        };
+        let address = Address::repeat_byte(0x1);
+
+        let mut mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        mainnet.insert_storage_value(1, address, H256::zero(), H256::repeat_byte(0x2));
+        mainnet.insert_block_raw_transactions(1, vec![]);
+        let mut testnet = Cache::with_namespace(config(false), String::from("http://testnet"));
+        testnet.insert_storage_value(1, address, H256::zero(), H256::repeat_byte(0x4));
+
+        let mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        assert_eq!(
+            Some(&H256::repeat_byte(0x2)),
+            mainnet.get_storage_value(1, &address, &H256::zero())
+        );
+        let testnet = Cache::with_namespace(config(false), String::from("http://testnet"));
+        assert_eq!(
+            Some(&H256::repeat_byte(0x4)),
+            testnet.get_storage_value(1, &address, &H256::zero())
+        );
+        assert_eq!(None, testnet.get_block_raw_transactions(&1));
+
+        let testnet = Cache::with_namespace(config(true), String::from("http://testnet"));
+        assert_eq!(None, testnet.get_storage_value(1, &address, &H256::zero()));
+        let mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        assert_eq!(
+            Some(&H256::repeat_byte(0x2)),
+            mainnet.get_storage_value(1, &address, &H256::zero())
+        );
+    }
+
+    #[test]
+    fn test_cache_config_file_keeps_namespaces_apart_and_can_reset_them() {
+        let cache_dir = TempDir::new("cache-test").expect("failed creating temporary dir");
+        let path = cache_dir
+            .path()
+            .join("anvil-zksync.cache")
+            .to_str()
+            .expect("invalid file name")
+            .to_string();
+        let config = |reset| CacheConfig::File {
+            path: path.clone(),
+            max_size: 1024 * 1024,
+            reset,
+        };
+        let address = Address::repeat_byte(0x1);
+
+        let mut mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        mainnet.insert_storage_value(1, address, H256::zero(), H256::repeat_byte(0x2));
+        mainnet.insert_factory_dep(H256::repeat_byte(0x3), vec![1, 2, 3]);
+        let mut testnet = Cache::with_namespace(config(false), String::from("http://testnet"));
+        testnet.insert_storage_value(1, address, H256::zero(), H256::repeat_byte(0x4));
+
+        let mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        assert_eq!(
+            Some(&H256::repeat_byte(0x2)),
+            mainnet.get_storage_value(1, &address, &H256::zero())
+        );
+        assert_eq!(
+            Some(&vec![1, 2, 3]),
+            mainnet.get_factory_dep(&H256::repeat_byte(0x3))
+        );
+
+        let testnet = Cache::with_namespace(config(true), String::from("http://testnet"));
+        assert_eq!(None, testnet.get_storage_value(1, &address, &H256::zero()));
+        let mainnet = Cache::with_namespace(config(false), String::from("http://mainnet"));
+        assert_eq!(
+            Some(&H256::repeat_byte(0x2)),
+            mainnet.get_storage_value(1, &address, &H256::zero())
+        );
+    }
+
+    #[test]
+    fn test_cache_config_file_keeps_recency_when_reopened() {
+        let cache_dir = TempDir::new("cache-test").expect("failed creating temporary dir");
+        let path = |name: &str| {
+            cache_dir
+                .path()
+                .join(name)
+                .to_str()
+                .expect("invalid file name")
+                .to_string()
+        };
+        let address = Address::repeat_byte(0x1);
+        let value = H256::repeat_byte(0x2);
+
+        // All storage value entries have the same size, measure it on a separate file.
+        let mut probe = Cache::new(CacheConfig::File {
+            path: path("probe.cache"),
+            max_size: 1024 * 1024,
+            reset: false,
+        });
+        probe.insert_storage_value(1, address, H256::zero(), value);
+        let entry_size = probe
+            .file
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .stats()
+            .live_size;
+
+        // Fits three entries, a fourth one evicts the least recently used.
+        let config = CacheConfig::File {
+            path: path("anvil-zksync.cache"),
+            max_size: entry_size * 7 / 2,
+            reset: false,
+        };
+        let mut cache = Cache::new(config.clone());
+        for key in 1..=3 {
+            cache.insert_storage_value(1, address, H256::repeat_byte(key), value);
+        }
+
+        let mut cache = Cache::new(config.clone());
+        assert_eq!(
+            Some(&value),
+            cache.get_storage_value(1, &address, &H256::repeat_byte(1))
+        );
+        cache.insert_storage_value(1, address, H256::repeat_byte(4), value);
+
+        let cache = Cache::new(config);
+        for (key, expected) in [
+            (1, Some(&value)),
+            (2, None),
+            (3, Some(&value)),
+            (4, Some(&value)),
+        ] {
+            assert_eq!(
+                expected,
+                cache.get_storage_value(1, &address, &H256::repeat_byte(key)),
+                "unexpected value for key {}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_config_file_drops_invalid_entries() {
+        let cache_dir = TempDir::new("cache-test").expect("failed creating temporary dir");
+        let config = CacheConfig::File {
+            path: cache_dir
+                .path()
+                .join("anvil-zksync.cache")
+                .to_str()
+                .expect("invalid file name")
+                .to_string(),
+            max_size: 1024 * 1024,
+            reset: false,
+        };
+        let address = Address::repeat_byte(0x1);
+
+        let mut cache = Cache::new(config.clone());
+        cache.insert_storage_value(1, address, H256::zero(), H256::repeat_byte(0x2));
+        cache
+            .file
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .insert("", "storage_values/invalid", b"not json")
+            .expect("failed inserting");
+
+        let cache = Cache::new(config);
+        assert_eq!(
+            Some(&H256::repeat_byte(0x2)),
+            cache.get_storage_value(1, &address, &H256::zero())
+        );
+        assert_eq!(
+            vec![cache_file_key(
+                CACHE_TYPE_STORAGE_VALUES,
+                &format!("1_{:#x}_{:#x}", address, H256::zero())
+            )],
+            cache.file.as_ref().unwrap().lock().unwrap().keys("")
+        );
+    }
 }
@@ -0,0 +1,631 @@
+//! Single-file key-value store backing the `file` cache type.
+//!
+//! Entries are grouped into namespaces (the fork URL they were fetched from), so that forks of different networks
+//! can share one file without mixing up their data.
+//!
+//! The file starts with a short header followed by records that are only ever appended:
+//! `key length (u32) | value length (u32) | CRC32 of key and value (u32) | key | value`, integers little-endian.
+//! A later record for the same key replaces the earlier one. All records are verified on open; the file is
+//! truncated at the first corrupted or incomplete record.
+//!
+//! The store is bounded by a maximum size. When the live records exceed it, the least recently used entries are
+//! evicted and the file is compacted, rewriting the survivors in recency order so that it carries over restarts.
+//!
+//! Only one process can have the store open at a time, which is ensured with an advisory lock on a `.lock` file next
+//! to it. Other processes fail to open the store while it is in use.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::{TryFrom, TryInto},
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::Crc;
+use fs2::FileExt;
+
+const MAGIC: &[u8; 4] = b"AZKC";
+const VERSION: u8 = 1;
+const FILE_HEADER_LEN: u64 = 5;
+const RECORD_HEADER_LEN: u64 = 12;
+/// Fraction (in percent) of the maximum size that eviction shrinks the store to, to avoid evicting on every insert.
+const EVICTION_TARGET_PERCENT: u64 = 90;
+
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry {
+    offset: u64,
+    key_len: u32,
+    value_len: u32,
+    checksum: u32,
+    last_used: u64,
+}
+
+impl IndexEntry {
+    fn record_len(&self) -> u64 {
+        RECORD_HEADER_LEN + self.key_len as u64 + self.value_len as u64
+    }
+}
+
+/// Size information of a [CacheFile].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheFileStats {
+    pub entries: usize,
+    /// Size of the file on disk, including replaced records.
+    pub file_size: u64,
+    /// Size of the records that are still in use.
+    pub live_size: u64,
+    pub max_size: u64,
+}
+
+/// Size information of a single namespace in a [CacheFile].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceStats {
+    pub entries: usize,
+    pub size: u64,
+}
+
+/// Single-file key-value store with LRU eviction, see the module documentation.
+#[derive(Debug)]
+pub struct CacheFile {
+    path: PathBuf,
+    file: File,
+    /// Holds the advisory lock on the store for as long as it is open.
+    _lock: File,
+    max_size: u64,
+    index: HashMap<String, IndexEntry>,
+    file_size: u64,
+    live_size: u64,
+    /// Logical clock used to track recency of entries.
+    clock: u64,
+}
+
+fn checksum(key: &[u8], value: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(key);
+    crc.update(value);
+    crc.sum()
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("slice has 4 bytes"))
+}
+
+/// Returns the key of a record. URLs don't contain spaces, so the first space ends the namespace.
+fn record_key(namespace: &str, key: &str) -> String {
+    format!("{} {}", namespace, key)
+}
+
+fn split_record_key(record_key: &str) -> (&str, &str) {
+    record_key.split_once(' ').unwrap_or(("", record_key))
+}
+
+impl CacheFile {
+    /// Opens the store at `path`, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>, max_size: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // The store itself is replaced on compaction, so the lock is taken on a separate file that never is.
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(path.with_extension("lock"))?;
+        lock.try_lock_exclusive().map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "cache file {:?} is in use by another process: {}",
+                    path, err
+                ),
+            )
+        })?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut store = Self {
+            path,
+            file: file.try_clone()?,
+            _lock: lock,
+            max_size,
+            index: HashMap::new(),
+            file_size: FILE_HEADER_LEN,
+            live_size: 0,
+            clock: 0,
+        };
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.write_all(&[VERSION])?;
+            file.sync_data()?;
+            return Ok(store);
+        }
+
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Reads and verifies all records, truncating the file at the first invalid one.
+    fn load(&mut self) -> io::Result<()> {
+        let file_len = self.file.metadata()?.len();
+        let mut reader = BufReader::new(self.file.try_clone()?);
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0u8; FILE_HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not a cache file of a supported version", self.path),
+            ));
+        }
+
+        let mut offset = FILE_HEADER_LEN;
+        while offset < file_len {
+            match Self::read_record(&mut reader, file_len - offset) {
+                Ok((key, _, entry)) => {
+                    self.insert_index(
+                        key,
+                        IndexEntry {
+                            offset,
+                            last_used: self.clock,
+                            ..entry
+                        },
+                    );
+                    self.clock += 1;
+                    offset += entry.record_len();
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "cache file {:?} is corrupted at offset {}, discarding the rest: {}",
+                        self.path,
+                        offset,
+                        err
+                    );
+                    self.file.set_len(offset)?;
+                    break;
+                }
+            }
+        }
+        self.file_size = offset;
+        Ok(())
+    }
+
+    /// Reads and verifies the record at the reader's position, returning its key, value and an index entry
+    /// without offset. `remaining` is the number of bytes from the record to the end of the file, records
+    /// claiming to be longer are rejected before anything is allocated for them.
+    fn read_record(
+        reader: &mut impl Read,
+        remaining: u64,
+    ) -> io::Result<(String, Vec<u8>, IndexEntry)> {
+        let mut header = [0u8; RECORD_HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        let key_len = read_u32(&header[0..4]);
+        let value_len = read_u32(&header[4..8]);
+        let stored_checksum = read_u32(&header[8..12]);
+        if RECORD_HEADER_LEN + key_len as u64 + value_len as u64 > remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "record exceeds the end of the file",
+            ));
+        }
+
+        let mut key = vec![0u8; key_len as usize];
+        reader.read_exact(&mut key)?;
+        let mut value = vec![0u8; value_len as usize];
+        reader.read_exact(&mut value)?;
+        if checksum(&key, &value) != stored_checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checksum mismatch",
+            ));
+        }
+        let key = String::from_utf8(key)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        Ok((
+            key,
+            value,
+            IndexEntry {
+                offset: 0,
+                key_len,
+                value_len,
+                checksum: stored_checksum,
+                last_used: 0,
+            },
+        ))
+    }
+
+    fn insert_index(&mut self, key: String, entry: IndexEntry) {
+        self.live_size += entry.record_len();
+        if let Some(previous) = self.index.insert(key, entry) {
+            self.live_size -= previous.record_len();
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Returns the value stored under `key` in `namespace` and marks it as recently used, verifying its checksum.
+    /// Corrupted entries are dropped.
+    pub fn get(&mut self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let key = &record_key(namespace, key);
+        let value = self.read_value(key)?;
+        if value.is_some() {
+            let now = self.tick();
+            if let Some(entry) = self.index.get_mut(key) {
+                entry.last_used = now;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Like [CacheFile::get], but leaves the recency of the entry unchanged.
+    pub fn peek(&mut self, namespace: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.read_value(&record_key(namespace, key))
+    }
+
+    /// Reads and verifies the value of the record stored under `key`, dropping it if it's corrupted.
+    fn read_value(&mut self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let entry = match self.index.get(key) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let value = match Self::read_record(&mut self.file, self.file_size - entry.offset) {
+            Ok((stored_key, value, _)) if stored_key == *key => value,
+            result => {
+                tracing::warn!(
+                    "dropping corrupted entry '{}' from cache file {:?}: {:?}",
+                    key,
+                    self.path,
+                    result.err()
+                );
+                self.remove_record(key);
+                return Ok(None);
+            }
+        };
+        Ok(Some(value))
+    }
+
+    /// Marks `key` in `namespace` as recently used.
+    pub fn touch(&mut self, namespace: &str, key: &str) {
+        let now = self.tick();
+        if let Some(entry) = self.index.get_mut(&record_key(namespace, key)) {
+            entry.last_used = now;
+        }
+    }
+
+    /// Stores `value` under `key` in `namespace`, evicting least recently used entries if the store grows over
+    /// its maximum size.
+    pub fn insert(&mut self, namespace: &str, key: &str, value: &[u8]) -> io::Result<()> {
+        let key = &record_key(namespace, key);
+        let key_len = u32::try_from(key.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let value_len = u32::try_from(value.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let entry = IndexEntry {
+            offset: self.file_size,
+            key_len,
+            value_len,
+            checksum: checksum(key.as_bytes(), value),
+            last_used: self.tick(),
+        };
+
+        let mut record = Vec::with_capacity(entry.record_len() as usize);
+        record.extend_from_slice(&key_len.to_le_bytes());
+        record.extend_from_slice(&value_len.to_le_bytes());
+        record.extend_from_slice(&entry.checksum.to_le_bytes());
+        record.extend_from_slice(key.as_bytes());
+        record.extend_from_slice(value);
+        self.file.seek(SeekFrom::Start(self.file_size))?;
+        self.file.write_all(&record)?;
+        self.file_size += entry.record_len();
+        self.insert_index(key.to_string(), entry);
+
+        if self.live_size > self.max_size {
+            self.shrink_to(self.max_size * EVICTION_TARGET_PERCENT / 100)?;
+        } else if self.file_size > self.max_size && self.file_size > 2 * self.live_size {
+            // Mostly replaced records; reclaim the space.
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Removes `key` from `namespace`.
+    pub fn remove(&mut self, namespace: &str, key: &str) {
+        self.remove_record(&record_key(namespace, key));
+    }
+
+    fn remove_record(&mut self, key: &str) {
+        if let Some(entry) = self.index.remove(key) {
+            self.live_size -= entry.record_len();
+        }
+    }
+
+    /// Returns all keys in `namespace`.
+    pub fn keys(&self, namespace: &str) -> Vec<String> {
+        self.index
+            .keys()
+            .map(|record_key| split_record_key(record_key))
+            .filter(|(entry_namespace, _)| *entry_namespace == namespace)
+            .map(|(_, key)| key.to_string())
+            .collect()
+    }
+
+    /// Removes all entries of `namespace` and compacts the file. Returns the number of removed entries.
+    pub fn remove_namespace(&mut self, namespace: &str) -> io::Result<usize> {
+        let keys = self
+            .index
+            .keys()
+            .filter(|record_key| split_record_key(record_key).0 == namespace)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in &keys {
+            self.remove_record(key);
+        }
+        self.compact()?;
+        Ok(keys.len())
+    }
+
+    /// Evicts least recently used entries until the live records fit into `max_size` and compacts the file.
+    /// Returns the number of evicted entries.
+    pub fn shrink_to(&mut self, max_size: u64) -> io::Result<usize> {
+        let mut entries = self
+            .index
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let mut evicted = 0;
+        for (_, key) in entries {
+            if self.live_size <= max_size {
+                break;
+            }
+            self.remove_record(&key);
+            evicted += 1;
+        }
+        if evicted > 0 {
+            tracing::debug!(
+                "evicted {} entries from cache file {:?}",
+                evicted,
+                self.path
+            );
+        }
+        self.compact()?;
+        Ok(evicted)
+    }
+
+    /// Rewrites the file with only the live records, least recently used first.
+    fn compact(&mut self) -> io::Result<()> {
+        let mut entries = self
+            .index
+            .iter()
+            .map(|(key, entry)| (key.clone(), *entry))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(_, entry)| entry.last_used);
+
+        let tmp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        let mut index = HashMap::with_capacity(entries.len());
+        let mut offset = FILE_HEADER_LEN;
+        for (key, entry) in entries {
+            let mut record = vec![0u8; entry.record_len() as usize];
+            self.file.seek(SeekFrom::Start(entry.offset))?;
+            self.file.read_exact(&mut record)?;
+            writer.write_all(&record)?;
+            index.insert(key, IndexEntry { offset, ..entry });
+            offset += entry.record_len();
+        }
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(&tmp_path, &self.path)?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.index = index;
+        self.file_size = offset;
+        Ok(())
+    }
+
+    /// Verifies the checksums of all entries, dropping the corrupted ones and compacting the file.
+    /// Returns the number of dropped entries.
+    pub fn verify(&mut self) -> io::Result<usize> {
+        let keys = self.index.keys().cloned().collect::<Vec<_>>();
+        let mut dropped = 0;
+        for key in keys {
+            let entry = self.index[&key];
+            self.file.seek(SeekFrom::Start(entry.offset))?;
+            let valid = matches!(
+                Self::read_record(&mut self.file, self.file_size - entry.offset),
+                Ok((stored_key, _, stored)) if stored_key == key && stored.checksum == entry.checksum
+            );
+            if !valid {
+                self.remove_record(&key);
+                dropped += 1;
+            }
+        }
+        self.compact()?;
+        Ok(dropped)
+    }
+
+    /// Returns the number and size of the entries of each namespace.
+    pub fn namespace_stats(&self) -> BTreeMap<String, NamespaceStats> {
+        let mut stats = BTreeMap::<String, NamespaceStats>::new();
+        for (record_key, entry) in &self.index {
+            let namespace = stats
+                .entry(split_record_key(record_key).0.to_string())
+                .or_default();
+            namespace.entries += 1;
+            namespace.size += entry.record_len();
+        }
+        stats
+    }
+
+    pub fn stats(&self) -> CacheFileStats {
+        CacheFileStats {
+            entries: self.index.len(),
+            file_size: self.file_size,
+            live_size: self.live_size,
+            max_size: self.max_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_cache_file_persists_entries() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed opening store");
+        store.insert("x", "a", b"first").expect("failed inserting");
+        store.insert("x", "b", b"second").expect("failed inserting");
+        store
+            .insert("x", "a", b"replaced")
+            .expect("failed inserting");
+        drop(store);
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed reopening store");
+        assert_eq!(2, store.stats().entries);
+        assert_eq!(
+            Some(b"replaced".to_vec()),
+            store.get("x", "a").expect("failed reading")
+        );
+        assert_eq!(
+            Some(b"second".to_vec()),
+            store.get("x", "b").expect("failed reading")
+        );
+        assert_eq!(None, store.get("x", "c").expect("failed reading"));
+    }
+
+    #[test]
+    fn test_cache_file_evicts_least_recently_used() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+        let value = vec![0u8; 100];
+        let record_len = RECORD_HEADER_LEN + record_key("x", "a").len() as u64 + value.len() as u64;
+
+        let mut store = CacheFile::open(&path, 3 * record_len).expect("failed opening store");
+        store.insert("x", "a", &value).expect("failed inserting");
+        store.insert("x", "b", &value).expect("failed inserting");
+        store.insert("x", "c", &value).expect("failed inserting");
+        store.touch("x", "a");
+        store.insert("x", "d", &value).expect("failed inserting");
+
+        let mut keys = store.keys("x");
+        keys.sort();
+        assert_eq!(vec!["a", "d"], keys);
+        assert!(store.stats().file_size <= 3 * record_len + FILE_HEADER_LEN);
+    }
+
+    #[test]
+    fn test_cache_file_truncates_corrupted_records() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed opening store");
+        store.insert("x", "a", b"first").expect("failed inserting");
+        store.insert("x", "b", b"second").expect("failed inserting");
+        drop(store);
+
+        // Flip the last byte of the value of "b".
+        let mut bytes = fs::read(&path).expect("failed reading file");
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, bytes).expect("failed writing file");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed reopening store");
+        assert_eq!(1, store.stats().entries);
+        assert_eq!(
+            Some(b"first".to_vec()),
+            store.get("x", "a").expect("failed reading")
+        );
+        assert_eq!(None, store.get("x", "b").expect("failed reading"));
+    }
+
+    #[test]
+    fn test_cache_file_truncates_records_with_corrupted_lengths() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed opening store");
+        store.insert("x", "a", b"first").expect("failed inserting");
+        let second_offset = store.stats().file_size as usize;
+        store.insert("x", "b", b"second").expect("failed inserting");
+        drop(store);
+
+        // Make the value of "b" claim to be 4 GiB long.
+        let mut bytes = fs::read(&path).expect("failed reading file");
+        bytes[second_offset + 4..second_offset + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, bytes).expect("failed writing file");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed reopening store");
+        assert_eq!(1, store.stats().entries);
+        assert_eq!(second_offset as u64, store.stats().file_size);
+        assert_eq!(
+            Some(b"first".to_vec()),
+            store.get("x", "a").expect("failed reading")
+        );
+    }
+
+    #[test]
+    fn test_cache_file_is_locked_while_open() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+
+        let store = CacheFile::open(&path, 1024 * 1024).expect("failed opening store");
+        assert!(CacheFile::open(&path, 1024 * 1024).is_err());
+        drop(store);
+        assert!(CacheFile::open(&path, 1024 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_cache_file_namespaces() {
+        let dir = TempDir::new("cache-file-test").expect("failed creating temporary dir");
+        let path = dir.path().join("cache.db");
+
+        let mut store = CacheFile::open(&path, 1024 * 1024).expect("failed opening store");
+        store
+            .insert("http://a", "key 1", b"1")
+            .expect("failed inserting");
+        store
+            .insert("http://a", "key 2", b"2")
+            .expect("failed inserting");
+        store
+            .insert("http://b", "key 1", b"3")
+            .expect("failed inserting");
+
+        let stats = store.namespace_stats();
+        assert_eq!(2, stats["http://a"].entries);
+        assert_eq!(1, stats["http://b"].entries);
+
+        assert_eq!(
+            2,
+            store.remove_namespace("http://a").expect("failed removing")
+        );
+        assert_eq!(1, store.stats().entries);
+        assert_eq!(
+            None,
+            store.get("http://a", "key 1").expect("failed reading")
+        );
+        assert_eq!(
+            Some(b"3".to_vec()),
+            store.get("http://b", "key 1").expect("failed reading")
+        );
+    }
+}
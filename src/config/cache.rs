@@ -9,6 +9,7 @@ pub enum CacheType {
     Memory,
     #[default]
     Disk,
+    File,
 }
 
 /// Cache configuration options.
@@ -20,6 +21,13 @@ pub enum CacheConfig {
     Memory,
     #[serde(rename = "disk")]
     Disk { dir: String, reset: bool },
+    /// Single file with a maximum size, evicting the least recently used entries.
+    #[serde(rename = "file")]
+    File {
+        path: String,
+        max_size: u64,
+        reset: bool,
+    },
 }
 
 impl Default for CacheConfig {
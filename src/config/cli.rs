@@ -1,18 +1,19 @@
+use std::convert::TryFrom;
 use std::env;
 use std::time::Duration;
 
 use clap::{arg, command, Parser, Subcommand};
 use rand::{rngs::StdRng, SeedableRng};
-use zksync_types::{H256, U256};
+use zksync_types::{ProtocolVersionId, H256, U256};
 
-use super::DEFAULT_DISK_CACHE_DIR;
+use super::{DEFAULT_CACHE_FILE_MAX_SIZE, DEFAULT_CACHE_FILE_PATH, DEFAULT_DISK_CACHE_DIR};
 use crate::config::constants::{DEFAULT_MNEMONIC, TEST_NODE_NETWORK_ID};
 use crate::config::{
     AccountGenerator, CacheConfig, CacheType, Genesis, ShowCalls, ShowGasDetails, ShowStorageLogs,
     ShowVMDetails, TestNodeConfig,
 };
 use crate::observability::LogLevel;
-use crate::system_contracts::Options as SystemContractsOptions;
+use crate::system_contracts::{self, built_in_protocol_version, Options as SystemContractsOptions};
 use crate::utils::parse_genesis_file;
 use alloy_signer_local::coins_bip39::{English, Mnemonic};
 use std::net::IpAddr;
@@ -39,6 +40,11 @@ pub struct Cli {
     /// The endpoint will return 200 OK if the node is healthy.
     pub health_check_endpoint: bool,
 
+    #[arg(long, help_heading = "General Options")]
+    /// Enable Prometheus metrics endpoint.
+    /// It will be available for GET requests at /metrics.
+    pub metrics_endpoint: bool,
+
     /// Writes output of `anvil-zksync` as json to user-specified file.
     #[arg(long, value_name = "OUT_FILE", help_heading = "General Options")]
     pub config_out: Option<String>,
@@ -58,6 +64,10 @@ pub struct Cli {
     )]
     pub host: Vec<IpAddr>,
 
+    #[arg(long, value_name = "NUM", value_parser = clap::value_parser!(u64).range(1..), help_heading = "Network Options")]
+    /// Number of worker threads serving JSON-RPC requests (default: 1). VM execution runs on a separate thread pool.
+    pub server_threads: Option<u64>,
+
     #[arg(long, help_heading = "Network Options")]
     /// Specify chain ID (default: 260).
     pub chain_id: Option<u32>,
@@ -126,6 +136,11 @@ pub struct Cli {
     /// Custom L1 pubdata price (in wei).
     pub l1_pubdata_price: Option<u64>,
 
+    #[arg(long, help_heading = "Gas Configuration")]
+    /// Run without fees: the base fee, pubdata price and L1 gas price are zero and transactions
+    /// of unfunded accounts are accepted. Can be toggled at runtime with `anvil_zks_setGasless`.
+    pub gasless: bool,
+
     #[arg(long, help_heading = "Gas Configuration")]
     /// Gas price estimation scale factor.
     pub price_scale_factor: Option<f64>,
@@ -151,6 +166,17 @@ pub struct Cli {
     /// Enables EVM emulation. Requires local system contracts.
     pub emulate_evm: bool,
 
+    #[arg(long, value_name = "VERSION", help_heading = "System Configuration")]
+    /// Protocol version of a new chain, e.g. 24 (default: latest).
+    /// Versions other than the latest one require local system contracts or a `--system-contracts-dir`.
+    /// Not supported when forking.
+    pub protocol_version: Option<u16>,
+
+    #[arg(long, value_name = "DIR", help_heading = "System Configuration")]
+    /// Directory with compiled bootloader (`<name>.yul.zbin`) and system contract artifacts to run instead of
+    /// the selected system contracts. Can be reloaded at runtime with `anvil_zks_reloadSystemContracts`.
+    pub system_contracts_dir: Option<String>,
+
     // Logging Configuration
     #[arg(long, help_heading = "Logging Configuration")]
     /// Log level (default: info).
@@ -160,13 +186,19 @@ pub struct Cli {
     /// Log file path (default: anvil-zksync.log).
     pub log_file_path: Option<String>,
 
+    #[arg(long, value_name = "FILE", help_heading = "Logging Configuration")]
+    /// Writes blocks, transactions, calls, events, console logs and storage writes
+    /// as newline-delimited JSON to the given file ("-" for stdout, which moves
+    /// all other console output to stderr).
+    pub json_log: Option<String>,
+
     #[arg(long, alias = "quiet", default_missing_value = "true", num_args(0..=1), help_heading = "Logging Configuration")]
     /// If true, the tool will not print anything on startup.
     pub silent: Option<bool>,
 
     // Cache Options
     #[arg(long, help_heading = "Cache Options")]
-    /// Cache type (none, memory, or disk). Default: "disk".
+    /// Cache type (none, memory, disk or file). Default: "disk".
     pub cache: Option<CacheType>,
 
     #[arg(long, help_heading = "Cache Options")]
@@ -177,6 +209,14 @@ pub struct Cli {
     /// Cache directory location for disk cache (default: .cache).
     pub cache_dir: Option<String>,
 
+    #[arg(long, value_name = "PATH", help_heading = "Cache Options")]
+    /// Cache file location for file cache (default: .cache/anvil-zksync.cache).
+    pub cache_file: Option<String>,
+
+    #[arg(long, value_name = "BYTES", help_heading = "Cache Options")]
+    /// Maximum size of the file cache in bytes; least recently used entries are evicted above it (default: 1 GiB).
+    pub cache_max_size: Option<u64>,
+
     /// Number of dev accounts to generate and configure.
     #[arg(
         long,
@@ -200,7 +240,7 @@ pub struct Cli {
     #[arg(long, value_name = "NUM")]
     pub timestamp: Option<u64>,
 
-    /// Initialize the genesis block with the given `genesis.json` file.
+    /// Initialize the genesis block and the accounts of its `alloc` section with the given `genesis.json` file.
     #[arg(long, value_name = "PATH", value_parser= parse_genesis_file)]
     pub init: Option<Genesis>,
 
@@ -245,6 +285,27 @@ pub struct Cli {
     /// Disable auto and interval mining, and mine on demand instead.
     #[arg(long, visible_alias = "no-mine", conflicts_with = "block_time")]
     pub no_mining: bool,
+
+    /// Maximum number of transactions per block [default: 1000].
+    #[arg(long, value_name = "COUNT", help_heading = "Block Sealing")]
+    pub max_transactions: Option<usize>,
+
+    /// Maximum gas the transactions of a batch may use. Transactions that no longer fit are moved
+    /// to the next batch [default: 80000000].
+    #[arg(long, value_name = "GAS", help_heading = "Block Sealing")]
+    pub batch_gas_limit: Option<u64>,
+
+    /// Maximum pubdata in bytes a batch may publish.
+    #[arg(long, value_name = "BYTES", help_heading = "Block Sealing")]
+    pub batch_pubdata_limit: Option<u64>,
+
+    /// Maximum number of circuits a batch may need to be proven [default: 31100].
+    #[arg(long, value_name = "CIRCUITS", help_heading = "Block Sealing")]
+    pub batch_circuits_limit: Option<u64>,
+
+    /// Maximum space in words the transactions of a batch may take in the bootloader memory.
+    #[arg(long, value_name = "WORDS", help_heading = "Block Sealing")]
+    pub batch_encoding_size_limit: Option<u64>,
 }
 
 #[derive(Debug, Subcommand, Clone)]
@@ -258,6 +319,17 @@ pub enum Command {
     /// Starts a local network that is a fork of another network, and replays a given TX on it.
     #[command(name = "replay_tx")]
     ReplayTx(ReplayArgs),
+    /// Starts a local network forked just before a block, replays all of its transactions and
+    /// reports where the local receipts diverge from the upstream ones.
+    #[command(name = "replay_block", alias = "replay-block")]
+    ReplayBlock(ReplayBlockArgs),
+    /// Starts a local network forked just before a block range, replays all of its transactions and
+    /// reports where the local receipts diverge from the upstream ones.
+    #[command(name = "replay_range", alias = "replay-range")]
+    ReplayRange(ReplayRangeArgs),
+    /// Inspects or prunes the file cache (see --cache-file) without starting a node.
+    #[command(name = "cache")]
+    Cache(CacheArgs),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -268,12 +340,17 @@ pub struct ForkArgs {
     ///  - mainnet
     ///  - sepolia-testnet
     ///  - http://XXX:YY
+    ///
+    /// May be repeated (or comma-separated) to give endpoints of the same network that requests
+    /// fall back to when the first one keeps failing.
     #[arg(
         long,
         alias = "network",
-        help = "Network to fork from (e.g., http://XXX:YY, mainnet, sepolia-testnet)."
+        help = "Network to fork from (e.g., http://XXX:YY, mainnet, sepolia-testnet). Repeat to add fallback endpoints.",
+        value_delimiter = ',',
+        required_unless_present = "fork_bundle"
     )]
-    pub fork_url: String,
+    pub fork_url: Vec<String>,
     // Fork at a given L2 miniblock height.
     // If not set - will use the current finalized block from the network.
     #[arg(
@@ -294,6 +371,20 @@ pub struct ForkArgs {
         conflicts_with = "fork_block_number"
     )]
     pub fork_transaction_hash: Option<H256>,
+
+    /// Record every read made from the forked network into a bundle file.
+    ///
+    /// The bundle can later be served offline with --fork-bundle.
+    #[arg(long, value_name = "PATH", requires = "fork_url")]
+    pub fork_record: Option<String>,
+
+    /// Serve the fork exclusively from a bundle recorded with --fork-record, without network access.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["fork_url", "fork_block_number", "fork_transaction_hash", "fork_record"]
+    )]
+    pub fork_bundle: Option<String>,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -316,7 +407,88 @@ pub struct ReplayArgs {
     pub tx: H256,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct ReplayBlockArgs {
+    #[arg(
+        long,
+        alias = "network",
+        help = "Network to fork from (e.g., http://XXX:YY, mainnet, sepolia-testnet)."
+    )]
+    pub fork_url: String,
+    /// Block to replay.
+    #[arg(value_name = "BLOCK", value_parser = clap::value_parser!(u64).range(1..))]
+    pub block: u64,
+    /// Writes the divergence report as JSON to the given file.
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ReplayRangeArgs {
+    #[arg(
+        long,
+        alias = "network",
+        help = "Network to fork from (e.g., http://XXX:YY, mainnet, sepolia-testnet)."
+    )]
+    pub fork_url: String,
+    /// First block to replay.
+    #[arg(value_name = "FROM", value_parser = clap::value_parser!(u64).range(1..))]
+    pub from: u64,
+    /// Last block to replay (inclusive).
+    #[arg(value_name = "TO")]
+    pub to: u64,
+    /// Writes the divergence report as JSON to the given file.
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<String>,
+}
+
+impl From<ReplayBlockArgs> for ReplayRangeArgs {
+    fn from(args: ReplayBlockArgs) -> Self {
+        Self {
+            fork_url: args.fork_url,
+            from: args.block,
+            to: args.block,
+            report: args.report,
+        }
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum CacheCommand {
+    /// Prints the size of the cache file and of the entries cached for each fork URL.
+    #[command(name = "inspect")]
+    Inspect,
+    /// Drops corrupted entries and compacts the cache file.
+    #[command(name = "prune")]
+    Prune {
+        /// Remove all entries cached for this fork URL or network (mainnet, sepolia-testnet, goerli-testnet).
+        #[arg(long, value_name = "URL")]
+        fork_url: Option<String>,
+        /// Evict least recently used entries until the cache fits into this many bytes.
+        #[arg(long, value_name = "BYTES")]
+        max_size: Option<u64>,
+    },
+}
+
 impl Cli {
+    /// Returns the location of the file cache.
+    pub fn cache_file_path(&self) -> String {
+        self.cache_file
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CACHE_FILE_PATH.to_string())
+    }
+
+    /// Returns the maximum size of the file cache in bytes.
+    pub fn cache_max_size(&self) -> u64 {
+        self.cache_max_size.unwrap_or(DEFAULT_CACHE_FILE_MAX_SIZE)
+    }
+
     /// Checks for deprecated options and warns users.
     pub fn deprecated_config_option() {
         if env::args().any(|arg| arg == "--config" || arg.starts_with("--config=")) {
@@ -329,13 +501,16 @@ impl Cli {
     /// Converts the CLI arguments to a `TestNodeConfig`.
     pub fn into_test_node_config(self) -> eyre::Result<TestNodeConfig> {
         let genesis_balance = U256::from(self.balance as u128 * 10u128.pow(18));
+        let protocol_version = self.protocol_version()?;
 
         let mut config = TestNodeConfig::default()
             .with_port(self.port)
+            .with_server_threads(self.server_threads.map(|n| n as usize))
             .with_offline(if self.offline { Some(true) } else { None })
             .with_l1_gas_price(self.l1_gas_price)
             .with_l2_gas_price(self.l2_gas_price)
             .with_l1_pubdata_price(self.l1_pubdata_price)
+            .with_gasless(if self.gasless { Some(true) } else { None })
             .with_show_tx_summary(self.show_tx_summary)
             .with_show_event_logs(self.show_event_logs)
             .with_disable_console_log(self.disable_console_log)
@@ -353,8 +528,10 @@ impl Cli {
             .with_silent(self.silent)
             .with_system_contracts(self.dev_system_contracts)
             .with_override_bytecodes_dir(self.override_bytecodes_dir.clone()) // Added
+            .with_system_contracts_dir(self.system_contracts_dir.clone())
             .with_log_level(self.log)
             .with_log_file_path(self.log_file_path.clone())
+            .with_json_log(self.json_log.clone())
             .with_account_generator(self.account_generator())
             .with_auto_impersonate(self.auto_impersonate)
             .with_genesis_balance(genesis_balance)
@@ -369,6 +546,11 @@ impl Cli {
                             .unwrap_or_else(|| DEFAULT_DISK_CACHE_DIR.to_string()),
                         reset: self.reset_cache.unwrap_or(false),
                     },
+                    CacheType::File => CacheConfig::File {
+                        path: self.cache_file_path(),
+                        max_size: self.cache_max_size(),
+                        reset: self.reset_cache.unwrap_or(false),
+                    },
                 }
             }))
             .with_genesis_timestamp(self.timestamp)
@@ -377,13 +559,24 @@ impl Cli {
             .set_config_out(self.config_out)
             .with_host(self.host)
             .with_evm_emulator(if self.emulate_evm { Some(true) } else { None })
+            .with_protocol_version(protocol_version)
             .with_health_check_endpoint(if self.health_check_endpoint {
                 Some(true)
             } else {
                 None
             })
+            .with_metrics_endpoint(if self.metrics_endpoint {
+                Some(true)
+            } else {
+                None
+            })
             .with_block_time(self.block_time)
-            .with_no_mining(self.no_mining);
+            .with_no_mining(self.no_mining)
+            .with_max_transactions(self.max_transactions)
+            .with_batch_gas_limit(self.batch_gas_limit)
+            .with_batch_pubdata_limit(self.batch_pubdata_limit)
+            .with_batch_circuits_limit(self.batch_circuits_limit)
+            .with_batch_encoding_size_limit(self.batch_encoding_size_limit);
 
         if self.emulate_evm && self.dev_system_contracts != Some(SystemContractsOptions::Local) {
             return Err(eyre::eyre!(
@@ -398,6 +591,40 @@ impl Cli {
         Ok(config)
     }
 
+    /// Parses and validates `--protocol-version` against the system contracts option.
+    fn protocol_version(&self) -> eyre::Result<Option<ProtocolVersionId>> {
+        let version = match self.protocol_version {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        if !matches!(self.command, None | Some(Command::Run)) {
+            return Err(eyre::eyre!(
+                "--protocol-version can only be used when starting a new chain."
+            ));
+        }
+        let protocol_version = ProtocolVersionId::try_from(version)
+            .ok()
+            .filter(|version| system_contracts::supported_new_chain_protocol_versions(*version))
+            .ok_or_else(|| {
+                eyre::eyre!(
+                    "Unsupported protocol version {}, supported versions are: {}.",
+                    version,
+                    system_contracts::new_chain_protocol_versions_to_string()
+                )
+            })?;
+        if protocol_version != built_in_protocol_version()
+            && self.dev_system_contracts != Some(SystemContractsOptions::Local)
+            && self.system_contracts_dir.is_none()
+        {
+            return Err(eyre::eyre!(
+                "The built-in system contracts are compiled for protocol version {}, protocol version {} requires the 'local' system contracts option or a --system-contracts-dir.",
+                built_in_protocol_version() as u16,
+                version
+            ));
+        }
+        Ok(Some(protocol_version))
+    }
+
     fn account_generator(&self) -> AccountGenerator {
         let mut gen = AccountGenerator::new(self.accounts as usize)
             .phrase(DEFAULT_MNEMONIC)
@@ -439,6 +666,7 @@ mod tests {
         env,
         net::{IpAddr, Ipv4Addr},
     };
+    use zksync_types::ProtocolVersionId;
 
     #[test]
     fn can_parse_host() {
@@ -483,4 +711,55 @@ mod tests {
                 .to_vec()
         );
     }
+
+    #[test]
+    fn can_parse_protocol_version() {
+        let latest = ProtocolVersionId::latest() as u16;
+        let config = Cli::parse_from(["anvil-zksync", "--protocol-version", &latest.to_string()])
+            .into_test_node_config()
+            .unwrap();
+        assert_eq!(config.protocol_version, ProtocolVersionId::latest());
+
+        let args = Cli::parse_from(["anvil-zksync", "--protocol-version", "24"]);
+        assert!(args.into_test_node_config().is_err());
+
+        let config = Cli::parse_from([
+            "anvil-zksync",
+            "--protocol-version",
+            "24",
+            "--dev-system-contracts",
+            "local",
+        ])
+        .into_test_node_config()
+        .unwrap();
+        assert_eq!(config.protocol_version, ProtocolVersionId::Version24);
+
+        let config = Cli::parse_from([
+            "anvil-zksync",
+            "--protocol-version",
+            "24",
+            "--system-contracts-dir",
+            "contracts",
+        ])
+        .into_test_node_config()
+        .unwrap();
+        assert_eq!(config.protocol_version, ProtocolVersionId::Version24);
+
+        // Executed by an older VM than `vm_latest`
+        let args = Cli::parse_from(["anvil-zksync", "--protocol-version", "22"]);
+        assert!(args.into_test_node_config().is_err());
+
+        let args = Cli::parse_from(["anvil-zksync", "--protocol-version", "9"]);
+        assert!(args.into_test_node_config().is_err());
+
+        let args = Cli::parse_from([
+            "anvil-zksync",
+            "--protocol-version",
+            &latest.to_string(),
+            "fork",
+            "--fork-url",
+            "mainnet",
+        ]);
+        assert!(args.into_test_node_config().is_err());
+    }
 }
@@ -1,5 +1,9 @@
 /// Default directory for disk cache
 pub const DEFAULT_DISK_CACHE_DIR: &str = ".cache";
+/// Default path of the single-file cache
+pub const DEFAULT_CACHE_FILE_PATH: &str = ".cache/anvil-zksync.cache";
+/// Default maximum size of the single-file cache in bytes (1 GiB)
+pub const DEFAULT_CACHE_FILE_MAX_SIZE: u64 = 1024 * 1024 * 1024;
 /// Default L1 gas price for transactions
 pub const DEFAULT_L1_GAS_PRICE: u64 = 35_932_364_075;
 /// Default L2 gas price for transactions if not provided via CLI
@@ -10,6 +14,8 @@ pub const DEFAULT_FAIR_PUBDATA_PRICE: u64 = 13_607_659_111;
 pub const DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR: f64 = 2.0;
 /// Scale factor for estimating gas limits
 pub const DEFAULT_ESTIMATE_GAS_SCALE_FACTOR: f32 = 1.3;
+/// Default maximum number of circuits a batch may need to be proven, matches the ZKsync Era state keeper
+pub const DEFAULT_MAX_CIRCUITS_PER_BATCH: u64 = 31_100;
 /// Default port for the test node server
 pub const NODE_PORT: u16 = 8011;
 /// Network ID for the test node
@@ -1,8 +1,12 @@
 use crate::fork::ForkDetails;
+use crate::json_log::JSON_LOG_STDOUT;
 use crate::{observability, system_contracts};
 use anyhow::anyhow;
 use std::net::{IpAddr, Ipv4Addr};
 use zksync_multivm::interface::L1BatchEnv;
+use zksync_multivm::utils::{get_bootloader_encoding_space, get_max_batch_gas_limit};
+use zksync_multivm::vm_latest::constants::MAX_VM_PUBDATA_PER_BATCH;
+use zksync_multivm::VmVersion;
 use zksync_types::api::TransactionVariant;
 
 use crate::config::{
@@ -19,13 +23,14 @@ use alloy_signer_local::{
 use colored::{Colorize, CustomColor};
 use observability::LogLevel;
 use rand::thread_rng;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, to_writer, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::time::Duration;
 use zksync_types::fee_model::FeeModelConfigV2;
-use zksync_types::{Bloom, H256, U256};
+use zksync_types::web3::Bytes;
+use zksync_types::{Address, Bloom, ProtocolVersionId, H256, U256};
 
 pub mod cache;
 pub mod cli;
@@ -59,6 +64,8 @@ pub struct TestNodeConfig {
     pub config_out: Option<String>,
     /// Port the node will listen on
     pub port: u16,
+    /// Number of worker threads serving JSON-RPC requests
+    pub server_threads: usize,
     /// Print node config on startup if true
     pub show_node_config: bool,
     /// Print transactions and calls summary if true
@@ -85,8 +92,12 @@ pub struct TestNodeConfig {
     pub system_contracts_options: system_contracts::Options,
     /// Directory to override bytecodes
     pub override_bytecodes_dir: Option<String>,
+    /// Directory with compiled bootloader and system contracts replacing the configured ones
+    pub system_contracts_dir: Option<String>,
     /// Enables EVM emulation mode
     pub use_evm_emulator: bool,
+    /// Protocol version of a new chain, selects the VM version and the system contracts
+    pub protocol_version: ProtocolVersionId,
     /// Optional chain ID for the node
     pub chain_id: Option<u32>,
     /// L1 gas price (optional override)
@@ -95,6 +106,8 @@ pub struct TestNodeConfig {
     pub l2_gas_price: Option<u64>,
     /// Price for pubdata on L1
     pub l1_pubdata_price: Option<u64>,
+    /// Whether all fees are zero and transactions of unfunded accounts are accepted
+    pub gasless: bool,
     /// L1 gas price scale factor for gas estimation
     pub price_scale_factor: Option<f64>,
     /// The factor by which to scale the gasLimit
@@ -103,6 +116,8 @@ pub struct TestNodeConfig {
     pub log_level: LogLevel,
     /// Path to the log file
     pub log_file_path: String,
+    /// Path of the NDJSON event stream (`-` for stdout), if enabled
+    pub json_log: Option<String>,
     /// Cache configuration for the test node
     pub cache_config: CacheConfig,
     /// Signer accounts that will be initialized with `genesis_balance` in the genesis block.
@@ -125,11 +140,21 @@ pub struct TestNodeConfig {
     pub host: Vec<IpAddr>,
     /// Whether we need to enable the health check endpoint.
     pub health_check_endpoint: bool,
+    /// Whether we need to enable the Prometheus metrics endpoint.
+    pub metrics_endpoint: bool,
     /// Block time in seconds for interval sealing.
     /// If unset, node seals a new block as soon as there is at least one transaction.
     pub block_time: Option<Duration>,
     /// Maximum number of transactions per block
     pub max_transactions: usize,
+    /// Maximum gas the transactions of a batch may use
+    pub batch_gas_limit: Option<u64>,
+    /// Maximum pubdata a batch may publish
+    pub batch_pubdata_limit: Option<u64>,
+    /// Maximum number of circuits a batch may need to be proven
+    pub batch_circuits_limit: Option<u64>,
+    /// Maximum space the transactions of a batch may take in the bootloader memory, in words
+    pub batch_encoding_size_limit: Option<u64>,
     /// Disable automatic sealing mode and use `BlockSealer::Noop` instead
     pub no_mining: bool,
 }
@@ -142,6 +167,7 @@ impl Default for TestNodeConfig {
             // Node configuration defaults
             config_out: None,
             port: NODE_PORT,
+            server_threads: 1,
             show_node_config: true,
             show_tx_summary: true,
             show_event_logs: false,
@@ -155,19 +181,23 @@ impl Default for TestNodeConfig {
             silent: false,
             system_contracts_options: Default::default(),
             override_bytecodes_dir: None,
+            system_contracts_dir: None,
             use_evm_emulator: false,
+            protocol_version: ProtocolVersionId::latest(),
             chain_id: None,
 
             // Gas configuration defaults
             l1_gas_price: None,
             l2_gas_price: None,
             l1_pubdata_price: None,
+            gasless: false,
             price_scale_factor: None,
             limit_scale_factor: None,
 
             // Log configuration defaults
             log_level: Default::default(),
             log_file_path: String::from(DEFAULT_LOG_FILE_PATH),
+            json_log: None,
 
             // Cache configuration default
             cache_config: Default::default(),
@@ -186,12 +216,17 @@ impl Default for TestNodeConfig {
             offline: false,
             host: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
             health_check_endpoint: false,
+            metrics_endpoint: false,
 
             // Block sealing configuration default
             block_time: None,
             no_mining: false,
 
             max_transactions: 1000,
+            batch_gas_limit: None,
+            batch_pubdata_limit: None,
+            batch_circuits_limit: None,
+            batch_encoding_size_limit: None,
         }
     }
 }
@@ -214,14 +249,14 @@ impl TestNodeConfig {
 
         let color = CustomColor::new(13, 71, 198);
 
-        println!("{}", BANNER.custom_color(color));
+        self.print_raw(BANNER.custom_color(color));
 
         tracing::info!("Version:        {}", VERSION_MESSAGE.green());
         tracing::info!(
             "Repository:     {}",
             "https://github.com/matter-labs/anvil-zksync".green()
         );
-        println!("\n");
+        self.print_raw("\n");
 
         tracing::info!("Rich Accounts");
         tracing::info!("========================");
@@ -229,7 +264,7 @@ impl TestNodeConfig {
         for (idx, account) in self.genesis_accounts.iter().enumerate() {
             tracing::info!("({}) {} ({balance})", idx, account.address());
         }
-        println!("\n");
+        self.print_raw("\n");
 
         tracing::info!("Private Keys");
         tracing::info!("========================");
@@ -237,7 +272,7 @@ impl TestNodeConfig {
             let private_key = hex::encode(account.credential().to_bytes());
             tracing::info!("({}) 0x{}", idx, private_key);
         }
-        println!("\n");
+        self.print_raw("\n");
 
         if let Some(ref generator) = self.account_generator {
             tracing::info!("Wallet");
@@ -252,7 +287,7 @@ impl TestNodeConfig {
                     .green()
             );
         }
-        println!("\n");
+        self.print_raw("\n");
 
         if let Some(fd) = fork_details {
             tracing::info!("Fork Details");
@@ -294,7 +329,7 @@ impl TestNodeConfig {
                     fee_config.max_pubdata_per_batch.to_string().green()
                 );
             }
-            println!("\n");
+            self.print_raw("\n");
         } else {
             tracing::info!("Network Configuration");
             tracing::info!("========================");
@@ -305,7 +340,7 @@ impl TestNodeConfig {
                     .to_string()
                     .green()
             );
-            println!("\n");
+            self.print_raw("\n");
         }
         tracing::info!("Gas Configuration");
         tracing::info!("========================");
@@ -321,6 +356,12 @@ impl TestNodeConfig {
             "L1 Pubdata Price (gwei):           {}",
             format_gwei(self.get_l1_pubdata_price().into()).green()
         );
+        if self.gasless {
+            tracing::info!(
+                "Gasless Mode:                      {}",
+                "all fees are zero".green()
+            );
+        }
         tracing::info!(
             "Estimated Gas Price Scale Factor:  {}",
             self.get_price_scale().to_string().green()
@@ -329,16 +370,21 @@ impl TestNodeConfig {
             "Estimated Gas Limit Scale Factor:  {}",
             self.get_gas_limit_scale().to_string().green()
         );
-        println!("\n");
+        self.print_raw("\n");
 
         tracing::info!("Genesis Timestamp");
         tracing::info!("========================");
         tracing::info!("{}", self.get_genesis_timestamp().to_string().green());
-        println!("\n");
+        self.print_raw("\n");
 
         tracing::info!("Node Configuration");
         tracing::info!("========================");
         tracing::info!("Port:               {}", self.port);
+        tracing::info!("Server Threads:     {}", self.server_threads);
+        tracing::info!(
+            "Protocol Version:   {}",
+            (self.protocol_version as u16).to_string().green()
+        );
         tracing::info!(
             "EVM Emulator:       {}",
             if self.use_evm_emulator {
@@ -355,7 +401,15 @@ impl TestNodeConfig {
                 "Disabled".red()
             }
         );
-        println!("\n");
+        tracing::info!(
+            "Metrics Endpoint: {}",
+            if self.metrics_endpoint {
+                "Enabled".green()
+            } else {
+                "Disabled".red()
+            }
+        );
+        self.print_raw("\n");
         tracing::info!("========================================");
         for host in &self.host {
             tracing::info!(
@@ -365,7 +419,16 @@ impl TestNodeConfig {
             );
         }
         tracing::info!("========================================");
-        println!("\n");
+        self.print_raw("\n");
+    }
+
+    /// Prints output that isn't going through the logger, keeping stdout free for the JSON log if it's written there.
+    fn print_raw(&self, output: impl std::fmt::Display) {
+        if self.is_json_log_on_stdout() {
+            eprintln!("{}", output);
+        } else {
+            println!("{}", output);
+        }
     }
 
     fn as_json(&self, fork: Option<&ForkPrintInfo>) -> Value {
@@ -439,6 +502,20 @@ impl TestNodeConfig {
         self.port
     }
 
+    /// Set the number of worker threads serving JSON-RPC requests
+    #[must_use]
+    pub fn with_server_threads(mut self, server_threads: Option<usize>) -> Self {
+        if let Some(server_threads) = server_threads {
+            self.server_threads = server_threads.max(1);
+        }
+        self
+    }
+
+    /// Get the number of worker threads serving JSON-RPC requests
+    pub fn get_server_threads(&self) -> usize {
+        self.server_threads
+    }
+
     /// Set the chain ID for the test node
     #[must_use]
     pub fn with_chain_id(mut self, chain_id: Option<u32>) -> Self {
@@ -487,6 +564,20 @@ impl TestNodeConfig {
         self.override_bytecodes_dir.as_ref()
     }
 
+    /// Set the directory to load the bootloader and system contracts from
+    #[must_use]
+    pub fn with_system_contracts_dir(mut self, dir: Option<String>) -> Self {
+        if let Some(dir) = dir {
+            self.system_contracts_dir = Some(dir);
+        }
+        self
+    }
+
+    /// Get the directory to load the bootloader and system contracts from
+    pub fn get_system_contracts_dir(&self) -> Option<&String> {
+        self.system_contracts_dir.as_ref()
+    }
+
     /// Enable or disable EVM emulation
     #[must_use]
     pub fn with_evm_emulator(mut self, enable: Option<bool>) -> Self {
@@ -501,6 +592,20 @@ impl TestNodeConfig {
         self.use_evm_emulator
     }
 
+    /// Set the protocol version of a new chain
+    #[must_use]
+    pub fn with_protocol_version(mut self, version: Option<ProtocolVersionId>) -> Self {
+        if let Some(version) = version {
+            self.protocol_version = version;
+        }
+        self
+    }
+
+    /// Get the protocol version
+    pub fn get_protocol_version(&self) -> ProtocolVersionId {
+        self.protocol_version
+    }
+
     /// Set the L1 gas price
     #[must_use]
     pub fn with_l1_gas_price(mut self, price: Option<u64>) -> Self {
@@ -559,6 +664,20 @@ impl TestNodeConfig {
         self
     }
 
+    /// Enable or disable the gasless mode
+    #[must_use]
+    pub fn with_gasless(mut self, enable: Option<bool>) -> Self {
+        if let Some(enable) = enable {
+            self.gasless = enable;
+        }
+        self
+    }
+
+    /// Get the gasless mode status
+    pub fn is_gasless(&self) -> bool {
+        self.gasless
+    }
+
     /// Set the log level
     #[must_use]
     pub fn with_log_level(mut self, level: Option<LogLevel>) -> Self {
@@ -601,6 +720,25 @@ impl TestNodeConfig {
         &self.log_file_path
     }
 
+    /// Set the path of the NDJSON event stream
+    #[must_use]
+    pub fn with_json_log(mut self, path: Option<String>) -> Self {
+        if path.is_some() {
+            self.json_log = path;
+        }
+        self
+    }
+
+    /// Get the path of the NDJSON event stream
+    pub fn get_json_log(&self) -> Option<&str> {
+        self.json_log.as_deref()
+    }
+
+    /// Whether the NDJSON event stream is written to stdout, in which case console output goes to stderr
+    pub fn is_json_log_on_stdout(&self) -> bool {
+        self.json_log.as_deref() == Some(JSON_LOG_STDOUT)
+    }
+
     /// Applies the defaults for debug mode.
     #[must_use]
     pub fn with_debug_mode(mut self) -> Self {
@@ -881,6 +1019,20 @@ impl TestNodeConfig {
         self.health_check_endpoint
     }
 
+    /// Set the metrics endpoint mode
+    #[must_use]
+    pub fn with_metrics_endpoint(mut self, metrics_endpoint: Option<bool>) -> Self {
+        if let Some(metrics_endpoint) = metrics_endpoint {
+            self.metrics_endpoint = metrics_endpoint;
+        }
+        self
+    }
+
+    /// Get the metrics endpoint mode status
+    pub fn is_metrics_endpoint_enabled(&self) -> bool {
+        self.metrics_endpoint
+    }
+
     /// Updates the configuration from fork details.
     pub async fn update_with_fork_details(
         &mut self,
@@ -928,6 +1080,68 @@ impl TestNodeConfig {
         self.no_mining = no_mining;
         self
     }
+
+    /// Set the maximum number of transactions per block
+    #[must_use]
+    pub fn with_max_transactions(mut self, max_transactions: Option<usize>) -> Self {
+        if let Some(max_transactions) = max_transactions {
+            self.max_transactions = max_transactions;
+        }
+        self
+    }
+
+    /// Set the maximum gas the transactions of a batch may use
+    #[must_use]
+    pub fn with_batch_gas_limit(mut self, limit: Option<u64>) -> Self {
+        self.batch_gas_limit = limit;
+        self
+    }
+
+    /// Get the maximum gas the transactions of a batch may use
+    pub fn get_batch_gas_limit(&self) -> u64 {
+        self.batch_gas_limit
+            .unwrap_or_else(|| get_max_batch_gas_limit(VmVersion::from(self.protocol_version)))
+    }
+
+    /// Set the maximum pubdata a batch may publish
+    #[must_use]
+    pub fn with_batch_pubdata_limit(mut self, limit: Option<u64>) -> Self {
+        self.batch_pubdata_limit = limit;
+        self
+    }
+
+    /// Get the maximum pubdata a batch may publish
+    pub fn get_batch_pubdata_limit(&self) -> u64 {
+        self.batch_pubdata_limit
+            .unwrap_or(MAX_VM_PUBDATA_PER_BATCH as u64)
+    }
+
+    /// Set the maximum number of circuits a batch may need to be proven
+    #[must_use]
+    pub fn with_batch_circuits_limit(mut self, limit: Option<u64>) -> Self {
+        self.batch_circuits_limit = limit;
+        self
+    }
+
+    /// Get the maximum number of circuits a batch may need to be proven
+    pub fn get_batch_circuits_limit(&self) -> u64 {
+        self.batch_circuits_limit
+            .unwrap_or(DEFAULT_MAX_CIRCUITS_PER_BATCH)
+    }
+
+    /// Set the maximum space the transactions of a batch may take in the bootloader memory
+    #[must_use]
+    pub fn with_batch_encoding_size_limit(mut self, limit: Option<u64>) -> Self {
+        self.batch_encoding_size_limit = limit;
+        self
+    }
+
+    /// Get the maximum space the transactions of a batch may take in the bootloader memory, in words
+    pub fn get_batch_encoding_size_limit(&self) -> u64 {
+        self.batch_encoding_size_limit.unwrap_or_else(|| {
+            get_bootloader_encoding_space(VmVersion::from(self.protocol_version)) as u64
+        })
+    }
 }
 
 /// Account Generator
@@ -1001,7 +1215,7 @@ impl AccountGenerator {
 }
 
 /// Genesis
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct Genesis {
     /// The hash of the genesis block. If not provided, it can be computed.
     pub hash: Option<H256>,
@@ -1019,4 +1233,30 @@ pub struct Genesis {
     pub gas_used: Option<U256>,
     /// The logs bloom filter.
     pub logs_bloom: Option<Bloom>,
+    /// Accounts to pre-populate the genesis state with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alloc: Option<BTreeMap<Address, GenesisAccount>>,
+}
+
+/// State of an account in the `alloc` section of a [Genesis].
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct GenesisAccount {
+    /// Native token balance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    /// Transaction nonce.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<U256>,
+    /// Deployment nonce, used to derive the addresses of contracts created by the account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment_nonce: Option<U256>,
+    /// EraVM bytecode, or EVM runtime bytecode if `evm` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    /// Whether `code` is EVM runtime bytecode, which requires the EVM emulator.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub evm: bool,
+    /// Storage slots of the account.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, H256>,
 }
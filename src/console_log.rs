@@ -50,11 +50,31 @@ impl ConsoleLogHandler {
         }
     }
     pub fn handle_call(&self, current_call: &Call) {
+        if let Some(message) = self.message_for_call(current_call) {
+            tracing::info!("{}", message.cyan());
+        }
+    }
+    /// Returns all `console.log` messages from the given calls (and their subcalls) in execution order.
+    pub fn collect_messages_recursive(&self, calls: &[Call]) -> Vec<String> {
+        let mut messages = Vec::new();
+        for call in calls {
+            self.collect_call_messages_recursive(call, &mut messages);
+        }
+        messages
+    }
+    fn collect_call_messages_recursive(&self, current_call: &Call, messages: &mut Vec<String>) {
+        messages.extend(self.message_for_call(current_call));
+        for call in &current_call.calls {
+            self.collect_call_messages_recursive(call, messages);
+        }
+    }
+    /// Decodes the `console.log` message for the call, if it targets the console contract.
+    fn message_for_call(&self, current_call: &Call) -> Option<String> {
         if current_call.to != self.target_contract {
-            return;
+            return None;
         }
         if current_call.input.len() < 4 {
-            return;
+            return None;
         }
         let signature = &current_call.input[..4];
         let message =
@@ -67,7 +87,7 @@ impl ConsoleLogHandler {
                         tokens.iter().map(|t| format!("{}", t)).join(" ")
                     })
                 });
-        tracing::info!("{}", message.cyan());
+        Some(message)
     }
 }
 
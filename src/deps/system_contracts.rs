@@ -1,6 +1,9 @@
 use crate::system_contracts::Options;
+use anyhow::{anyhow, Context};
 use once_cell::sync::Lazy;
 use serde_json::Value;
+use std::fs;
+use std::path::Path;
 use zksync_types::system_contracts::get_system_smart_contracts;
 use zksync_types::{
     block::DeployedContract, ACCOUNT_CODE_STORAGE_ADDRESS, BOOTLOADER_ADDRESS,
@@ -32,150 +35,204 @@ pub const TIMESTAMP_ASSERTER_ADDRESS: Address = H160([
 ]);
 
 pub fn bytecode_from_slice(artifact_name: &str, contents: &[u8]) -> Vec<u8> {
-    let artifact: Value = serde_json::from_slice(contents).expect(artifact_name);
+    try_bytecode_from_slice(artifact_name, contents).unwrap_or_else(|err| panic!("{:#}", err))
+}
+
+/// Fallible version of [bytecode_from_slice], for artifacts that are not compiled in.
+pub fn try_bytecode_from_slice(artifact_name: &str, contents: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let artifact: Value = serde_json::from_slice(contents)
+        .with_context(|| format!("Can't parse artifact {:?}", artifact_name))?;
     let bytecode = artifact["bytecode"]
         .as_str()
-        .unwrap_or_else(|| panic!("Bytecode not found in {:?}", artifact_name))
+        .ok_or_else(|| anyhow!("Bytecode not found in {:?}", artifact_name))?
         .strip_prefix("0x")
-        .unwrap_or_else(|| panic!("Bytecode in {:?} is not hex", artifact_name));
+        .ok_or_else(|| anyhow!("Bytecode in {:?} is not hex", artifact_name))?;
+
+    hex::decode(bytecode).with_context(|| format!("Can't decode bytecode in {:?}", artifact_name))
+}
+
+/// Reads the bytecode of the artifact `name` from `dir`.
+///
+/// Both flat directories (`<name>.json`) and compiler output directories (`<name>.sol/<name>.json`) are
+/// supported, as well as raw Yul bytecode (`<name>.yul.zbin`). Returns `None` if the directory has no
+/// artifact for `name`.
+pub fn read_artifact_from_dir(dir: &Path, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let json_paths = [
+        dir.join(format!("{}.json", name)),
+        dir.join(format!("{}.sol", name))
+            .join(format!("{}.json", name)),
+    ];
+    if let Some(path) = json_paths.iter().find(|path| path.is_file()) {
+        let contents =
+            fs::read(path).with_context(|| format!("Can't read artifact {}", path.display()))?;
+        return try_bytecode_from_slice(&path.display().to_string(), &contents).map(Some);
+    }
+
+    let zbin_path = dir.join(format!("{}.yul.zbin", name));
+    if zbin_path.is_file() {
+        let bytecode = fs::read(&zbin_path)
+            .with_context(|| format!("Can't read artifact {}", zbin_path.display()))?;
+        return Ok(Some(bytecode));
+    }
+
+    Ok(None)
+}
 
-    hex::decode(bytecode)
-        .unwrap_or_else(|err| panic!("Can't decode bytecode in {:?}: {}", artifact_name, err))
+/// Returns the system contracts that have an artifact in `dir`, skipping the ones that don't.
+pub fn system_contracts_from_dir(dir: &Path) -> anyhow::Result<Vec<DeployedContract>> {
+    let mut contracts = Vec::new();
+    for (name, address, _) in SOLIDITY_SYSTEM_CONTRACTS
+        .iter()
+        .chain(YUL_SYSTEM_CONTRACTS.iter())
+    {
+        if let Some(bytecode) = read_artifact_from_dir(dir, name)? {
+            contracts.push(DeployedContract {
+                account_id: AccountTreeId::new(*address),
+                bytecode,
+            });
+        }
+    }
+    Ok(contracts)
 }
 
+/// Solidity system contracts as `(name, address, artifact)`, the artifact being `contracts/<name>.json`.
+const SOLIDITY_SYSTEM_CONTRACTS: [(&str, Address, &[u8]); 14] = [
+    (
+        "AccountCodeStorage",
+        ACCOUNT_CODE_STORAGE_ADDRESS,
+        include_bytes!("contracts/AccountCodeStorage.json"),
+    ),
+    (
+        "NonceHolder",
+        NONCE_HOLDER_ADDRESS,
+        include_bytes!("contracts/NonceHolder.json"),
+    ),
+    (
+        "KnownCodesStorage",
+        KNOWN_CODES_STORAGE_ADDRESS,
+        include_bytes!("contracts/KnownCodesStorage.json"),
+    ),
+    (
+        "ImmutableSimulator",
+        IMMUTABLE_SIMULATOR_STORAGE_ADDRESS,
+        include_bytes!("contracts/ImmutableSimulator.json"),
+    ),
+    (
+        "ContractDeployer",
+        CONTRACT_DEPLOYER_ADDRESS,
+        include_bytes!("contracts/ContractDeployer.json"),
+    ),
+    (
+        "L1Messenger",
+        L1_MESSENGER_ADDRESS,
+        include_bytes!("contracts/L1Messenger.json"),
+    ),
+    (
+        "MsgValueSimulator",
+        MSG_VALUE_SIMULATOR_ADDRESS,
+        include_bytes!("contracts/MsgValueSimulator.json"),
+    ),
+    (
+        "L2BaseToken",
+        L2_BASE_TOKEN_ADDRESS,
+        include_bytes!("contracts/L2BaseToken.json"),
+    ),
+    (
+        "SystemContext",
+        SYSTEM_CONTEXT_ADDRESS,
+        include_bytes!("contracts/SystemContext.json"),
+    ),
+    (
+        "BootloaderUtilities",
+        BOOTLOADER_UTILITIES_ADDRESS,
+        include_bytes!("contracts/BootloaderUtilities.json"),
+    ),
+    (
+        "Compressor",
+        COMPRESSOR_ADDRESS,
+        include_bytes!("contracts/Compressor.json"),
+    ),
+    (
+        "PubdataChunkPublisher",
+        PUBDATA_CHUNK_PUBLISHER_ADDRESS,
+        include_bytes!("contracts/PubdataChunkPublisher.json"),
+    ),
+    (
+        "Create2Factory",
+        CREATE2_FACTORY_ADDRESS,
+        include_bytes!("contracts/Create2Factory.json"),
+    ),
+    (
+        "TimestampAsserter",
+        TIMESTAMP_ASSERTER_ADDRESS,
+        include_bytes!("contracts/TimestampAsserter.json"),
+    ),
+];
+
+/// Yul system contracts as `(name, address, bytecode)`, the bytecode being `contracts/<name>.yul.zbin`.
+const YUL_SYSTEM_CONTRACTS: [(&str, Address, &[u8]); 9] = [
+    (
+        "Keccak256",
+        KECCAK256_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/Keccak256.yul.zbin"),
+    ),
+    (
+        "SHA256",
+        SHA256_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/SHA256.yul.zbin"),
+    ),
+    (
+        "Ecrecover",
+        ECRECOVER_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/Ecrecover.yul.zbin"),
+    ),
+    (
+        "EventWriter",
+        EVENT_WRITER_ADDRESS,
+        include_bytes!("contracts/EventWriter.yul.zbin"),
+    ),
+    (
+        "EcAdd",
+        ECADD_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/EcAdd.yul.zbin"),
+    ),
+    (
+        "EcMul",
+        ECMUL_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/EcMul.yul.zbin"),
+    ),
+    (
+        "EcPairing",
+        EC_PAIRING_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/EcPairing.yul.zbin"),
+    ),
+    (
+        "CodeOracle",
+        CODE_ORACLE_ADDRESS,
+        include_bytes!("contracts/CodeOracle.yul.zbin"),
+    ),
+    (
+        "P256Verify",
+        P256VERIFY_PRECOMPILE_ADDRESS,
+        include_bytes!("contracts/P256Verify.yul.zbin"),
+    ),
+];
+
 pub static COMPILED_IN_SYSTEM_CONTRACTS: Lazy<Vec<DeployedContract>> = Lazy::new(|| {
-    let mut deployed_system_contracts = [
-        (
-            "AccountCodeStorage",
-            ACCOUNT_CODE_STORAGE_ADDRESS,
-            include_bytes!("contracts/AccountCodeStorage.json").to_vec(),
-        ),
-        (
-            "NonceHolder",
-            NONCE_HOLDER_ADDRESS,
-            include_bytes!("contracts/NonceHolder.json").to_vec(),
-        ),
-        (
-            "KnownCodesStorage",
-            KNOWN_CODES_STORAGE_ADDRESS,
-            include_bytes!("contracts/KnownCodesStorage.json").to_vec(),
-        ),
-        (
-            "ImmutableSimulator",
-            IMMUTABLE_SIMULATOR_STORAGE_ADDRESS,
-            include_bytes!("contracts/ImmutableSimulator.json").to_vec(),
-        ),
-        (
-            "ContractDeployer",
-            CONTRACT_DEPLOYER_ADDRESS,
-            include_bytes!("contracts/ContractDeployer.json").to_vec(),
-        ),
-        (
-            "L1Messenger",
-            L1_MESSENGER_ADDRESS,
-            include_bytes!("contracts/L1Messenger.json").to_vec(),
-        ),
-        (
-            "MsgValueSimulator",
-            MSG_VALUE_SIMULATOR_ADDRESS,
-            include_bytes!("contracts/MsgValueSimulator.json").to_vec(),
-        ),
-        (
-            "L2BaseToken",
-            L2_BASE_TOKEN_ADDRESS,
-            include_bytes!("contracts/L2BaseToken.json").to_vec(),
-        ),
-        (
-            "SystemContext",
-            SYSTEM_CONTEXT_ADDRESS,
-            include_bytes!("contracts/SystemContext.json").to_vec(),
-        ),
-        (
-            "BootloaderUtilities",
-            BOOTLOADER_UTILITIES_ADDRESS,
-            include_bytes!("contracts/BootloaderUtilities.json").to_vec(),
-        ),
-        (
-            "Compressor",
-            COMPRESSOR_ADDRESS,
-            include_bytes!("contracts/Compressor.json").to_vec(),
-        ),
-        (
-            "PubdataChunkPublisher",
-            PUBDATA_CHUNK_PUBLISHER_ADDRESS,
-            include_bytes!("contracts/PubdataChunkPublisher.json").to_vec(),
-        ),
-        (
-            "Create2Factory",
-            CREATE2_FACTORY_ADDRESS,
-            include_bytes!("contracts/Create2Factory.json").to_vec(),
-        ),
-        (
-            "TimestampAsserter",
-            TIMESTAMP_ASSERTER_ADDRESS,
-            include_bytes!("contracts/TimestampAsserter.json").to_vec(),
-        ),
-    ]
-    .map(|(pname, address, contents)| DeployedContract {
-        account_id: AccountTreeId::new(address),
-
-        bytecode: bytecode_from_slice(pname, &contents),
-    })
-    .to_vec();
-
-    let yul_contracts = [
-        (
-            "Keccak256",
-            KECCAK256_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/Keccak256.yul.zbin").to_vec(),
-        ),
-        (
-            "SHA256",
-            SHA256_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/SHA256.yul.zbin").to_vec(),
-        ),
-        (
-            "Ecrecover",
-            ECRECOVER_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/Ecrecover.yul.zbin").to_vec(),
-        ),
-        (
-            "EventWriter",
-            EVENT_WRITER_ADDRESS,
-            include_bytes!("contracts/EventWriter.yul.zbin").to_vec(),
-        ),
-        (
-            "EcAdd",
-            ECADD_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/EcAdd.yul.zbin").to_vec(),
-        ),
-        (
-            "EcMul",
-            ECMUL_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/EcMul.yul.zbin").to_vec(),
-        ),
-        (
-            "EcPairing",
-            EC_PAIRING_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/EcPairing.yul.zbin").to_vec(),
-        ),
-        (
-            "CodeOracle",
-            CODE_ORACLE_ADDRESS,
-            include_bytes!("contracts/CodeOracle.yul.zbin").to_vec(),
-        ),
-        (
-            "P256Verify",
-            P256VERIFY_PRECOMPILE_ADDRESS,
-            include_bytes!("contracts/P256Verify.yul.zbin").to_vec(),
-        ),
-    ]
-    .map(|(_pname, address, contents)| DeployedContract {
-        account_id: AccountTreeId::new(address),
-        bytecode: contents,
-    });
-
-    deployed_system_contracts.extend(yul_contracts);
+    let mut deployed_system_contracts: Vec<_> = SOLIDITY_SYSTEM_CONTRACTS
+        .iter()
+        .map(|(name, address, contents)| DeployedContract {
+            account_id: AccountTreeId::new(*address),
+            bytecode: bytecode_from_slice(name, contents),
+        })
+        .collect();
+
+    deployed_system_contracts.extend(YUL_SYSTEM_CONTRACTS.iter().map(|(_, address, contents)| {
+        DeployedContract {
+            account_id: AccountTreeId::new(*address),
+            bytecode: contents.to_vec(),
+        }
+    }));
 
     let empty_bytecode = bytecode_from_slice(
         "EmptyContract",
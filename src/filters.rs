@@ -232,6 +232,14 @@ impl EthFilters {
             }
         })
     }
+
+    /// Notify available filters of a transaction log that was removed from the chain by a rollback.
+    /// The log is delivered with `removed` set to `true`; `latest_block_number` is the one before the rollback.
+    pub fn notify_removed_log(&mut self, log: &Log, latest_block_number: U64) {
+        let mut log = log.clone();
+        log.removed = Some(true);
+        self.notify_new_log(&log, latest_block_number)
+    }
 }
 
 #[cfg(test)]
@@ -405,6 +413,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_notify_removed_log_flags_log_as_removed() {
+        let mut filters = EthFilters::default();
+        let id = filters
+            .add_log_filter(
+                BlockNumber::Earliest,
+                BlockNumber::Latest,
+                vec![],
+                Default::default(),
+            )
+            .expect("failed adding filter");
+
+        let log = LogBuilder::new().set_block(U64::from(2)).build();
+        filters.notify_removed_log(&log, U64::from(2));
+
+        match filters.filters.get(&id).unwrap() {
+            FilterType::Log(f) => {
+                assert_eq!(1, f.updates.len());
+                assert_eq!(Some(true), f.updates[0].removed);
+            }
+            _ => panic!("invalid filter"),
+        }
+    }
+
     #[test]
     fn test_notify_new_pending_transaction_appends_updates() {
         let mut filters = EthFilters::default();
@@ -10,6 +10,9 @@ use crate::config::{
         DEFAULT_FAIR_PUBDATA_PRICE, TEST_NODE_NETWORK_ID,
     },
 };
+use crate::fork_client::{ForkClient, RetryConfig};
+use crate::metrics::{CacheKind, METRICS};
+use crate::node::{Journal, JournaledMap};
 use crate::system_contracts;
 use crate::{deps::InMemoryStorage, http_fork_source::HttpForkSource};
 use eyre::eyre;
@@ -73,6 +76,16 @@ pub enum ForkNetwork {
 }
 
 impl ForkNetwork {
+    /// Returns the network for a given fork name or URL.
+    pub fn from_name(fork: &str) -> Self {
+        match fork {
+            "mainnet" => ForkNetwork::Mainnet,
+            "sepolia-testnet" => ForkNetwork::SepoliaTestnet,
+            "goerli-testnet" => ForkNetwork::GoerliTestnet,
+            _ => ForkNetwork::Other(fork.to_string()),
+        }
+    }
+
     /// Return the URL for the underlying fork source.
     pub fn to_url(&self) -> &str {
         match self {
@@ -110,10 +123,14 @@ pub struct ForkStorage<S> {
 pub struct ForkStorageInner<S> {
     // Underlying local storage
     pub raw_storage: InMemoryStorage,
+    // Undo log of the local storage values, used for snapshots.
+    pub storage_journal: Journal<StorageKey, StorageValue>,
+    // Undo log of the local factory deps, used for snapshots.
+    pub factory_deps_journal: Journal<H256, Vec<u8>>,
     // Cache of data that was read from remote location.
-    pub value_read_cache: HashMap<StorageKey, H256>,
+    pub value_read_cache: JournaledMap<StorageKey, H256>,
     // Cache of factory deps that were read from remote location.
-    pub factory_dep_cache: HashMap<H256, Option<Vec<u8>>>,
+    pub factory_dep_cache: JournaledMap<H256, Option<Vec<u8>>>,
     // If set - it hold the necessary information on where to fetch the data.
     // If not set - it will simply read from underlying storage.
     pub fork: Option<Box<ForkDetails>>,
@@ -144,6 +161,8 @@ impl<S: ForkSource> ForkStorage<S> {
                     system_contracts_options,
                     use_evm_emulator,
                 ),
+                storage_journal: Default::default(),
+                factory_deps_journal: Default::default(),
                 value_read_cache: Default::default(),
                 fork: fork.map(Box::new),
                 factory_dep_cache: Default::default(),
@@ -185,52 +204,95 @@ impl<S: ForkSource> ForkStorage<S> {
         &self,
         key: &StorageKey,
     ) -> eyre::Result<zksync_types::StorageValue> {
-        let mut mutator = self.inner.write().unwrap();
-        let local_storage = mutator.raw_storage.read_value(key);
+        let local_storage = self.read_local_value(key);
+        if !H256::is_zero(&local_storage) {
+            return Ok(local_storage);
+        }
 
-        if let Some(fork) = &mutator.fork {
-            if !H256::is_zero(&local_storage) {
-                return Ok(local_storage);
-            }
+        self.read_fork_value_internal(key)
+    }
+
+    /// Reads the value of `key` from the local storage only, zero if it was never written locally.
+    pub fn read_local_value(&self, key: &StorageKey) -> zksync_types::StorageValue {
+        self.inner
+            .read()
+            .unwrap()
+            .raw_storage
+            .state
+            .get(key)
+            .copied()
+            .unwrap_or_default()
+    }
 
-            if let Some(value) = mutator.value_read_cache.get(key) {
+    /// Reads the value of `key` from the fork (or the fork read cache), ignoring the local storage.
+    /// Returns zero if the storage is not forked.
+    pub fn read_fork_value_internal(
+        &self,
+        key: &StorageKey,
+    ) -> eyre::Result<zksync_types::StorageValue> {
+        let (fork_source, l2_miniblock) = {
+            let reader = self.inner.read().unwrap();
+            let Some(fork) = &reader.fork else {
+                return Ok(H256::zero());
+            };
+            if let Some(value) = METRICS
+                .observe_cache_lookup(CacheKind::StorageValues, reader.value_read_cache.get(key))
+            {
                 return Ok(*value);
             }
-            let l2_miniblock = fork.l2_miniblock;
-            let key_ = *key;
-
-            let result = fork.fork_source.get_storage_at(
-                *key_.account().address(),
-                h256_to_u256(*key_.key()),
-                Some(BlockIdVariant::BlockNumber(BlockNumber::Number(U64::from(
-                    l2_miniblock,
-                )))),
-            )?;
-
-            mutator.value_read_cache.insert(*key, result);
-            Ok(result)
-        } else {
-            Ok(local_storage)
+            (fork.fork_source.clone(), fork.l2_miniblock)
+        };
+
+        // The lock is released while fetching, so that a slow upstream does not block other readers.
+        let result = fork_source.get_storage_at(
+            *key.account().address(),
+            h256_to_u256(*key.key()),
+            Some(BlockIdVariant::BlockNumber(BlockNumber::Number(U64::from(
+                l2_miniblock,
+            )))),
+        )?;
+
+        let mut mutator = self.inner.write().unwrap();
+        if !mutator.is_forked_from(&fork_source, l2_miniblock) {
+            return Ok(result);
         }
+        if let Some(value) = mutator.value_read_cache.get(key) {
+            return Ok(*value);
+        }
+        mutator.value_read_cache.insert(*key, result);
+        Ok(result)
     }
 
     pub fn load_factory_dep_internal(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
-        let mut mutator = self.inner.write().unwrap();
-        let local_storage = mutator.raw_storage.load_factory_dep(hash);
-        if let Some(fork) = &mutator.fork {
+        let (fork_source, l2_miniblock) = {
+            let reader = self.inner.read().unwrap();
+            let local_storage = reader.raw_storage.load_factory_dep(hash);
+            let Some(fork) = &reader.fork else {
+                return Ok(local_storage);
+            };
             if local_storage.is_some() {
                 return Ok(local_storage);
             }
-            if let Some(value) = mutator.factory_dep_cache.get(&hash) {
+            if let Some(value) = METRICS
+                .observe_cache_lookup(CacheKind::FactoryDeps, reader.factory_dep_cache.get(&hash))
+            {
                 return Ok(value.clone());
             }
+            (fork.fork_source.clone(), fork.l2_miniblock)
+        };
 
-            let result = fork.fork_source.get_bytecode_by_hash(hash)?;
-            mutator.factory_dep_cache.insert(hash, result.clone());
-            Ok(result)
-        } else {
-            Ok(local_storage)
+        // See `read_fork_value_internal` on why the lock is not held here.
+        let result = fork_source.get_bytecode_by_hash(hash)?;
+
+        let mut mutator = self.inner.write().unwrap();
+        if !mutator.is_forked_from(&fork_source, l2_miniblock) {
+            return Ok(result);
         }
+        if let Some(value) = mutator.factory_dep_cache.get(&hash) {
+            return Ok(value.clone());
+        }
+        mutator.factory_dep_cache.insert(hash, result.clone());
+        Ok(result)
     }
 
     /// Check if this is the first time when we're ever writing to this key.
@@ -258,7 +320,7 @@ impl<S: ForkSource> ForkStorage<S> {
     }
 
     /// Retrieves the enumeration index for a given `key`.
-    fn get_enumeration_index_internal(&self, _key: &StorageKey) -> Option<u64> {
+    pub(crate) fn get_enumeration_index_internal(&self, _key: &StorageKey) -> Option<u64> {
         // TODO: Update this file to use proper enumeration index value once it's exposed for forks via API
         Some(0_u64)
     }
@@ -267,8 +329,40 @@ impl<S: ForkSource> ForkStorage<S> {
     /// stored data and cached data read from the fork.
     pub fn dump_state(&self) -> SerializableForkStorage {
         let inner = self.inner.read().unwrap();
-        let mut state = BTreeMap::from_iter(inner.value_read_cache.clone());
-        state.extend(inner.raw_storage.state.clone());
+        let local_state = inner.raw_storage.state.clone();
+        Self::serialize_state(&inner, local_state, &inner.raw_storage.factory_deps)
+    }
+
+    /// Same as [ForkStorage::dump_state], but with locally stored data as of `checkpoint`.
+    pub fn dump_state_at(&self, checkpoint: &ForkStorageCheckpoint) -> SerializableForkStorage {
+        let inner = self.inner.read().unwrap();
+        let local_state = inner
+            .storage_journal
+            .values_at(checkpoint.storage, &inner.raw_storage.state);
+        let factory_deps = inner
+            .factory_deps_journal
+            .values_at(checkpoint.factory_deps, &inner.raw_storage.factory_deps);
+        Self::serialize_state(&inner, local_state, &factory_deps)
+    }
+
+    /// Returns locally stored data as of `checkpoint`.
+    pub fn local_state_at(
+        &self,
+        checkpoint: &ForkStorageCheckpoint,
+    ) -> HashMap<StorageKey, StorageValue> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .storage_journal
+            .values_at(checkpoint.storage, &inner.raw_storage.state)
+    }
+
+    fn serialize_state(
+        inner: &ForkStorageInner<S>,
+        local_state: HashMap<StorageKey, StorageValue>,
+        local_factory_deps: &HashMap<H256, Vec<u8>>,
+    ) -> SerializableForkStorage {
+        let mut state = BTreeMap::from_iter((*inner.value_read_cache).clone());
+        state.extend(local_state);
         let mut factory_deps = BTreeMap::from_iter(
             inner
                 .factory_dep_cache
@@ -278,9 +372,7 @@ impl<S: ForkSource> ForkStorage<S> {
                 .map(|(k, v)| (*k, Bytes::from(v.clone()))),
         );
         factory_deps.extend(
-            inner
-                .raw_storage
-                .factory_deps
+            local_factory_deps
                 .iter()
                 .map(|(k, v)| (*k, Bytes::from(v.clone()))),
         );
@@ -298,11 +390,12 @@ impl<S: ForkSource> ForkStorage<S> {
             "loading fork storage from supplied state"
         );
         let mut inner = self.inner.write().unwrap();
-        inner.raw_storage.state.extend(state.storage.0);
-        inner
-            .raw_storage
-            .factory_deps
-            .extend(state.factory_deps.into_iter().map(|(k, v)| (k, v.0)));
+        for (key, value) in state.storage.0 {
+            inner.set_value(key, value);
+        }
+        for (hash, bytecode) in state.factory_deps {
+            inner.store_factory_dep(hash, bytecode.0);
+        }
     }
 }
 
@@ -342,14 +435,110 @@ impl<S: std::fmt::Debug + ForkSource> ReadStorage for &ForkStorage<S> {
     }
 }
 
+impl<S> ForkStorageInner<S> {
+    /// Sets the local storage value, recording the previous one in the storage journal.
+    pub fn set_value(&mut self, key: StorageKey, value: StorageValue) {
+        self.storage_journal
+            .record(&key, self.raw_storage.state.get(&key));
+        self.raw_storage.set_value(key, value)
+    }
+
+    /// Removes the local storage value, recording the previous one in the storage journal.
+    pub fn remove_value(&mut self, key: &StorageKey) {
+        self.storage_journal
+            .record(key, self.raw_storage.state.get(key));
+        self.raw_storage.state.remove(key);
+    }
+
+    /// Stores a local factory dependency, recording the previous one in the factory deps journal.
+    pub fn store_factory_dep(&mut self, hash: H256, bytecode: Vec<u8>) {
+        self.factory_deps_journal
+            .record(&hash, self.raw_storage.factory_deps.get(&hash));
+        self.raw_storage.store_factory_dep(hash, bytecode)
+    }
+
+    /// Whether the storage is still forked from `fork_source` at `l2_miniblock`, i.e. whether a value fetched from
+    /// them while the lock was released may be cached.
+    fn is_forked_from(
+        &self,
+        fork_source: &Arc<dyn ForkSource + Send + Sync>,
+        l2_miniblock: u64,
+    ) -> bool {
+        self.fork.as_ref().map_or(false, |fork| {
+            Arc::as_ptr(&fork.fork_source) as *const () == Arc::as_ptr(fork_source) as *const ()
+                && fork.l2_miniblock == l2_miniblock
+        })
+    }
+}
+
+/// Journal checkpoints of a [ForkStorage], one per journal, see [ForkStorage::checkpoint].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ForkStorageCheckpoint {
+    storage: usize,
+    factory_deps: usize,
+    value_read_cache: usize,
+    factory_dep_cache: usize,
+}
+
 impl<S> ForkStorage<S> {
     pub fn set_value(&mut self, key: StorageKey, value: zksync_types::StorageValue) {
         let mut mutator = self.inner.write().unwrap();
-        mutator.raw_storage.set_value(key, value)
+        mutator.set_value(key, value)
+    }
+
+    /// Replaces locally stored data with `state`, e.g. one archived for a previous block.
+    /// All modifications are recorded in the storage journal.
+    pub fn restore_local_state(&self, state: &HashMap<StorageKey, StorageValue>) {
+        let mut mutator = self.inner.write().unwrap();
+        let removed_keys = mutator
+            .raw_storage
+            .state
+            .keys()
+            .filter(|key| !state.contains_key(key))
+            .copied()
+            .collect::<Vec<_>>();
+        for key in removed_keys {
+            mutator.remove_value(&key);
+        }
+        for (key, value) in state {
+            if mutator.raw_storage.state.get(key) != Some(value) {
+                mutator.set_value(*key, *value);
+            }
+        }
+    }
+
+    /// Opens a new layer in the journals of local storage values, local factory deps and the fork read caches,
+    /// see [Journal::checkpoint].
+    pub fn checkpoint(&self) -> ForkStorageCheckpoint {
+        let mut mutator = self.inner.write().unwrap();
+        ForkStorageCheckpoint {
+            storage: mutator.storage_journal.checkpoint(),
+            factory_deps: mutator.factory_deps_journal.checkpoint(),
+            value_read_cache: mutator.value_read_cache.checkpoint(),
+            factory_dep_cache: mutator.factory_dep_cache.checkpoint(),
+        }
+    }
+
+    /// Reverts everything journaled to `checkpoint`, see [Journal::revert].
+    pub fn revert(&self, checkpoint: &ForkStorageCheckpoint) {
+        let mut mutator = self.inner.write().unwrap();
+        let ForkStorageInner {
+            raw_storage,
+            storage_journal,
+            factory_deps_journal,
+            value_read_cache,
+            factory_dep_cache,
+            ..
+        } = &mut *mutator;
+        storage_journal.revert(checkpoint.storage, &mut raw_storage.state);
+        factory_deps_journal.revert(checkpoint.factory_deps, &mut raw_storage.factory_deps);
+        value_read_cache.revert(checkpoint.value_read_cache);
+        factory_dep_cache.revert(checkpoint.factory_dep_cache);
     }
+
     pub fn store_factory_dep(&mut self, hash: H256, bytecode: Vec<u8>) {
         let mut mutator = self.inner.write().unwrap();
-        mutator.raw_storage.store_factory_dep(hash, bytecode)
+        mutator.store_factory_dep(hash, bytecode)
     }
 }
 
@@ -440,7 +629,7 @@ pub trait ForkSource {
 /// Holds the information about the original chain.
 pub struct ForkDetails {
     // Source of the fork data (for example HttpForkSource)
-    pub fork_source: Box<dyn ForkSource + Send + Sync>,
+    pub fork_source: Arc<dyn ForkSource + Send + Sync>,
     // Chain ID of fork
     pub chain_id: L2ChainId,
     // Block number at which we forked (the next block to create is l1_block + 1)
@@ -514,16 +703,19 @@ impl fmt::Debug for ForkDetails {
 impl ForkDetails {
     pub async fn from_network_and_miniblock_and_chain(
         network: ForkNetwork,
-        client: Client<L2>,
+        client: &ForkClient,
         miniblock: u64,
         chain_id: Option<L2ChainId>,
         cache_config: &CacheConfig,
     ) -> eyre::Result<Self> {
         let url = network.to_url();
         let opt_block_details = client
-            .get_block_details(L2BlockNumber(miniblock as u32))
-            .await
-            .map_err(|error| eyre!(error))?;
+            .request_async("get_block_details", move |client| async move {
+                client
+                    .get_block_details(L2BlockNumber(miniblock as u32))
+                    .await
+            })
+            .await?;
         let block_details = opt_block_details
             .ok_or_else(|| eyre!("Could not find block {:?} in {:?}", miniblock, url))?;
         let root_hash = block_details
@@ -531,9 +723,10 @@ impl ForkDetails {
             .root_hash
             .ok_or_else(|| eyre!("fork block #{} missing root hash", miniblock))?;
         let opt_block = client
-            .get_block_by_hash(root_hash, true)
-            .await
-            .map_err(|error| eyre!(error))?;
+            .request_async("get_block_by_hash", move |client| async move {
+                client.get_block_by_hash(root_hash, true).await
+            })
+            .await?;
         let block = opt_block.ok_or_else(|| {
             eyre!(
                 "Could not find block #{:?} ({:#x}) in {:?}",
@@ -555,7 +748,12 @@ impl ForkDetails {
 
         let (estimate_gas_price_scale_factor, estimate_gas_scale_factor) =
             network.local_gas_scale_factors();
-        let fee_params = match client.get_fee_params().await {
+        let fee_params = match client
+            .request_async("get_fee_params", |client| async move {
+                client.get_fee_params().await
+            })
+            .await
+        {
             Ok(fp) => Some(fp),
             Err(error) => {
                 tracing::warn!("Cannot get fee params: {:?}", error);
@@ -564,7 +762,10 @@ impl ForkDetails {
         };
 
         Ok(ForkDetails {
-            fork_source: Box::new(HttpForkSource::new(url.to_owned(), cache_config.clone())),
+            fork_source: Arc::new(
+                HttpForkSource::new(url.to_owned(), cache_config.clone())
+                    .with_fallback_urls(client.fallback_urls().to_vec()),
+            ),
             chain_id: chain_id.unwrap_or_else(|| L2ChainId::from(TEST_NODE_NETWORK_ID)),
             l1_block: l1_batch_number,
             l2_block: block,
@@ -585,29 +786,34 @@ impl ForkDetails {
         })
     }
     /// Create a fork from a given network at a given height.
+    ///
+    /// Requests fall back to `fallback_urls` (networks or URLs of the same chain) when the network fails transiently.
     pub async fn from_network(
         fork: &str,
+        fallback_urls: &[String],
         fork_block_number: Option<u64>,
         cache_config: &CacheConfig,
     ) -> eyre::Result<Self> {
-        let (network, client) = Self::fork_network_and_client(fork)?;
-        let chain_id_u64 = client.chain_id().await?;
+        let (network, client) = Self::fork_network_and_fork_client(fork, fallback_urls);
+        let chain_id_u64 = client
+            .request_async("chain_id", |client| async move { client.chain_id().await })
+            .await?;
         let chain_id = L2ChainId::from(chain_id_u64.as_u32());
 
         let l2_miniblock = if let Some(fork_block_number) = fork_block_number {
             fork_block_number
         } else {
-            match client.get_block_number().await {
-                Ok(bn) => bn.as_u64(),
-                Err(error) => {
-                    return Err(eyre!(error));
-                }
-            }
+            client
+                .request_async("get_block_number", |client| async move {
+                    client.get_block_number().await
+                })
+                .await?
+                .as_u64()
         };
 
         Self::from_network_and_miniblock_and_chain(
             network,
-            client,
+            &client,
             l2_miniblock,
             chain_id.into(),
             cache_config,
@@ -617,16 +823,20 @@ impl ForkDetails {
 
     /// Create a fork from a given network, at a height BEFORE a transaction.
     /// This will allow us to apply this transaction locally on top of this fork.
+    ///
+    /// Requests fall back to `fallback_urls` the same way as in [ForkDetails::from_network].
     pub async fn from_network_tx(
         fork: &str,
+        fallback_urls: &[String],
         tx: H256,
         cache_config: &CacheConfig,
     ) -> eyre::Result<Self> {
-        let (network, client) = Self::fork_network_and_client(fork)?;
+        let (network, client) = Self::fork_network_and_fork_client(fork, fallback_urls);
         let opt_tx_details = client
-            .get_transaction_by_hash(tx)
-            .await
-            .map_err(|error| eyre!(error))?;
+            .request_async("get_transaction_by_hash", move |client| async move {
+                client.get_transaction_by_hash(tx).await
+            })
+            .await?;
         let tx_details = opt_tx_details.ok_or_else(|| eyre!("could not find {:?}", tx))?;
         let overwrite_chain_id = L2ChainId::try_from(tx_details.chain_id.as_u64())
             .map_err(|error| eyre!("erroneous chain id {}: {:?}", tx_details.chain_id, error))?;
@@ -639,7 +849,7 @@ impl ForkDetails {
 
         Self::from_network_and_miniblock_and_chain(
             network,
-            client,
+            &client,
             l2_miniblock,
             Some(overwrite_chain_id),
             cache_config,
@@ -653,22 +863,27 @@ impl ForkDetails {
         fork_block_number: Option<u64>,
         cache_config: CacheConfig,
     ) -> eyre::Result<Self> {
-        let parsed_url = SensitiveUrl::from_str(&url)?;
-        let builder = Client::http(parsed_url).map_err(|error| eyre!(error))?;
-        let client = builder.build();
+        let client = ForkClient::new(vec![url.clone()], RetryConfig::default());
 
         block_on(async move {
-            let chain_id_u64 = client.chain_id().await?;
+            let chain_id_u64 = client
+                .request_async("chain_id", |client| async move { client.chain_id().await })
+                .await?;
             let chain_id = L2ChainId::from(chain_id_u64.as_u32());
             let l2_miniblock = if let Some(fork_block_number) = fork_block_number {
                 fork_block_number
             } else {
-                client.get_block_number().await?.as_u64()
+                client
+                    .request_async("get_block_number", |client| async move {
+                        client.get_block_number().await
+                    })
+                    .await?
+                    .as_u64()
             };
 
             Self::from_network_and_miniblock_and_chain(
                 ForkNetwork::Other(url),
-                client,
+                &client,
                 l2_miniblock,
                 chain_id.into(),
                 &cache_config,
@@ -679,12 +894,7 @@ impl ForkDetails {
 
     /// Return [`ForkNetwork`] and HTTP client for a given fork name.
     pub fn fork_network_and_client(fork: &str) -> eyre::Result<(ForkNetwork, Client<L2>)> {
-        let network = match fork {
-            "mainnet" => ForkNetwork::Mainnet,
-            "sepolia-testnet" => ForkNetwork::SepoliaTestnet,
-            "goerli-testnet" => ForkNetwork::GoerliTestnet,
-            _ => ForkNetwork::Other(fork.to_string()),
-        };
+        let network = ForkNetwork::from_name(fork);
 
         let url = network.to_url();
         let parsed_url = SensitiveUrl::from_str(url)
@@ -694,6 +904,22 @@ impl ForkDetails {
         Ok((network, builder.build()))
     }
 
+    /// Return [`ForkNetwork`] and a [`ForkClient`] for a given fork name, falling back to `fallback_urls`.
+    pub fn fork_network_and_fork_client(
+        fork: &str,
+        fallback_urls: &[String],
+    ) -> (ForkNetwork, ForkClient) {
+        let network = ForkNetwork::from_name(fork);
+        let urls = std::iter::once(network.to_url().to_string())
+            .chain(
+                fallback_urls
+                    .iter()
+                    .map(|fork| ForkNetwork::from_name(fork).to_url().to_string()),
+            )
+            .collect();
+        (network, ForkClient::new(urls, RetryConfig::default()))
+    }
+
     /// Returns transactions that are in the same L2 miniblock as replay_tx, but were executed before it.
     pub fn get_earlier_transactions_in_same_block(
         &self,
@@ -774,7 +1000,7 @@ impl ForkDetails {
 
     /// Sets fork's internal URL. Assumes the underlying chain is the same as before.
     pub fn set_rpc_url(&mut self, url: String) {
-        self.fork_source = Box::new(HttpForkSource::new(url, self.cache_config.clone()));
+        self.fork_source = Arc::new(HttpForkSource::new(url, self.cache_config.clone()));
     }
 }
 
@@ -859,9 +1085,16 @@ mod serde_from {
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use httptest::{
+        matchers::request,
+        responders::{delay_and_then, json_encoded},
+        Expectation,
+    };
     use zksync_multivm::interface::storage::ReadStorage;
     use zksync_types::{api::TransactionVariant, StorageKey};
-    use zksync_types::{AccountTreeId, L1BatchNumber, H256};
+    use zksync_types::{AccountTreeId, L1BatchNumber, H160, H256};
 
     use crate::config::{
         cache::CacheConfig,
@@ -870,7 +1103,9 @@ mod tests {
             DEFAULT_FAIR_PUBDATA_PRICE, DEFAULT_L2_GAS_PRICE, TEST_NODE_NETWORK_ID,
         },
     };
-    use crate::{deps::InMemoryStorage, system_contracts, testing};
+    use crate::{
+        deps::InMemoryStorage, http_fork_source::HttpForkSource, system_contracts, testing,
+    };
 
     use super::{ForkDetails, ForkStorage};
 
@@ -886,12 +1121,14 @@ mod tests {
 
         let external_storage = testing::ExternalStorage {
             raw_storage: in_memory_storage,
+            read_delay: Default::default(),
+            transactions: Default::default(),
         };
 
         let options = system_contracts::Options::default();
 
         let fork_details = ForkDetails {
-            fork_source: Box::new(external_storage),
+            fork_source: Arc::new(external_storage),
             chain_id: TEST_NODE_NETWORK_ID.into(),
             l1_block: L1BatchNumber(1),
             l2_block: zksync_types::api::Block::<TransactionVariant>::default(),
@@ -925,8 +1162,10 @@ mod tests {
     #[test]
     fn test_get_block_gas_details() {
         let fork_details = ForkDetails {
-            fork_source: Box::new(testing::ExternalStorage {
+            fork_source: Arc::new(testing::ExternalStorage {
                 raw_storage: InMemoryStorage::default(),
+                read_delay: Default::default(),
+                transactions: Default::default(),
             }),
             chain_id: TEST_NODE_NETWORK_ID.into(),
             l1_block: L1BatchNumber(0),
@@ -949,4 +1188,68 @@ mod tests {
 
         assert_eq!(actual_result, expected_result);
     }
+
+    #[test]
+    fn test_fork_read_does_not_hold_storage_lock() {
+        let key = StorageKey::new(AccountTreeId::new(H160::repeat_byte(0x1)), H256::zero());
+        let mock_server = testing::MockServer::run();
+        mock_server
+            .inner
+            .expect(
+                Expectation::matching(request::method("POST")).respond_with(delay_and_then(
+                    Duration::from_millis(500),
+                    json_encoded(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": 0,
+                        "result": format!("{:#x}", H256::repeat_byte(0x2)),
+                    })),
+                )),
+            );
+        let fork_details = ForkDetails {
+            fork_source: Arc::new(HttpForkSource::new(mock_server.url(), CacheConfig::None)),
+            chain_id: TEST_NODE_NETWORK_ID.into(),
+            l1_block: L1BatchNumber(1),
+            l2_block: zksync_types::api::Block::<TransactionVariant>::default(),
+            l2_miniblock: 1,
+            l2_miniblock_hash: H256::zero(),
+            block_timestamp: 0,
+            overwrite_chain_id: None,
+            l1_gas_price: 100,
+            l2_fair_gas_price: DEFAULT_L2_GAS_PRICE,
+            fair_pubdata_price: DEFAULT_FAIR_PUBDATA_PRICE,
+            estimate_gas_price_scale_factor: DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR,
+            estimate_gas_scale_factor: DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+            fee_params: None,
+            cache_config: CacheConfig::None,
+        };
+        let fork_storage: ForkStorage<HttpForkSource> = ForkStorage::new(
+            Some(fork_details),
+            &system_contracts::Options::default(),
+            false,
+            None,
+        );
+
+        let reader = {
+            let fork_storage = fork_storage.clone();
+            std::thread::spawn(move || fork_storage.read_fork_value_internal(&key))
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        // The upstream request is still in flight, yet the storage can be locked.
+        assert!(fork_storage.inner.try_write().is_ok());
+
+        let value = reader
+            .join()
+            .expect("reader panicked")
+            .expect("failed reading fork value");
+        assert_eq!(H256::repeat_byte(0x2), value);
+        assert_eq!(
+            Some(&value),
+            fork_storage
+                .inner
+                .read()
+                .unwrap()
+                .value_read_cache
+                .get(&key)
+        );
+    }
 }
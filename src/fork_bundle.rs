@@ -0,0 +1,623 @@
+//! Portable fork bundles for running forked nodes without network access.
+//!
+//! A bundle is a newline-delimited JSON file. The first line is a [ForkBundleHeader] with everything needed to
+//! construct [ForkDetails]; every following line is a [ForkBundleRecord] holding the response to one upstream
+//! request. [RecordingForkSource] appends a record for every distinct request it forwards to the wrapped source,
+//! while [BundleForkSource] serves exclusively from a previously recorded bundle.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    sync::{Arc, Mutex},
+};
+
+use eyre::{eyre, Context};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zksync_types::{
+    api::{
+        Block, BlockDetails, BlockIdVariant, BlockNumber, BridgeAddresses, Transaction,
+        TransactionDetails, TransactionVariant,
+    },
+    fee_model::FeeParams,
+    Address, L1BatchNumber, L2BlockNumber, L2ChainId, H256, U256,
+};
+use zksync_web3_decl::types::{Index, Token};
+
+use crate::{
+    config::cache::CacheConfig,
+    fork::{ForkDetails, ForkSource},
+};
+
+/// Version of the bundle file format, bumped on incompatible changes.
+pub const FORK_BUNDLE_VERSION: u32 = 1;
+
+/// First line of a bundle file, describing the forked chain and block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkBundleHeader {
+    pub version: u32,
+    pub fork_url: String,
+    pub chain_id: u64,
+    pub overwrite_chain_id: Option<u64>,
+    pub l1_block: L1BatchNumber,
+    pub l2_block: Block<TransactionVariant>,
+    pub l2_miniblock: u64,
+    pub l2_miniblock_hash: H256,
+    pub block_timestamp: u64,
+    pub l1_gas_price: u64,
+    pub l2_fair_gas_price: u64,
+    pub fair_pubdata_price: u64,
+    pub estimate_gas_price_scale_factor: f64,
+    pub estimate_gas_scale_factor: f32,
+    pub fee_params: Option<FeeParams>,
+}
+
+impl ForkBundleHeader {
+    pub fn new(fork_details: &ForkDetails) -> Self {
+        Self {
+            version: FORK_BUNDLE_VERSION,
+            fork_url: fork_details.fork_source.get_fork_url().unwrap_or_default(),
+            chain_id: fork_details.chain_id.as_u64(),
+            overwrite_chain_id: fork_details.overwrite_chain_id.map(|id| id.as_u64()),
+            l1_block: fork_details.l1_block,
+            l2_block: fork_details.l2_block.clone(),
+            l2_miniblock: fork_details.l2_miniblock,
+            l2_miniblock_hash: fork_details.l2_miniblock_hash,
+            block_timestamp: fork_details.block_timestamp,
+            l1_gas_price: fork_details.l1_gas_price,
+            l2_fair_gas_price: fork_details.l2_fair_gas_price,
+            fair_pubdata_price: fork_details.fair_pubdata_price,
+            estimate_gas_price_scale_factor: fork_details.estimate_gas_price_scale_factor,
+            estimate_gas_scale_factor: fork_details.estimate_gas_scale_factor,
+            fee_params: fork_details.fee_params,
+        }
+    }
+}
+
+/// A single recorded upstream response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForkBundleRecord {
+    /// Method name and parameters of the request, see [request_key].
+    pub request: String,
+    pub response: serde_json::Value,
+}
+
+/// Returns the key a request is recorded under: its method name and parameters encoded as JSON.
+fn request_key(method: &str, params: impl Serialize) -> String {
+    serde_json::to_string(&(method, params)).expect("request parameters are serializable")
+}
+
+struct BundleWriter {
+    writer: BufWriter<File>,
+    recorded: HashSet<String>,
+}
+
+impl BundleWriter {
+    fn write_line(&mut self, value: &impl Serialize) -> eyre::Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        // Flushed after every record so that a bundle stays usable even if the node is killed.
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Fork source that forwards all requests to another source and records their responses into a bundle file.
+pub struct RecordingForkSource {
+    inner: Arc<dyn ForkSource + Send + Sync>,
+    path: String,
+    bundle: Mutex<BundleWriter>,
+}
+
+impl RecordingForkSource {
+    /// Creates (or truncates) the bundle at `path` and writes its header.
+    pub fn new(
+        inner: Arc<dyn ForkSource + Send + Sync>,
+        header: &ForkBundleHeader,
+        path: &str,
+    ) -> eyre::Result<Self> {
+        let file =
+            File::create(path).wrap_err_with(|| format!("failed creating fork bundle {}", path))?;
+        let mut bundle = BundleWriter {
+            writer: BufWriter::new(file),
+            recorded: HashSet::new(),
+        };
+        bundle.write_line(header)?;
+
+        Ok(Self {
+            inner,
+            path: path.to_string(),
+            bundle: Mutex::new(bundle),
+        })
+    }
+
+    /// Records a successful response under `request`; failed requests are not recorded.
+    fn record<T: Serialize>(&self, request: String, result: eyre::Result<T>) -> eyre::Result<T> {
+        let response = result?;
+        let mut bundle = self
+            .bundle
+            .lock()
+            .map_err(|err| eyre!("failed acquiring fork bundle lock: {:?}", err))?;
+        if !bundle.recorded.contains(&request) {
+            let record = ForkBundleRecord {
+                request: request.clone(),
+                response: serde_json::to_value(&response)?,
+            };
+            bundle
+                .write_line(&record)
+                .wrap_err_with(|| format!("failed writing to fork bundle {}", self.path))?;
+            bundle.recorded.insert(request);
+        }
+        Ok(response)
+    }
+}
+
+impl ForkSource for RecordingForkSource {
+    fn get_fork_url(&self) -> eyre::Result<String> {
+        self.inner.get_fork_url()
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        idx: U256,
+        block: Option<BlockIdVariant>,
+    ) -> eyre::Result<H256> {
+        let request = request_key("get_storage_at", (address, idx, &block));
+        self.record(request, self.inner.get_storage_at(address, idx, block))
+    }
+
+    fn get_bytecode_by_hash(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
+        let request = request_key("get_bytecode_by_hash", (hash,));
+        self.record(request, self.inner.get_bytecode_by_hash(hash))
+    }
+
+    fn get_transaction_by_hash(&self, hash: H256) -> eyre::Result<Option<Transaction>> {
+        let request = request_key("get_transaction_by_hash", (hash,));
+        self.record(request, self.inner.get_transaction_by_hash(hash))
+    }
+
+    fn get_transaction_details(&self, hash: H256) -> eyre::Result<Option<TransactionDetails>> {
+        let request = request_key("get_transaction_details", (hash,));
+        self.record(request, self.inner.get_transaction_details(hash))
+    }
+
+    fn get_raw_block_transactions(
+        &self,
+        block_number: L2BlockNumber,
+    ) -> eyre::Result<Vec<zksync_types::Transaction>> {
+        let request = request_key("get_raw_block_transactions", (block_number,));
+        self.record(request, self.inner.get_raw_block_transactions(block_number))
+    }
+
+    fn get_block_by_hash(
+        &self,
+        hash: H256,
+        full_transactions: bool,
+    ) -> eyre::Result<Option<Block<TransactionVariant>>> {
+        let request = request_key("get_block_by_hash", (hash, full_transactions));
+        self.record(
+            request,
+            self.inner.get_block_by_hash(hash, full_transactions),
+        )
+    }
+
+    fn get_block_by_number(
+        &self,
+        block_number: BlockNumber,
+        full_transactions: bool,
+    ) -> eyre::Result<Option<Block<TransactionVariant>>> {
+        let request = request_key("get_block_by_number", (block_number, full_transactions));
+        self.record(
+            request,
+            self.inner
+                .get_block_by_number(block_number, full_transactions),
+        )
+    }
+
+    fn get_block_details(&self, miniblock: L2BlockNumber) -> eyre::Result<Option<BlockDetails>> {
+        let request = request_key("get_block_details", (miniblock,));
+        self.record(request, self.inner.get_block_details(miniblock))
+    }
+
+    fn get_fee_params(&self) -> eyre::Result<FeeParams> {
+        let request = request_key("get_fee_params", ());
+        self.record(request, self.inner.get_fee_params())
+    }
+
+    fn get_block_transaction_count_by_hash(&self, block_hash: H256) -> eyre::Result<Option<U256>> {
+        let request = request_key("get_block_transaction_count_by_hash", (block_hash,));
+        self.record(
+            request,
+            self.inner.get_block_transaction_count_by_hash(block_hash),
+        )
+    }
+
+    fn get_block_transaction_count_by_number(
+        &self,
+        block_number: BlockNumber,
+    ) -> eyre::Result<Option<U256>> {
+        let request = request_key("get_block_transaction_count_by_number", (block_number,));
+        self.record(
+            request,
+            self.inner
+                .get_block_transaction_count_by_number(block_number),
+        )
+    }
+
+    fn get_transaction_by_block_hash_and_index(
+        &self,
+        block_hash: H256,
+        index: Index,
+    ) -> eyre::Result<Option<Transaction>> {
+        let request = request_key(
+            "get_transaction_by_block_hash_and_index",
+            (block_hash, index),
+        );
+        self.record(
+            request,
+            self.inner
+                .get_transaction_by_block_hash_and_index(block_hash, index),
+        )
+    }
+
+    fn get_transaction_by_block_number_and_index(
+        &self,
+        block_number: BlockNumber,
+        index: Index,
+    ) -> eyre::Result<Option<Transaction>> {
+        let request = request_key(
+            "get_transaction_by_block_number_and_index",
+            (block_number, index),
+        );
+        self.record(
+            request,
+            self.inner
+                .get_transaction_by_block_number_and_index(block_number, index),
+        )
+    }
+
+    fn get_bridge_contracts(&self) -> eyre::Result<BridgeAddresses> {
+        let request = request_key("get_bridge_contracts", ());
+        self.record(request, self.inner.get_bridge_contracts())
+    }
+
+    fn get_confirmed_tokens(&self, from: u32, limit: u8) -> eyre::Result<Vec<Token>> {
+        let request = request_key("get_confirmed_tokens", (from, limit));
+        self.record(request, self.inner.get_confirmed_tokens(from, limit))
+    }
+}
+
+/// Fork source that serves exclusively from a recorded bundle and never touches the network.
+///
+/// Requests that were not recorded fail with an error naming the missing request.
+#[derive(Debug, Clone)]
+pub struct BundleForkSource {
+    path: String,
+    header: ForkBundleHeader,
+    responses: HashMap<String, serde_json::Value>,
+}
+
+impl BundleForkSource {
+    /// Reads the bundle at `path`.
+    pub fn open(path: &str) -> eyre::Result<Self> {
+        let file =
+            File::open(path).wrap_err_with(|| format!("failed opening fork bundle {}", path))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines
+            .next()
+            .ok_or_else(|| eyre!("fork bundle {} is empty", path))??;
+        let header: ForkBundleHeader = serde_json::from_str(&header_line)
+            .wrap_err_with(|| format!("failed parsing header of fork bundle {}", path))?;
+        if header.version != FORK_BUNDLE_VERSION {
+            return Err(eyre!(
+                "fork bundle {} has unsupported version {}, expected {}",
+                path,
+                header.version,
+                FORK_BUNDLE_VERSION
+            ));
+        }
+
+        let mut responses = HashMap::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: ForkBundleRecord = serde_json::from_str(&line).wrap_err_with(|| {
+                format!("failed parsing record #{} of fork bundle {}", index, path)
+            })?;
+            responses.insert(record.request, record.response);
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            header,
+            responses,
+        })
+    }
+
+    pub fn header(&self) -> &ForkBundleHeader {
+        &self.header
+    }
+
+    /// Converts the bundle into [ForkDetails] that are served from it.
+    pub fn into_fork_details(self, cache_config: &CacheConfig) -> eyre::Result<ForkDetails> {
+        let header = self.header.clone();
+        let chain_id = L2ChainId::try_from(header.chain_id)
+            .map_err(|err| eyre!("erroneous chain id {}: {:?}", header.chain_id, err))?;
+        let overwrite_chain_id = header
+            .overwrite_chain_id
+            .map(L2ChainId::try_from)
+            .transpose()
+            .map_err(|err| eyre!("erroneous chain id: {:?}", err))?;
+
+        Ok(ForkDetails {
+            fork_source: Arc::new(self),
+            chain_id,
+            l1_block: header.l1_block,
+            l2_block: header.l2_block,
+            l2_miniblock: header.l2_miniblock,
+            l2_miniblock_hash: header.l2_miniblock_hash,
+            block_timestamp: header.block_timestamp,
+            overwrite_chain_id,
+            l1_gas_price: header.l1_gas_price,
+            l2_fair_gas_price: header.l2_fair_gas_price,
+            fair_pubdata_price: header.fair_pubdata_price,
+            estimate_gas_price_scale_factor: header.estimate_gas_price_scale_factor,
+            estimate_gas_scale_factor: header.estimate_gas_scale_factor,
+            fee_params: header.fee_params,
+            cache_config: cache_config.clone(),
+        })
+    }
+
+    fn replay<T: DeserializeOwned>(&self, request: String) -> eyre::Result<T> {
+        let response = self
+            .responses
+            .get(&request)
+            .ok_or_else(|| eyre!("request {} is not in fork bundle {}", request, self.path))?;
+        serde_json::from_value(response.clone()).wrap_err_with(|| {
+            format!(
+                "failed decoding response to {} from fork bundle {}",
+                request, self.path
+            )
+        })
+    }
+}
+
+impl ForkSource for BundleForkSource {
+    fn get_fork_url(&self) -> eyre::Result<String> {
+        Ok(self.header.fork_url.clone())
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        idx: U256,
+        block: Option<BlockIdVariant>,
+    ) -> eyre::Result<H256> {
+        self.replay(request_key("get_storage_at", (address, idx, &block)))
+    }
+
+    fn get_bytecode_by_hash(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
+        self.replay(request_key("get_bytecode_by_hash", (hash,)))
+    }
+
+    fn get_transaction_by_hash(&self, hash: H256) -> eyre::Result<Option<Transaction>> {
+        self.replay(request_key("get_transaction_by_hash", (hash,)))
+    }
+
+    fn get_transaction_details(&self, hash: H256) -> eyre::Result<Option<TransactionDetails>> {
+        self.replay(request_key("get_transaction_details", (hash,)))
+    }
+
+    fn get_raw_block_transactions(
+        &self,
+        block_number: L2BlockNumber,
+    ) -> eyre::Result<Vec<zksync_types::Transaction>> {
+        self.replay(request_key("get_raw_block_transactions", (block_number,)))
+    }
+
+    fn get_block_by_hash(
+        &self,
+        hash: H256,
+        full_transactions: bool,
+    ) -> eyre::Result<Option<Block<TransactionVariant>>> {
+        self.replay(request_key("get_block_by_hash", (hash, full_transactions)))
+    }
+
+    fn get_block_by_number(
+        &self,
+        block_number: BlockNumber,
+        full_transactions: bool,
+    ) -> eyre::Result<Option<Block<TransactionVariant>>> {
+        self.replay(request_key(
+            "get_block_by_number",
+            (block_number, full_transactions),
+        ))
+    }
+
+    fn get_block_details(&self, miniblock: L2BlockNumber) -> eyre::Result<Option<BlockDetails>> {
+        self.replay(request_key("get_block_details", (miniblock,)))
+    }
+
+    fn get_fee_params(&self) -> eyre::Result<FeeParams> {
+        self.replay(request_key("get_fee_params", ()))
+    }
+
+    fn get_block_transaction_count_by_hash(&self, block_hash: H256) -> eyre::Result<Option<U256>> {
+        self.replay(request_key(
+            "get_block_transaction_count_by_hash",
+            (block_hash,),
+        ))
+    }
+
+    fn get_block_transaction_count_by_number(
+        &self,
+        block_number: BlockNumber,
+    ) -> eyre::Result<Option<U256>> {
+        self.replay(request_key(
+            "get_block_transaction_count_by_number",
+            (block_number,),
+        ))
+    }
+
+    fn get_transaction_by_block_hash_and_index(
+        &self,
+        block_hash: H256,
+        index: Index,
+    ) -> eyre::Result<Option<Transaction>> {
+        self.replay(request_key(
+            "get_transaction_by_block_hash_and_index",
+            (block_hash, index),
+        ))
+    }
+
+    fn get_transaction_by_block_number_and_index(
+        &self,
+        block_number: BlockNumber,
+        index: Index,
+    ) -> eyre::Result<Option<Transaction>> {
+        self.replay(request_key(
+            "get_transaction_by_block_number_and_index",
+            (block_number, index),
+        ))
+    }
+
+    fn get_bridge_contracts(&self) -> eyre::Result<BridgeAddresses> {
+        self.replay(request_key("get_bridge_contracts", ()))
+    }
+
+    fn get_confirmed_tokens(&self, from: u32, limit: u8) -> eyre::Result<Vec<Token>> {
+        self.replay(request_key("get_confirmed_tokens", (from, limit)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps::InMemoryStorage, testing};
+    use tempdir::TempDir;
+    use zksync_types::{AccountTreeId, StorageKey};
+    use zksync_utils::u256_to_h256;
+
+    fn test_header() -> ForkBundleHeader {
+        ForkBundleHeader {
+            version: FORK_BUNDLE_VERSION,
+            fork_url: "http://localhost:3050".to_string(),
+            chain_id: 260,
+            overwrite_chain_id: None,
+            l1_block: L1BatchNumber(1),
+            l2_block: Block::<TransactionVariant>::default(),
+            l2_miniblock: 10,
+            l2_miniblock_hash: H256::repeat_byte(0x1),
+            block_timestamp: 1000,
+            l1_gas_price: 100,
+            l2_fair_gas_price: 200,
+            fair_pubdata_price: 300,
+            estimate_gas_price_scale_factor: 1.5,
+            estimate_gas_scale_factor: 1.3,
+            fee_params: None,
+        }
+    }
+
+    #[test]
+    fn test_recorded_bundle_replays_reads_offline() {
+        let dir = TempDir::new("fork-bundle-test").expect("failed creating temporary dir");
+        let path = dir.path().join("bundle.jsonl");
+        let path = path.to_str().unwrap();
+
+        let address = Address::repeat_byte(0x1);
+        let key = StorageKey::new(AccountTreeId::new(address), u256_to_h256(U256::from(2)));
+        let bytecode_hash = H256::repeat_byte(0x2);
+        let mut raw_storage = InMemoryStorage::default();
+        raw_storage.set_value(key, H256::repeat_byte(0x3));
+        raw_storage.store_factory_dep(bytecode_hash, vec![0x4; 32]);
+
+        let recorder = RecordingForkSource::new(
+            Box::new(testing::ExternalStorage {
+                raw_storage,
+                read_delay: Default::default(),
+                transactions: Default::default(),
+            }),
+            &test_header(),
+            path,
+        )
+        .expect("failed creating recorder");
+        let block = Some(BlockIdVariant::BlockNumber(BlockNumber::Number(10.into())));
+        let value = recorder
+            .get_storage_at(address, U256::from(2), block.clone())
+            .expect("failed reading storage");
+        // Repeated reads are recorded only once.
+        recorder
+            .get_storage_at(address, U256::from(2), block.clone())
+            .expect("failed reading storage");
+        let bytecode = recorder
+            .get_bytecode_by_hash(bytecode_hash)
+            .expect("failed reading bytecode");
+        drop(recorder);
+
+        let bundle = BundleForkSource::open(path).expect("failed opening bundle");
+        assert_eq!(2, bundle.responses.len());
+        assert_eq!(
+            value,
+            bundle
+                .get_storage_at(address, U256::from(2), block)
+                .expect("storage read must be replayed")
+        );
+        assert_eq!(
+            bytecode,
+            bundle
+                .get_bytecode_by_hash(bytecode_hash)
+                .expect("bytecode read must be replayed")
+        );
+        assert_eq!(
+            "http://localhost:3050",
+            bundle.get_fork_url().expect("failed getting fork url")
+        );
+
+        let error = bundle
+            .get_storage_at(address, U256::from(3), None)
+            .expect_err("unrecorded read must fail");
+        assert!(error.to_string().contains("is not in fork bundle"));
+    }
+
+    #[test]
+    fn test_bundle_into_fork_details() {
+        let dir = TempDir::new("fork-bundle-test").expect("failed creating temporary dir");
+        let path = dir.path().join("bundle.jsonl");
+        let path = path.to_str().unwrap();
+
+        drop(
+            RecordingForkSource::new(
+                Box::new(testing::ExternalStorage {
+                    raw_storage: InMemoryStorage::default(),
+                    read_delay: Default::default(),
+                    transactions: Default::default(),
+                }),
+                &test_header(),
+                path,
+            )
+            .expect("failed creating recorder"),
+        );
+
+        let fork_details = BundleForkSource::open(path)
+            .expect("failed opening bundle")
+            .into_fork_details(&CacheConfig::None)
+            .expect("failed converting bundle");
+        assert_eq!(260, fork_details.chain_id.as_u64());
+        assert_eq!(10, fork_details.l2_miniblock);
+        assert_eq!(H256::repeat_byte(0x1), fork_details.l2_miniblock_hash);
+        assert_eq!(200, fork_details.l2_fair_gas_price);
+        assert_eq!(
+            "http://localhost:3050",
+            fork_details
+                .fork_source
+                .get_fork_url()
+                .expect("failed getting fork url")
+        );
+    }
+}
@@ -0,0 +1,636 @@
+//! Request layer used by [HttpForkSource](crate::http_fork_source::HttpForkSource) to talk to the forked network.
+//!
+//! Requests that fail transiently (connection errors, timeouts, rate limiting and server errors) are retried with
+//! exponential backoff, rotating across all configured endpoints. Storage reads are additionally coalesced and
+//! batched: identical concurrent reads share a single request, and reads issued while another batch is in flight
+//! are sent together as one JSON-RPC batch.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use eyre::eyre;
+use serde::de::DeserializeOwned;
+use zksync_types::{api::BlockIdVariant, url::SensitiveUrl, Address, H256, U256};
+use zksync_web3_decl::{
+    client::{Client, L2},
+    jsonrpsee::core::ClientError,
+};
+
+use crate::fork::block_on;
+
+/// Default number of retries of a transiently failed request.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Default delay before the first retry; doubled on every following retry.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+/// Default upper bound of the delay between retries.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+/// Default maximum number of storage reads sent in one JSON-RPC batch.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// JSON-RPC error codes used by providers to signal rate limiting.
+const RATE_LIMIT_ERROR_CODES: [i64; 2] = [429, -32005];
+
+/// Retry policy of the [ForkClient].
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Returns the delay before the retry following `attempt` failed attempts.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+/// Error of a raw JSON-RPC request sent by the [ForkClient].
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ForkClientError {
+    #[error("failed sending request to {url}: {message}")]
+    Transport { url: String, message: String },
+    #[error("{url} responded with HTTP status {status}")]
+    Status { url: String, status: u16 },
+    #[error("{url} responded with error {code}: {message}")]
+    Rpc {
+        url: String,
+        code: i64,
+        message: String,
+    },
+    #[error("invalid response from {url}: {message}")]
+    InvalidResponse { url: String, message: String },
+}
+
+impl ForkClientError {
+    /// Whether the request may succeed when retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ForkClientError::Transport { .. } => true,
+            ForkClientError::Status { status, .. } => *status == 429 || *status >= 500,
+            ForkClientError::Rpc { code, .. } => RATE_LIMIT_ERROR_CODES.contains(code),
+            ForkClientError::InvalidResponse { .. } => false,
+        }
+    }
+}
+
+/// Whether a request made with the typed client may succeed when retried.
+fn is_transient_client_error(err: &ClientError) -> bool {
+    match err {
+        ClientError::Transport(_) | ClientError::RequestTimeout => true,
+        ClientError::Call(err) => RATE_LIMIT_ERROR_CODES.contains(&i64::from(err.code())),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+struct PendingRead {
+    /// Number of callers waiting for this read.
+    waiters: usize,
+    result: Option<Result<H256, ForkClientError>>,
+}
+
+#[derive(Debug, Default)]
+struct StorageReads {
+    /// Reads that are queued, in flight or not yet picked up by all waiters, keyed by their JSON-encoded params.
+    reads: HashMap<String, PendingRead>,
+    /// Reads that were not sent yet.
+    queued: Vec<String>,
+    /// Whether a batch is currently in flight.
+    sending: bool,
+}
+
+/// Client for the upstream network of a fork, see the module documentation.
+#[derive(Debug)]
+pub struct ForkClient {
+    urls: Vec<String>,
+    /// Index of the endpoint requests are currently sent to.
+    active: AtomicUsize,
+    retry: RetryConfig,
+    max_batch_size: usize,
+    storage_reads: Mutex<StorageReads>,
+    storage_reads_ready: Condvar,
+    /// Typed clients used by [ForkClient::request_async], kept per endpoint so that consecutive requests share them.
+    async_clients: Mutex<HashMap<usize, Client<L2>>>,
+}
+
+impl ForkClient {
+    /// Creates a client sending requests to the first of `urls`, falling back to the others on transient failures.
+    pub fn new(urls: Vec<String>, retry: RetryConfig) -> Self {
+        assert!(!urls.is_empty(), "fork client requires at least one URL");
+        Self {
+            urls,
+            active: AtomicUsize::new(0),
+            retry,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            storage_reads: Default::default(),
+            storage_reads_ready: Condvar::new(),
+            async_clients: Default::default(),
+        }
+    }
+
+    /// Switches to the endpoint following `failed`, unless another request has switched already.
+    fn rotate(&self, failed: usize) {
+        let next = (failed + 1) % self.urls.len();
+        let _ = self
+            .active
+            .compare_exchange(failed, next, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Returns the endpoints requests fall back to when the first one fails transiently.
+    pub fn fallback_urls(&self) -> &[String] {
+        &self.urls[1..]
+    }
+
+    /// Switches endpoints after `attempt` failed attempts on endpoint `failed` and returns the delay before retrying.
+    fn retry_delay(
+        &self,
+        method: &str,
+        failed: usize,
+        attempt: u32,
+        err: &impl std::fmt::Display,
+    ) -> Duration {
+        let backoff = self.retry.backoff(attempt);
+        tracing::warn!(
+            "{} request to {} failed, retrying in {:?}: {}",
+            method,
+            self.urls[failed],
+            backoff,
+            err
+        );
+        self.rotate(failed);
+        backoff
+    }
+
+    /// Waits before retrying after `attempt` failed attempts on endpoint `failed`.
+    fn backoff(&self, method: &str, failed: usize, attempt: u32, err: &impl std::fmt::Display) {
+        std::thread::sleep(self.retry_delay(method, failed, attempt, err));
+    }
+
+    /// Sends a request with a typed client, retrying it on transient failures.
+    pub fn request<T, Fut>(
+        &self,
+        method: &str,
+        request: impl Fn(Client<L2>) -> Fut,
+    ) -> eyre::Result<T>
+    where
+        T: Send + 'static,
+        Fut: Future<Output = Result<T, ClientError>> + Send + 'static,
+    {
+        let mut attempt = 0;
+        loop {
+            let index = self.active.load(Ordering::Relaxed);
+            let client = create_client(&self.urls[index])?;
+            match block_on(request(client)) {
+                Ok(value) => return Ok(value),
+                Err(err) if is_transient_client_error(&err) && attempt < self.retry.max_retries => {
+                    self.backoff(method, index, attempt, &err);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Same as [ForkClient::request], for callers that are already running in an async context.
+    pub async fn request_async<T, Fut>(
+        &self,
+        method: &str,
+        request: impl Fn(Client<L2>) -> Fut,
+    ) -> eyre::Result<T>
+    where
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let index = self.active.load(Ordering::Relaxed);
+            let client = self.async_client(index)?;
+            match request(client).await {
+                Ok(value) => return Ok(value),
+                Err(err) if is_transient_client_error(&err) && attempt < self.retry.max_retries => {
+                    tokio::time::sleep(self.retry_delay(method, index, attempt, &err)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Returns the typed client of endpoint `index` for [ForkClient::request_async], creating it on first use.
+    fn async_client(&self, index: usize) -> eyre::Result<Client<L2>> {
+        let mut clients = self
+            .async_clients
+            .lock()
+            .map_err(|err| eyre!("failed acquiring clients lock: {:?}", err))?;
+        if let Some(client) = clients.get(&index) {
+            return Ok(client.clone());
+        }
+        let client = create_client(&self.urls[index])?;
+        clients.insert(index, client.clone());
+        Ok(client)
+    }
+
+    /// Reads a storage slot, sharing the request with identical concurrent reads and batching it with other reads
+    /// issued while a batch is in flight.
+    pub fn get_storage_at(
+        &self,
+        address: Address,
+        idx: U256,
+        block: Option<BlockIdVariant>,
+    ) -> eyre::Result<H256> {
+        let read = serde_json::to_string(&(address, idx, block))?;
+
+        let mut state = self
+            .storage_reads
+            .lock()
+            .map_err(|err| eyre!("failed acquiring storage reads lock: {:?}", err))?;
+        match state.reads.get_mut(&read) {
+            Some(pending) => pending.waiters += 1,
+            None => {
+                state.reads.insert(
+                    read.clone(),
+                    PendingRead {
+                        waiters: 1,
+                        result: None,
+                    },
+                );
+                state.queued.push(read.clone());
+            }
+        }
+
+        loop {
+            let pending = state
+                .reads
+                .get_mut(&read)
+                .expect("pending read is only removed by its last waiter");
+            if let Some(result) = pending.result.clone() {
+                pending.waiters -= 1;
+                if pending.waiters == 0 {
+                    state.reads.remove(&read);
+                }
+                return result.map_err(Into::into);
+            }
+
+            if !state.sending && !state.queued.is_empty() {
+                // No batch is in flight, so this caller sends everything queued so far.
+                let batch_size = state.queued.len().min(self.max_batch_size);
+                let batch = state.queued.drain(..batch_size).collect::<Vec<_>>();
+                state.sending = true;
+                drop(state);
+
+                let results = self.send_storage_reads(&batch);
+
+                state = self
+                    .storage_reads
+                    .lock()
+                    .map_err(|err| eyre!("failed acquiring storage reads lock: {:?}", err))?;
+                for (read, result) in batch.into_iter().zip(results) {
+                    if let Some(pending) = state.reads.get_mut(&read) {
+                        pending.result = Some(result);
+                    }
+                }
+                state.sending = false;
+                self.storage_reads_ready.notify_all();
+                continue;
+            }
+
+            state = self
+                .storage_reads_ready
+                .wait(state)
+                .map_err(|err| eyre!("failed waiting for storage reads: {:?}", err))?;
+        }
+    }
+
+    /// Sends `eth_getStorageAt` for all `reads`, retrying the transiently failed ones.
+    fn send_storage_reads(&self, reads: &[String]) -> Vec<Result<H256, ForkClientError>> {
+        let mut results: Vec<Option<Result<H256, ForkClientError>>> = vec![None; reads.len()];
+        let mut attempt = 0;
+        loop {
+            let remaining = (0..reads.len())
+                .filter(|index| results[*index].is_none())
+                .collect::<Vec<_>>();
+            let index = self.active.load(Ordering::Relaxed);
+            let url = self.urls[index].clone();
+            let params = remaining
+                .iter()
+                .map(|read| reads[*read].clone())
+                .collect::<Vec<_>>();
+            let can_retry = attempt < self.retry.max_retries;
+
+            let mut retry_error = None;
+            match block_on(send_batch(url, "eth_getStorageAt", params)) {
+                Ok(responses) => {
+                    for (read, response) in remaining.into_iter().zip(responses) {
+                        match response {
+                            Err(err) if err.is_transient() && can_retry => retry_error = Some(err),
+                            response => results[read] = Some(response),
+                        }
+                    }
+                }
+                Err(err) if err.is_transient() && can_retry => retry_error = Some(err),
+                Err(err) => {
+                    for read in remaining {
+                        results[read] = Some(Err(err.clone()));
+                    }
+                }
+            }
+
+            match retry_error {
+                Some(err) => {
+                    self.backoff("eth_getStorageAt", index, attempt, &err);
+                    attempt += 1;
+                }
+                None => {
+                    return results
+                        .into_iter()
+                        .map(|result| result.expect("all reads are resolved"))
+                        .collect()
+                }
+            }
+        }
+    }
+}
+
+fn create_client(url: &str) -> eyre::Result<Client<L2>> {
+    let parsed_url =
+        SensitiveUrl::from_str(url).map_err(|_| eyre!("Unable to parse client URL: {}", url))?;
+    Ok(Client::http(parsed_url)
+        .map_err(|_| eyre!("Unable to create a client for fork: {}", url))?
+        .build())
+}
+
+/// Sends `method` once per JSON-encoded `params` to `url`, as a JSON-RPC batch if there is more than one request.
+///
+/// The outer error is returned if the whole request failed, the inner ones are per-request results.
+async fn send_batch<T: DeserializeOwned>(
+    url: String,
+    method: &'static str,
+    params: Vec<String>,
+) -> Result<Vec<Result<T, ForkClientError>>, ForkClientError> {
+    let requests = params
+        .iter()
+        .enumerate()
+        .map(|(id, params)| {
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": serde_json::from_str::<serde_json::Value>(params)
+                    .expect("request params are valid JSON"),
+            })
+        })
+        .collect::<Vec<_>>();
+    let body = if requests.len() == 1 {
+        requests[0].clone()
+    } else {
+        serde_json::Value::Array(requests)
+    };
+
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|err| ForkClientError::Transport {
+            url: url.clone(),
+            message: err.to_string(),
+        })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(ForkClientError::Status {
+            url: url.clone(),
+            status: status.as_u16(),
+        });
+    }
+    let response = response
+        .bytes()
+        .await
+        .map_err(|err| ForkClientError::Transport {
+            url: url.clone(),
+            message: err.to_string(),
+        })?;
+    let response: serde_json::Value =
+        serde_json::from_slice(&response).map_err(|err| invalid_response(&url, err.to_string()))?;
+
+    let responses = match response {
+        serde_json::Value::Array(responses) => responses,
+        // A single error object in response to a batch means the whole batch failed.
+        response if params.len() > 1 => {
+            return Err(response_result::<T>(&url, &response)
+                .err()
+                .unwrap_or_else(|| invalid_response(&url, "expected a batch response")))
+        }
+        response => vec![response],
+    };
+
+    let mut results = HashMap::new();
+    for response in responses {
+        let id = response
+            .get("id")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| invalid_response(&url, "response without id"))?;
+        results.insert(id, response_result(&url, &response));
+    }
+    Ok((0..params.len() as u64)
+        .map(|id| {
+            results
+                .remove(&id)
+                .unwrap_or_else(|| Err(invalid_response(&url, format!("missing response {}", id))))
+        })
+        .collect())
+}
+
+/// Extracts the result of a single JSON-RPC response.
+fn response_result<T: DeserializeOwned>(
+    url: &str,
+    response: &serde_json::Value,
+) -> Result<T, ForkClientError> {
+    if let Some(error) = response.get("error") {
+        return Err(ForkClientError::Rpc {
+            url: url.to_string(),
+            code: error
+                .get("code")
+                .and_then(serde_json::Value::as_i64)
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+
+    let result = response
+        .get("result")
+        .ok_or_else(|| invalid_response(url, "response without result"))?;
+    serde_json::from_value(result.clone()).map_err(|err| invalid_response(url, err.to_string()))
+}
+
+fn invalid_response(url: &str, message: impl Into<String>) -> ForkClientError {
+    ForkClientError::InvalidResponse {
+        url: url.to_string(),
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use httptest::{matchers::request, responders::status_code, Expectation};
+    use zksync_types::{H160, U64};
+
+    fn no_backoff() -> RetryConfig {
+        RetryConfig {
+            max_retries: 2,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_max() {
+        let retry = RetryConfig {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+
+        assert_eq!(Duration::from_millis(100), retry.backoff(0));
+        assert_eq!(Duration::from_millis(200), retry.backoff(1));
+        assert_eq!(Duration::from_millis(400), retry.backoff(2));
+        assert_eq!(Duration::from_millis(500), retry.backoff(3));
+    }
+
+    #[test]
+    fn test_storage_reads_are_sent_as_batch() {
+        let address = H160::repeat_byte(0x1);
+        let mock_server = testing::MockServer::run();
+        mock_server.expect(
+            serde_json::json!([
+                {
+                    "jsonrpc": "2.0",
+                    "id": 0,
+                    "method": "eth_getStorageAt",
+                    "params": [format!("{:#x}", address), "0x0", "0x8"],
+                },
+                {
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "eth_getStorageAt",
+                    "params": [format!("{:#x}", address), "0x1", "0x8"],
+                },
+            ]),
+            // Responses to a batch may come in any order.
+            serde_json::json!([
+                {
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "result": format!("{:#x}", H256::repeat_byte(0x2)),
+                },
+                {
+                    "jsonrpc": "2.0",
+                    "id": 0,
+                    "result": format!("{:#x}", H256::repeat_byte(0x1)),
+                },
+            ]),
+        );
+
+        let fork_client = ForkClient::new(vec![mock_server.url()], no_backoff());
+        let block =
+            BlockIdVariant::BlockNumber(zksync_types::api::BlockNumber::Number(U64::from(8)));
+        let reads = [U256::zero(), U256::one()]
+            .iter()
+            .map(|idx| serde_json::to_string(&(address, idx, Some(block.clone()))).unwrap())
+            .collect::<Vec<_>>();
+
+        let results = fork_client.send_storage_reads(&reads);
+
+        assert_eq!(
+            vec![H256::repeat_byte(0x1), H256::repeat_byte(0x2)],
+            results
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .expect("storage reads failed")
+        );
+    }
+
+    #[test]
+    fn test_transient_failure_falls_back_to_next_url() {
+        let address = H160::repeat_byte(0x1);
+        let failing_server = testing::MockServer::run();
+        failing_server
+            .inner
+            .expect(Expectation::matching(request::method("POST")).respond_with(status_code(503)));
+        let mock_server = testing::MockServer::run();
+        mock_server.expect(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "eth_getStorageAt",
+                "params": [format!("{:#x}", address), "0x0", null],
+            }),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": format!("{:#x}", H256::repeat_byte(0x1)),
+            }),
+        );
+
+        let fork_client =
+            ForkClient::new(vec![failing_server.url(), mock_server.url()], no_backoff());
+        let value = fork_client
+            .get_storage_at(address, U256::zero(), None)
+            .expect("storage read failed");
+
+        assert_eq!(H256::repeat_byte(0x1), value);
+        assert_eq!(1, fork_client.active.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_rpc_error_is_not_retried() {
+        let address = H160::repeat_byte(0x1);
+        let mock_server = testing::MockServer::run();
+        mock_server.expect(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "eth_getStorageAt",
+                "params": [format!("{:#x}", address), "0x0", null],
+            }),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "error": { "code": -32602, "message": "invalid params" },
+            }),
+        );
+
+        let fork_client = ForkClient::new(vec![mock_server.url()], no_backoff());
+        let error = fork_client
+            .get_storage_at(address, U256::zero(), None)
+            .expect_err("storage read must fail");
+
+        assert!(error.to_string().contains("invalid params"));
+    }
+}
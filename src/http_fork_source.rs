@@ -1,25 +1,21 @@
-use std::{
-    str::FromStr,
-    sync::{Arc, RwLock},
-};
+use std::sync::{Arc, RwLock};
 
 use crate::{
     cache::Cache,
     config::cache::CacheConfig,
-    fork::{block_on, ForkSource},
+    fork::ForkSource,
+    fork_client::{ForkClient, RetryConfig},
+    metrics::METRICS,
 };
 use eyre::Context;
-use zksync_types::{
-    api::{BridgeAddresses, Transaction},
-    url::SensitiveUrl,
-};
+use zksync_types::api::{BlockIdVariant, BlockNumber, BridgeAddresses, Transaction};
 use zksync_types::{H256, U256};
+use zksync_utils::u256_to_h256;
+use zksync_web3_decl::types::Token;
 use zksync_web3_decl::{
-    client::Client,
     namespaces::{EthNamespaceClient, ZksNamespaceClient},
     types::Index,
 };
-use zksync_web3_decl::{client::L2, types::Token};
 
 #[derive(Debug, Clone)]
 /// Fork source that gets the data via HTTP requests.
@@ -28,22 +24,32 @@ pub struct HttpForkSource {
     pub fork_url: String,
     /// Cache for network data.
     pub(crate) cache: Arc<RwLock<Cache>>,
+    /// Request layer retrying, batching and rotating requests across endpoints.
+    pub(crate) fork_client: Arc<ForkClient>,
 }
 
 impl HttpForkSource {
     pub fn new(fork_url: String, cache_config: CacheConfig) -> Self {
         Self {
+            fork_client: Arc::new(ForkClient::new(
+                vec![fork_url.clone()],
+                RetryConfig::default(),
+            )),
+            cache: Arc::new(RwLock::new(Cache::with_namespace(
+                cache_config,
+                fork_url.clone(),
+            ))),
             fork_url,
-            cache: Arc::new(RwLock::new(Cache::new(cache_config))),
         }
     }
 
-    pub fn create_client(&self) -> Client<L2> {
-        let url = SensitiveUrl::from_str(&self.fork_url)
-            .unwrap_or_else(|_| panic!("Unable to parse client URL: {}", &self.fork_url));
-        Client::http(url)
-            .unwrap_or_else(|_| panic!("Unable to create a client for fork: {}", self.fork_url))
-            .build()
+    /// Adds endpoints of the same network that requests fall back to when the fork URL fails transiently.
+    pub fn with_fallback_urls(mut self, fallback_urls: Vec<String>) -> Self {
+        let urls = std::iter::once(self.fork_url.clone())
+            .chain(fallback_urls)
+            .collect();
+        self.fork_client = Arc::new(ForkClient::new(urls, RetryConfig::default()));
+        self
     }
 }
 
@@ -58,15 +64,71 @@ impl ForkSource for HttpForkSource {
         idx: zksync_types::U256,
         block: Option<zksync_types::api::BlockIdVariant>,
     ) -> eyre::Result<zksync_types::H256> {
-        let client = self.create_client();
-        block_on(async move { client.get_storage_at(address, idx, block).await })
+        // Only values at a fixed block number are immutable and therefore safe to cache.
+        let block_number = match &block {
+            Some(BlockIdVariant::BlockNumber(BlockNumber::Number(number))) => Some(number.as_u64()),
+            _ => None,
+        };
+        let key = u256_to_h256(idx);
+
+        if let Some(value) = block_number.and_then(|number| {
+            self.cache
+                .read()
+                .ok()
+                .and_then(|guard| guard.get_storage_value(number, &address, &key).cloned())
+        }) {
+            tracing::debug!("using cached storage value for {address:#x} at {key:#x}");
+            return Ok(value);
+        }
+
+        METRICS.fork_requests[&"get_storage_at"].inc();
+        self.fork_client
+            .get_storage_at(address, idx, block)
             .wrap_err("fork http client failed")
+            .inspect(|value| {
+                if let Some(number) = block_number {
+                    self.cache
+                        .write()
+                        .map(|mut guard| guard.insert_storage_value(number, address, key, *value))
+                        .unwrap_or_else(|err| {
+                            tracing::warn!(
+                                "failed writing to cache for 'get_storage_at': {:?}",
+                                err
+                            )
+                        });
+                }
+            })
     }
 
     fn get_bytecode_by_hash(&self, hash: zksync_types::H256) -> eyre::Result<Option<Vec<u8>>> {
-        let client = self.create_client();
-        block_on(async move { client.get_bytecode_by_hash(hash).await })
+        if let Ok(Some(bytecode)) = self
+            .cache
+            .read()
+            .map(|guard| guard.get_factory_dep(&hash).cloned())
+        {
+            tracing::debug!("using cached bytecode for {hash}");
+            return Ok(Some(bytecode));
+        }
+
+        METRICS.fork_requests[&"get_bytecode_by_hash"].inc();
+        self.fork_client
+            .request("get_bytecode_by_hash", move |client| async move {
+                client.get_bytecode_by_hash(hash).await
+            })
             .wrap_err("fork http client failed")
+            .inspect(|maybe_bytecode| {
+                if let Some(bytecode) = &maybe_bytecode {
+                    self.cache
+                        .write()
+                        .map(|mut guard| guard.insert_factory_dep(hash, bytecode.clone()))
+                        .unwrap_or_else(|err| {
+                            tracing::warn!(
+                                "failed writing to cache for 'get_bytecode_by_hash': {:?}",
+                                err
+                            )
+                        });
+                }
+            })
     }
 
     fn get_transaction_by_hash(
@@ -82,8 +144,11 @@ impl ForkSource for HttpForkSource {
             return Ok(Some(transaction));
         }
 
-        let client = self.create_client();
-        block_on(async move { client.get_transaction_by_hash(hash).await })
+        METRICS.fork_requests[&"get_transaction_by_hash"].inc();
+        self.fork_client
+            .request("get_transaction_by_hash", move |client| async move {
+                client.get_transaction_by_hash(hash).await
+            })
             .inspect(|maybe_transaction| {
                 if let Some(transaction) = &maybe_transaction {
                     self.cache
@@ -104,11 +169,14 @@ impl ForkSource for HttpForkSource {
         &self,
         hash: H256,
     ) -> eyre::Result<Option<zksync_types::api::TransactionDetails>> {
-        let client = self.create_client();
+        METRICS.fork_requests[&"get_transaction_details"].inc();
         // n.b- We don't cache these responses as they will change through the lifecycle of the transaction
         // and caching could be error-prone. in theory we could cache responses once the txn status
         // is `final` or `failed` but currently this does not warrant the additional complexity.
-        block_on(async move { client.get_transaction_details(hash).await })
+        self.fork_client
+            .request("get_transaction_details", move |client| async move {
+                client.get_transaction_details(hash).await
+            })
             .wrap_err("fork http client failed")
     }
 
@@ -126,8 +194,11 @@ impl ForkSource for HttpForkSource {
             return Ok(transaction);
         }
 
-        let client = self.create_client();
-        block_on(async move { client.get_raw_block_transactions(block_number).await })
+        METRICS.fork_requests[&"get_raw_block_transactions"].inc();
+        self.fork_client
+            .request("get_raw_block_transactions", move |client| async move {
+                client.get_raw_block_transactions(block_number).await
+            })
             .wrap_err("fork http client failed")
             .inspect(|transactions| {
                 if !transactions.is_empty() {
@@ -160,8 +231,11 @@ impl ForkSource for HttpForkSource {
             return Ok(Some(block));
         }
 
-        let client = self.create_client();
-        block_on(async move { client.get_block_by_hash(hash, full_transactions).await })
+        METRICS.fork_requests[&"get_block_by_hash"].inc();
+        self.fork_client
+            .request("get_block_by_hash", move |client| async move {
+                client.get_block_by_hash(hash, full_transactions).await
+            })
             .inspect(|block| {
                 if let Some(block) = &block {
                     self.cache
@@ -199,34 +273,41 @@ impl ForkSource for HttpForkSource {
             return Ok(Some(block));
         }
 
-        let client = self.create_client();
-        block_on(async move {
-            client
-                .get_block_by_number(block_number, full_transactions)
-                .await
-        })
-        .inspect(|block| {
-            if let Some(block) = &block {
-                self.cache
-                    .write()
-                    .map(|mut guard| {
-                        guard.insert_block(block.hash, full_transactions, block.clone())
-                    })
-                    .unwrap_or_else(|err| {
-                        tracing::warn!(
-                            "failed writing to cache for 'get_block_by_number': {:?}",
-                            err
-                        )
-                    });
-            }
-        })
-        .wrap_err("fork http client failed")
+        METRICS.fork_requests[&"get_block_by_number"].inc();
+        self.fork_client
+            .request("get_block_by_number", move |client| async move {
+                client
+                    .get_block_by_number(block_number, full_transactions)
+                    .await
+            })
+            .inspect(|block| {
+                if let Some(block) = &block {
+                    self.cache
+                        .write()
+                        .map(|mut guard| {
+                            guard.insert_block(block.hash, full_transactions, block.clone())
+                        })
+                        .unwrap_or_else(|err| {
+                            tracing::warn!(
+                                "failed writing to cache for 'get_block_by_number': {:?}",
+                                err
+                            )
+                        });
+                }
+            })
+            .wrap_err("fork http client failed")
     }
 
     /// Returns the  transaction count for a given block hash.
     fn get_block_transaction_count_by_hash(&self, block_hash: H256) -> eyre::Result<Option<U256>> {
-        let client = self.create_client();
-        block_on(async move { client.get_block_transaction_count_by_hash(block_hash).await })
+        METRICS.fork_requests[&"get_block_transaction_count_by_hash"].inc();
+        self.fork_client
+            .request(
+                "get_block_transaction_count_by_hash",
+                move |client| async move {
+                    client.get_block_transaction_count_by_hash(block_hash).await
+                },
+            )
             .wrap_err("fork http client failed")
     }
 
@@ -235,13 +316,17 @@ impl ForkSource for HttpForkSource {
         &self,
         block_number: zksync_types::api::BlockNumber,
     ) -> eyre::Result<Option<U256>> {
-        let client = self.create_client();
-        block_on(async move {
-            client
-                .get_block_transaction_count_by_number(block_number)
-                .await
-        })
-        .wrap_err("fork http client failed")
+        METRICS.fork_requests[&"get_block_transaction_count_by_number"].inc();
+        self.fork_client
+            .request(
+                "get_block_transaction_count_by_number",
+                move |client| async move {
+                    client
+                        .get_block_transaction_count_by_number(block_number)
+                        .await
+                },
+            )
+            .wrap_err("fork http client failed")
     }
 
     /// Returns information about a transaction by block hash and transaction index position.
@@ -250,13 +335,17 @@ impl ForkSource for HttpForkSource {
         block_hash: H256,
         index: Index,
     ) -> eyre::Result<Option<Transaction>> {
-        let client = self.create_client();
-        block_on(async move {
-            client
-                .get_transaction_by_block_hash_and_index(block_hash, index)
-                .await
-        })
-        .wrap_err("fork http client failed")
+        METRICS.fork_requests[&"get_transaction_by_block_hash_and_index"].inc();
+        self.fork_client
+            .request(
+                "get_transaction_by_block_hash_and_index",
+                move |client| async move {
+                    client
+                        .get_transaction_by_block_hash_and_index(block_hash, index)
+                        .await
+                },
+            )
+            .wrap_err("fork http client failed")
     }
 
     /// Returns information about a transaction by block number and transaction index position.
@@ -265,13 +354,17 @@ impl ForkSource for HttpForkSource {
         block_number: zksync_types::api::BlockNumber,
         index: Index,
     ) -> eyre::Result<Option<Transaction>> {
-        let client = self.create_client();
-        block_on(async move {
-            client
-                .get_transaction_by_block_number_and_index(block_number, index)
-                .await
-        })
-        .wrap_err("fork http client failed")
+        METRICS.fork_requests[&"get_transaction_by_block_number_and_index"].inc();
+        self.fork_client
+            .request(
+                "get_transaction_by_block_number_and_index",
+                move |client| async move {
+                    client
+                        .get_transaction_by_block_number_and_index(block_number, index)
+                        .await
+                },
+            )
+            .wrap_err("fork http client failed")
     }
 
     /// Returns details of a block, given miniblock number
@@ -279,17 +372,25 @@ impl ForkSource for HttpForkSource {
         &self,
         miniblock: zksync_types::L2BlockNumber,
     ) -> eyre::Result<Option<zksync_types::api::BlockDetails>> {
-        let client = self.create_client();
-        block_on(async move { client.get_block_details(miniblock).await }).wrap_err(format!(
-            "Failed to get block details for {} l2 block in fork http client",
-            miniblock
-        ))
+        METRICS.fork_requests[&"get_block_details"].inc();
+        self.fork_client
+            .request("get_block_details", move |client| async move {
+                client.get_block_details(miniblock).await
+            })
+            .wrap_err(format!(
+                "Failed to get block details for {} l2 block in fork http client",
+                miniblock
+            ))
     }
 
     /// Returns fee parameters for the give source.
     fn get_fee_params(&self) -> eyre::Result<zksync_types::fee_model::FeeParams> {
-        let client = self.create_client();
-        block_on(async move { client.get_fee_params().await }).wrap_err("fork http client failed")
+        METRICS.fork_requests[&"get_fee_params"].inc();
+        self.fork_client
+            .request("get_fee_params", move |client| async move {
+                client.get_fee_params().await
+            })
+            .wrap_err("fork http client failed")
     }
 
     /// Returns addresses of the default bridge contracts.
@@ -304,8 +405,11 @@ impl ForkSource for HttpForkSource {
             return Ok(bridge_addresses);
         };
 
-        let client = self.create_client();
-        block_on(async move { client.get_bridge_contracts().await })
+        METRICS.fork_requests[&"get_bridge_contracts"].inc();
+        self.fork_client
+            .request("get_bridge_contracts", move |client| async move {
+                client.get_bridge_contracts().await
+            })
             .inspect(|bridge_addresses| {
                 self.cache
                     .write()
@@ -332,8 +436,11 @@ impl ForkSource for HttpForkSource {
             return Ok(confirmed_tokens);
         };
 
-        let client = self.create_client();
-        block_on(async move { client.get_confirmed_tokens(from, limit).await })
+        METRICS.fork_requests[&"get_confirmed_tokens"].inc();
+        self.fork_client
+            .request("get_confirmed_tokens", move |client| async move {
+                client.get_confirmed_tokens(from, limit).await
+            })
             .inspect(|confirmed_tokens| {
                 self.cache
                     .write()
@@ -776,4 +883,79 @@ mod tests {
             .expect("failed fetching tokens");
         assert_eq!(tokens.len(), 1);
     }
+
+    #[test]
+    fn test_get_storage_at_fixed_block_is_cached() {
+        let input_address = H160::repeat_byte(0x1);
+        let input_storage_value = H256::repeat_byte(0xcd);
+
+        let mock_server = testing::MockServer::run();
+        mock_server.expect(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "eth_getStorageAt",
+                "params": [
+                    format!("{:#x}", input_address),
+                    "0x0",
+                    "0x8",
+                ],
+            }),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": format!("{:#x}", input_storage_value),
+            }),
+        );
+
+        let fork_source = HttpForkSource::new(mock_server.url(), CacheConfig::Memory);
+        let block = Some(BlockIdVariant::BlockNumber(BlockNumber::Number(U64::from(
+            8,
+        ))));
+
+        let actual_value = fork_source
+            .get_storage_at(input_address, U256::zero(), block.clone())
+            .expect("failed fetching storage value");
+        assert_eq!(input_storage_value, actual_value);
+
+        let actual_value = fork_source
+            .get_storage_at(input_address, U256::zero(), block)
+            .expect("failed fetching cached storage value");
+        assert_eq!(input_storage_value, actual_value);
+    }
+
+    #[test]
+    fn test_get_bytecode_by_hash_is_cached() {
+        let input_hash = H256::repeat_byte(0x1);
+        let input_bytecode = vec![0x1, 0x2, 0x3];
+
+        let mock_server = testing::MockServer::run();
+        mock_server.expect(
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "zks_getBytecodeByHash",
+                "params": [
+                    format!("{:#x}", input_hash)
+                ],
+            }),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": input_bytecode,
+            }),
+        );
+
+        let fork_source = HttpForkSource::new(mock_server.url(), CacheConfig::Memory);
+
+        let actual_bytecode = fork_source
+            .get_bytecode_by_hash(input_hash)
+            .expect("failed fetching bytecode");
+        assert_eq!(Some(input_bytecode.clone()), actual_bytecode);
+
+        let actual_bytecode = fork_source
+            .get_bytecode_by_hash(input_hash)
+            .expect("failed fetching cached bytecode");
+        assert_eq!(Some(input_bytecode), actual_bytecode);
+    }
 }
@@ -0,0 +1,391 @@
+//! Machine-readable stream of node activity.
+//!
+//! When enabled via `--json-log <FILE>`, the node appends one JSON object per line (NDJSON) for every
+//! sealed block and every executed transaction, together with its calls, events, `console.log`
+//! invocations and storage writes. Passing `-` as the file name writes the stream to stdout.
+//!
+//! Every record carries a `type` field identifying its shape. The schema is versioned via
+//! [JSON_LOG_SCHEMA_VERSION] and fields are only ever added, never renamed or removed, within a version.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use zksync_multivm::interface::{Call, CallType, ExecutionResult, VmExecutionResultAndLogs};
+use zksync_types::api::{Block, TransactionVariant};
+use zksync_types::web3::Bytes;
+use zksync_types::{Address, StorageLogKind, Transaction, H256, U256, U64};
+
+/// Version of the emitted record schema.
+pub const JSON_LOG_SCHEMA_VERSION: u8 = 1;
+
+/// File name that makes the stream go to stdout instead of a file.
+pub const JSON_LOG_STDOUT: &str = "-";
+
+/// A single line of the JSON log stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonLogRecord {
+    Block(BlockRecord),
+    Transaction(TransactionRecord),
+    Call(CallRecord),
+    Event(EventRecord),
+    ConsoleLog(ConsoleLogRecord),
+    StorageWrite(StorageWriteRecord),
+    ForkSelected(ForkSelectedRecord),
+}
+
+/// A sealed L2 block.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockRecord {
+    pub schema_version: u8,
+    pub number: U64,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub l1_batch_number: Option<U64>,
+    pub timestamp: U256,
+    pub gas_used: U256,
+    pub base_fee_per_gas: U256,
+    pub transactions: Vec<H256>,
+}
+
+/// A transaction that was executed and included in a block.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionRecord {
+    pub schema_version: u8,
+    pub hash: H256,
+    pub block_number: U64,
+    pub l1_batch_number: u32,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub nonce: Option<u32>,
+    /// One of `success`, `reverted` or `halted`.
+    pub status: &'static str,
+    pub gas_limit: U256,
+    pub gas_used: U256,
+    pub gas_refunded: u64,
+    pub pubdata_published: u32,
+    pub output: Bytes,
+    pub revert_reason: Option<String>,
+}
+
+/// A single call frame of a transaction's call tree, flattened in depth-first order.
+#[derive(Debug, Clone, Serialize)]
+pub struct CallRecord {
+    pub schema_version: u8,
+    pub tx_hash: H256,
+    /// Position of this call in the depth-first traversal of the call tree.
+    pub index: usize,
+    /// Index of the parent call, `None` for top-level calls.
+    pub parent_index: Option<usize>,
+    pub depth: usize,
+    /// One of `call`, `create` or `near_call`.
+    pub call_type: &'static str,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub gas: u64,
+    pub gas_used: u64,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub error: Option<String>,
+    pub revert_reason: Option<String>,
+}
+
+/// An event emitted by a transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    pub schema_version: u8,
+    pub tx_hash: H256,
+    pub log_index: usize,
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+}
+
+/// A `console.log` invocation made by a transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsoleLogRecord {
+    pub schema_version: u8,
+    pub tx_hash: H256,
+    pub message: String,
+}
+
+/// A storage slot written by a transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageWriteRecord {
+    pub schema_version: u8,
+    pub tx_hash: H256,
+    pub address: Address,
+    pub key: H256,
+    pub previous_value: H256,
+    pub value: H256,
+    /// `true` if this is the first time the slot has ever been written to.
+    pub initial: bool,
+}
+
+/// Another fork was selected, the following records belong to it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkSelectedRecord {
+    pub schema_version: u8,
+    pub name: String,
+    pub block_number: U64,
+}
+
+/// Sharable handle to the JSON log output.
+#[derive(Clone)]
+pub struct JsonLogSink {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl fmt::Debug for JsonLogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonLogSink").finish_non_exhaustive()
+    }
+}
+
+impl JsonLogSink {
+    /// Opens the sink at the given path, truncating any existing file. Use [JSON_LOG_STDOUT] to write to stdout.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> = if path == JSON_LOG_STDOUT {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Self::from_writer(writer))
+    }
+
+    /// Creates a sink on top of an arbitrary writer.
+    pub fn from_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Writes a single record followed by a newline.
+    pub fn emit(&self, record: &JsonLogRecord) {
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(err) => {
+                tracing::warn!("json log writer lock is poisoned: {}", err);
+                return;
+            }
+        };
+        let result = serde_json::to_writer(&mut *writer, record)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+        if let Err(err) = result {
+            tracing::warn!("failed writing json log record: {}", err);
+        }
+    }
+
+    /// Emits a record for a freshly sealed block.
+    pub fn emit_block(&self, block: &Block<TransactionVariant>) {
+        let transactions = block
+            .transactions
+            .iter()
+            .map(|tx| match tx {
+                TransactionVariant::Full(tx) => tx.hash,
+                TransactionVariant::Hash(hash) => *hash,
+            })
+            .collect();
+        self.emit(&JsonLogRecord::Block(BlockRecord {
+            schema_version: JSON_LOG_SCHEMA_VERSION,
+            number: block.number,
+            hash: block.hash,
+            parent_hash: block.parent_hash,
+            l1_batch_number: block.l1_batch_number,
+            timestamp: block.timestamp,
+            gas_used: block.gas_used,
+            base_fee_per_gas: block.base_fee_per_gas,
+            transactions,
+        }));
+    }
+
+    /// Emits a record for a newly selected fork, starting at its latest block.
+    pub fn emit_fork_selected(&self, name: &str, block_number: u64) {
+        self.emit(&JsonLogRecord::ForkSelected(ForkSelectedRecord {
+            schema_version: JSON_LOG_SCHEMA_VERSION,
+            name: name.to_string(),
+            block_number: U64::from(block_number),
+        }));
+    }
+
+    /// Emits the transaction record followed by its calls, events, console logs and storage writes.
+    pub fn emit_transaction(
+        &self,
+        tx: &Transaction,
+        block_number: U64,
+        l1_batch_number: u32,
+        result: &VmExecutionResultAndLogs,
+        call_traces: &[Call],
+        console_logs: Vec<String>,
+    ) {
+        let tx_hash = tx.hash();
+        let (status, output, revert_reason) = match &result.result {
+            ExecutionResult::Success { output } => ("success", output.clone(), None),
+            ExecutionResult::Revert { output } => {
+                ("reverted", output.encoded_data(), Some(output.to_string()))
+            }
+            ExecutionResult::Halt { reason } => ("halted", vec![], Some(reason.to_string())),
+        };
+        let gas_limit = tx.gas_limit();
+        self.emit(&JsonLogRecord::Transaction(TransactionRecord {
+            schema_version: JSON_LOG_SCHEMA_VERSION,
+            hash: tx_hash,
+            block_number,
+            l1_batch_number,
+            from: tx.initiator_account(),
+            to: tx.recipient_account(),
+            value: tx.execute.value,
+            nonce: tx.nonce().map(|nonce| nonce.0),
+            status,
+            gas_limit,
+            gas_used: gas_limit.saturating_sub(U256::from(result.refunds.gas_refunded)),
+            gas_refunded: result.refunds.gas_refunded,
+            pubdata_published: result.statistics.pubdata_published,
+            output: output.into(),
+            revert_reason,
+        }));
+
+        let mut index = 0;
+        for call in call_traces {
+            self.emit_call_recursive(tx_hash, call, None, 0, &mut index);
+        }
+
+        for (log_index, event) in result.logs.events.iter().enumerate() {
+            self.emit(&JsonLogRecord::Event(EventRecord {
+                schema_version: JSON_LOG_SCHEMA_VERSION,
+                tx_hash,
+                log_index,
+                address: event.address,
+                topics: event.indexed_topics.clone(),
+                data: event.value.clone().into(),
+            }));
+        }
+
+        for message in console_logs {
+            self.emit(&JsonLogRecord::ConsoleLog(ConsoleLogRecord {
+                schema_version: JSON_LOG_SCHEMA_VERSION,
+                tx_hash,
+                message,
+            }));
+        }
+
+        for log_query in result
+            .logs
+            .storage_logs
+            .iter()
+            .filter(|log_query| log_query.log.is_write())
+        {
+            self.emit(&JsonLogRecord::StorageWrite(StorageWriteRecord {
+                schema_version: JSON_LOG_SCHEMA_VERSION,
+                tx_hash,
+                address: *log_query.log.key.address(),
+                key: *log_query.log.key.key(),
+                previous_value: log_query.previous_value,
+                value: log_query.log.value,
+                initial: log_query.log.kind == StorageLogKind::InitialWrite,
+            }));
+        }
+    }
+
+    fn emit_call_recursive(
+        &self,
+        tx_hash: H256,
+        call: &Call,
+        parent_index: Option<usize>,
+        depth: usize,
+        index: &mut usize,
+    ) {
+        let current_index = *index;
+        *index += 1;
+        let call_type = match call.r#type {
+            CallType::Call(_) => "call",
+            CallType::Create => "create",
+            CallType::NearCall => "near_call",
+        };
+        self.emit(&JsonLogRecord::Call(CallRecord {
+            schema_version: JSON_LOG_SCHEMA_VERSION,
+            tx_hash,
+            index: current_index,
+            parent_index,
+            depth,
+            call_type,
+            from: call.from,
+            to: call.to,
+            value: call.value,
+            gas: call.gas,
+            gas_used: call.gas_used,
+            input: call.input.clone().into(),
+            output: call.output.clone().into(),
+            error: call.error.clone(),
+            revert_reason: call.revert_reason.clone(),
+        }));
+        for child in &call.calls {
+            self.emit_call_recursive(tx_hash, child, Some(current_index), depth + 1, index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_fork_source::HttpForkSource;
+    use crate::node::InMemoryNode;
+    use crate::testing;
+
+    /// Writer that shares its buffer so the test can inspect what the sink wrote.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_log_emits_transaction_and_block_records() {
+        let buffer = SharedBuffer::default();
+        let mut node = InMemoryNode::<HttpForkSource>::default();
+        node.json_log = Some(JsonLogSink::from_writer(Box::new(buffer.clone())));
+
+        let (_, block_number, tx) = testing::apply_tx(&node, H256::repeat_byte(0x01));
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let records = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let tx_record = records
+            .iter()
+            .find(|record| record["type"] == "transaction")
+            .expect("transaction record is missing");
+        assert_eq!(tx_record["hash"], serde_json::json!(tx.hash()));
+        assert_eq!(tx_record["block_number"], serde_json::json!(block_number));
+        assert_eq!(tx_record["status"], "success");
+        assert_eq!(tx_record["schema_version"], JSON_LOG_SCHEMA_VERSION);
+
+        let block_record = records
+            .iter()
+            .find(|record| {
+                record["type"] == "block" && record["number"] == serde_json::json!(block_number)
+            })
+            .expect("block record is missing");
+        assert_eq!(block_record["transactions"], serde_json::json!([tx.hash()]));
+
+        assert!(records.iter().any(|record| record["type"] == "call"));
+        assert!(records
+            .iter()
+            .any(|record| record["type"] == "storage_write"));
+    }
+}
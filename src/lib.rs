@@ -42,16 +42,22 @@
 //! Contributions to improve `anvil-zksync` are welcome. Please refer to the [contribution guidelines](https://github.com/matter-labs/anvil-zksync/blob/main/.github/CONTRIBUTING.md) for more details.
 
 pub mod bootloader_debug;
+pub mod cache_file;
 pub mod config;
 pub mod console_log;
 pub mod deps;
 pub mod filters;
 pub mod fork;
+pub mod fork_bundle;
+pub mod fork_client;
 pub mod formatter;
 pub mod http_fork_source;
+pub mod json_log;
+pub mod metrics;
 pub mod namespaces;
 pub mod node;
 pub mod observability;
+pub mod replay;
 pub mod resolver;
 pub mod system_contracts;
 pub mod utils;
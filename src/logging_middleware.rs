@@ -3,10 +3,30 @@ use futures::Future;
 use futures::{future::Either, FutureExt};
 use itertools::Itertools;
 use jsonrpc_core::{
-    middleware, Call, FutureResponse, Metadata, Middleware, Params, Request, Response,
+    middleware, Call, ErrorCode, FutureResponse, Metadata, Middleware, Output, Params, Request,
+    Response,
 };
+use std::time::Instant;
 use tracing_subscriber::filter::LevelFilter;
 
+use crate::metrics::METRICS;
+
+/// Metrics label used for calls to methods the server does not expose, so that clients cannot
+/// create arbitrary time series by sending random method names.
+const UNKNOWN_METHOD_LABEL: &str = "unknown";
+
+/// Returns the metrics label for a call to `method` that produced `response`.
+fn method_label(method: String, response: &Option<Response>) -> String {
+    match response {
+        Some(Response::Single(Output::Failure(failure)))
+            if failure.error.code == ErrorCode::MethodNotFound =>
+        {
+            UNKNOWN_METHOD_LABEL.to_string()
+        }
+        _ => method,
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Meta();
 impl Metadata for Meta {}
@@ -33,6 +53,11 @@ impl Middleware<Meta> for LoggingMiddleware {
         F: FnOnce(Request, Meta) -> X + Send,
         X: Future<Output = Option<Response>> + Send + 'static,
     {
+        let started_at = Instant::now();
+        let method = match &request {
+            Request::Single(Call::MethodCall(method_call)) => Some(method_call.method.clone()),
+            _ => None,
+        };
         if let Request::Single(Call::MethodCall(method_call)) = &request {
             match self.log_level_filter {
                 LevelFilter::TRACE => {
@@ -86,8 +111,52 @@ impl Middleware<Meta> for LoggingMiddleware {
         };
 
         Either::Left(Box::pin(next(request, meta).map(move |res| {
+            if let Some(method) = method {
+                let method = method_label(method, &res);
+                METRICS.rpc_requests[&method].inc();
+                METRICS.rpc_latency[&method].observe(started_at.elapsed());
+            }
             tracing::trace!("API response => {:?}", res);
             res
         })))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::{Error, Failure, Id, Success, Value, Version};
+
+    #[test]
+    fn test_method_label_maps_unknown_methods() {
+        let not_found = Some(Response::Single(Output::Failure(Failure {
+            jsonrpc: Some(Version::V2),
+            error: Error::method_not_found(),
+            id: Id::Num(1),
+        })));
+        assert_eq!(
+            method_label("random_method_1234".to_string(), &not_found),
+            UNKNOWN_METHOD_LABEL
+        );
+
+        let invalid_params = Some(Response::Single(Output::Failure(Failure {
+            jsonrpc: Some(Version::V2),
+            error: Error::invalid_params("bad"),
+            id: Id::Num(1),
+        })));
+        assert_eq!(
+            method_label("eth_call".to_string(), &invalid_params),
+            "eth_call"
+        );
+
+        let success = Some(Response::Single(Output::Success(Success {
+            jsonrpc: Some(Version::V2),
+            result: Value::Null,
+            id: Id::Num(1),
+        })));
+        assert_eq!(
+            method_label("eth_chainId".to_string(), &success),
+            "eth_chainId"
+        );
+    }
+}
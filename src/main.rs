@@ -2,15 +2,17 @@ use crate::observability::Observability;
 use anvil_zksync::config::constants::DEFAULT_L1_GAS_PRICE;
 use anyhow::anyhow;
 use bytecode_override::override_bytecodes;
+use cache_file::CacheFile;
 use clap::Parser;
-use config::cli::{Cli, Command};
+use config::cli::{CacheCommand, Cli, Command, ReplayRangeArgs};
 use config::constants::{
     DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR, DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
     DEFAULT_FAIR_PUBDATA_PRICE, DEFAULT_L2_GAS_PRICE, LEGACY_RICH_WALLETS, RICH_WALLETS,
     TEST_NODE_NETWORK_ID,
 };
 use config::ForkPrintInfo;
-use fork::{ForkDetails, ForkSource};
+use fork::{ForkDetails, ForkNetwork, ForkSource};
+use fork_bundle::{BundleForkSource, ForkBundleHeader, RecordingForkSource};
 use http_fork_source::HttpForkSource;
 use logging_middleware::LoggingMiddleware;
 use tracing_subscriber::filter::LevelFilter;
@@ -18,17 +20,23 @@ use tracing_subscriber::filter::LevelFilter;
 mod bootloader_debug;
 mod bytecode_override;
 mod cache;
+mod cache_file;
 mod config;
 mod console_log;
 mod deps;
 mod filters;
 mod fork;
+mod fork_bundle;
+mod fork_client;
 mod formatter;
 mod http_fork_source;
+mod json_log;
 mod logging_middleware;
+mod metrics;
 mod namespaces;
 mod node;
 mod observability;
+mod replay;
 mod resolver;
 mod system_contracts;
 mod testing;
@@ -36,7 +44,7 @@ mod utils;
 
 use node::InMemoryNode;
 use std::fs::File;
-use std::{env, net::SocketAddr, str::FromStr};
+use std::{env, net::SocketAddr, str::FromStr, sync::Arc};
 use zksync_types::fee_model::{FeeModelConfigV2, FeeParams};
 use zksync_web3_decl::namespaces::ZksNamespaceClient;
 
@@ -46,6 +54,7 @@ use futures::{
     FutureExt,
 };
 use jsonrpc_core::MetaIoHandler;
+use jsonrpc_http_server::{hyper, Response};
 use zksync_types::H160;
 
 use crate::namespaces::{
@@ -56,7 +65,6 @@ use crate::namespaces::{
 use crate::node::{
     BlockProducer, BlockSealer, BlockSealerMode, ImpersonationManager, TimestampManager, TxPool,
 };
-use crate::system_contracts::SystemContracts;
 
 #[allow(clippy::too_many_arguments)]
 async fn build_json_http<
@@ -65,7 +73,9 @@ async fn build_json_http<
     addr: SocketAddr,
     log_level_filter: LevelFilter,
     node: InMemoryNode<S>,
+    server_threads: usize,
     enable_health_api: bool,
+    enable_metrics_api: bool,
 ) -> tokio::task::JoinHandle<()> {
     let (sender, recv) = oneshot::channel::<()>();
 
@@ -88,10 +98,12 @@ async fn build_json_http<
     std::thread::spawn(move || {
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
-            .worker_threads(1)
+            .worker_threads(server_threads)
             .build()
             .unwrap();
 
+        // VM-heavy methods (`eth_call`, `eth_estimateGas`, ...) run on the runtime's blocking thread pool, so
+        // a slow call does not stall the event loop serving the other requests.
         let mut builder = jsonrpc_http_server::ServerBuilder::new(io_handler)
             .threads(1)
             .event_loop_executor(runtime.handle().clone());
@@ -100,6 +112,25 @@ async fn build_json_http<
             builder = builder.health_api(("/health", "web3_clientVersion"));
         }
 
+        if enable_metrics_api {
+            builder = builder.request_middleware(|request: hyper::Request<hyper::Body>| {
+                if request.method() == hyper::Method::GET
+                    && request.uri().path() == metrics::METRICS_ENDPOINT
+                {
+                    Response {
+                        code: hyper::StatusCode::OK,
+                        content_type: hyper::header::HeaderValue::from_static(
+                            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+                        ),
+                        content: metrics::render(),
+                    }
+                    .into()
+                } else {
+                    request.into()
+                }
+            });
+        }
+
         let server = builder.start_http(&addr).unwrap();
 
         server.wait();
@@ -109,6 +140,49 @@ async fn build_json_http<
     tokio::spawn(recv.map(drop))
 }
 
+/// Inspects or prunes the cache file at `path`.
+fn run_cache_command(path: &str, max_size: u64, command: &CacheCommand) -> anyhow::Result<()> {
+    let mut cache_file = CacheFile::open(path, max_size)
+        .map_err(|err| anyhow!("failed opening cache file {:?}: {:?}", path, err))?;
+
+    match command {
+        CacheCommand::Inspect => {}
+        CacheCommand::Prune { fork_url, max_size } => {
+            let dropped = cache_file.verify()?;
+            println!("Dropped {} corrupted entries", dropped);
+            if let Some(fork_url) = fork_url {
+                // Entries are cached under the URL the network alias resolves to.
+                let fork_url = ForkNetwork::from_name(fork_url).to_url().to_string();
+                let removed = cache_file.remove_namespace(&fork_url)?;
+                println!("Removed {} entries cached for {}", removed, fork_url);
+            }
+            if let Some(max_size) = max_size {
+                let evicted = cache_file.shrink_to(*max_size)?;
+                println!("Evicted {} least recently used entries", evicted);
+            }
+        }
+    }
+
+    let stats = cache_file.stats();
+    println!("Cache file: {}", path);
+    println!(
+        "Entries: {}, size: {} bytes ({} bytes in use), max size: {} bytes",
+        stats.entries, stats.file_size, stats.live_size, stats.max_size
+    );
+    for (namespace, namespace_stats) in cache_file.namespace_stats() {
+        let namespace = if namespace.is_empty() {
+            "<no fork>"
+        } else {
+            namespace.as_str()
+        };
+        println!(
+            "  {}: {} entries, {} bytes",
+            namespace, namespace_stats.entries, namespace_stats.size
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Check for deprecated options
@@ -117,6 +191,10 @@ async fn main() -> anyhow::Result<()> {
     let opt = Cli::parse();
     let command = opt.command.clone();
 
+    if let Some(Command::Cache(cache)) = &command {
+        return run_cache_command(&opt.cache_file_path(), opt.cache_max_size(), &cache.command);
+    }
+
     let mut config = opt.into_test_node_config().map_err(|e| anyhow!(e))?;
 
     let log_level_filter = LevelFilter::from(config.log_level);
@@ -128,10 +206,25 @@ async fn main() -> anyhow::Result<()> {
         log_level_filter,
         log_file,
         config.silent,
+        config.is_json_log_on_stdout(),
     )?;
 
     // Use `Command::Run` as default.
     let command = command.as_ref().unwrap_or(&Command::Run);
+    let replay_range = match command {
+        Command::ReplayBlock(replay_block) => Some(ReplayRangeArgs::from(replay_block.clone())),
+        Command::ReplayRange(replay_range) => {
+            if replay_range.to < replay_range.from {
+                return Err(anyhow!(
+                    "invalid block range {}..={}",
+                    replay_range.from,
+                    replay_range.to
+                ));
+            }
+            Some(replay_range.clone())
+        }
+        _ => None,
+    };
     let fork_details = match command {
         Command::Run => {
             if config.offline {
@@ -199,17 +292,45 @@ async fn main() -> anyhow::Result<()> {
             }
         }
         Command::Fork(fork) => {
-            let fork_details_result = if let Some(tx_hash) = fork.fork_transaction_hash {
-                // If fork_transaction_hash is provided, use from_network_tx
-                ForkDetails::from_network_tx(&fork.fork_url, tx_hash, &config.cache_config).await
+            let fork_details_result = if let Some(bundle) = &fork.fork_bundle {
+                // Serve the fork from a recorded bundle without touching the network
+                BundleForkSource::open(bundle)
+                    .and_then(|source| source.into_fork_details(&config.cache_config))
             } else {
-                // Otherwise, use from_network
-                ForkDetails::from_network(
-                    &fork.fork_url,
-                    fork.fork_block_number,
-                    &config.cache_config,
-                )
-                .await
+                let (fork_url, fallback_urls) = fork
+                    .fork_url
+                    .split_first()
+                    .map(|(fork_url, fallback_urls)| (fork_url.as_str(), fallback_urls))
+                    .unwrap_or_default();
+                let fork_details_result = if let Some(tx_hash) = fork.fork_transaction_hash {
+                    // If fork_transaction_hash is provided, use from_network_tx
+                    ForkDetails::from_network_tx(
+                        fork_url,
+                        fallback_urls,
+                        tx_hash,
+                        &config.cache_config,
+                    )
+                    .await
+                } else {
+                    // Otherwise, use from_network
+                    ForkDetails::from_network(
+                        fork_url,
+                        fallback_urls,
+                        fork.fork_block_number,
+                        &config.cache_config,
+                    )
+                    .await
+                };
+
+                match &fork.fork_record {
+                    Some(path) => fork_details_result.and_then(|mut fd| {
+                        let header = ForkBundleHeader::new(&fd);
+                        fd.fork_source =
+                            Arc::new(RecordingForkSource::new(fd.fork_source, &header, path)?);
+                        Ok(fd)
+                    }),
+                    None => fork_details_result,
+                }
             };
 
             config.update_with_fork_details(fork_details_result).await?
@@ -217,6 +338,7 @@ async fn main() -> anyhow::Result<()> {
         Command::ReplayTx(replay_tx) => {
             let fork_details_result = ForkDetails::from_network_tx(
                 &replay_tx.fork_url,
+                &[],
                 replay_tx.tx,
                 &config.cache_config,
             )
@@ -224,6 +346,22 @@ async fn main() -> anyhow::Result<()> {
 
             config.update_with_fork_details(fork_details_result).await?
         }
+        Command::ReplayBlock(_) | Command::ReplayRange(_) => {
+            let replay_range = replay_range
+                .as_ref()
+                .expect("replay range is set for replay commands");
+            // Fork just before the first block, so that all of its transactions can be replayed on top.
+            let fork_details_result = ForkDetails::from_network(
+                &replay_range.fork_url,
+                &[],
+                Some(replay_range.from - 1),
+                &config.cache_config,
+            )
+            .await;
+
+            config.update_with_fork_details(fork_details_result).await?
+        }
+        Command::Cache(_) => unreachable!("cache commands are handled before starting the node"),
     };
 
     // If we're replaying the transaction, we need to sync to the previous block
@@ -306,6 +444,10 @@ async fn main() -> anyhow::Result<()> {
         block_sealer.clone(),
     );
 
+    if config.system_contracts_dir.is_some() {
+        node.reload_system_contracts()?;
+    }
+
     if let Some(ref bytecodes_dir) = config.override_bytecodes_dir {
         override_bytecodes(&node, bytecodes_dir.to_string()).unwrap();
     }
@@ -314,6 +456,24 @@ async fn main() -> anyhow::Result<()> {
         let _ = node.apply_txs(transactions_to_replay, config.max_transactions);
     }
 
+    if let Some(replay_range) = &replay_range {
+        let (_, client) = ForkDetails::fork_network_and_client(&replay_range.fork_url)
+            .map_err(|e| anyhow!("Failed to initialize client: {:?}", e))?;
+        let report = replay::replay_blocks(
+            &node,
+            &time,
+            &client,
+            &replay_range.fork_url,
+            replay_range.from,
+            replay_range.to,
+        )
+        .await?;
+        report.log_summary();
+        if let Some(path) = &replay_range.report {
+            report.write_to_file(path)?;
+        }
+    }
+
     for signer in config.genesis_accounts.iter() {
         let address = H160::from_slice(signer.address().as_ref());
         node.set_rich_account(address, config.genesis_balance);
@@ -332,6 +492,7 @@ async fn main() -> anyhow::Result<()> {
         let address = wallet.0;
         node.set_rich_account(H160::from_str(address).unwrap(), config.genesis_balance);
     }
+    node.write_inner()?.apply_genesis_alloc()?;
 
     let mut threads = future::join_all(config.host.iter().map(|host| {
         let addr = SocketAddr::new(*host, config.port);
@@ -339,19 +500,14 @@ async fn main() -> anyhow::Result<()> {
             addr,
             log_level_filter,
             node.clone(),
+            config.server_threads,
             config.health_check_endpoint,
+            config.metrics_endpoint,
         )
     }))
     .await;
 
-    let system_contracts =
-        SystemContracts::from_options(&config.system_contracts_options, config.use_evm_emulator);
-    let block_producer_handle = tokio::task::spawn(BlockProducer::new(
-        node,
-        pool,
-        block_sealer,
-        system_contracts,
-    ));
+    let block_producer_handle = tokio::task::spawn(BlockProducer::new(node, pool, block_sealer));
     threads.push(block_producer_handle);
 
     config.print(fork_print_info.as_ref());
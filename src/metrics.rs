@@ -0,0 +1,115 @@
+//! Prometheus metrics exposed by the node.
+//!
+//! Metrics are collected via [`vise`] and served in the OpenMetrics text format at `/metrics`
+//! when the node is started with `--metrics-endpoint`.
+
+use std::time::Duration;
+
+use vise::{
+    Buckets, Counter, EncodeLabelSet, EncodeLabelValue, Family, Gauge, Histogram, LabeledFamily,
+    Metrics,
+};
+
+/// Path of the metrics endpoint on the HTTP server.
+pub const METRICS_ENDPOINT: &str = "/metrics";
+
+/// Kind of data looked up in the fork caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+#[metrics(rename_all = "snake_case")]
+pub enum CacheKind {
+    Blocks,
+    BlockHashes,
+    BlockRawTransactions,
+    ConfirmedTokens,
+    Transactions,
+    ResolverSelectors,
+    BridgeAddresses,
+    StorageValues,
+    FactoryDeps,
+    BlockStorageValues,
+    Bytecodes,
+}
+
+/// Outcome of a cache lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelValue)]
+#[metrics(rename_all = "snake_case")]
+pub enum CacheOutcome {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct CacheLabels {
+    pub kind: CacheKind,
+    pub outcome: CacheOutcome,
+}
+
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "anvil_zksync")]
+pub struct NodeMetrics {
+    /// Number of JSON-RPC requests, per method (`unknown` for methods the node does not expose).
+    #[metrics(labels = ["method"])]
+    pub rpc_requests: LabeledFamily<String, Counter>,
+    /// Latency of JSON-RPC requests, per method (`unknown` for methods the node does not expose).
+    #[metrics(labels = ["method"], buckets = Buckets::LATENCIES)]
+    pub rpc_latency: LabeledFamily<String, Histogram<Duration>>,
+    /// Number of sealed blocks (including virtual blocks).
+    pub blocks_sealed: Counter,
+    /// Number of transactions included into sealed blocks.
+    pub txs_sealed: Counter,
+    /// Number of transactions currently waiting in the pool.
+    pub pool_size: Gauge<usize>,
+    /// Time spent in the VM executing a single transaction.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub tx_execution_time: Histogram<Duration>,
+    /// Number of requests sent to the fork source, per method.
+    #[metrics(labels = ["method"])]
+    pub fork_requests: LabeledFamily<&'static str, Counter>,
+    /// Number of fork cache lookups, per data kind and outcome.
+    pub fork_cache_lookups: Family<CacheLabels, Counter>,
+    /// Number of snapshots currently held by the node.
+    pub snapshots: Gauge<usize>,
+}
+
+impl NodeMetrics {
+    /// Records a fork cache lookup for the given kind and passes the looked up value through.
+    pub fn observe_cache_lookup<T>(&self, kind: CacheKind, value: Option<T>) -> Option<T> {
+        let hit = value.is_some();
+        let outcome = if hit {
+            CacheOutcome::Hit
+        } else {
+            CacheOutcome::Miss
+        };
+        self.fork_cache_lookups[&CacheLabels { kind, outcome }].inc();
+        value
+    }
+}
+
+#[vise::register]
+pub static METRICS: vise::Global<NodeMetrics> = vise::Global::new();
+
+/// Renders all registered metrics in the OpenMetrics text format.
+pub fn render() -> String {
+    let registry = vise::MetricsCollection::default().collect();
+    let mut buffer = String::new();
+    if let Err(err) = registry.encode(&mut buffer, vise::Format::OpenMetricsForPrometheus) {
+        tracing::warn!("failed encoding metrics: {:?}", err);
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_node_metrics() {
+        METRICS.blocks_sealed.inc();
+        METRICS.observe_cache_lookup(CacheKind::StorageValues, Some(()));
+
+        let rendered = render();
+
+        assert!(rendered.contains("anvil_zksync_blocks_sealed"));
+        assert!(rendered.contains("anvil_zksync_fork_cache_lookups"));
+    }
+}
@@ -1,8 +1,9 @@
 use super::{ResetRequest, RpcResult};
+use crate::config::Genesis;
 use crate::utils::Numeric;
 use jsonrpc_derive::rpc;
 use serde::{Deserialize, Serialize};
-use zksync_types::api::{Block, Transaction};
+use zksync_types::api::{Block, BlockNumber, DebugCall, Transaction};
 use zksync_types::web3::Bytes;
 use zksync_types::{Address, H256, U256, U64};
 
@@ -159,6 +160,285 @@ pub trait AnvilNamespaceT {
     #[rpc(name = "anvil_revert")]
     fn revert(&self, id: U64) -> RpcResult<bool>;
 
+    /// Snapshot the state of the blockchain at the current block under the given name. Unlike `anvil_snapshot`,
+    /// a named snapshot is kept after reverting to it, so it can be reverted to any number of times.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot, must be unique
+    ///
+    /// # Returns
+    /// `true` if a snapshot was created.
+    #[rpc(name = "anvil_snapshotNamed")]
+    fn snapshot_named(&self, name: String) -> RpcResult<bool>;
+
+    /// Lists all named snapshots in the order they were created.
+    ///
+    /// # Returns
+    /// Names of the snapshots along with the blocks they were taken at.
+    #[rpc(name = "anvil_listSnapshots")]
+    fn list_snapshots(&self) -> RpcResult<Vec<NamedSnapshot>>;
+
+    /// Deletes a named snapshot without affecting the state of the blockchain.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot to delete
+    ///
+    /// # Returns
+    /// `true` if a snapshot was deleted, `false` if it did not exist.
+    #[rpc(name = "anvil_deleteSnapshot")]
+    fn delete_snapshot(&self, name: String) -> RpcResult<bool>;
+
+    /// Revert the state of the blockchain to a named snapshot. The snapshot is kept, while all snapshots
+    /// (named or not) taken after it are deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot to revert to
+    ///
+    /// # Returns
+    /// `true` if a snapshot was reverted.
+    #[rpc(name = "anvil_revertNamed")]
+    fn revert_named(&self, name: String) -> RpcResult<bool>;
+
+    /// Serializes the state captured by a named snapshot in the `anvil_dumpState` format.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot to save
+    ///
+    /// # Returns
+    /// The serialized state of the snapshot, which can be passed to `anvil_loadSnapshot`.
+    #[rpc(name = "anvil_saveSnapshot")]
+    fn save_snapshot(&self, name: String) -> RpcResult<Bytes>;
+
+    /// Loads a state returned by `anvil_saveSnapshot` (or `anvil_dumpState`) into a node without existing state
+    /// and creates a named snapshot of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the snapshot to create
+    /// * `bytes` - The serialized state
+    ///
+    /// # Returns
+    /// `true` if the snapshot was loaded.
+    #[rpc(name = "anvil_loadSnapshot")]
+    fn load_snapshot(&self, name: String, bytes: Bytes) -> RpcResult<bool>;
+
+    /// Returns every storage slot that differs between two points in the node history. Well-known system
+    /// contract slots (base token balances, nonces and deployed code hashes) are decoded where possible.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Block number (or tag), or `{"snapshot": "<name>"}` for a named snapshot, to diff from
+    /// * `to` - Block number (or tag), or `{"snapshot": "<name>"}` for a named snapshot, to diff to
+    ///
+    /// # Returns
+    /// The changed storage slots with their values at both points.
+    #[rpc(name = "anvil_zks_stateDiff")]
+    fn state_diff(&self, from: StateDiffPoint, to: StateDiffPoint) -> RpcResult<StateDiff>;
+
+    /// Fetches a transaction from the forked network and executes it on top of the current local state without
+    /// committing it. Signature checks are bypassed like for impersonated accounts, and the nonce is replaced by
+    /// the sender's current local nonce.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Hash of the upstream transaction
+    ///
+    /// # Returns
+    /// The result of the execution with the full call trace.
+    #[rpc(name = "anvil_zks_replayTransaction")]
+    fn replay_transaction(&self, hash: H256) -> RpcResult<DebugCall>;
+
+    /// Upgrades the running chain in place, like an upgrade transaction would on a real network. The given
+    /// bootloaders and default account replace the current ones, system contracts are deployed to their addresses
+    /// and the protocol version is bumped. The upgrade takes effect with the next sealed batch, calls keep running
+    /// with the current contracts until then.
+    ///
+    /// # Arguments
+    ///
+    /// * `upgrade` - The contracts and protocol version to upgrade to, anything not given is kept
+    ///
+    /// # Returns
+    /// `true` if the chain was upgraded.
+    #[rpc(name = "anvil_zks_upgradeProtocol")]
+    fn upgrade_protocol(&self, upgrade: ProtocolUpgrade) -> RpcResult<bool>;
+
+    /// Exports the state of the running chain as a genesis file. Starting a node with `--init` pointing at the
+    /// exported file recreates the accounts, balances, nonces, code and storage without replaying transactions.
+    /// Not supported for forked chains.
+    ///
+    /// # Returns
+    /// The genesis with an `alloc` section holding every account that differs from a new chain.
+    #[rpc(name = "anvil_zks_exportGenesis")]
+    fn export_genesis(&self) -> RpcResult<Genesis>;
+
+    /// Reloads the bootloader and system contracts from `--system-contracts-dir` into the running chain, so that
+    /// rebuilt contracts can be tested without a restart. Blocks sealed from now on run with the reloaded contracts.
+    ///
+    /// # Returns
+    /// `true` if the contracts were reloaded.
+    #[rpc(name = "anvil_zks_reloadSystemContracts")]
+    fn reload_system_contracts(&self) -> RpcResult<bool>;
+
+    /// Sets the L1 gas price used for the next batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The L1 gas price in wei
+    ///
+    /// # Returns
+    /// `true` if the price was set.
+    #[rpc(name = "anvil_zks_setL1GasPrice")]
+    fn set_l1_gas_price(&self, price: U64) -> RpcResult<bool>;
+
+    /// Sets the L1 pubdata price the fair pubdata price of the next batches is derived from.
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The pubdata price in wei per byte
+    ///
+    /// # Returns
+    /// `true` if the price was set.
+    #[rpc(name = "anvil_zks_setFairPubdataPrice")]
+    fn set_fair_pubdata_price(&self, price: U64) -> RpcResult<bool>;
+
+    /// Sets the minimal L2 gas price of the next batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The minimal L2 gas price in wei
+    ///
+    /// # Returns
+    /// `true` if the price was set.
+    #[rpc(name = "anvil_zks_setMinimalL2GasPrice")]
+    fn set_minimal_l2_gas_price(&self, price: U64) -> RpcResult<bool>;
+
+    /// Changes the fee params of the next batches, covering the prices and the `FeeModelConfigV2` parameters.
+    /// Once the fee params are changed, a forked chain no longer mirrors the fees of the forked network.
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - The fee params to change, anything not given is kept
+    ///
+    /// # Returns
+    /// `true` if the fee params were changed.
+    #[rpc(name = "anvil_zks_setFeeParams")]
+    fn set_fee_params(&self, update: FeeParamsUpdate) -> RpcResult<bool>;
+
+    /// Schedules a change of the fee params for when a batch is reached, e.g. to simulate a pubdata price spike.
+    /// The change stays in effect for the following batches until it is changed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - The batch the change takes effect with, must not be sealed yet
+    /// * `update` - The fee params to change, anything not given is kept
+    ///
+    /// # Returns
+    /// `true` if the change was scheduled.
+    #[rpc(name = "anvil_zks_scheduleFeeParams")]
+    fn schedule_fee_params(&self, batch: U64, update: FeeParamsUpdate) -> RpcResult<bool>;
+
+    /// Enables or disables the gasless mode. In gasless mode the base fee, pubdata price and L1 gas price are zero,
+    /// and transactions of accounts that cannot pay for the gas they request are accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `enable` - Whether the gasless mode is enabled
+    ///
+    /// # Returns
+    /// `true` if the mode was changed.
+    #[rpc(name = "anvil_zks_setGasless")]
+    fn set_gasless(&self, enable: bool) -> RpcResult<bool>;
+
+    /// Changes the limits at which the next batches are sealed. Transactions that no longer fit into a batch are
+    /// moved to the next one, and transactions exceeding a limit on their own are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The limits to change, anything not given is kept
+    ///
+    /// # Returns
+    /// `true` if the limits were changed.
+    #[rpc(name = "anvil_zks_setBatchLimits")]
+    fn set_batch_limits(&self, limits: BatchLimitsUpdate) -> RpcResult<bool>;
+
+    /// Removes the latest blocks from the chain. Blocks, transactions and storage are rewound to the new latest
+    /// block, and log filters receive the removed logs flagged with `removed: true`.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The number of blocks to remove, defaults to `1`
+    /// * `requeue` - Whether to put the removed transactions back into the pool instead of discarding them
+    #[rpc(name = "anvil_rollback")]
+    fn rollback(&self, depth: Option<U64>, requeue: Option<bool>) -> RpcResult<()>;
+
+    /// Replaces the latest blocks of the chain. The removed transactions are discarded, the given transactions
+    /// are mined instead and empty blocks are mined until the chain reaches its previous height.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - The number of blocks to replace
+    /// * `txs` - Raw signed transactions to mine in the replacement blocks
+    #[rpc(name = "anvil_reorg")]
+    fn reorg(&self, depth: U64, txs: Option<Vec<Bytes>>) -> RpcResult<()>;
+
+    /// Creates a new named fork held next to the existing ones. The active fork is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the fork, must be unique
+    /// * `url` - The URL to fork from, a fresh local chain is created if not set
+    /// * `block_number` - The block to fork at, defaults to the latest one
+    ///
+    /// # Returns
+    /// `true` if a fork was created.
+    #[rpc(name = "anvil_createFork")]
+    fn create_fork(
+        &self,
+        name: String,
+        url: Option<String>,
+        block_number: Option<U64>,
+    ) -> RpcResult<bool>;
+
+    /// Creates a new named fork and makes it active, see `anvil_createFork`.
+    #[rpc(name = "anvil_createSelectFork")]
+    fn create_select_fork(
+        &self,
+        name: String,
+        url: Option<String>,
+        block_number: Option<U64>,
+    ) -> RpcResult<bool>;
+
+    /// Makes the given fork active. The previously active fork keeps its state, including snapshots and time,
+    /// until it is selected again.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the fork to select. The fork the node was started with is named `default`
+    ///
+    /// # Returns
+    /// `true` if the fork was selected.
+    #[rpc(name = "anvil_selectFork")]
+    fn select_fork(&self, name: String) -> RpcResult<bool>;
+
+    /// Lists all forks held by the node, starting with the active one.
+    #[rpc(name = "anvil_listForks")]
+    fn list_forks(&self) -> RpcResult<Vec<ForkInfo>>;
+
+    /// Removes an inactive fork along with all its state.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the fork to remove
+    ///
+    /// # Returns
+    /// `true` if a fork was removed, `false` if it did not exist.
+    #[rpc(name = "anvil_removeFork")]
+    fn remove_fork(&self, name: String) -> RpcResult<bool>;
+
     /// Set the current timestamp for the node.
     /// Warning: This will allow you to move backwards in time, which may cause new blocks to appear to be
     /// mined before old blocks. This will result in an invalid state.
@@ -286,6 +566,8 @@ pub trait AnvilNamespaceT {
 
     /// Modifies the bytecode stored at an account's address.
     ///
+    /// With `--emulate-evm`, EVM runtime bytecode is accepted as well and runs in the EVM emulator.
+    ///
     /// # Arguments
     ///
     /// * `address` - The address where the given code should be stored.
@@ -310,6 +592,42 @@ pub trait AnvilNamespaceT {
     /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
     #[rpc(name = "anvil_setStorageAt")]
     fn set_storage_at(&self, address: Address, slot: U256, value: U256) -> RpcResult<bool>;
+
+    /// Sets the ERC20 balance of an account. The storage slot holding the balance is discovered by tracing the
+    /// storage reads of a `balanceOf` call, so this works for proxied tokens such as the bridged tokens on Era as
+    /// well.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The address of the ERC20 token
+    /// * `account` - The account whose balance is to be set
+    /// * `amount` - The new token balance of `account`
+    ///
+    /// # Returns
+    /// `true` if `balanceOf(account)` returns `amount` afterwards.
+    #[rpc(name = "anvil_dealERC20")]
+    fn deal_erc20(&self, token: Address, account: Address, amount: U256) -> RpcResult<bool>;
+
+    /// Sets the ERC20 allowance given by an owner to a spender. The storage slot is discovered by tracing the
+    /// storage reads of an `allowance` call.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The address of the ERC20 token
+    /// * `owner` - The account that gives the allowance
+    /// * `spender` - The account that is allowed to spend the tokens
+    /// * `amount` - The new allowance
+    ///
+    /// # Returns
+    /// `true` if `allowance(owner, spender)` returns `amount` afterwards.
+    #[rpc(name = "anvil_setERC20Allowance")]
+    fn set_erc20_allowance(
+        &self,
+        token: Address,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> RpcResult<bool>;
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -324,3 +642,171 @@ pub struct DetailedTransaction {
     #[serde(default)]
     pub revert_reason: Option<String>,
 }
+
+/// A named snapshot, as returned by `anvil_listSnapshots`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedSnapshot {
+    pub name: String,
+    /// Number of the last block at the time the snapshot was taken.
+    pub block_number: U64,
+    /// Hash of the last block at the time the snapshot was taken.
+    pub block_hash: H256,
+}
+
+/// A fork held by the node, as returned by `anvil_listForks`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkInfo {
+    pub name: String,
+    pub active: bool,
+    /// URL the fork was created from, not set for local chains.
+    pub url: Option<String>,
+    /// Block of the remote chain the fork was created at, not set for local chains.
+    pub fork_block_number: Option<U64>,
+    /// Number of the latest block of the fork.
+    pub block_number: U64,
+}
+
+/// Point in the node history to compute a state diff at.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StateDiffPoint {
+    /// State at the end of a block. Only the latest block and the ones kept in historical states are available.
+    Block(BlockNumber),
+    /// State captured by a named snapshot.
+    Snapshot { snapshot: String },
+}
+
+/// Result of `anvil_zks_stateDiff`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiff {
+    pub from_block: U64,
+    pub to_block: U64,
+    /// Changed storage slots, ordered by address and key.
+    pub changes: Vec<StorageChange>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageChange {
+    pub address: Address,
+    pub key: H256,
+    pub before: H256,
+    pub after: H256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub decoded: Option<DecodedStorageChange>,
+}
+
+/// Meaning of a changed slot that belongs to a well-known system contract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DecodedStorageChange {
+    #[serde(rename_all = "camelCase")]
+    BaseTokenBalance {
+        account: Address,
+        before: U256,
+        after: U256,
+    },
+    #[serde(rename_all = "camelCase")]
+    Nonce {
+        account: Address,
+        tx_nonce_before: U256,
+        tx_nonce_after: U256,
+        deployment_nonce_before: U256,
+        deployment_nonce_after: U256,
+    },
+    #[serde(rename_all = "camelCase")]
+    CodeHash {
+        account: Address,
+        before: H256,
+        after: H256,
+    },
+}
+
+/// Upgrade applied by `anvil_zks_upgradeProtocol`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolUpgrade {
+    /// Protocol version to run from the next batch on. Must not be lower than the current one.
+    #[serde(default)]
+    pub protocol_version: Option<u16>,
+    #[serde(default)]
+    pub bootloader: BootloaderUpgrade,
+    /// Bytecode of the new default account.
+    #[serde(default)]
+    pub default_account: Option<Bytes>,
+    #[serde(default)]
+    pub system_contracts: Vec<SystemContractUpgrade>,
+}
+
+/// Bytecodes of the bootloader variants the node runs transactions with, one per execution mode.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootloaderUpgrade {
+    /// Used to execute transactions.
+    #[serde(default)]
+    pub proved_batch: Option<Bytes>,
+    /// Used to execute transactions of impersonated accounts.
+    #[serde(default)]
+    pub proved_batch_impersonating: Option<Bytes>,
+    /// Used for `eth_call`.
+    #[serde(default)]
+    pub playground_batch: Option<Bytes>,
+    /// Used for gas estimation.
+    #[serde(default)]
+    pub fee_estimate: Option<Bytes>,
+    /// Used for gas estimation of impersonated accounts.
+    #[serde(default)]
+    pub fee_estimate_impersonating: Option<Bytes>,
+}
+
+/// System contract code deployed by a protocol upgrade.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SystemContractUpgrade {
+    pub address: Address,
+    pub bytecode: Bytes,
+}
+
+/// Change of the fee params applied by `anvil_zks_setFeeParams` and `anvil_zks_scheduleFeeParams`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeParamsUpdate {
+    #[serde(default)]
+    pub l1_gas_price: Option<U64>,
+    /// L1 pubdata price, the fair pubdata price additionally includes the batch overhead.
+    #[serde(default)]
+    pub fair_pubdata_price: Option<U64>,
+    #[serde(default)]
+    pub minimal_l2_gas_price: Option<U64>,
+    #[serde(default)]
+    pub compute_overhead_part: Option<f64>,
+    #[serde(default)]
+    pub pubdata_overhead_part: Option<f64>,
+    #[serde(default)]
+    pub batch_overhead_l1_gas: Option<U64>,
+    #[serde(default)]
+    pub max_gas_per_batch: Option<U64>,
+    #[serde(default)]
+    pub max_pubdata_per_batch: Option<U64>,
+}
+
+/// Change of the batch limits applied by `anvil_zks_setBatchLimits`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchLimitsUpdate {
+    #[serde(default)]
+    pub max_transactions: Option<U64>,
+    #[serde(default)]
+    pub gas: Option<U64>,
+    /// Pubdata in bytes.
+    #[serde(default)]
+    pub pubdata: Option<U64>,
+    #[serde(default)]
+    pub circuits: Option<U64>,
+    /// Space in the bootloader memory, in words.
+    #[serde(default)]
+    pub encoding_size: Option<U64>,
+}
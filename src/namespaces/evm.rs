@@ -78,4 +78,15 @@ pub trait EvmNamespaceT {
     /// `true` if a snapshot was reverted, otherwise `false`.
     #[rpc(name = "evm_revert")]
     fn revert_snapshot(&self, snapshot_id: U64) -> RpcResult<bool>;
+
+    /// Sets the maximum gas the transactions of the next batches may use. Transactions that no longer fit into a
+    /// batch are moved to the next one, and a transaction requiring more gas on its own is rejected.
+    ///
+    /// # Parameters
+    /// - `gas_limit`: The new gas limit
+    ///
+    /// # Returns
+    /// `true` if the gas limit was set.
+    #[rpc(name = "evm_setBlockGasLimit")]
+    fn set_block_gas_limit(&self, gas_limit: U64) -> RpcResult<bool>;
 }
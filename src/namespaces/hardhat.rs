@@ -1,6 +1,6 @@
 use jsonrpc_derive::rpc;
 use serde::{Deserialize, Serialize};
-use zksync_types::{Address, U256, U64};
+use zksync_types::{Address, H256, U256, U64};
 
 use super::RpcResult;
 
@@ -22,6 +22,29 @@ pub struct ResetRequest {
     pub forking: Option<ResetRequestForking>,
 }
 
+/// Metadata of the running chain returned by `hardhat_metadata`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardhatMetadata {
+    pub client_version: String,
+    pub chain_id: u64,
+    /// Random identifier of the chain, changes whenever the chain is reset.
+    pub instance_id: H256,
+    pub latest_block_number: u64,
+    pub latest_block_hash: H256,
+    /// Set if the chain is forked from another network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_network: Option<ForkedNetwork>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkedNetwork {
+    pub chain_id: u64,
+    pub fork_block_number: u64,
+    pub fork_block_hash: H256,
+}
+
 #[rpc]
 pub trait HardhatNamespaceT {
     /// Sets the balance of the given address to the given balance.
@@ -149,4 +172,83 @@ pub trait HardhatNamespaceT {
     /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
     #[rpc(name = "hardhat_setStorageAt")]
     fn set_storage_at(&self, address: Address, slot: U256, value: U256) -> RpcResult<bool>;
+
+    /// Sets the coinbase address, returned by `block.coinbase` in the next blocks. On ZKsync the coinbase is the
+    /// fee account of a batch, so it also receives the fees of the following batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The new coinbase address
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
+    #[rpc(name = "hardhat_setCoinbase")]
+    fn set_coinbase(&self, address: Address) -> RpcResult<bool>;
+
+    /// Sets the value returned by `block.prevrandao`. It is kept by the `SystemContext` contract, so it applies to
+    /// the next block and every block after it until it is set again.
+    ///
+    /// # Arguments
+    ///
+    /// * `prev_randao` - The new PREVRANDAO value
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
+    #[rpc(name = "hardhat_setPrevRandao")]
+    fn set_prev_randao(&self, prev_randao: H256) -> RpcResult<bool>;
+
+    /// Returns the metadata of the running chain, including the forked network if any.
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with the [HardhatMetadata] of the chain.
+    #[rpc(name = "hardhat_metadata")]
+    fn metadata(&self) -> RpcResult<HardhatMetadata>;
+
+    /// Sets the base fee of the next block, like `anvil_setNextBlockBaseFeePerGas`.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_fee` - Value to be set as base fee for the next block
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
+    #[rpc(name = "hardhat_setNextBlockBaseFeePerGas")]
+    fn set_next_block_base_fee_per_gas(&self, base_fee: U256) -> RpcResult<bool>;
+
+    /// Removes a transaction from the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - Hash of the transaction to be removed from the pool
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with `true` if the transaction was in the pool, `false` otherwise.
+    #[rpc(name = "hardhat_dropTransaction")]
+    fn drop_transaction(&self, hash: H256) -> RpcResult<bool>;
+
+    /// Set the minimum gas price for the node. Unsupported for ZKsync as it is only relevant for pre-EIP1559
+    /// chains, Hardhat rejects it as well once EIP-1559 is active.
+    ///
+    /// # Arguments
+    ///
+    /// * `gas_price` - The minimum gas price to be set
+    #[rpc(name = "hardhat_setMinGasPrice")]
+    fn set_min_gas_price(&self, gas_price: U256) -> RpcResult<bool>;
+
+    /// Enable or disable logging.
+    ///
+    /// # Arguments
+    ///
+    /// * `enable` - if `true` logging will be enabled, disabled otherwise
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with a `bool` representing the success of the operation.
+    #[rpc(name = "hardhat_setLoggingEnabled")]
+    fn set_logging_enabled(&self, enable: bool) -> RpcResult<bool>;
 }
@@ -9,13 +9,17 @@ mod net;
 mod web3;
 mod zks;
 
-pub use anvil::{AnvilNamespaceT, DetailedTransaction};
+pub use anvil::{
+    AnvilNamespaceT, BatchLimitsUpdate, BootloaderUpgrade, DecodedStorageChange,
+    DetailedTransaction, FeeParamsUpdate, ForkInfo, NamedSnapshot, ProtocolUpgrade, StateDiff,
+    StateDiffPoint, StorageChange, SystemContractUpgrade,
+};
 pub use config::ConfigurationApiNamespaceT;
 pub use debug::DebugNamespaceT;
 pub use eth::EthNamespaceT;
 pub use eth_test::EthTestNodeNamespaceT;
 pub use evm::EvmNamespaceT;
-pub use hardhat::{HardhatNamespaceT, ResetRequest};
+pub use hardhat::{ForkedNetwork, HardhatMetadata, HardhatNamespaceT, ResetRequest};
 pub use net::NetNamespaceT;
 pub use web3::Web3NamespaceT;
 pub use zks::ZksNamespaceT;
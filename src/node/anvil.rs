@@ -1,9 +1,13 @@
-use zksync_types::api::Block;
+use zksync_types::api::{Block, DebugCall};
 use zksync_types::web3::Bytes;
 use zksync_types::{Address, H256, U256, U64};
 use zksync_web3_decl::error::Web3Error;
 
-use crate::namespaces::DetailedTransaction;
+use crate::config::Genesis;
+use crate::namespaces::{
+    BatchLimitsUpdate, DetailedTransaction, FeeParamsUpdate, ForkInfo, NamedSnapshot,
+    ProtocolUpgrade, StateDiff, StateDiffPoint,
+};
 use crate::utils::Numeric;
 use crate::{
     fork::ForkSource,
@@ -152,6 +156,238 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> AnvilNames
             .into_boxed_future()
     }
 
+    fn snapshot_named(&self, name: String) -> RpcResult<bool> {
+        self.create_named_snapshot(name)
+            .map_err(|err| {
+                tracing::error!("failed creating snapshot: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn list_snapshots(&self) -> RpcResult<Vec<NamedSnapshot>> {
+        self.list_named_snapshots()
+            .map_err(|err| {
+                tracing::error!("failed listing snapshots: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn delete_snapshot(&self, name: String) -> RpcResult<bool> {
+        self.delete_named_snapshot(name)
+            .map_err(|err| {
+                tracing::error!("failed deleting snapshot: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn revert_named(&self, name: String) -> RpcResult<bool> {
+        self.revert_to_named_snapshot(name)
+            .map_err(|err| {
+                tracing::error!("failed reverting snapshot: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn save_snapshot(&self, name: String) -> RpcResult<Bytes> {
+        self.save_named_snapshot(name)
+            .map_err(|err| {
+                tracing::error!("failed saving snapshot: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn load_snapshot(&self, name: String, bytes: Bytes) -> RpcResult<bool> {
+        self.load_named_snapshot(name, bytes)
+            .map_err(Into::into)
+            .into_boxed_future()
+    }
+
+    fn state_diff(&self, from: StateDiffPoint, to: StateDiffPoint) -> RpcResult<StateDiff> {
+        self.state_diff(from, to)
+            .map_err(|err| {
+                tracing::error!("failed computing state diff: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn replay_transaction(&self, hash: H256) -> RpcResult<DebugCall> {
+        self.replay_transaction(hash)
+            .map_err(|err| {
+                tracing::error!("failed replaying transaction: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn upgrade_protocol(&self, upgrade: ProtocolUpgrade) -> RpcResult<bool> {
+        self.upgrade_protocol(upgrade)
+            .map_err(|err| {
+                tracing::error!("failed upgrading protocol: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn export_genesis(&self) -> RpcResult<Genesis> {
+        self.export_genesis()
+            .map_err(|err| {
+                tracing::error!("failed exporting genesis: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn reload_system_contracts(&self) -> RpcResult<bool> {
+        self.reload_system_contracts()
+            .map_err(|err| {
+                tracing::error!("failed reloading system contracts: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_l1_gas_price(&self, price: U64) -> RpcResult<bool> {
+        self.set_l1_gas_price(price)
+            .map_err(|err| {
+                tracing::error!("failed setting L1 gas price: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_fair_pubdata_price(&self, price: U64) -> RpcResult<bool> {
+        self.set_fair_pubdata_price(price)
+            .map_err(|err| {
+                tracing::error!("failed setting fair pubdata price: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_minimal_l2_gas_price(&self, price: U64) -> RpcResult<bool> {
+        self.set_minimal_l2_gas_price(price)
+            .map_err(|err| {
+                tracing::error!("failed setting minimal L2 gas price: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_fee_params(&self, update: FeeParamsUpdate) -> RpcResult<bool> {
+        self.set_fee_params(update)
+            .map_err(|err| {
+                tracing::error!("failed setting fee params: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn schedule_fee_params(&self, batch: U64, update: FeeParamsUpdate) -> RpcResult<bool> {
+        self.schedule_fee_params(batch, update)
+            .map_err(|err| {
+                tracing::error!("failed scheduling fee params: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_gasless(&self, enable: bool) -> RpcResult<bool> {
+        self.set_gasless(enable)
+            .map_err(|err| {
+                tracing::error!("failed setting gasless mode: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_batch_limits(&self, limits: BatchLimitsUpdate) -> RpcResult<bool> {
+        self.set_batch_limits(limits)
+            .map_err(|err| {
+                tracing::error!("failed setting batch limits: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn rollback(&self, depth: Option<U64>, requeue: Option<bool>) -> RpcResult<()> {
+        self.rollback(depth, requeue)
+            .map_err(|err| {
+                tracing::error!("failed rolling back blocks: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn reorg(&self, depth: U64, txs: Option<Vec<Bytes>>) -> RpcResult<()> {
+        self.reorg(depth, txs.unwrap_or_default())
+            .map_err(|err| {
+                tracing::error!("failed reorganizing blocks: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn create_fork(
+        &self,
+        name: String,
+        url: Option<String>,
+        block_number: Option<U64>,
+    ) -> RpcResult<bool> {
+        self.create_fork(name, url, block_number, false)
+            .map_err(|err| {
+                tracing::error!("failed creating fork: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn create_select_fork(
+        &self,
+        name: String,
+        url: Option<String>,
+        block_number: Option<U64>,
+    ) -> RpcResult<bool> {
+        self.create_fork(name, url, block_number, true)
+            .map_err(|err| {
+                tracing::error!("failed creating fork: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn select_fork(&self, name: String) -> RpcResult<bool> {
+        self.select_fork(name)
+            .map_err(|err| {
+                tracing::error!("failed selecting fork: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn list_forks(&self) -> RpcResult<Vec<ForkInfo>> {
+        self.list_forks()
+            .map_err(|err| {
+                tracing::error!("failed listing forks: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn remove_fork(&self, name: String) -> RpcResult<bool> {
+        self.remove_fork(name)
+            .map_err(|err| {
+                tracing::error!("failed removing fork: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
     fn set_time(&self, timestamp: Numeric) -> RpcResult<i128> {
         self.set_time(timestamp)
             .map_err(|err| {
@@ -257,4 +493,28 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> AnvilNames
             })
             .into_boxed_future()
     }
+
+    fn deal_erc20(&self, token: Address, account: Address, amount: U256) -> RpcResult<bool> {
+        self.deal_erc20(token, account, amount)
+            .map_err(|err| {
+                tracing::error!("failed setting ERC20 balance: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_erc20_allowance(
+        &self,
+        token: Address,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> RpcResult<bool> {
+        self.set_erc20_allowance(token, owner, spender, amount)
+            .map_err(|err| {
+                tracing::error!("failed setting ERC20 allowance: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
 }
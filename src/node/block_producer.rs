@@ -2,7 +2,6 @@ use crate::fork::ForkSource;
 use crate::node::pool::{TxBatch, TxPool};
 use crate::node::sealer::BlockSealer;
 use crate::node::InMemoryNode;
-use crate::system_contracts::SystemContracts;
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
@@ -13,21 +12,14 @@ pub struct BlockProducer<S: Clone> {
     node: InMemoryNode<S>,
     pool: TxPool,
     block_sealer: BlockSealer,
-    system_contracts: SystemContracts,
 }
 
 impl<S: Clone> BlockProducer<S> {
-    pub fn new(
-        node: InMemoryNode<S>,
-        pool: TxPool,
-        block_sealer: BlockSealer,
-        system_contracts: SystemContracts,
-    ) -> Self {
+    pub fn new(node: InMemoryNode<S>, pool: TxPool, block_sealer: BlockSealer) -> Self {
         Self {
             node,
             pool,
             block_sealer,
-            system_contracts,
         }
     }
 }
@@ -42,9 +34,9 @@ impl<S: ForkSource + Clone + fmt::Debug> Future for BlockProducer<S> {
                 let TxBatch { impersonating, txs } = tx_batch;
 
                 let base_system_contracts = pin
-                    .system_contracts
-                    .contracts(TxExecutionMode::VerifyExecute, impersonating)
-                    .clone();
+                    .node
+                    .base_system_contracts(TxExecutionMode::VerifyExecute, impersonating)
+                    .expect("failed reading system contracts");
                 pin.node
                     .seal_block(&mut pin.node.time.lock(), txs, base_system_contracts)
                     .expect("block sealing failed");
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use once_cell::sync::OnceCell;
+use zksync_multivm::interface::storage::ReadStorage;
+use zksync_multivm::interface::{L1BatchEnv, SystemEnv};
+use zksync_types::api::Log;
+use zksync_types::{StorageKey, StorageValue, H256};
+
+use crate::fork::{ForkSource, ForkStorage};
+
+/// View of the node state as of the last sealed block.
+///
+/// It is published right before a new block starts being sealed and dropped once the block is applied, so that reads
+/// can be served from it instead of waiting for [super::InMemoryNodeInner] while the block is being built. Nothing is
+/// copied: storage is read from the live [ForkStorage], except for the keys written by the block being sealed, whose
+/// previous values are kept in [CommittedState::retain_values] before they are overwritten.
+#[derive(Debug)]
+pub struct CommittedState<S> {
+    /// Number of the last sealed block.
+    pub block_number: u64,
+    /// Hash of the last sealed block.
+    pub block_hash: H256,
+    /// Number of logs emitted up to the last sealed block, see [CommittedStateHandle::logs].
+    pub log_count: usize,
+    /// Batch environment to execute calls on top of the last sealed block.
+    pub batch_env: L1BatchEnv,
+    /// System environment to execute calls with.
+    pub system_env: SystemEnv,
+    /// Live storage of the node.
+    pub fork_storage: ForkStorage<S>,
+    /// Local values as of the last sealed block of the keys overwritten by the block being sealed.
+    overwritten: OnceCell<HashMap<StorageKey, StorageValue>>,
+}
+
+impl<S> CommittedState<S> {
+    pub fn new(
+        block_number: u64,
+        block_hash: H256,
+        log_count: usize,
+        batch_env: L1BatchEnv,
+        system_env: SystemEnv,
+        fork_storage: ForkStorage<S>,
+    ) -> Self {
+        Self {
+            block_number,
+            block_hash,
+            log_count,
+            batch_env,
+            system_env,
+            fork_storage,
+            overwritten: OnceCell::new(),
+        }
+    }
+}
+
+impl<S: ForkSource + std::fmt::Debug> CommittedState<S> {
+    /// Keeps the current local values of `keys`, which must be called before the sealed block overwrites them.
+    /// This is O(number of keys) and only ever done once per block.
+    pub fn retain_values<'a>(&self, keys: impl Iterator<Item = &'a StorageKey>) {
+        let values = keys
+            .map(|key| (*key, self.fork_storage.read_local_value(key)))
+            .collect();
+        if self.overwritten.set(values).is_err() {
+            tracing::error!("values of the committed state were already retained");
+        }
+    }
+
+    /// Reads the value of `key` as of the last sealed block.
+    pub fn read_value(&self, key: &StorageKey) -> eyre::Result<StorageValue> {
+        // The live value must be read first: the overwritten values are always retained before the live ones
+        // change, so if the live value is already the new one, the previous one is retained as well.
+        let live = self.fork_storage.read_local_value(key);
+        let local = self
+            .overwritten
+            .get()
+            .and_then(|overwritten| overwritten.get(key).copied())
+            .unwrap_or(live);
+        if !local.is_zero() {
+            return Ok(local);
+        }
+        self.fork_storage.read_fork_value_internal(key)
+    }
+
+    /// Loads the bytecode for the given hash. Factory dependencies are never removed while sealing,
+    /// so they are read from the live storage.
+    pub fn load_factory_dep(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
+        self.fork_storage.load_factory_dep_internal(hash)
+    }
+}
+
+impl<S: ForkSource + std::fmt::Debug> ReadStorage for &CommittedState<S> {
+    fn read_value(&mut self, key: &StorageKey) -> StorageValue {
+        CommittedState::read_value(self, key).unwrap()
+    }
+
+    fn is_write_initial(&mut self, key: &StorageKey) -> bool {
+        CommittedState::read_value(self, key).unwrap().is_zero()
+    }
+
+    fn load_factory_dep(&mut self, hash: H256) -> Option<Vec<u8>> {
+        CommittedState::load_factory_dep(self, hash).unwrap()
+    }
+
+    fn get_enumeration_index(&mut self, key: &StorageKey) -> Option<u64> {
+        self.fork_storage.get_enumeration_index_internal(key)
+    }
+}
+
+/// Logs of the sealed blocks, only ever appended to while blocks are sealed.
+#[derive(Debug, Default)]
+pub struct SealedLogs {
+    /// Hash of the latest block the logs were collected for.
+    block_hash: H256,
+    /// Number of transactions the logs were collected from.
+    tx_count: usize,
+    logs: Vec<Log>,
+}
+
+impl SealedLogs {
+    /// Returns the logs emitted up to the block of `committed`.
+    pub fn up_to<S>(&self, committed: &CommittedState<S>) -> &[Log] {
+        &self.logs[..committed.log_count.min(self.logs.len())]
+    }
+}
+
+/// Shared handle to the latest [CommittedState].
+///
+/// The lock is only ever held to clone or swap the inner [Arc], so readers never wait for block sealing.
+#[derive(Debug)]
+pub struct CommittedStateHandle<S> {
+    state: Arc<RwLock<Option<Arc<CommittedState<S>>>>>,
+    logs: Arc<RwLock<SealedLogs>>,
+}
+
+impl<S> Clone for CommittedStateHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            logs: self.logs.clone(),
+        }
+    }
+}
+
+impl<S> Default for CommittedStateHandle<S> {
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            logs: Default::default(),
+        }
+    }
+}
+
+impl<S> CommittedStateHandle<S> {
+    /// Returns the committed state if a block is currently being sealed.
+    pub fn load(&self) -> Option<Arc<CommittedState<S>>> {
+        self.state
+            .read()
+            .expect("CommittedStateHandle lock is poisoned")
+            .clone()
+    }
+
+    /// Publishes the committed state before a block starts being sealed.
+    pub fn store(&self, state: Arc<CommittedState<S>>) {
+        *self
+            .state
+            .write()
+            .expect("CommittedStateHandle lock is poisoned") = Some(state);
+    }
+
+    /// Drops the committed state once the sealed block has been applied.
+    pub fn clear(&self) {
+        *self
+            .state
+            .write()
+            .expect("CommittedStateHandle lock is poisoned") = None;
+    }
+
+    /// Drops the committed state along with the collected logs, e.g. when another fork is selected.
+    pub fn reset(&self) {
+        self.clear();
+        *self
+            .logs
+            .write()
+            .expect("CommittedStateHandle lock is poisoned") = SealedLogs::default();
+    }
+
+    /// Returns the logs of the sealed blocks, see [SealedLogs::up_to].
+    pub fn logs(&self) -> RwLockReadGuard<'_, SealedLogs> {
+        self.logs
+            .read()
+            .expect("CommittedStateHandle lock is poisoned")
+    }
+
+    /// Returns the number of logs emitted up to the block `block_hash`, after `tx_count` transactions.
+    ///
+    /// The logs are only collected again with `collect` if the chain was changed other than by sealing blocks,
+    /// e.g. by a revert or a reset.
+    pub fn sync_logs(
+        &self,
+        block_hash: H256,
+        tx_count: usize,
+        collect: impl FnOnce() -> Vec<Log>,
+    ) -> usize {
+        let mut sealed = self
+            .logs
+            .write()
+            .expect("CommittedStateHandle lock is poisoned");
+        if sealed.block_hash != block_hash || sealed.tx_count != tx_count {
+            *sealed = SealedLogs {
+                block_hash,
+                tx_count,
+                logs: collect(),
+            };
+        }
+        sealed.logs.len()
+    }
+
+    /// Appends the logs of a newly sealed block.
+    pub fn append_logs(&self, block_hash: H256, tx_count: usize, logs: impl Iterator<Item = Log>) {
+        let mut sealed = self
+            .logs
+            .write()
+            .expect("CommittedStateHandle lock is poisoned");
+        sealed.block_hash = block_hash;
+        sealed.tx_count = tx_count;
+        sealed.logs.extend(logs);
+    }
+}
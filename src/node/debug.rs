@@ -20,7 +20,7 @@ use crate::{
     fork::ForkSource,
     namespaces::{DebugNamespaceT, Result, RpcResult},
     node::{InMemoryNode, MAX_TX_SIZE},
-    utils::{create_debug_output, into_jsrpc_error, to_real_block_number},
+    utils::{create_debug_output, into_jsrpc_error, spawn_blocking_rpc, to_real_block_number},
 };
 
 impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> DebugNamespaceT
@@ -145,8 +145,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> DebugNames
         let only_top = options.is_some_and(|o| o.tracer_config.only_top_call);
         let inner = self.get_inner().clone();
         let time = self.time.clone();
-        let system_contracts = self.system_contracts.contracts_for_l2_call().clone();
-        Box::pin(async move {
+        spawn_blocking_rpc(move || {
             if block.is_some() && !matches!(block, Some(BlockId::Number(BlockNumber::Latest))) {
                 return Err(jsonrpc_core::Error::invalid_params(
                     "tracing only supported at `latest` block",
@@ -159,6 +158,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> DebugNames
                 )))
             })?;
 
+            let system_contracts = inner.system_contracts.contracts_for_l2_call().clone();
             let allow_no_target = system_contracts.evm_emulator.is_some();
             let mut l2_tx = L2Tx::from_request(request.into(), MAX_TX_SIZE, allow_no_target)
                 .map_err(|err| into_jsrpc_error(Web3Error::SerializationError(err)))?;
@@ -171,7 +171,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> DebugNames
 
             // update the enforced_base_fee within l1_batch_env to match the logic in zksync_core
             l1_batch_env.enforced_base_fee = Some(l2_tx.common_data.fee.max_fee_per_gas.as_u64());
-            let system_env = inner.create_system_env(system_contracts.clone(), execution_mode);
+            let system_env = inner.create_system_env(system_contracts, execution_mode);
             let mut vm: Vm<_, HistoryDisabled> = Vm::new(l1_batch_env, system_env, storage);
 
             // We must inject *some* signature (otherwise bootloader code fails to generate hash).
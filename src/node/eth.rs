@@ -8,7 +8,6 @@ use zksync_multivm::interface::{ExecutionResult, TxExecutionMode};
 use zksync_multivm::vm_latest::constants::ETH_CALL_GAS_LIMIT;
 use zksync_types::{
     api::{Block, BlockIdVariant, BlockNumber, TransactionVariant},
-    fee::Fee,
     get_code_key, get_nonce_key,
     l2::L2Tx,
     transaction_request::TransactionRequest,
@@ -31,8 +30,9 @@ use crate::{
     namespaces::{EthNamespaceT, EthTestNodeNamespaceT, RpcResult},
     node::{InMemoryNode, TransactionResult, MAX_TX_SIZE, PROTOCOL_VERSION},
     utils::{
-        self, h256_to_u64, into_jsrpc_error, not_implemented, report_into_jsrpc_error,
-        IntoBoxedFuture, TransparentError,
+        self, h256_to_u64, into_jsrpc_error, is_evm_bytecode_hash, not_implemented,
+        report_into_jsrpc_error, spawn_blocking_rpc, trim_padded_evm_bytecode, IntoBoxedFuture,
+        TransparentError,
     },
 };
 
@@ -41,7 +41,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         &self,
         req: zksync_types::transaction_request::CallRequest,
     ) -> Result<Bytes, Web3Error> {
-        let system_contracts = self.system_contracts.contracts_for_l2_call().clone();
+        let system_contracts = self.base_system_contracts(TxExecutionMode::EthCall, false)?;
         let allow_no_target = system_contracts.evm_emulator.is_some();
 
         let mut tx = L2Tx::from_request(req.into(), MAX_TX_SIZE, allow_no_target)?;
@@ -80,7 +80,15 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         }
     }
 
-    fn send_raw_transaction_impl(&self, tx_bytes: Bytes) -> Result<H256, Web3Error> {
+    pub(crate) fn send_raw_transaction_impl(&self, tx_bytes: Bytes) -> Result<H256, Web3Error> {
+        let l2_tx = self.decode_raw_transaction(tx_bytes)?;
+        let hash = l2_tx.hash();
+        self.pool.add_tx(l2_tx);
+        Ok(hash)
+    }
+
+    /// Decodes a raw signed transaction and checks that its hash matches the signed data.
+    pub(crate) fn decode_raw_transaction(&self, tx_bytes: Bytes) -> Result<L2Tx, Web3Error> {
         let chain_id = self
             .get_inner()
             .read()
@@ -105,8 +113,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
             return Err(err.into());
         };
 
-        self.pool.add_tx(l2_tx);
-        Ok(hash)
+        Ok(l2_tx)
     }
 
     fn send_transaction_impl(
@@ -120,7 +127,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
                 .map_err(|_| anyhow::anyhow!("Failed to acquire read lock"))?;
             (
                 reader.fork_storage.chain_id,
-                reader.fee_input_provider.gas_price(),
+                reader
+                    .fee_input_provider
+                    .gas_price(reader.protocol_version()),
             )
         };
 
@@ -219,9 +228,8 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
         req: zksync_types::transaction_request::CallRequest,
         _block: Option<BlockIdVariant>,
     ) -> RpcResult<Bytes> {
-        self.call_impl(req)
-            .map_err(into_jsrpc_error)
-            .into_boxed_future()
+        let node = self.clone();
+        spawn_blocking_rpc(move || node.call_impl(req).map_err(into_jsrpc_error))
     }
 
     /// Returns the balance of the specified address.
@@ -235,7 +243,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
     ///
     /// A `BoxFuture` that resolves to a `Result` containing the balance of the specified address as a `U256` or a `jsonrpc_core::Error` if an error occurred.
     fn get_balance(&self, address: Address, _block: Option<BlockIdVariant>) -> RpcResult<U256> {
-        let inner = self.get_inner().clone();
+        let node = self.clone();
 
         Box::pin(async move {
             let balance_key = storage_key_for_standard_token_balance(
@@ -243,19 +251,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
                 &address,
             );
 
-            match inner.write() {
-                Ok(inner_guard) => {
-                    match inner_guard.fork_storage.read_value_internal(&balance_key) {
-                        Ok(balance) => Ok(h256_to_u256(balance)),
-                        Err(error) => Err(report_into_jsrpc_error(error)),
-                    }
-                }
-                Err(_) => {
-                    let error_message = "Error acquiring write lock for balance retrieval";
-                    let web3_error = Web3Error::InternalError(anyhow::Error::msg(error_message));
-                    Err(into_jsrpc_error(web3_error))
-                }
-            }
+            node.read_latest_value(&balance_key)
+                .map(h256_to_u256)
+                .map_err(report_into_jsrpc_error)
         })
     }
 
@@ -362,28 +360,25 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
         address: zksync_types::Address,
         _block: Option<BlockIdVariant>,
     ) -> RpcResult<Bytes> {
-        let inner = self.get_inner().clone();
+        let node = self.clone();
 
         Box::pin(async move {
             let code_key = get_code_key(&address);
 
-            match inner.write() {
-                Ok(guard) => match guard.fork_storage.read_value_internal(&code_key) {
-                    Ok(code_hash) => {
-                        match guard.fork_storage.load_factory_dep_internal(code_hash) {
-                            Ok(raw_code) => {
-                                let code = raw_code.unwrap_or_default();
-                                Ok(Bytes::from(code))
-                            }
-                            Err(error) => Err(report_into_jsrpc_error(error)),
-                        }
-                    }
-                    Err(error) => Err(report_into_jsrpc_error(error)),
-                },
-                Err(_) => Err(into_jsrpc_error(Web3Error::InternalError(
-                    anyhow::Error::msg("Failed to acquire write lock for code retrieval"),
-                ))),
+            let code_hash = node
+                .read_latest_value(&code_key)
+                .map_err(report_into_jsrpc_error)?;
+            let raw_code = node
+                .load_latest_factory_dep(code_hash)
+                .map_err(report_into_jsrpc_error)?
+                .unwrap_or_default();
+            // EVM bytecode is stored padded behind the EVM emulator, return the original one
+            if is_evm_bytecode_hash(code_hash) {
+                return trim_padded_evm_bytecode(&raw_code)
+                    .map(|code| Bytes::from(code.to_vec()))
+                    .map_err(|err| into_jsrpc_error(Web3Error::InternalError(err)));
             }
+            Ok(Bytes::from(raw_code))
         })
     }
 
@@ -402,20 +397,14 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
         address: zksync_types::Address,
         _block: Option<BlockIdVariant>,
     ) -> RpcResult<U256> {
-        let inner = self.get_inner().clone();
+        let node = self.clone();
 
         Box::pin(async move {
             let nonce_key = get_nonce_key(&address);
 
-            match inner.write() {
-                Ok(guard) => match guard.fork_storage.read_value_internal(&nonce_key) {
-                    Ok(result) => Ok(h256_to_u64(result).into()),
-                    Err(error) => Err(report_into_jsrpc_error(error)),
-                },
-                Err(_) => Err(into_jsrpc_error(Web3Error::InternalError(
-                    anyhow::Error::msg("Failed to acquire write lock for nonce retrieval"),
-                ))),
-            }
+            node.read_latest_value(&nonce_key)
+                .map(|result| h256_to_u64(result).into())
+                .map_err(report_into_jsrpc_error)
         })
     }
 
@@ -660,31 +649,28 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
         _block: Option<BlockNumber>,
     ) -> RpcResult<U256> {
         let inner = self.get_inner().clone();
-        let reader = match inner.read() {
-            Ok(r) => r,
-            Err(_) => {
-                return futures::future::err(into_jsrpc_error(Web3Error::InternalError(
-                    anyhow::Error::msg("Failed to acquire read lock for gas estimation."),
+        let time = self.time.clone();
+        spawn_blocking_rpc(move || {
+            let reader = inner.read().map_err(|_| {
+                into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(
+                    "Failed to acquire read lock for gas estimation.",
                 )))
-                .boxed()
-            }
-        };
-
-        let result: jsonrpc_core::Result<Fee> = reader.estimate_gas_impl(&self.time, req);
-        match result {
-            Ok(fee) => Ok(fee.gas_limit).into_boxed_future(),
-            Err(err) => return futures::future::err(err).boxed(),
-        }
+            })?;
+            reader
+                .estimate_gas_impl(&time, req)
+                .map(|fee| fee.gas_limit)
+        })
     }
 
     /// Returns the current gas price in U256 format.
     fn gas_price(&self) -> RpcResult<U256> {
-        let fair_l2_gas_price: u64 = self
+        let reader = self
             .get_inner()
             .read()
-            .expect("Failed to acquire read lock")
+            .expect("Failed to acquire read lock");
+        let fair_l2_gas_price: u64 = reader
             .fee_input_provider
-            .gas_price();
+            .gas_price(reader.protocol_version());
         Ok(U256::from(fair_l2_gas_price)).into_boxed_future()
     }
 
@@ -848,16 +834,6 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
     ///
     /// A `BoxFuture` containing a `jsonrpc_core::Result` that resolves to an array of logs.
     fn get_logs(&self, filter: Filter) -> RpcResult<Vec<zksync_types::api::Log>> {
-        let inner = self.get_inner();
-        let reader = match inner.read() {
-            Ok(r) => r,
-            Err(_) => {
-                return futures::future::err(into_jsrpc_error(Web3Error::InternalError(
-                    anyhow::Error::msg("Failed to acquire read lock for logs."),
-                )))
-                .boxed()
-            }
-        };
         let from_block = filter.from_block.unwrap_or(BlockNumber::Earliest);
         let to_block = filter.to_block.unwrap_or(BlockNumber::Latest);
         let addresses = filter.address.unwrap_or_default().0;
@@ -877,6 +853,30 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
 
         let log_filter = LogFilter::new(from_block, to_block, addresses, topics);
 
+        // Serve logs from the last sealed block while a new one is being sealed
+        if let Some(committed) = self.committed_state() {
+            let latest_block_number = U64::from(committed.block_number);
+            let logs = self
+                .committed
+                .logs()
+                .up_to(&committed)
+                .iter()
+                .filter(|log| log_filter.matches(log, latest_block_number))
+                .cloned()
+                .collect_vec();
+            return Ok(logs).into_boxed_future();
+        }
+
+        let inner = self.get_inner();
+        let reader = match inner.read() {
+            Ok(r) => r,
+            Err(_) => {
+                return futures::future::err(into_jsrpc_error(Web3Error::InternalError(
+                    anyhow::Error::msg("Failed to acquire read lock for logs."),
+                )))
+                .boxed()
+            }
+        };
         let latest_block_number = U64::from(reader.current_miniblock);
         let logs = reader
             .tx_results
@@ -1087,30 +1087,42 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
         block: Option<BlockIdVariant>,
     ) -> RpcResult<zksync_types::H256> {
         let inner = self.get_inner().clone();
+        let committed = match block {
+            None | Some(BlockIdVariant::BlockNumber(BlockNumber::Latest)) => self.committed_state(),
+            _ => None,
+        };
 
         Box::pin(async move {
-            let writer = match inner.write() {
+            let storage_key = StorageKey::new(AccountTreeId::new(address), u256_to_h256(idx));
+
+            // Serve the latest value from the last sealed block while a new one is being sealed
+            if let Some(committed) = committed {
+                return committed
+                    .read_value(&storage_key)
+                    .map(|value| H256(value.0))
+                    .map_err(report_into_jsrpc_error);
+            }
+
+            let reader = match inner.read() {
                 Ok(r) => r,
                 Err(_) => {
                     return Err(into_jsrpc_error(Web3Error::InternalError(
-                        anyhow::Error::msg("Failed to acquire write lock for storage retrieval."),
+                        anyhow::Error::msg("Failed to acquire read lock for storage retrieval."),
                     )));
                 }
             };
 
-            let storage_key = StorageKey::new(AccountTreeId::new(address), u256_to_h256(idx));
-
             let block_number = block
                 .map(|block| match block {
                     BlockIdVariant::BlockNumber(block_number) => Ok(utils::to_real_block_number(
                         block_number,
-                        U64::from(writer.current_miniblock),
+                        U64::from(reader.current_miniblock),
                     )),
                     BlockIdVariant::BlockNumberObject(o) => Ok(utils::to_real_block_number(
                         o.block_number,
-                        U64::from(writer.current_miniblock),
+                        U64::from(reader.current_miniblock),
                     )),
-                    BlockIdVariant::BlockHashObject(o) => writer
+                    BlockIdVariant::BlockHashObject(o) => reader
                         .blocks
                         .get(&o.block_hash)
                         .map(|block| block.number)
@@ -1124,24 +1136,24 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
                             )))
                         }),
                 })
-                .unwrap_or_else(|| Ok(U64::from(writer.current_miniblock)))?;
+                .unwrap_or_else(|| Ok(U64::from(reader.current_miniblock)))?;
 
-            if block_number.as_u64() == writer.current_miniblock {
-                match writer.fork_storage.read_value_internal(&storage_key) {
+            if block_number.as_u64() == reader.current_miniblock {
+                match reader.fork_storage.read_value_internal(&storage_key) {
                     Ok(value) => Ok(H256(value.0)),
                     Err(error) => Err(report_into_jsrpc_error(error)),
                 }
-            } else if writer.block_hashes.contains_key(&block_number.as_u64()) {
-                let value = writer
+            } else if reader.block_hashes.contains_key(&block_number.as_u64()) {
+                let value = reader
                     .block_hashes
                     .get(&block_number.as_u64())
-                    .and_then(|block_hash| writer.previous_states.get(block_hash))
+                    .and_then(|block_hash| reader.previous_states.get(block_hash))
                     .and_then(|state| state.get(&storage_key))
                     .cloned()
                     .unwrap_or_default();
 
                 if value.is_zero() {
-                    match writer.fork_storage.read_value_internal(&storage_key) {
+                    match reader.fork_storage.read_value_internal(&storage_key) {
                         Ok(value) => Ok(H256(value.0)),
                         Err(error) => Err(report_into_jsrpc_error(error)),
                     }
@@ -1149,7 +1161,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
                     Ok(value)
                 }
             } else {
-                writer
+                reader
                     .fork_storage
                     .inner
                     .read()
@@ -1409,8 +1421,14 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> EthNamespa
                 // Can't be more than the total number of blocks
                 .clamp(1, reader.current_miniblock + 1);
 
-            let mut base_fee_per_gas =
-                vec![U256::from(reader.fee_input_provider.gas_price()); block_count as usize];
+            let mut base_fee_per_gas = vec![
+                U256::from(
+                    reader
+                        .fee_input_provider
+                        .gas_price(reader.protocol_version())
+                );
+                block_count as usize
+            ];
 
             let oldest_block = reader.current_miniblock + 1 - base_fee_per_gas.len() as u64;
             // We do not store gas used ratio for blocks, returns array of zeroes as a placeholder.
@@ -1470,13 +1488,14 @@ mod tests {
         },
         fork::ForkDetails,
         http_fork_source::HttpForkSource,
-        node::{compute_hash, InMemoryNode, Snapshot},
+        node::{compute_hash, InMemoryNode, JournalDepths, Snapshot},
         testing::{
             self, default_tx_debug_info, ForkBlockConfig, LogBuilder, MockServer,
             TransactionResponseBuilder,
         },
     };
     use maplit::hashmap;
+    use std::sync::Arc;
     use zksync_multivm::utils::get_max_batch_gas_limit;
     use zksync_types::l2::TransactionType;
     use zksync_types::vm::VmVersion;
@@ -1491,7 +1510,7 @@ mod tests {
 
     async fn test_node(url: &str) -> InMemoryNode<HttpForkSource> {
         InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(url, None, &CacheConfig::None)
+            ForkDetails::from_network(url, &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ))
@@ -1504,6 +1523,65 @@ mod tests {
         assert!(matches!(syncing, SyncState::NotSyncing));
     }
 
+    #[tokio::test]
+    async fn test_slow_call_does_not_block_block_number() {
+        use crate::config::constants::{
+            DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR, DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+            DEFAULT_FAIR_PUBDATA_PRICE, TEST_NODE_NETWORK_ID,
+        };
+
+        // Every storage read of the fork takes a while, so the call is slow
+        let raw_storage = InMemoryNode::<HttpForkSource>::default()
+            .get_inner()
+            .read()
+            .unwrap()
+            .fork_storage
+            .inner
+            .read()
+            .unwrap()
+            .raw_storage
+            .clone();
+        let node = InMemoryNode::<testing::ExternalStorage>::default_fork(Some(ForkDetails {
+            fork_source: Arc::new(testing::ExternalStorage {
+                raw_storage,
+                read_delay: std::time::Duration::from_millis(10),
+                transactions: Default::default(),
+            }),
+            chain_id: TEST_NODE_NETWORK_ID.into(),
+            l1_block: zksync_types::L1BatchNumber(1),
+            l2_block: Block::default(),
+            l2_miniblock: 2,
+            l2_miniblock_hash: Default::default(),
+            block_timestamp: 1002,
+            overwrite_chain_id: None,
+            l1_gas_price: 1000,
+            l2_fair_gas_price: DEFAULT_L2_GAS_PRICE,
+            fair_pubdata_price: DEFAULT_FAIR_PUBDATA_PRICE,
+            fee_params: None,
+            estimate_gas_price_scale_factor: DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR,
+            estimate_gas_scale_factor: DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+            cache_config: CacheConfig::None,
+        }));
+
+        let request = zksync_types::transaction_request::CallRequestBuilder::default()
+            .to(Some(H160::repeat_byte(0x1)))
+            .build();
+        let call = tokio::spawn(node.call(request, None));
+        // Let the call start executing
+        tokio::task::yield_now().await;
+
+        let block_number = node
+            .get_block_number()
+            .await
+            .expect("failed getting block number");
+        assert_eq!(U64::from(2), block_number);
+        assert!(!call.is_finished(), "call finished before the block number");
+
+        call.await
+            .expect("call task panicked")
+            .expect("failed calling");
+    }
+
     #[tokio::test]
     async fn test_get_fee_history_with_1_block() {
         let node = InMemoryNode::<HttpForkSource>::default();
@@ -1669,7 +1747,9 @@ mod tests {
             number: U64::from(1),
             l1_batch_number: Some(U64::from(1)),
             gas_used: actual_block.gas_used, // Checked separately, see below
-            gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::latest())),
+            gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::from(
+                node.read_inner().unwrap().protocol_version(),
+            ))),
             base_fee_per_gas: actual_block.base_fee_per_gas, // Checked separately, see below
             extra_data: Default::default(),
             logs_bloom: actual_block.logs_bloom, // Checked separately, see below
@@ -1798,7 +1878,9 @@ mod tests {
             number: U64::from(expected_block_number),
             l1_batch_number: Some(U64::from(1)),
             gas_used: actual_block.gas_used, // Checked separately, see below
-            gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::latest())),
+            gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::from(
+                node.read_inner().unwrap().protocol_version(),
+            ))),
             base_fee_per_gas: actual_block.base_fee_per_gas, // Checked separately, see below
             extra_data: Default::default(),
             logs_bloom: actual_block.logs_bloom, // Checked separately, see below
@@ -2476,9 +2558,9 @@ mod tests {
 
                 writer.previous_states.insert(
                     historical_block.hash,
-                    hashmap! {
+                    Arc::new(hashmap! {
                         input_storage_key => input_storage_value,
-                    },
+                    }),
                 );
                 writer
                     .blocks
@@ -2562,6 +2644,50 @@ mod tests {
         assert_eq!(input_storage_value, actual_value);
     }
 
+    #[tokio::test]
+    async fn test_get_storage_returns_state_as_of_the_end_of_historical_block() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let mut builder = testing::TransactionBuilder::new();
+        let tx0 = builder
+            .set_hash(H256::repeat_byte(0x1))
+            .set_nonce(0)
+            .build();
+        let tx1 = builder
+            .set_hash(H256::repeat_byte(0x2))
+            .set_nonce(1)
+            .build();
+        let from_account = tx0.common_data.initiator_address;
+        node.set_rich_account(from_account, U256::from(DEFAULT_ACCOUNT_BALANCE));
+
+        // Block 1 executes `tx0` (block 2 is virtual) and block 3 executes `tx1` (block 4 is virtual)
+        node.apply_txs(vec![tx0, tx1], 1)
+            .expect("failed applying txs");
+
+        let nonce_key = get_nonce_key(&from_account);
+        let mut nonces = Vec::new();
+        for number in 1..=4u64 {
+            let value = node
+                .get_storage(
+                    *nonce_key.address(),
+                    h256_to_u256(*nonce_key.key()),
+                    Some(zksync_types::api::BlockIdVariant::BlockNumberObject(
+                        BlockNumberObject {
+                            block_number: BlockNumber::Number(U64::from(number)),
+                        },
+                    )),
+                )
+                .await
+                .expect("failed retrieving storage");
+            nonces.push(h256_to_u256(value));
+        }
+
+        // Each block reports the state after its own transactions and before any later ones
+        assert_eq!(
+            vec![U256::one(), U256::one(), U256::from(2), U256::from(2)],
+            nonces
+        );
+    }
+
     #[tokio::test]
     async fn test_get_storage_fetches_state_for_deployed_smart_contract_in_current_block() {
         let node = InMemoryNode::<HttpForkSource>::default();
@@ -2865,24 +2991,33 @@ mod tests {
             H256::repeat_byte(0x1),
         );
 
-        let storage = inner.fork_storage.inner.read().unwrap();
+        let (expected_value_read_cache, expected_factory_dep_cache) = {
+            let storage = inner.fork_storage.inner.read().unwrap();
+            (
+                (*storage.value_read_cache).clone(),
+                (*storage.factory_dep_cache).clone(),
+            )
+        };
+        let actual_snapshot = inner.snapshot().expect("failed taking snapshot");
         let expected_snapshot = Snapshot {
             current_batch: inner.current_batch,
             current_miniblock: inner.current_miniblock,
             current_miniblock_hash: inner.current_miniblock_hash,
             fee_input_provider: inner.fee_input_provider.clone(),
-            tx_results: inner.tx_results.clone(),
-            blocks: inner.blocks.clone(),
-            block_hashes: inner.block_hashes.clone(),
+            journal_depths: JournalDepths {
+                tx_results: 0,
+                blocks: 0,
+                block_hashes: 0,
+                fork_storage: actual_snapshot.journal_depths.fork_storage,
+            },
             filters: inner.filters.clone(),
             impersonation_state: inner.impersonation.state(),
             rich_accounts: inner.rich_accounts.clone(),
             previous_states: inner.previous_states.clone(),
-            raw_storage: storage.raw_storage.clone(),
-            value_read_cache: storage.value_read_cache.clone(),
-            factory_dep_cache: storage.factory_dep_cache.clone(),
+            system_contracts: inner.system_contracts.clone(),
+            protocol_version: inner.config.protocol_version,
+            pending_protocol_upgrades: inner.pending_protocol_upgrades.clone(),
         };
-        let actual_snapshot = inner.snapshot().expect("failed taking snapshot");
 
         assert_eq!(
             expected_snapshot.current_batch,
@@ -2901,11 +3036,17 @@ mod tests {
             actual_snapshot.fee_input_provider
         );
         assert_eq!(
-            expected_snapshot.tx_results.keys().collect_vec(),
-            actual_snapshot.tx_results.keys().collect_vec()
+            expected_snapshot.journal_depths.tx_results,
+            actual_snapshot.journal_depths.tx_results
+        );
+        assert_eq!(
+            expected_snapshot.journal_depths.blocks,
+            actual_snapshot.journal_depths.blocks
+        );
+        assert_eq!(
+            expected_snapshot.journal_depths.block_hashes,
+            actual_snapshot.journal_depths.block_hashes
         );
-        assert_eq!(expected_snapshot.blocks, actual_snapshot.blocks);
-        assert_eq!(expected_snapshot.block_hashes, actual_snapshot.block_hashes);
         assert_eq!(expected_snapshot.filters, actual_snapshot.filters);
         assert_eq!(
             expected_snapshot.impersonation_state,
@@ -2919,15 +3060,33 @@ mod tests {
             expected_snapshot.previous_states,
             actual_snapshot.previous_states
         );
-        assert_eq!(expected_snapshot.raw_storage, actual_snapshot.raw_storage);
         assert_eq!(
-            expected_snapshot.value_read_cache,
-            actual_snapshot.value_read_cache
+            expected_snapshot
+                .system_contracts
+                .base_system_contracts_hashes(),
+            actual_snapshot
+                .system_contracts
+                .base_system_contracts_hashes()
+        );
+        assert_eq!(
+            expected_snapshot.protocol_version,
+            actual_snapshot.protocol_version
         );
         assert_eq!(
-            expected_snapshot.factory_dep_cache,
-            actual_snapshot.factory_dep_cache
+            expected_snapshot.pending_protocol_upgrades,
+            actual_snapshot.pending_protocol_upgrades
         );
+
+        let storage = inner.fork_storage.inner.read().unwrap();
+        assert_eq!(expected_value_read_cache, *storage.value_read_cache);
+        assert_eq!(expected_factory_dep_cache, *storage.factory_dep_cache);
+        drop(storage);
+
+        let next_snapshot = inner.snapshot().expect("failed taking snapshot");
+        assert_eq!(1, next_snapshot.journal_depths.tx_results);
+        assert!(actual_snapshot
+            .journal_depths
+            .precedes(&next_snapshot.journal_depths));
     }
 
     #[tokio::test]
@@ -2966,24 +3125,30 @@ mod tests {
             H256::repeat_byte(0x1),
         );
 
-        let expected_snapshot = {
-            let storage = inner.fork_storage.inner.read().unwrap();
-            Snapshot {
-                current_batch: inner.current_batch,
-                current_miniblock: inner.current_miniblock,
-                current_miniblock_hash: inner.current_miniblock_hash,
-                fee_input_provider: inner.fee_input_provider.clone(),
-                tx_results: inner.tx_results.clone(),
-                blocks: inner.blocks.clone(),
-                block_hashes: inner.block_hashes.clone(),
-                filters: inner.filters.clone(),
-                impersonation_state: inner.impersonation.state(),
-                rich_accounts: inner.rich_accounts.clone(),
-                previous_states: inner.previous_states.clone(),
-                raw_storage: storage.raw_storage.clone(),
-                value_read_cache: storage.value_read_cache.clone(),
-                factory_dep_cache: storage.factory_dep_cache.clone(),
-            }
+        let expected_tx_results = inner.tx_results.keys().cloned().collect_vec();
+        let expected_blocks = (*inner.blocks).clone();
+        let expected_block_hashes = (*inner.block_hashes).clone();
+        let expected_filters = inner.filters.clone();
+        let expected_impersonation_state = inner.impersonation.state();
+        let expected_rich_accounts = inner.rich_accounts.clone();
+        let expected_previous_states = inner.previous_states.clone();
+        inner
+            .fork_storage
+            .store_factory_dep(H256::repeat_byte(0x1), vec![0x1]);
+        let (expected_raw_storage, expected_value_read_cache, expected_factory_dep_cache) = {
+            let mut storage = inner.fork_storage.inner.write().unwrap();
+            storage.value_read_cache.insert(
+                StorageKey::new(AccountTreeId::new(H160::repeat_byte(0x1)), H256::zero()),
+                H256::repeat_byte(0x1),
+            );
+            storage
+                .factory_dep_cache
+                .insert(H256::repeat_byte(0x1), Some(vec![0x1]));
+            (
+                storage.raw_storage.clone(),
+                (*storage.value_read_cache).clone(),
+                (*storage.factory_dep_cache).clone(),
+            )
         };
 
         // snapshot and modify node state
@@ -3017,6 +3182,29 @@ mod tests {
             StorageKey::new(AccountTreeId::new(H160::repeat_byte(0x2)), H256::zero()),
             H256::repeat_byte(0x2),
         );
+        inner.fork_storage.set_value(
+            StorageKey::new(AccountTreeId::new(H160::repeat_byte(0x1)), H256::zero()),
+            H256::repeat_byte(0x3),
+        );
+        inner
+            .fork_storage
+            .store_factory_dep(H256::repeat_byte(0x2), vec![0x2]);
+        inner
+            .fork_storage
+            .store_factory_dep(H256::repeat_byte(0x1), vec![0x3]);
+        {
+            let mut storage = inner.fork_storage.inner.write().unwrap();
+            storage.value_read_cache.insert(
+                StorageKey::new(AccountTreeId::new(H160::repeat_byte(0x2)), H256::zero()),
+                H256::repeat_byte(0x2),
+            );
+            storage
+                .factory_dep_cache
+                .insert(H256::repeat_byte(0x1), None);
+            storage
+                .factory_dep_cache
+                .insert(H256::repeat_byte(0x2), Some(vec![0x2]));
+        }
 
         // restore
         inner
@@ -3024,36 +3212,26 @@ mod tests {
             .expect("failed restoring snapshot");
 
         let storage = inner.fork_storage.inner.read().unwrap();
-        assert_eq!(expected_snapshot.current_batch, inner.current_batch);
-        assert_eq!(expected_snapshot.current_miniblock, inner.current_miniblock);
+        assert_eq!(1, inner.current_batch);
+        assert_eq!(1, inner.current_miniblock);
+        assert_eq!(H256::repeat_byte(0x1), inner.current_miniblock_hash);
         assert_eq!(
-            expected_snapshot.current_miniblock_hash,
-            inner.current_miniblock_hash
-        );
-
-        assert_eq!(
-            expected_snapshot.fee_input_provider,
-            inner.fee_input_provider
-        );
-        assert_eq!(
-            expected_snapshot.tx_results.keys().collect_vec(),
-            inner.tx_results.keys().collect_vec()
-        );
-        assert_eq!(expected_snapshot.blocks, inner.blocks);
-        assert_eq!(expected_snapshot.block_hashes, inner.block_hashes);
-        assert_eq!(expected_snapshot.filters, inner.filters);
-        assert_eq!(
-            expected_snapshot.impersonation_state,
-            inner.impersonation.state()
+            expected_tx_results,
+            inner.tx_results.keys().cloned().collect_vec()
         );
-        assert_eq!(expected_snapshot.rich_accounts, inner.rich_accounts);
-        assert_eq!(expected_snapshot.previous_states, inner.previous_states);
-        assert_eq!(expected_snapshot.raw_storage, storage.raw_storage);
-        assert_eq!(expected_snapshot.value_read_cache, storage.value_read_cache);
+        assert_eq!(expected_blocks, *inner.blocks);
+        assert_eq!(expected_block_hashes, *inner.block_hashes);
+        assert_eq!(expected_filters, inner.filters);
+        assert_eq!(expected_impersonation_state, inner.impersonation.state());
+        assert_eq!(expected_rich_accounts, inner.rich_accounts);
+        assert_eq!(expected_previous_states, inner.previous_states);
+        assert_eq!(expected_raw_storage, storage.raw_storage);
         assert_eq!(
-            expected_snapshot.factory_dep_cache,
-            storage.factory_dep_cache
+            expected_raw_storage.factory_deps,
+            storage.raw_storage.factory_deps
         );
+        assert_eq!(expected_value_read_cache, *storage.value_read_cache);
+        assert_eq!(expected_factory_dep_cache, *storage.factory_dep_cache);
     }
 
     #[tokio::test]
@@ -1,13 +1,18 @@
+use std::collections::BTreeMap;
+
 use zksync_multivm::utils::derive_base_fee_and_gas_per_pubdata;
 use zksync_multivm::VmVersion;
 use zksync_types::fee_model::{
     BaseTokenConversionRatio, BatchFeeInput, FeeModelConfigV2, FeeParams, FeeParamsV2,
 };
+use zksync_types::ProtocolVersionId;
 
 use crate::config::constants::{
     DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR, DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
     DEFAULT_FAIR_PUBDATA_PRICE, DEFAULT_L1_GAS_PRICE, DEFAULT_L2_GAS_PRICE,
 };
+use crate::namespaces::FeeParamsUpdate;
+
 #[derive(Debug, Clone)]
 pub struct TestNodeFeeInputProvider {
     /// L1 Gas Price Scale Factor for gas estimation.
@@ -17,6 +22,12 @@ pub struct TestNodeFeeInputProvider {
 
     fee_params: FeeParamsV2,
     forced_base_fee: Option<u64>,
+    /// Fee updates that take effect once the batch they are keyed by is reached.
+    fee_schedule: BTreeMap<u32, FeeParamsUpdate>,
+    /// Whether the fee params were changed at runtime, forks stop mirroring the fees of the forked network then.
+    customized: bool,
+    /// Whether all fees are zero.
+    gasless: bool,
 }
 
 // TODO: Derive PartialEq for `FeeParamsV2` in upstream
@@ -36,6 +47,9 @@ impl PartialEq for TestNodeFeeInputProvider {
             && self.fee_params.l1_gas_price() == other.fee_params.l1_gas_price()
             && self.fee_params.l1_pubdata_price() == other.fee_params.l1_pubdata_price()
             && eq_config(self.fee_params.config(), other.fee_params.config())
+            && self.fee_schedule == other.fee_schedule
+            && self.customized == other.customized
+            && self.gasless == other.gasless
     }
 }
 
@@ -52,6 +66,9 @@ impl TestNodeFeeInputProvider {
                 estimate_gas_scale_factor,
                 fee_params,
                 forced_base_fee: None,
+                fee_schedule: Default::default(),
+                customized: false,
+                gasless: false,
             },
         }
     }
@@ -86,20 +103,27 @@ impl TestNodeFeeInputProvider {
     }
 
     pub(crate) fn get_batch_fee_input(&self) -> BatchFeeInput {
+        if self.gasless {
+            return BatchFeeInput::pubdata_independent(0, 0, 0);
+        }
         let fee_input = self.get_params().scale(1.0, 1.0);
         self.enforce_base_fee(fee_input)
     }
 
     pub(crate) fn get_batch_fee_input_scaled(&self) -> BatchFeeInput {
+        if self.gasless {
+            return BatchFeeInput::pubdata_independent(0, 0, 0);
+        }
         let scale_factor = self.estimate_gas_price_scale_factor;
         let fee_input = self.get_params().scale(scale_factor, scale_factor);
         self.enforce_base_fee(fee_input)
     }
 
-    pub fn gas_price(&self) -> u64 {
+    /// Returns the L2 gas price under the fee model of `protocol_version`.
+    pub fn gas_price(&self, protocol_version: ProtocolVersionId) -> u64 {
         let (base_fee, _) = derive_base_fee_and_gas_per_pubdata(
             self.get_batch_fee_input_scaled(),
-            VmVersion::latest(),
+            VmVersion::from(protocol_version),
         );
         base_fee
     }
@@ -111,6 +135,95 @@ impl TestNodeFeeInputProvider {
     pub fn set_base_fee(&mut self, base_fee: u64) {
         self.forced_base_fee = Some(base_fee);
     }
+
+    /// Whether the fee params were changed at runtime or the gasless mode is enabled.
+    pub(crate) fn is_customized(&self) -> bool {
+        self.customized || self.gasless
+    }
+
+    /// Whether all fees are zero.
+    pub fn is_gasless(&self) -> bool {
+        self.gasless
+    }
+
+    /// Enables or disables the gasless mode, the fee params are kept and apply again once it is disabled.
+    pub fn set_gasless(&mut self, gasless: bool) {
+        self.gasless = gasless;
+    }
+
+    /// Applies the update to the fee params of the next batch, fields that are not set are kept.
+    pub fn update_fee_params(&mut self, update: &FeeParamsUpdate) {
+        let mut config = self.fee_params.config();
+        let mut l1_gas_price = self.fee_params.l1_gas_price();
+        let mut l1_pubdata_price = self.fee_params.l1_pubdata_price();
+        if let Some(price) = update.l1_gas_price {
+            l1_gas_price = price.as_u64();
+        }
+        if let Some(price) = update.fair_pubdata_price {
+            l1_pubdata_price = price.as_u64();
+        }
+        if let Some(price) = update.minimal_l2_gas_price {
+            config.minimal_l2_gas_price = price.as_u64();
+        }
+        if let Some(part) = update.compute_overhead_part {
+            config.compute_overhead_part = part;
+        }
+        if let Some(part) = update.pubdata_overhead_part {
+            config.pubdata_overhead_part = part;
+        }
+        if let Some(gas) = update.batch_overhead_l1_gas {
+            config.batch_overhead_l1_gas = gas.as_u64();
+        }
+        if let Some(gas) = update.max_gas_per_batch {
+            config.max_gas_per_batch = gas.as_u64();
+        }
+        if let Some(pubdata) = update.max_pubdata_per_batch {
+            config.max_pubdata_per_batch = pubdata.as_u64();
+        }
+        self.fee_params = FeeParamsV2::new(
+            config,
+            l1_gas_price,
+            l1_pubdata_price,
+            self.fee_params.conversion_ratio(),
+        );
+        self.customized = true;
+    }
+
+    /// Schedules the update to be applied once `batch` is reached. Updates scheduled for the same batch are
+    /// merged, with the fields of the latest one taking precedence.
+    pub fn schedule_fee_params(&mut self, batch: u32, update: FeeParamsUpdate) {
+        let scheduled = self.fee_schedule.entry(batch).or_default();
+        *scheduled = FeeParamsUpdate {
+            l1_gas_price: update.l1_gas_price.or(scheduled.l1_gas_price),
+            fair_pubdata_price: update.fair_pubdata_price.or(scheduled.fair_pubdata_price),
+            minimal_l2_gas_price: update
+                .minimal_l2_gas_price
+                .or(scheduled.minimal_l2_gas_price),
+            compute_overhead_part: update
+                .compute_overhead_part
+                .or(scheduled.compute_overhead_part),
+            pubdata_overhead_part: update
+                .pubdata_overhead_part
+                .or(scheduled.pubdata_overhead_part),
+            batch_overhead_l1_gas: update
+                .batch_overhead_l1_gas
+                .or(scheduled.batch_overhead_l1_gas),
+            max_gas_per_batch: update.max_gas_per_batch.or(scheduled.max_gas_per_batch),
+            max_pubdata_per_batch: update
+                .max_pubdata_per_batch
+                .or(scheduled.max_pubdata_per_batch),
+        };
+    }
+
+    /// Applies every scheduled update that is due by `next_batch`, in batch order.
+    pub(crate) fn apply_fee_schedule(&mut self, next_batch: u32) {
+        let pending = self.fee_schedule.split_off(&next_batch.saturating_add(1));
+        let due = std::mem::replace(&mut self.fee_schedule, pending);
+        for (batch, update) in due {
+            tracing::info!("Applying fee params scheduled for batch {}", batch);
+            self.update_fee_params(&update);
+        }
+    }
 }
 
 impl Default for TestNodeFeeInputProvider {
@@ -132,6 +245,120 @@ impl Default for TestNodeFeeInputProvider {
                 BaseTokenConversionRatio::default(),
             ),
             forced_base_fee: None,
+            fee_schedule: Default::default(),
+            customized: false,
+            gasless: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zksync_types::U64;
+
+    #[test]
+    fn test_update_fee_params_keeps_unset_fields() {
+        let mut provider = TestNodeFeeInputProvider::default();
+        assert!(!provider.is_customized());
+
+        provider.update_fee_params(&FeeParamsUpdate {
+            fair_pubdata_price: Some(U64::from(1_000)),
+            max_gas_per_batch: Some(U64::from(100_000_000)),
+            ..Default::default()
+        });
+
+        assert!(provider.is_customized());
+        assert_eq!(provider.fee_params.l1_gas_price(), DEFAULT_L1_GAS_PRICE);
+        assert_eq!(provider.fee_params.l1_pubdata_price(), 1_000);
+        let config = provider.get_fee_model_config();
+        assert_eq!(config.minimal_l2_gas_price, DEFAULT_L2_GAS_PRICE);
+        assert_eq!(config.max_gas_per_batch, 100_000_000);
+        assert_eq!(config.max_pubdata_per_batch, 500000);
+    }
+
+    #[test]
+    fn test_fee_schedule_is_applied_when_batch_is_reached() {
+        let mut provider = TestNodeFeeInputProvider::default();
+        provider.schedule_fee_params(
+            3,
+            FeeParamsUpdate {
+                fair_pubdata_price: Some(U64::from(1_000_000_000_000u64)),
+                ..Default::default()
+            },
+        );
+        provider.schedule_fee_params(
+            4,
+            FeeParamsUpdate {
+                fair_pubdata_price: Some(U64::from(DEFAULT_FAIR_PUBDATA_PRICE)),
+                ..Default::default()
+            },
+        );
+
+        // The fair pubdata price includes the batch overhead on top of the L1 pubdata price.
+        let initial = provider.get_batch_fee_input().fair_pubdata_price();
+        let overhead = initial - DEFAULT_FAIR_PUBDATA_PRICE;
+
+        provider.apply_fee_schedule(2);
+        assert_eq!(provider.get_batch_fee_input().fair_pubdata_price(), initial);
+        assert!(!provider.is_customized());
+
+        provider.apply_fee_schedule(3);
+        assert_eq!(
+            provider.get_batch_fee_input().fair_pubdata_price(),
+            1_000_000_000_000 + overhead
+        );
+
+        provider.apply_fee_schedule(4);
+        assert_eq!(provider.get_batch_fee_input().fair_pubdata_price(), initial);
+        assert!(provider.fee_schedule.is_empty());
+    }
+
+    #[test]
+    fn test_gasless_fee_input_is_zero() {
+        let mut provider = TestNodeFeeInputProvider::default();
+        provider.set_gasless(true);
+
+        let fee_input = provider.get_batch_fee_input();
+        assert_eq!(fee_input.l1_gas_price(), 0);
+        assert_eq!(fee_input.fair_l2_gas_price(), 0);
+        assert_eq!(fee_input.fair_pubdata_price(), 0);
+        assert_eq!(provider.gas_price(ProtocolVersionId::latest()), 0);
+        assert_eq!(provider.fair_pubdata_price(), 0);
+
+        provider.set_gasless(false);
+        assert_eq!(
+            provider.gas_price(ProtocolVersionId::latest()),
+            TestNodeFeeInputProvider::default().gas_price(ProtocolVersionId::latest())
+        );
+    }
+
+    #[test]
+    fn test_schedule_fee_params_merges_updates_for_the_same_batch() {
+        let mut provider = TestNodeFeeInputProvider::default();
+        provider.schedule_fee_params(
+            5,
+            FeeParamsUpdate {
+                l1_gas_price: Some(U64::from(1)),
+                fair_pubdata_price: Some(U64::from(2)),
+                ..Default::default()
+            },
+        );
+        provider.schedule_fee_params(
+            5,
+            FeeParamsUpdate {
+                fair_pubdata_price: Some(U64::from(3)),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            provider.fee_schedule.get(&5),
+            Some(&FeeParamsUpdate {
+                l1_gas_price: Some(U64::from(1)),
+                fair_pubdata_price: Some(U64::from(3)),
+                ..Default::default()
+            })
+        );
+    }
+}
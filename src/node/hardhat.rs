@@ -1,9 +1,9 @@
-use zksync_types::{Address, U256, U64};
+use zksync_types::{Address, H256, U256, U64};
 use zksync_web3_decl::error::Web3Error;
 
 use crate::{
     fork::ForkSource,
-    namespaces::{HardhatNamespaceT, ResetRequest, RpcResult},
+    namespaces::{HardhatMetadata, HardhatNamespaceT, ResetRequest, RpcResult},
     node::InMemoryNode,
     utils::{into_jsrpc_error, into_jsrpc_error_message, IntoBoxedFuture},
 };
@@ -91,4 +91,66 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> HardhatNam
             })
             .into_boxed_future()
     }
+
+    fn set_coinbase(&self, address: Address) -> RpcResult<bool> {
+        self.set_coinbase(address)
+            .map_err(|err| {
+                tracing::error!("failed setting coinbase: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_prev_randao(&self, prev_randao: H256) -> RpcResult<bool> {
+        self.set_prev_randao(prev_randao)
+            .map_err(|err| {
+                tracing::error!("failed setting prevrandao: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn metadata(&self) -> RpcResult<HardhatMetadata> {
+        self.metadata()
+            .map_err(|err| {
+                tracing::error!("failed getting metadata: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_next_block_base_fee_per_gas(&self, base_fee: U256) -> RpcResult<bool> {
+        self.set_next_block_base_fee_per_gas(base_fee)
+            .map(|_| true)
+            .map_err(|err| {
+                tracing::error!("failed setting next block's base fee: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn drop_transaction(&self, hash: H256) -> RpcResult<bool> {
+        self.drop_transaction(hash)
+            .map(|dropped| dropped.is_some())
+            .map_err(|err| {
+                tracing::error!("failed dropping transaction: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
+
+    fn set_min_gas_price(&self, _gas_price: U256) -> RpcResult<bool> {
+        tracing::info!("hardhat_setMinGasPrice is unsupported as ZKsync is a post-EIP1559 chain");
+        Err(into_jsrpc_error(Web3Error::MethodNotImplemented)).into_boxed_future()
+    }
+
+    fn set_logging_enabled(&self, enable: bool) -> RpcResult<bool> {
+        self.set_logging_enabled(enable)
+            .map(|_| true)
+            .map_err(|err| {
+                tracing::error!("failed setting logging enabled: {:?}", err);
+                into_jsrpc_error(Web3Error::InternalError(err))
+            })
+            .into_boxed_future()
+    }
 }
@@ -1,4 +1,5 @@
 //! In-memory node, that supports forking other networks.
+use anyhow::Context;
 use colored::Colorize;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
@@ -7,12 +8,14 @@ use indexmap::IndexMap;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
-use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, RwLockReadGuard, RwLockWriteGuard};
 use std::{
-    collections::{HashMap, HashSet},
-    convert::TryInto,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
     str::FromStr,
     sync::{Arc, RwLock},
+    time::Instant,
 };
 use zksync_contracts::BaseSystemContracts;
 use zksync_multivm::vm_latest::HistoryEnabled;
@@ -29,7 +32,7 @@ use zksync_multivm::{
         get_batch_base_fee, get_max_batch_gas_limit, get_max_gas_per_pubdata_byte,
     },
     vm_latest::{
-        constants::{BATCH_COMPUTATIONAL_GAS_LIMIT, BATCH_GAS_LIMIT, MAX_VM_PUBDATA_PER_BATCH},
+        constants::{BATCH_COMPUTATIONAL_GAS_LIMIT, BATCH_GAS_LIMIT},
         utils::l2_blocks::load_last_l2_block,
         HistoryDisabled, ToTracerPointer, Vm,
     },
@@ -41,43 +44,58 @@ use zksync_types::{
     fee::Fee,
     fee_model::{BatchFeeInput, PubdataIndependentBatchFeeModelInput},
     get_code_key, get_nonce_key,
+    l1::L1Tx,
     l2::{L2Tx, TransactionType},
     utils::{decompose_full_nonce, nonces_to_full_nonce, storage_key_for_eth_balance},
     web3::{keccak256, Bytes, Index},
     AccountTreeId, Address, Bloom, BloomInput, L1BatchNumber, L2BlockNumber, PackedEthSignature,
-    StorageKey, StorageValue, Transaction, ACCOUNT_CODE_STORAGE_ADDRESS, EMPTY_UNCLES_HASH, H160,
-    H256, H64, MAX_L2_TX_GAS_LIMIT, SYSTEM_CONTEXT_ADDRESS, SYSTEM_CONTEXT_BLOCK_INFO_POSITION,
-    U256, U64,
+    ProtocolVersionId, StorageKey, StorageValue, Transaction, ACCOUNT_CODE_STORAGE_ADDRESS,
+    BOOTLOADER_ADDRESS, CONTRACT_DEPLOYER_ADDRESS, EMPTY_UNCLES_HASH, H160, H256, H64,
+    IMMUTABLE_SIMULATOR_STORAGE_ADDRESS, KNOWN_CODES_STORAGE_ADDRESS, L2_BASE_TOKEN_ADDRESS,
+    MAX_L2_TX_GAS_LIMIT, NONCE_HOLDER_ADDRESS, SYSTEM_CONTEXT_ADDRESS,
+    SYSTEM_CONTEXT_BLOCK_INFO_POSITION, SYSTEM_CONTEXT_COINBASE_POSITION,
+    SYSTEM_CONTEXT_DIFFICULTY_POSITION, U256, U64,
+};
+use zksync_utils::{
+    bytecode::{hash_bytecode, validate_bytecode},
+    h256_to_account_address, h256_to_u256, u256_to_h256,
 };
-use zksync_utils::{bytecode::hash_bytecode, h256_to_account_address, h256_to_u256, u256_to_h256};
 use zksync_web3_decl::error::Web3Error;
 
-use crate::fork::SerializableStorage;
+use crate::fork::{ForkStorageCheckpoint, SerializableForkStorage, SerializableStorage};
+use crate::node::committed::{CommittedState, CommittedStateHandle};
 use crate::node::error::LoadStateError;
 use crate::node::impersonate::{ImpersonationManager, ImpersonationState};
+use crate::node::sealer::{BatchCapacity, BatchUsage, SealCriteria, SealResolution};
 use crate::node::state::{StateV1, VersionedState};
 use crate::node::time::{AdvanceTime, ReadTime, TimestampManager};
-use crate::node::{BlockSealer, TxPool};
+use crate::node::{BlockSealer, JournaledMap, TxPool};
 use crate::{
     bootloader_debug::{BootloaderDebug, BootloaderDebugTracer},
     config::{
         cache::CacheConfig,
         constants::{LEGACY_RICH_WALLETS, NON_FORK_FIRST_BLOCK_TIMESTAMP, RICH_WALLETS},
         show_details::{ShowCalls, ShowGasDetails, ShowStorageLogs, ShowVMDetails},
-        Genesis, TestNodeConfig,
+        Genesis, GenesisAccount, TestNodeConfig,
     },
     console_log::ConsoleLogHandler,
     deps::{storage_view::StorageView, InMemoryStorage},
     filters::EthFilters,
     fork::{ForkDetails, ForkSource, ForkStorage},
     formatter,
+    json_log::JsonLogSink,
+    metrics::METRICS,
+    namespaces::{BootloaderUpgrade, ProtocolUpgrade, SystemContractUpgrade},
     node::{
         call_error_tracer::CallErrorTracer, fee_model::TestNodeFeeInputProvider,
         storage_logs::print_storage_logs_details,
     },
     observability::Observability,
     system_contracts::{self, SystemContracts},
-    utils::{bytecode_to_factory_dep, create_debug_output, into_jsrpc_error},
+    utils::{
+        bytecode_to_factory_dep, create_debug_output, evm_bytecode_to_factory_dep,
+        into_jsrpc_error, is_evm_bytecode_hash, trim_padded_evm_bytecode,
+    },
 };
 
 /// Max possible size of an ABI encoded tx (in bytes).
@@ -101,6 +119,7 @@ pub fn compute_hash<'a>(block_number: u64, tx_hashes: impl IntoIterator<Item = &
 pub fn create_genesis_from_json(
     genesis: &Genesis,
     timestamp: Option<u64>,
+    protocol_version: ProtocolVersionId,
 ) -> Block<TransactionVariant> {
     let hash = genesis.hash.unwrap_or_else(|| compute_hash(0, []));
     let timestamp = timestamp
@@ -124,6 +143,7 @@ pub fn create_genesis_from_json(
 
     create_block(
         &l1_batch_env,
+        protocol_version,
         hash,
         genesis.parent_hash.unwrap_or_else(H256::zero),
         genesis.block_number.unwrap_or(0),
@@ -134,7 +154,10 @@ pub fn create_genesis_from_json(
     )
 }
 
-pub fn create_genesis<TX>(timestamp: Option<u64>) -> Block<TX> {
+pub fn create_genesis<TX>(
+    timestamp: Option<u64>,
+    protocol_version: ProtocolVersionId,
+) -> Block<TX> {
     let hash = compute_hash(0, []);
     let timestamp = timestamp.unwrap_or(NON_FORK_FIRST_BLOCK_TIMESTAMP);
     let batch_env = L1BatchEnv {
@@ -153,6 +176,7 @@ pub fn create_genesis<TX>(timestamp: Option<u64>) -> Block<TX> {
     };
     create_block(
         &batch_env,
+        protocol_version,
         hash,
         H256::zero(),
         0,
@@ -163,9 +187,136 @@ pub fn create_genesis<TX>(timestamp: Option<u64>) -> Block<TX> {
     )
 }
 
+/// Storage key of `block.coinbase` in the `SystemContext` contract.
+pub fn coinbase_key() -> StorageKey {
+    StorageKey::new(
+        AccountTreeId::new(SYSTEM_CONTEXT_ADDRESS),
+        SYSTEM_CONTEXT_COINBASE_POSITION,
+    )
+}
+
+/// Storage key of `block.prevrandao` (formerly `block.difficulty`) in the `SystemContext` contract.
+pub fn prev_randao_key() -> StorageKey {
+    StorageKey::new(
+        AccountTreeId::new(SYSTEM_CONTEXT_ADDRESS),
+        SYSTEM_CONTEXT_DIFFICULTY_POSITION,
+    )
+}
+
+/// Stores `bytecode` as a known factory dependency and returns its hash.
+pub(crate) fn store_code<S>(
+    fork_storage: &mut ForkStorage<S>,
+    bytecode: Vec<u8>,
+) -> anyhow::Result<H256> {
+    let hashcode = bytecode_to_factory_dep(bytecode)?;
+    let hash = u256_to_h256(hashcode.0);
+    let code = hashcode
+        .1
+        .iter()
+        .flat_map(|entry| {
+            let mut bytes = vec![0u8; 32];
+            entry.to_big_endian(&mut bytes);
+            bytes.to_vec()
+        })
+        .collect();
+    fork_storage.store_factory_dep(hash, code);
+    Ok(hash)
+}
+
+/// Whether `address` is in the kernel space reserved for system contracts.
+pub(crate) fn is_system_address(address: &Address) -> bool {
+    address.as_bytes()[..18].iter().all(|byte| *byte == 0)
+}
+
+/// System contracts whose storage holds the state of accounts, as opposed to the ones tracking the progress of the
+/// chain such as block numbers, timestamps, hashes and transaction counters.
+const ACCOUNT_STATE_SYSTEM_CONTRACTS: [Address; 6] = [
+    ACCOUNT_CODE_STORAGE_ADDRESS,
+    CONTRACT_DEPLOYER_ADDRESS,
+    IMMUTABLE_SIMULATOR_STORAGE_ADDRESS,
+    KNOWN_CODES_STORAGE_ADDRESS,
+    L2_BASE_TOKEN_ADDRESS,
+    NONCE_HOLDER_ADDRESS,
+];
+
+/// Virtual machine the code deployed with [InMemoryNodeInner::set_code] runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeKind {
+    /// EraVM bytecode.
+    EraVm,
+    /// EVM runtime bytecode, run by the EVM emulator.
+    Evm,
+}
+
+/// Fee a payer, either the paymaster or the initiator, prepays to the bootloader for a transaction in gasless mode.
+///
+/// The bootloader has the payer prepay `gas_limit * max_fee_per_gas` no matter the base fee. As all of it is refunded
+/// with a zero base fee, the payer is credited with it right before the transaction and it is withdrawn right after.
+struct GaslessPrepayment<'a, S> {
+    storage: &'a StoragePtr<S>,
+    payer: Address,
+    fee: U256,
+    /// Balance of the payer before it was credited.
+    balance: U256,
+}
+
+impl<'a, S: WriteStorage> GaslessPrepayment<'a, S> {
+    /// Credits the payer of `tx` with the fee it prepays.
+    fn credit(storage: &'a StoragePtr<S>, tx: &L2Tx) -> Self {
+        let paymaster = tx.common_data.paymaster_params.paymaster;
+        let payer = if paymaster.is_zero() {
+            tx.initiator_account()
+        } else {
+            paymaster
+        };
+        let fee = tx
+            .common_data
+            .fee
+            .gas_limit
+            .saturating_mul(tx.common_data.fee.max_fee_per_gas);
+        let key = storage_key_for_eth_balance(&payer);
+        let balance = h256_to_u256(storage.borrow_mut().read_value(&key));
+        storage
+            .borrow_mut()
+            .set_value(key, u256_to_h256(balance.saturating_add(fee)));
+        Self {
+            storage,
+            payer,
+            fee,
+            balance,
+        }
+    }
+
+    /// Withdraws the credited fee once the transaction is executed. Fails if the transaction spent any of it.
+    fn withdraw(&self) -> anyhow::Result<()> {
+        let key = storage_key_for_eth_balance(&self.payer);
+        let mut storage = self.storage.borrow_mut();
+        let balance = h256_to_u256(storage.read_value(&key));
+        let balance = balance.checked_sub(self.fee).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{:#x} spent the fee credited in gasless mode, balance {} is below {}",
+                self.payer,
+                balance,
+                self.fee
+            )
+        })?;
+        storage.set_value(key, u256_to_h256(balance));
+        Ok(())
+    }
+
+    /// Restores the balance of the payer once the transaction is rolled back.
+    fn restore(&self) {
+        self.storage.borrow_mut().set_value(
+            storage_key_for_eth_balance(&self.payer),
+            u256_to_h256(self.balance),
+        );
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn create_block<TX>(
     batch_env: &L1BatchEnv,
+    protocol_version: ProtocolVersionId,
     hash: H256,
     parent_hash: H256,
     number: u64,
@@ -180,12 +331,15 @@ fn create_block<TX>(
         uncles_hash: EMPTY_UNCLES_HASH, // Static for non-PoW chains, see EIP-3675
         number: U64::from(number),
         l1_batch_number: Some(U64::from(batch_env.number.0)),
-        base_fee_per_gas: U256::from(get_batch_base_fee(batch_env, VmVersion::latest())),
+        base_fee_per_gas: U256::from(get_batch_base_fee(
+            batch_env,
+            VmVersion::from(protocol_version),
+        )),
         timestamp: U256::from(timestamp),
         l1_batch_timestamp: Some(U256::from(batch_env.timestamp)),
         transactions,
         gas_used,
-        gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::latest())),
+        gas_limit: U256::from(get_max_batch_gas_limit(VmVersion::from(protocol_version))),
         logs_bloom,
         author: Address::default(), // Matches core's behavior, irrelevant for ZKsync
         state_root: H256::default(), // Intentionally empty as blocks in ZKsync don't have state - batches do
@@ -249,11 +403,11 @@ pub struct InMemoryNodeInner<S> {
     /// The fee input provider.
     pub fee_input_provider: TestNodeFeeInputProvider,
     // Map from transaction to details about the exeuction
-    pub tx_results: HashMap<H256, TransactionResult>,
+    pub tx_results: JournaledMap<H256, TransactionResult>,
     // Map from block hash to information about the block.
-    pub blocks: HashMap<H256, Block<TransactionVariant>>,
+    pub blocks: JournaledMap<H256, Block<TransactionVariant>>,
     // Map from block number to a block hash.
-    pub block_hashes: HashMap<u64, H256>,
+    pub block_hashes: JournaledMap<u64, H256>,
     // Map from filter_id to the eth filter
     pub filters: EthFilters,
     // Underlying storage
@@ -265,7 +419,11 @@ pub struct InMemoryNodeInner<S> {
     pub impersonation: ImpersonationManager,
     pub rich_accounts: HashSet<H160>,
     /// Keeps track of historical states indexed via block hash. Limited to [MAX_PREVIOUS_STATES].
-    pub previous_states: IndexMap<H256, HashMap<StorageKey, StorageValue>>,
+    pub previous_states: IndexMap<H256, Arc<HashMap<StorageKey, StorageValue>>>,
+    /// Random identifier of this chain, changes whenever the chain is reset.
+    pub instance_id: H256,
+    /// Protocol upgrades that take effect with the next sealed batch, in the order they were scheduled.
+    pub pending_protocol_upgrades: Vec<ProtocolUpgrade>,
 }
 
 #[derive(Debug)]
@@ -275,6 +433,13 @@ pub struct TxExecutionOutput {
     bytecodes: HashMap<U256, Vec<U256>>,
 }
 
+/// Outcome of an `eth_call` executed by [InMemoryNode::execute_l2_call].
+#[derive(Debug)]
+pub(crate) struct L2CallResult {
+    pub result: VmExecutionResultAndLogs,
+    pub call_traces: Vec<Call>,
+}
+
 impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
     /// Create the state to be used implementing [InMemoryNode].
     pub fn new(
@@ -296,7 +461,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             let mut blocks = HashMap::<H256, Block<TransactionVariant>>::new();
             blocks.insert(f.l2_block.hash, f.l2_block.clone());
 
-            let fee_input_provider = if let Some(params) = f.fee_params {
+            let mut fee_input_provider = if let Some(params) = f.fee_params {
                 TestNodeFeeInputProvider::from_fee_params_and_estimate_scale_factors(
                     params,
                     f.estimate_gas_price_scale_factor,
@@ -308,6 +473,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                     f.estimate_gas_scale_factor,
                 )
             };
+            fee_input_provider.set_gasless(updated_config.is_gasless());
             time.set_current_timestamp_unchecked(f.block_timestamp);
 
             InMemoryNodeInner {
@@ -316,8 +482,8 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 current_miniblock_hash: f.l2_miniblock_hash,
                 fee_input_provider,
                 tx_results: Default::default(),
-                blocks,
-                block_hashes,
+                blocks: blocks.into(),
+                block_hashes: block_hashes.into(),
                 filters: Default::default(),
                 fork_storage: ForkStorage::new(
                     fork,
@@ -331,6 +497,8 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 impersonation,
                 rich_accounts: HashSet::new(),
                 previous_states: Default::default(),
+                instance_id: H256(rand::random()),
+                pending_protocol_upgrades: Vec::new(),
             }
         } else {
             let mut block_hashes = HashMap::<u64, H256>::new();
@@ -339,13 +507,14 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             let mut blocks = HashMap::<H256, Block<TransactionVariant>>::new();
             let genesis_block: Block<TransactionVariant> = if let Some(ref genesis) = config.genesis
             {
-                create_genesis_from_json(genesis, config.genesis_timestamp)
+                create_genesis_from_json(genesis, config.genesis_timestamp, config.protocol_version)
             } else {
-                create_genesis(config.genesis_timestamp)
+                create_genesis(config.genesis_timestamp, config.protocol_version)
             };
 
             blocks.insert(block_hash, genesis_block);
-            let fee_input_provider = TestNodeFeeInputProvider::default();
+            let mut fee_input_provider = TestNodeFeeInputProvider::default();
+            fee_input_provider.set_gasless(config.is_gasless());
             time.set_current_timestamp_unchecked(NON_FORK_FIRST_BLOCK_TIMESTAMP);
 
             InMemoryNodeInner {
@@ -354,8 +523,8 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 current_miniblock_hash: block_hash,
                 fee_input_provider,
                 tx_results: Default::default(),
-                blocks,
-                block_hashes,
+                blocks: blocks.into(),
+                block_hashes: block_hashes.into(),
                 filters: Default::default(),
                 fork_storage: ForkStorage::new(
                     fork,
@@ -369,6 +538,8 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 impersonation,
                 rich_accounts: HashSet::new(),
                 previous_states: Default::default(),
+                instance_id: H256(rand::random()),
+                pending_protocol_upgrades: Vec::new(),
             }
         }
     }
@@ -401,12 +572,16 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             timestamp: time.peek_next_timestamp(),
         };
 
-        let fee_input = if let Some(fork) = &self
+        // Forks mirror the fees of the forked network until the fee params are changed at runtime or the gasless
+        // mode is enabled.
+        let fee_input = if let Some(fork) = self
             .fork_storage
             .inner
             .read()
             .expect("fork_storage lock is already held by the current thread")
             .fork
+            .as_ref()
+            .filter(|_| !self.fee_input_provider.is_customized())
         {
             BatchFeeInput::PubdataIndependent(PubdataIndependentBatchFeeModelInput {
                 l1_gas_price: fork.l1_gas_price,
@@ -417,13 +592,22 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             self.fee_input_provider.get_batch_fee_input()
         };
 
+        // The batch fees are paid to the coinbase set with `hardhat_setCoinbase`, the default coinbase of
+        // `SystemContext` is the bootloader itself.
+        let coinbase = h256_to_account_address(&storage.borrow_mut().read_value(&coinbase_key()));
+        let fee_account = if coinbase == BOOTLOADER_ADDRESS {
+            H160::zero()
+        } else {
+            coinbase
+        };
+
         let batch_env = L1BatchEnv {
             // TODO: set the previous batch hash properly (take from fork, when forking, and from local storage, when this is not the first block).
             previous_batch_hash: None,
             number: L1BatchNumber::from(block_ctx.batch),
             timestamp: block_ctx.timestamp,
             fee_input,
-            fee_account: H160::zero(),
+            fee_account,
             enforced_base_fee: None,
             first_l2_block: L2BlockEnv {
                 // the 'current_miniblock' contains the block that was already produced.
@@ -445,6 +629,22 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         (batch_env, block_ctx)
     }
 
+    /// Protocol version the VM and the system contracts of the node belong to.
+    pub fn protocol_version(&self) -> ProtocolVersionId {
+        self.config.get_protocol_version()
+    }
+
+    /// Returns the limits at which the next batches are sealed.
+    pub fn seal_criteria(&self) -> SealCriteria {
+        SealCriteria {
+            max_transactions: self.config.max_transactions,
+            max_gas: self.config.get_batch_gas_limit(),
+            max_pubdata: self.config.get_batch_pubdata_limit(),
+            max_circuits: self.config.get_batch_circuits_limit(),
+            max_encoding_size: self.config.get_batch_encoding_size_limit(),
+        }
+    }
+
     pub fn create_system_env(
         &self,
         base_system_contracts: BaseSystemContracts,
@@ -453,7 +653,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         SystemEnv {
             zk_porter_available: false,
             // TODO: when forking, we could consider taking the protocol version id from the fork itself.
-            version: zksync_types::ProtocolVersionId::latest(),
+            version: self.protocol_version(),
             base_system_smart_contracts: base_system_contracts,
             bootloader_gas_limit: BATCH_COMPUTATIONAL_GAS_LIMIT,
             execution_mode,
@@ -476,12 +676,12 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         time: &T,
         req: zksync_types::transaction_request::CallRequest,
     ) -> jsonrpc_core::Result<Fee> {
+        let vm_version = VmVersion::from(self.protocol_version());
         let mut request_with_gas_per_pubdata_overridden = req;
 
         if let Some(ref mut eip712_meta) = request_with_gas_per_pubdata_overridden.eip712_meta {
             if eip712_meta.gas_per_pubdata == U256::zero() {
-                eip712_meta.gas_per_pubdata =
-                    get_max_gas_per_pubdata_byte(VmVersion::latest()).into();
+                eip712_meta.gas_per_pubdata = get_max_gas_per_pubdata_byte(vm_version).into();
             }
         }
 
@@ -515,12 +715,12 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 fee_input,
                 tx.gas_per_pubdata_byte_limit(),
                 None,
-                VmVersion::latest(),
+                vm_version,
             )
         };
 
         let (base_fee, gas_per_pubdata_byte) =
-            derive_base_fee_and_gas_per_pubdata(fee_input, VmVersion::latest());
+            derive_base_fee_and_gas_per_pubdata(fee_input, vm_version);
 
         // Properly format signature
         if l2_tx.common_data.signature.is_empty() {
@@ -535,7 +735,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         }
 
         l2_tx.common_data.fee.gas_per_pubdata_limit =
-            get_max_gas_per_pubdata_byte(VmVersion::latest()).into();
+            get_max_gas_per_pubdata_byte(vm_version).into();
         l2_tx.common_data.fee.max_fee_per_gas = base_fee.into();
         l2_tx.common_data.fee.max_priority_fee_per_gas = base_fee.into();
 
@@ -547,6 +747,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         batch_env.fee_input = fee_input;
 
         let system_env = self.create_system_env(system_contracts, execution_mode);
+        let seal_criteria = self.seal_criteria();
 
         // When the pubdata cost grows very high, the total gas limit required may become very high as well. If
         // we do binary search over any possible gas limit naively, we may end up with a very high number of iterations,
@@ -573,7 +774,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 &self.fork_storage,
             );
 
-            if result.statistics.pubdata_published > MAX_VM_PUBDATA_PER_BATCH.try_into().unwrap() {
+            if result.statistics.pubdata_published as u64 > seal_criteria.max_pubdata {
                 return Err(into_jsrpc_error(Web3Error::SubmitTransactionError(
                     "exceeds limit for published pubdata".into(),
                     Default::default(),
@@ -646,7 +847,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             gas_per_pubdata_byte as u32,
             tx.encoding_len(),
             l2_tx.common_data.transaction_type as u8,
-            VmVersion::latest(),
+            vm_version,
         ) as u64;
 
         match estimate_gas_result.result {
@@ -731,6 +932,25 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                     }
                 };
 
+                // Transactions the batch seal criteria reject on their own would never be included.
+                let usage = BatchUsage {
+                    transactions: 1,
+                    gas: estimate_gas_result.statistics.gas_used,
+                    pubdata: estimate_gas_result.statistics.pubdata_published as u64,
+                    circuits: estimate_gas_result.statistics.circuit_statistic,
+                    encoding_size: tx.encoding_len() as u64,
+                };
+                if let Err(resolution) = BatchCapacity::new(seal_criteria).include(&usage) {
+                    tracing::info!(
+                        "{}",
+                        format!("Unable to estimate gas for the request: {}", resolution).red()
+                    );
+                    return Err(into_jsrpc_error(Web3Error::SubmitTransactionError(
+                        resolution.to_string(),
+                        Default::default(),
+                    )));
+                }
+
                 tracing::trace!("Gas Estimation Results");
                 tracing::trace!("  tx_body_gas_limit: {}", tx_body_gas_limit);
                 tracing::trace!(
@@ -769,7 +989,7 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
                 gas_per_pubdata_byte as u32,
                 tx.encoding_len(),
                 l2_tx.common_data.transaction_type as u8,
-                VmVersion::latest(),
+                VmVersion::from(system_env.version),
             ) as u64;
         l2_tx.common_data.fee.gas_limit = gas_limit_with_overhead.into();
 
@@ -817,87 +1037,533 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         );
         self.previous_states.insert(
             self.current_miniblock_hash,
-            self.fork_storage
-                .inner
-                .read()
-                .map_err(|err| err.to_string())?
-                .raw_storage
-                .state
-                .clone(),
+            Arc::new(
+                self.fork_storage
+                    .inner
+                    .read()
+                    .map_err(|err| err.to_string())?
+                    .raw_storage
+                    .state
+                    .clone(),
+            ),
+        );
+
+        Ok(())
+    }
+
+    /// Sets the balance of `address` and marks it as a rich account.
+    pub fn set_rich_account(&mut self, address: H160, balance: U256) {
+        let key = storage_key_for_eth_balance(&address);
+        let keys = {
+            let mut storage_view = StorageView::new(&self.fork_storage);
+            // Set balance to the specified amount
+            storage_view.set_value(key, u256_to_h256(balance));
+            storage_view.modified_storage_keys().clone()
+        };
+
+        for (key, value) in keys.iter() {
+            self.fork_storage.set_value(*key, *value);
+        }
+        self.rich_accounts.insert(address);
+    }
+
+    /// Funds all default rich wallets.
+    pub fn fund_rich_wallets(&mut self) {
+        for wallet in LEGACY_RICH_WALLETS.iter() {
+            let address = wallet.0;
+            self.set_rich_account(
+                H160::from_str(address).unwrap(),
+                U256::from(100u128 * 10u128.pow(18)),
+            );
+        }
+        for wallet in RICH_WALLETS.iter() {
+            let address = wallet.0;
+            self.set_rich_account(
+                H160::from_str(address).unwrap(),
+                U256::from(100u128 * 10u128.pow(18)),
+            );
+        }
+    }
+
+    /// Deploys `bytecode` at `address` without running a constructor.
+    ///
+    /// EraVM bytecode is validated and stored as is. EVM runtime bytecode requires the EVM emulator, and is stored
+    /// padded behind an EVM versioned hash so that calls to `address` run in the emulator.
+    pub fn set_code(
+        &mut self,
+        address: Address,
+        bytecode: Vec<u8>,
+        kind: CodeKind,
+    ) -> anyhow::Result<()> {
+        let hash = match kind {
+            CodeKind::EraVm => {
+                validate_bytecode(&bytecode).context("invalid EraVM bytecode")?;
+                store_code(&mut self.fork_storage, bytecode)?
+            }
+            CodeKind::Evm => {
+                anyhow::ensure!(
+                    self.config.use_evm_emulator,
+                    "EVM bytecode can only be set with --emulate-evm"
+                );
+                let (hash, padded) = evm_bytecode_to_factory_dep(&bytecode)?;
+                self.fork_storage.store_factory_dep(hash, padded);
+                hash
+            }
+        };
+        self.fork_storage.set_value(get_code_key(&address), hash);
+        Ok(())
+    }
+
+    /// Pre-populates a new chain with the `alloc` section of its `--init` genesis. Forked chains are left as is.
+    pub fn apply_genesis_alloc(&mut self) -> anyhow::Result<()> {
+        let alloc = match self
+            .config
+            .genesis
+            .as_ref()
+            .and_then(|genesis| genesis.alloc.clone())
+        {
+            Some(alloc) => alloc,
+            None => return Ok(()),
+        };
+        let storage =
+            self.fork_storage.inner.read().map_err(|err| {
+                anyhow::anyhow!("failed acquiring read lock on storage: {:?}", err)
+            })?;
+        if storage.fork.is_some() {
+            return Ok(());
+        }
+        let nonces = alloc
+            .keys()
+            .map(|address| {
+                let nonce_key = get_nonce_key(address);
+                let full_nonce = storage
+                    .raw_storage
+                    .state
+                    .get(&nonce_key)
+                    .copied()
+                    .unwrap_or_default();
+                (nonce_key, decompose_full_nonce(h256_to_u256(full_nonce)))
+            })
+            .collect::<Vec<_>>();
+        drop(storage);
+
+        for ((address, account), (nonce_key, (nonce, deployment_nonce))) in
+            alloc.into_iter().zip(nonces)
+        {
+            if let Some(balance) = account.balance {
+                self.fork_storage
+                    .set_value(storage_key_for_eth_balance(&address), u256_to_h256(balance));
+            }
+            if account.nonce.is_some() || account.deployment_nonce.is_some() {
+                let full_nonce = nonces_to_full_nonce(
+                    account.nonce.unwrap_or(nonce),
+                    account.deployment_nonce.unwrap_or(deployment_nonce),
+                );
+                self.fork_storage
+                    .set_value(nonce_key, u256_to_h256(full_nonce));
+            }
+            if let Some(code) = account.code {
+                let kind = if account.evm {
+                    CodeKind::Evm
+                } else {
+                    CodeKind::EraVm
+                };
+                self.set_code(address, code.0, kind)
+                    .with_context(|| format!("invalid genesis code of {:#x}", address))?;
+            }
+            for (slot, value) in account.storage {
+                self.fork_storage
+                    .set_value(StorageKey::new(AccountTreeId::new(address), slot), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Exports the state of a non-forked chain as a [Genesis] whose `alloc` recreates it on a new chain.
+    ///
+    /// Only storage that differs from a new chain is exported. Balances, nonces and code are attributed to the
+    /// accounts they belong to where the account is known, anything else is exported as raw storage slots. Storage
+    /// of system contracts tracking the progress of the chain is left out, so that the new chain starts from
+    /// its own first block.
+    pub fn export_genesis(&self) -> anyhow::Result<Genesis> {
+        let storage =
+            self.fork_storage.inner.read().map_err(|err| {
+                anyhow::anyhow!("failed acquiring read lock on storage: {:?}", err)
+            })?;
+        anyhow::ensure!(
+            storage.fork.is_none(),
+            "exporting a genesis is not supported for forked chains"
+        );
+
+        let new_chain = InMemoryStorage::with_system_contracts_and_chain_id(
+            self.fork_storage.chain_id,
+            hash_bytecode,
+            &self.config.system_contracts_options,
+            self.config.use_evm_emulator,
         );
+        let mut changed = storage
+            .raw_storage
+            .state
+            .iter()
+            .filter(|(key, _)| {
+                !is_system_address(key.address())
+                    || ACCOUNT_STATE_SYSTEM_CONTRACTS.contains(key.address())
+            })
+            .filter(|(key, value)| new_chain.state.get(key).copied().unwrap_or_default() != **value)
+            .map(|(key, value)| (*key, *value))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut alloc = BTreeMap::<Address, GenesisAccount>::new();
+        let code_keys = changed
+            .keys()
+            .filter(|key| *key.address() == ACCOUNT_CODE_STORAGE_ADDRESS)
+            .copied()
+            .collect::<Vec<_>>();
+        for key in code_keys {
+            let address = h256_to_account_address(key.key());
+            let hash = changed[&key];
+            let code = match storage.raw_storage.factory_deps.get(&hash) {
+                Some(code) if get_code_key(&address) == key => code,
+                _ => continue,
+            };
+            let evm = is_evm_bytecode_hash(hash);
+            let code = if evm {
+                trim_padded_evm_bytecode(code)?.to_vec()
+            } else {
+                code.clone()
+            };
+            let account = alloc.entry(address).or_default();
+            account.code = Some(Bytes(code));
+            account.evm = evm;
+            changed.remove(&key);
+        }
+
+        let accounts = changed
+            .keys()
+            .map(|key| *key.address())
+            .chain(alloc.keys().copied())
+            .chain(self.rich_accounts.iter().copied())
+            .chain(self.tx_results.values().flat_map(|tx_result| {
+                let receipt = &tx_result.receipt;
+                std::iter::once(receipt.from)
+                    .chain(receipt.to)
+                    .chain(receipt.contract_address)
+            }))
+            .collect::<BTreeSet<_>>();
+        for address in accounts {
+            if let Some(balance) = changed.remove(&storage_key_for_eth_balance(&address)) {
+                alloc.entry(address).or_default().balance = Some(h256_to_u256(balance));
+            }
+            if let Some(full_nonce) = changed.remove(&get_nonce_key(&address)) {
+                let (nonce, deployment_nonce) = decompose_full_nonce(h256_to_u256(full_nonce));
+                let account = alloc.entry(address).or_default();
+                account.nonce = Some(nonce);
+                account.deployment_nonce = Some(deployment_nonce);
+            }
+        }
+
+        for (key, value) in changed {
+            alloc
+                .entry(*key.address())
+                .or_default()
+                .storage
+                .insert(*key.key(), value);
+        }
+
+        let mut genesis = self.config.genesis.clone().unwrap_or_default();
+        genesis.alloc = Some(alloc);
+        Ok(genesis)
+    }
+
+    /// Applies a protocol upgrade right away, replacing the bootloaders, the default account and system contracts
+    /// of the chain.
+    ///
+    /// Every bytecode is validated before anything is changed, so a failed upgrade leaves the chain untouched.
+    pub fn apply_protocol_upgrade(&mut self, upgrade: ProtocolUpgrade) -> anyhow::Result<()> {
+        let protocol_version = self.validate_protocol_upgrade(&upgrade)?;
+        for (key, hash) in self.upgrade_system_contracts(upgrade, protocol_version)? {
+            self.fork_storage.set_value(key, hash);
+        }
+        Ok(())
+    }
 
+    /// Schedules a protocol upgrade to take effect with the next sealed batch, so that calls keep being executed
+    /// with the protocol version and contracts of the last sealed batch until then.
+    ///
+    /// The upgrade is validated right away, so a failed upgrade is never scheduled.
+    pub fn schedule_protocol_upgrade(&mut self, upgrade: ProtocolUpgrade) -> anyhow::Result<()> {
+        self.validate_protocol_upgrade(&upgrade)?;
+        self.pending_protocol_upgrades.push(upgrade);
         Ok(())
     }
 
+    /// Returns the protocol version the chain has once the scheduled protocol upgrades took effect.
+    pub fn next_protocol_version(&self) -> ProtocolVersionId {
+        self.pending_protocol_upgrades
+            .iter()
+            .rev()
+            .find_map(|upgrade| upgrade.protocol_version)
+            .and_then(|version| ProtocolVersionId::try_from(version).ok())
+            .unwrap_or_else(|| self.protocol_version())
+    }
+
+    /// Checks the protocol version and every bytecode of `upgrade`, returning the protocol version to upgrade to.
+    fn validate_protocol_upgrade(
+        &self,
+        upgrade: &ProtocolUpgrade,
+    ) -> anyhow::Result<Option<ProtocolVersionId>> {
+        let protocol_version = match upgrade.protocol_version {
+            Some(version) => {
+                let protocol_version = ProtocolVersionId::try_from(version)
+                    .ok()
+                    .filter(|version| {
+                        system_contracts::supported_new_chain_protocol_versions(*version)
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("unsupported protocol version {}", version))?;
+                if protocol_version < self.next_protocol_version() {
+                    anyhow::bail!(
+                        "cannot downgrade protocol version {} to {}",
+                        self.next_protocol_version() as u16,
+                        version
+                    );
+                }
+                Some(protocol_version)
+            }
+            None => None,
+        };
+
+        let bootloader = &upgrade.bootloader;
+        let bytecodes = [
+            &bootloader.proved_batch,
+            &bootloader.proved_batch_impersonating,
+            &bootloader.playground_batch,
+            &bootloader.fee_estimate,
+            &bootloader.fee_estimate_impersonating,
+            &upgrade.default_account,
+        ]
+        .iter()
+        .copied()
+        .flatten()
+        .chain(
+            upgrade
+                .system_contracts
+                .iter()
+                .map(|contract| &contract.bytecode),
+        );
+        for bytecode in bytecodes {
+            validate_bytecode(&bytecode.0).context("invalid bytecode in protocol upgrade")?;
+        }
+        Ok(protocol_version)
+    }
+
+    /// Replaces the bootloaders, the default account and the protocol version with the ones of a validated
+    /// `upgrade`, returning the code hashes to write to the storage for the upgraded system contracts.
+    fn upgrade_system_contracts(
+        &mut self,
+        upgrade: ProtocolUpgrade,
+        protocol_version: Option<ProtocolVersionId>,
+    ) -> anyhow::Result<Vec<(StorageKey, H256)>> {
+        let BootloaderUpgrade {
+            proved_batch,
+            proved_batch_impersonating,
+            playground_batch,
+            fee_estimate,
+            fee_estimate_impersonating,
+        } = upgrade.bootloader;
+        let bootloaders = [
+            (TxExecutionMode::VerifyExecute, false, proved_batch),
+            (
+                TxExecutionMode::VerifyExecute,
+                true,
+                proved_batch_impersonating,
+            ),
+            (TxExecutionMode::EthCall, false, playground_batch),
+            (TxExecutionMode::EstimateFee, false, fee_estimate),
+            (
+                TxExecutionMode::EstimateFee,
+                true,
+                fee_estimate_impersonating,
+            ),
+        ];
+        for (execution_mode, impersonating, bytecode) in bootloaders {
+            if let Some(bytecode) = bytecode {
+                self.system_contracts
+                    .replace_bootloader(execution_mode, impersonating, bytecode.0);
+            }
+        }
+        if let Some(bytecode) = upgrade.default_account {
+            store_code(&mut self.fork_storage, bytecode.0.clone())?;
+            self.system_contracts.replace_default_account(bytecode.0);
+        }
+        let mut code_hashes = Vec::with_capacity(upgrade.system_contracts.len());
+        for SystemContractUpgrade { address, bytecode } in upgrade.system_contracts {
+            let hash = store_code(&mut self.fork_storage, bytecode.0)?;
+            code_hashes.push((get_code_key(&address), hash));
+        }
+        if let Some(protocol_version) = protocol_version {
+            self.config.protocol_version = protocol_version;
+        }
+        Ok(code_hashes)
+    }
+
+    /// Applies the scheduled protocol upgrades to the batch about to be sealed on top of `storage`, returning
+    /// `system_contracts` to seal it with, upgraded if they are the ones the chain used to seal batches with.
+    fn take_pending_protocol_upgrades<W: WriteStorage>(
+        &mut self,
+        storage: &StoragePtr<W>,
+        system_contracts: BaseSystemContracts,
+    ) -> anyhow::Result<BaseSystemContracts> {
+        if self.pending_protocol_upgrades.is_empty() {
+            return Ok(system_contracts);
+        }
+        let impersonating = [false, true].iter().copied().find(|impersonating| {
+            self.system_contracts
+                .contracts(TxExecutionMode::VerifyExecute, *impersonating)
+                .hashes()
+                == system_contracts.hashes()
+        });
+        for upgrade in std::mem::take(&mut self.pending_protocol_upgrades) {
+            let protocol_version = upgrade
+                .protocol_version
+                .and_then(|version| ProtocolVersionId::try_from(version).ok());
+            for (key, hash) in self.upgrade_system_contracts(upgrade, protocol_version)? {
+                storage.borrow_mut().set_value(key, hash);
+            }
+        }
+        Ok(match impersonating {
+            Some(impersonating) => self
+                .system_contracts
+                .contracts(TxExecutionMode::VerifyExecute, impersonating)
+                .clone(),
+            None => system_contracts,
+        })
+    }
+
+    /// Loads the system contracts of `--system-contracts-dir` into the chain, if one is configured.
+    ///
+    /// # Returns
+    /// The directory the contracts were loaded from.
+    pub fn load_system_contracts_dir(&mut self) -> anyhow::Result<Option<String>> {
+        let dir = match self.config.system_contracts_dir.clone() {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let upgrade = system_contracts::upgrade_from_dir(
+            Path::new(&dir),
+            &self.config.system_contracts_options,
+        )
+        .with_context(|| format!("failed loading system contracts from {}", dir))?;
+        self.apply_protocol_upgrade(upgrade)?;
+        Ok(Some(dir))
+    }
+
     /// Creates a [Snapshot] of the current state of the node.
-    pub fn snapshot(&self) -> Result<Snapshot, String> {
-        let storage = self
-            .fork_storage
-            .inner
-            .read()
-            .map_err(|err| format!("failed acquiring read lock on storage: {:?}", err))?;
+    ///
+    /// Large collections are not copied. Instead, a checkpoint is opened in their journals, so that taking
+    /// a snapshot is cheap and restoring it is proportional to the amount of changes made since.
+    pub fn snapshot(&mut self) -> Result<Snapshot, String> {
+        let journal_depths = JournalDepths {
+            tx_results: self.tx_results.checkpoint(),
+            blocks: self.blocks.checkpoint(),
+            block_hashes: self.block_hashes.checkpoint(),
+            fork_storage: self.fork_storage.checkpoint(),
+        };
 
         Ok(Snapshot {
             current_batch: self.current_batch,
             current_miniblock: self.current_miniblock,
             current_miniblock_hash: self.current_miniblock_hash,
             fee_input_provider: self.fee_input_provider.clone(),
-            tx_results: self.tx_results.clone(),
-            blocks: self.blocks.clone(),
-            block_hashes: self.block_hashes.clone(),
+            journal_depths,
             filters: self.filters.clone(),
             impersonation_state: self.impersonation.state(),
             rich_accounts: self.rich_accounts.clone(),
             previous_states: self.previous_states.clone(),
-            raw_storage: storage.raw_storage.clone(),
-            value_read_cache: storage.value_read_cache.clone(),
-            factory_dep_cache: storage.factory_dep_cache.clone(),
+            system_contracts: self.system_contracts.clone(),
+            protocol_version: self.config.protocol_version,
+            pending_protocol_upgrades: self.pending_protocol_upgrades.clone(),
         })
     }
 
-    /// Restores a previously created [Snapshot] of the node.
+    /// Restores a previously created [Snapshot] of the node. Snapshots taken after it become invalid.
     pub fn restore_snapshot(&mut self, snapshot: Snapshot) -> Result<(), String> {
-        let mut storage = self
-            .fork_storage
-            .inner
-            .write()
-            .map_err(|err| format!("failed acquiring write lock on storage: {:?}", err))?;
-
         self.current_batch = snapshot.current_batch;
         self.current_miniblock = snapshot.current_miniblock;
         self.current_miniblock_hash = snapshot.current_miniblock_hash;
         self.fee_input_provider = snapshot.fee_input_provider;
-        self.tx_results = snapshot.tx_results;
-        self.blocks = snapshot.blocks;
-        self.block_hashes = snapshot.block_hashes;
+        let depths = snapshot.journal_depths;
+        self.tx_results.revert(depths.tx_results);
+        self.blocks.revert(depths.blocks);
+        self.block_hashes.revert(depths.block_hashes);
+        self.fork_storage.revert(&depths.fork_storage);
         self.filters = snapshot.filters;
         self.impersonation.set_state(snapshot.impersonation_state);
         self.rich_accounts = snapshot.rich_accounts;
         self.previous_states = snapshot.previous_states;
-        storage.raw_storage = snapshot.raw_storage;
-        storage.value_read_cache = snapshot.value_read_cache;
-        storage.factory_dep_cache = snapshot.factory_dep_cache;
+        self.system_contracts = snapshot.system_contracts;
+        self.config.protocol_version = snapshot.protocol_version;
+        self.pending_protocol_upgrades = snapshot.pending_protocol_upgrades;
 
         Ok(())
     }
 
     fn dump_state(&self, preserve_historical_states: bool) -> anyhow::Result<VersionedState> {
-        let fork_storage = self.fork_storage.dump_state();
+        Ok(Self::versioned_state(
+            self.blocks.values().cloned().collect(),
+            self.tx_results.values().cloned().collect(),
+            self.fork_storage.dump_state(),
+            &self.previous_states,
+            preserve_historical_states,
+        ))
+    }
+
+    /// Same as [InMemoryNodeInner::dump_state], but for the state captured by a [Snapshot] that has not been
+    /// reverted past yet.
+    fn dump_snapshot_state(
+        &self,
+        snapshot: &Snapshot,
+        preserve_historical_states: bool,
+    ) -> anyhow::Result<VersionedState> {
+        let depths = &snapshot.journal_depths;
+        Ok(Self::versioned_state(
+            self.blocks.values_at(depths.blocks).into_values().collect(),
+            self.tx_results
+                .values_at(depths.tx_results)
+                .into_values()
+                .collect(),
+            self.fork_storage.dump_state_at(&depths.fork_storage),
+            &snapshot.previous_states,
+            preserve_historical_states,
+        ))
+    }
+
+    fn versioned_state(
+        blocks: Vec<Block<TransactionVariant>>,
+        transactions: Vec<TransactionResult>,
+        fork_storage: SerializableForkStorage,
+        previous_states: &IndexMap<H256, Arc<HashMap<StorageKey, StorageValue>>>,
+        preserve_historical_states: bool,
+    ) -> VersionedState {
         let historical_states = if preserve_historical_states {
-            self.previous_states
+            previous_states
                 .iter()
-                .map(|(k, v)| (*k, SerializableStorage(v.clone().into_iter().collect())))
+                .map(|(k, v)| {
+                    (
+                        *k,
+                        SerializableStorage(v.iter().map(|(k, v)| (*k, *v)).collect()),
+                    )
+                })
                 .collect()
         } else {
             Vec::new()
         };
 
-        Ok(VersionedState::v1(StateV1 {
-            blocks: self.blocks.values().cloned().collect(),
-            transactions: self.tx_results.values().cloned().collect(),
+        VersionedState::v1(StateV1 {
+            blocks,
+            transactions,
             fork_storage,
             historical_states,
-        }))
+        })
     }
 
     fn load_blocks<T: AdvanceTime>(&mut self, mut time: T, blocks: Vec<Block<TransactionVariant>>) {
@@ -988,12 +1654,95 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
             state
                 .historical_states
                 .into_iter()
-                .map(|(k, v)| (k, v.0.into_iter().collect())),
+                .map(|(k, v)| (k, Arc::new(v.0.into_iter().collect()))),
         );
 
         Ok(true)
     }
 
+    /// Removes the last `depth` blocks along with their transactions and rewinds the storage to the state as of
+    /// the new latest block. Log filters are notified of the removed logs.
+    ///
+    /// Returns the transactions of the removed blocks, in the order they were executed.
+    pub fn rollback<T: AdvanceTime>(
+        &mut self,
+        time: &mut T,
+        depth: u64,
+    ) -> anyhow::Result<Vec<L2Tx>> {
+        let latest_number = self.current_miniblock;
+        anyhow::ensure!(
+            depth <= latest_number,
+            "cannot roll back {} blocks, the latest block is #{}",
+            depth,
+            latest_number
+        );
+        if depth == 0 {
+            return Ok(Vec::new());
+        }
+        let target_number = latest_number - depth;
+        let target_hash = *self
+            .block_hashes
+            .get(&target_number)
+            .ok_or_else(|| anyhow::anyhow!("block #{} does not exist", target_number))?;
+        let target_state = self
+            .previous_states
+            .get(&target_hash)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "state for block #{} is not available, only the last {} blocks can be rolled back",
+                    target_number,
+                    MAX_PREVIOUS_STATES
+                )
+            })?;
+
+        let mut removed_txs = Vec::new();
+        for number in target_number + 1..=latest_number {
+            let Some(hash) = self.block_hashes.remove(&number) else {
+                continue;
+            };
+            self.previous_states.shift_remove(&hash);
+            let Some(block) = self.blocks.remove(&hash) else {
+                continue;
+            };
+            for tx in block.transactions {
+                let tx_hash = match tx {
+                    TransactionVariant::Full(tx) => tx.hash,
+                    TransactionVariant::Hash(hash) => hash,
+                };
+                let Some(tx_result) = self.tx_results.remove(&tx_hash) else {
+                    continue;
+                };
+                for log in &tx_result.receipt.logs {
+                    self.filters
+                        .notify_removed_log(log, U64::from(latest_number));
+                }
+                removed_txs.push(tx_result.info.tx);
+            }
+        }
+        self.fork_storage.restore_local_state(&target_state);
+
+        let target_block = self
+            .blocks
+            .get(&target_hash)
+            .expect("block must exist as its hash is known");
+        let Some(target_batch_number) = target_block.l1_batch_number.map(|n| n.as_u32()) else {
+            panic!("encountered a block with no batch; this is not supposed to happen")
+        };
+        time.reset_to(target_block.timestamp.as_u64());
+        self.current_miniblock = target_number;
+        self.current_miniblock_hash = target_hash;
+        self.current_batch = target_batch_number;
+        tracing::info!(
+            number = target_number,
+            hash = %target_hash,
+            removed_transactions = removed_txs.len(),
+            "latest block after rollback"
+        );
+
+        Ok(removed_txs)
+    }
+
     fn apply_block<T: AdvanceTime>(
         &mut self,
         time: &mut T,
@@ -1046,6 +1795,14 @@ impl<S: std::fmt::Debug + ForkSource> InMemoryNodeInner<S> {
         self.filters.notify_new_block(block_hash);
     }
 
+    /// Collects the logs emitted by all transactions.
+    pub fn collect_logs(&self) -> Vec<Log> {
+        self.tx_results
+            .values()
+            .flat_map(|tx_result| tx_result.receipt.logs.iter().cloned())
+            .collect()
+    }
+
     fn get_block(&self, block_number: L2BlockNumber) -> Option<&Block<TransactionVariant>> {
         self.block_hashes
             .get(&(block_number.0 as u64))
@@ -1060,19 +1817,65 @@ pub struct Snapshot {
     pub(crate) current_batch: u32,
     pub(crate) current_miniblock: u64,
     pub(crate) current_miniblock_hash: H256,
-    // Currently, the fee is static and the fee input provider is immutable during the test node life cycle,
-    // but in the future, it may contain some mutable state.
+    /// Fee params along with the fee updates that are still scheduled.
     pub(crate) fee_input_provider: TestNodeFeeInputProvider,
-    pub(crate) tx_results: HashMap<H256, TransactionResult>,
-    pub(crate) blocks: HashMap<H256, Block<TransactionVariant>>,
-    pub(crate) block_hashes: HashMap<u64, H256>,
+    /// Journal checkpoints of transactions, blocks and storage at the time the snapshot was taken.
+    pub(crate) journal_depths: JournalDepths,
     pub(crate) filters: EthFilters,
     pub(crate) impersonation_state: ImpersonationState,
     pub(crate) rich_accounts: HashSet<H160>,
-    pub(crate) previous_states: IndexMap<H256, HashMap<StorageKey, StorageValue>>,
-    pub(crate) raw_storage: InMemoryStorage,
-    pub(crate) value_read_cache: HashMap<StorageKey, H256>,
-    pub(crate) factory_dep_cache: HashMap<H256, Option<Vec<u8>>>,
+    pub(crate) previous_states: IndexMap<H256, Arc<HashMap<StorageKey, StorageValue>>>,
+    /// Bootloaders and default account, along with the protocol version and upgrades that are still scheduled.
+    pub(crate) system_contracts: SystemContracts,
+    pub(crate) protocol_version: ProtocolVersionId,
+    pub(crate) pending_protocol_upgrades: Vec<ProtocolUpgrade>,
+}
+
+/// Journal checkpoints taken with a [Snapshot], one per journal, so that each journal is reverted to its own
+/// checkpoint no matter how many layers the others have.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct JournalDepths {
+    pub(crate) tx_results: usize,
+    pub(crate) blocks: usize,
+    pub(crate) block_hashes: usize,
+    pub(crate) fork_storage: ForkStorageCheckpoint,
+}
+
+impl JournalDepths {
+    /// Whether the snapshot these depths were taken with precedes the one `other` were taken with.
+    pub(crate) fn precedes(&self, other: &JournalDepths) -> bool {
+        self.tx_results < other.tx_results
+    }
+}
+
+/// Name of the fork the node starts with.
+pub const DEFAULT_FORK_NAME: &str = "default";
+
+/// A fork that is not currently selected, along with everything that is specific to it.
+pub struct StashedFork<S> {
+    pub(crate) inner: InMemoryNodeInner<S>,
+    pub(crate) snapshots: Vec<Snapshot>,
+    pub(crate) named_snapshots: IndexMap<String, Snapshot>,
+    pub(crate) timestamp: u64,
+    pub(crate) impersonation: ImpersonationState,
+    /// Transactions that were waiting in the pool when the fork was deselected.
+    pub(crate) pool: Vec<L2Tx>,
+}
+
+/// Named forks held by the node. Only the active one is backed by [InMemoryNode::inner],
+/// the others keep their state untouched until they are selected again.
+pub struct ForkRegistry<S> {
+    pub(crate) active: String,
+    pub(crate) inactive: IndexMap<String, StashedFork<S>>,
+}
+
+impl<S> Default for ForkRegistry<S> {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_FORK_NAME.to_string(),
+            inactive: Default::default(),
+        }
+    }
 }
 
 /// In-memory node, that can be used for local & unit testing.
@@ -1082,8 +1885,12 @@ pub struct Snapshot {
 pub struct InMemoryNode<S: Clone> {
     /// A thread safe reference to the [InMemoryNodeInner].
     pub(crate) inner: Arc<RwLock<InMemoryNodeInner<S>>>,
-    /// List of snapshots of the [InMemoryNodeInner]. This is bounded at runtime by [MAX_SNAPSHOTS].
+    /// List of snapshots of the [InMemoryNodeInner].
     pub(crate) snapshots: Arc<RwLock<Vec<Snapshot>>>,
+    /// Named snapshots of the [InMemoryNodeInner]. Unlike numbered ones, they are kept when reverted to.
+    pub(crate) named_snapshots: Arc<RwLock<IndexMap<String, Snapshot>>>,
+    /// Named forks that are not currently selected.
+    pub(crate) forks: Arc<RwLock<ForkRegistry<S>>>,
     /// Configuration option that survives reset.
     #[allow(dead_code)]
     pub(crate) system_contracts_options: system_contracts::Options,
@@ -1093,19 +1900,79 @@ pub struct InMemoryNode<S: Clone> {
     pub(crate) observability: Option<Observability>,
     pub(crate) pool: TxPool,
     pub(crate) sealer: BlockSealer,
+    /// System contracts the node was started with. Protocol upgrades only replace the ones of
+    /// [InMemoryNodeInner], see [InMemoryNode::base_system_contracts].
     pub(crate) system_contracts: SystemContracts,
+    /// An optional machine-readable event stream. Survives reset.
+    pub(crate) json_log: Option<JsonLogSink>,
+    /// State as of the last sealed block, published while a new block is being sealed.
+    pub(crate) committed: CommittedStateHandle<S>,
+    /// Held for the whole time a block is being sealed and by every other writer of the chain state, see
+    /// [InMemoryNode::write_inner]. Reads are served from [Self::committed] meanwhile.
+    pub(crate) seal_lock: Arc<Mutex<()>>,
 }
 
-fn contract_address_from_tx_result(execution_result: &VmExecutionResultAndLogs) -> Option<H160> {
-    for query in execution_result.logs.storage_logs.iter().rev() {
-        if query.log.is_write() && query.log.key.address() == &ACCOUNT_CODE_STORAGE_ADDRESS {
-            return Some(h256_to_account_address(query.log.key.key()));
-        }
-    }
-    None
+/// Write access to [InMemoryNodeInner] that also holds [InMemoryNode::seal_lock], so that the state cannot change
+/// while a block is being sealed.
+pub struct InnerWriteGuard<'a, S> {
+    inner: RwLockWriteGuard<'a, InMemoryNodeInner<S>>,
+    _seal: MutexGuard<'a, ()>,
 }
 
-impl<S: ForkSource + std::fmt::Debug + Clone> Default for InMemoryNode<S> {
+impl<S> std::ops::Deref for InnerWriteGuard<'_, S> {
+    type Target = InMemoryNodeInner<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<S> std::ops::DerefMut for InnerWriteGuard<'_, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// Returns the events emitted by a transaction executed in the block of `block_ctx` as API logs.
+fn logs_from_tx_result(
+    execution_result: &VmExecutionResultAndLogs,
+    tx_hash: H256,
+    block_ctx: &BlockContext,
+    batch_env: &L1BatchEnv,
+) -> Vec<Log> {
+    execution_result
+        .logs
+        .events
+        .iter()
+        .enumerate()
+        .map(|(log_idx, log)| Log {
+            address: log.address,
+            topics: log.indexed_topics.clone(),
+            data: Bytes(log.value.clone()),
+            block_hash: Some(block_ctx.hash),
+            block_number: Some(block_ctx.miniblock.into()),
+            l1_batch_number: Some(U64::from(batch_env.number.0)),
+            transaction_hash: Some(tx_hash),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::from(log_idx)),
+            transaction_log_index: Some(U256::from(log_idx)),
+            log_type: None,
+            removed: Some(false),
+            block_timestamp: Some(block_ctx.timestamp.into()),
+        })
+        .collect()
+}
+
+fn contract_address_from_tx_result(execution_result: &VmExecutionResultAndLogs) -> Option<H160> {
+    for query in execution_result.logs.storage_logs.iter().rev() {
+        if query.log.is_write() && query.log.key.address() == &ACCOUNT_CODE_STORAGE_ADDRESS {
+            return Some(h256_to_account_address(query.log.key.key()));
+        }
+    }
+    None
+}
+
+impl<S: ForkSource + std::fmt::Debug + Clone> Default for InMemoryNode<S> {
     fn default() -> Self {
         let impersonation = ImpersonationManager::default();
         InMemoryNode::new(
@@ -1142,9 +2009,20 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             impersonation.clone(),
             system_contracts.clone(),
         );
+        let json_log = config
+            .get_json_log()
+            .and_then(|path| match JsonLogSink::open(path) {
+                Ok(sink) => Some(sink),
+                Err(err) => {
+                    tracing::error!("failed to open JSON log '{}': {}", path, err);
+                    None
+                }
+            });
         InMemoryNode {
             inner: Arc::new(RwLock::new(inner)),
             snapshots: Default::default(),
+            named_snapshots: Default::default(),
+            forks: Default::default(),
             system_contracts_options,
             time,
             impersonation,
@@ -1152,6 +2030,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             pool,
             sealer,
             system_contracts,
+            json_log,
+            committed: Default::default(),
+            seal_lock: Default::default(),
         }
     }
 
@@ -1180,10 +2061,53 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             .map_err(|e| anyhow::anyhow!("InMemoryNode lock is poisoned: {}", e))
     }
 
-    pub fn write_inner(&self) -> anyhow::Result<RwLockWriteGuard<'_, InMemoryNodeInner<S>>> {
-        self.inner
+    /// Returns write access to the node state, waiting for a block that is being sealed to be applied first.
+    ///
+    /// Must not be called while the seal lock is held, i.e. from within block sealing.
+    pub fn write_inner(&self) -> anyhow::Result<InnerWriteGuard<'_, S>> {
+        let seal = self.lock_sealing()?;
+        let inner = self
+            .inner
             .write()
-            .map_err(|e| anyhow::anyhow!("InMemoryNode lock is poisoned: {}", e))
+            .map_err(|e| anyhow::anyhow!("InMemoryNode lock is poisoned: {}", e))?;
+        Ok(InnerWriteGuard { inner, _seal: seal })
+    }
+
+    /// Acquires [InMemoryNode::seal_lock].
+    fn lock_sealing(&self) -> anyhow::Result<MutexGuard<'_, ()>> {
+        self.seal_lock
+            .lock()
+            .map_err(|e| anyhow::anyhow!("seal lock is poisoned: {}", e))
+    }
+
+    /// Returns the state as of the last sealed block if a new block is currently being sealed.
+    pub fn committed_state(&self) -> Option<Arc<CommittedState<S>>> {
+        self.committed.load()
+    }
+
+    /// Reads a storage value as of the latest block. While a new block is being sealed, the value is
+    /// served from the [CommittedState] instead of waiting for the sealing to finish.
+    pub fn read_latest_value(&self, key: &StorageKey) -> eyre::Result<StorageValue> {
+        if let Some(committed) = self.committed_state() {
+            return committed.read_value(key);
+        }
+        self.inner
+            .read()
+            .map_err(|e| eyre::eyre!("InMemoryNode lock is poisoned: {}", e))?
+            .fork_storage
+            .read_value_internal(key)
+    }
+
+    /// Loads a factory dependency as of the latest block, see [Self::read_latest_value].
+    pub fn load_latest_factory_dep(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
+        if let Some(committed) = self.committed_state() {
+            return committed.load_factory_dep(hash);
+        }
+        self.inner
+            .read()
+            .map_err(|e| eyre::eyre!("InMemoryNode lock is poisoned: {}", e))?
+            .fork_storage
+            .load_factory_dep_internal(hash)
     }
 
     pub fn get_cache_config(&self) -> Result<CacheConfig, String> {
@@ -1213,42 +2137,34 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
 
     pub fn reset(&self, fork: Option<ForkDetails>) -> Result<(), String> {
         let config = self.get_config()?;
-        let inner = InMemoryNodeInner::new(
+        let mut inner = InMemoryNodeInner::new(
             fork,
             &config,
             &self.time,
             self.impersonation.clone(),
             self.system_contracts.clone(),
         );
+        inner
+            .load_system_contracts_dir()
+            .map_err(|err| format!("{:#}", err))?;
 
         let mut writer = self
             .snapshots
             .write()
             .map_err(|e| format!("Failed to acquire write lock: {}", e))?;
         writer.clear();
-
-        {
-            let mut guard = self
-                .inner
-                .write()
-                .map_err(|e| format!("Failed to acquire write lock: {}", e))?;
-            *guard = inner;
-        }
-
-        for wallet in LEGACY_RICH_WALLETS.iter() {
-            let address = wallet.0;
-            self.set_rich_account(
-                H160::from_str(address).unwrap(),
-                U256::from(100u128 * 10u128.pow(18)),
-            );
-        }
-        for wallet in RICH_WALLETS.iter() {
-            let address = wallet.0;
-            self.set_rich_account(
-                H160::from_str(address).unwrap(),
-                U256::from(100u128 * 10u128.pow(18)),
-            );
-        }
+        self.named_snapshots
+            .write()
+            .map_err(|e| format!("Failed to acquire write lock: {}", e))?
+            .clear();
+        METRICS.snapshots.set(0);
+
+        let mut guard = self.write_inner().map_err(|e| format!("{:#}", e))?;
+        *guard = inner;
+        guard.fund_rich_wallets();
+        guard
+            .apply_genesis_alloc()
+            .map_err(|err| format!("{:#}", err))?;
         Ok(())
     }
 
@@ -1256,27 +2172,47 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
     /// executable. Note that on error this method may leave node in partially applied state (i.e.
     /// some txs have been applied while others have not).
     pub fn apply_txs(&self, txs: Vec<L2Tx>, max_transactions: usize) -> anyhow::Result<()> {
+        // Lock time so that the produced blocks are guaranteed to be sequential in time.
+        self.apply_txs_with_time(&mut self.time.lock(), txs, max_transactions)
+    }
+
+    /// Same as [Self::apply_txs], but with time already locked by the caller.
+    pub(crate) fn apply_txs_with_time<T: AdvanceTime>(
+        &self,
+        time: &mut T,
+        txs: Vec<L2Tx>,
+        max_transactions: usize,
+    ) -> anyhow::Result<()> {
         tracing::debug!(count = txs.len(), "applying transactions");
 
         // Create a temporary tx pool (i.e. state is not shared with the node mempool).
         let pool = TxPool::new(self.impersonation.clone());
         pool.add_txs(txs);
 
-        // Lock time so that the produced blocks are guaranteed to be sequential in time.
-        let mut time = self.time.lock();
         while let Some(tx_batch) = pool.take_uniform(max_transactions) {
             // Getting contracts is reasonably cheap, so we don't cache them. We may need differing contracts
             // depending on whether impersonation should be enabled for a block.
-            let system_contracts = self
-                .system_contracts
-                .contracts(TxExecutionMode::VerifyExecute, tx_batch.impersonating)
-                .clone();
-            let expected_tx_hashes = tx_batch
+            let system_contracts =
+                self.base_system_contracts(TxExecutionMode::VerifyExecute, tx_batch.impersonating)?;
+            let mut expected_tx_hashes = tx_batch
                 .txs
                 .iter()
                 .map(|tx| tx.hash())
                 .collect::<HashSet<_>>();
-            let block_numer = self.seal_block(&mut time, tx_batch.txs, system_contracts)?;
+            let batch_size = tx_batch.txs.len();
+            let (block_numer, excluded_txs) =
+                self.seal_block_with_excluded(time, tx_batch.txs, system_contracts)?;
+            if excluded_txs.len() == batch_size {
+                anyhow::bail!(
+                    "transaction {:?} does not fit into an empty batch",
+                    excluded_txs[0].hash()
+                );
+            }
+            // Transactions that did not fit into the batch are applied with the next one
+            for tx in &excluded_txs {
+                expected_tx_hashes.remove(&tx.hash());
+            }
+            pool.requeue(excluded_txs);
 
             // Fetch the block that was just sealed
             let inner = self.read_inner()?;
@@ -1310,74 +2246,103 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
 
     /// Adds a lot of tokens to a given account with a specified balance.
     pub fn set_rich_account(&self, address: H160, balance: U256) {
-        let key = storage_key_for_eth_balance(&address);
-
-        let mut inner = match self.inner.write() {
+        let mut inner = match self.write_inner() {
             Ok(guard) => guard,
             Err(e) => {
                 tracing::info!("Failed to acquire write lock: {}", e);
                 return;
             }
         };
-
-        let keys = {
-            let mut storage_view = StorageView::new(&inner.fork_storage);
-            // Set balance to the specified amount
-            storage_view.set_value(key, u256_to_h256(balance));
-            storage_view.modified_storage_keys().clone()
-        };
-
-        for (key, value) in keys.iter() {
-            inner.fork_storage.set_value(*key, *value);
-        }
-        inner.rich_accounts.insert(address);
+        inner.set_rich_account(address, balance);
     }
 
     pub fn system_contracts_for_tx(
         &self,
         tx_initiator: Address,
     ) -> anyhow::Result<BaseSystemContracts> {
-        Ok(if self.impersonation.is_impersonating(&tx_initiator) {
+        let impersonating = self.impersonation.is_impersonating(&tx_initiator);
+        if impersonating {
             tracing::info!("🕵️ Executing tx from impersonated account {tx_initiator:?}");
-            self.system_contracts
-                .contracts(TxExecutionMode::VerifyExecute, true)
-                .clone()
-        } else {
-            self.system_contracts
-                .contracts(TxExecutionMode::VerifyExecute, false)
-                .clone()
-        })
+        }
+        self.base_system_contracts(TxExecutionMode::VerifyExecute, impersonating)
     }
 
-    /// Runs L2 'eth call' method - that doesn't commit to a block.
-    pub fn run_l2_call(
+    /// Returns the base system contracts of the active chain, which may differ from the ones the node was
+    /// started with after a protocol upgrade.
+    pub fn base_system_contracts(
+        &self,
+        execution_mode: TxExecutionMode,
+        impersonating: bool,
+    ) -> anyhow::Result<BaseSystemContracts> {
+        if let (TxExecutionMode::EthCall, false, Some(committed)) =
+            (execution_mode, impersonating, self.committed_state())
+        {
+            return Ok(committed.system_env.base_system_smart_contracts.clone());
+        }
+        Ok(self
+            .read_inner()?
+            .system_contracts
+            .contracts(execution_mode, impersonating)
+            .clone())
+    }
+
+    /// Executes `l2_tx` as an `eth_call` on top of the current state without any output,
+    /// returning the full execution result including its storage logs and call traces.
+    ///
+    /// The `overrides` are applied to the call's own view of the storage only, the node state is left untouched.
+    pub(crate) fn execute_l2_call(
         &self,
         mut l2_tx: L2Tx,
         base_contracts: BaseSystemContracts,
-    ) -> anyhow::Result<ExecutionResult> {
-        let execution_mode = TxExecutionMode::EthCall;
+        overrides: &HashMap<StorageKey, StorageValue>,
+    ) -> anyhow::Result<L2CallResult> {
+        // We must inject *some* signature (otherwise bootloader code fails to generate hash).
+        if l2_tx.common_data.signature.is_empty() {
+            l2_tx.common_data.signature = PackedEthSignature::default().serialize_packed().into();
+        }
+
+        // While a block is being sealed, the call is executed on top of the last sealed block
+        if let Some(committed) = self.committed_state() {
+            let storage = StorageView::new(&*committed).into_rc_ptr();
+            for (key, value) in overrides {
+                storage.borrow_mut().set_value(*key, *value);
+            }
+            let system_env = SystemEnv {
+                base_system_smart_contracts: base_contracts,
+                ..committed.system_env.clone()
+            };
+            let mut vm: Vm<_, HistoryDisabled> =
+                Vm::new(committed.batch_env.clone(), system_env, storage);
+            return Ok(Self::inspect_l2_call(&mut vm, l2_tx));
+        }
 
         let inner = self
             .inner
             .read()
             .map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?;
 
-        let storage = StorageView::new(&inner.fork_storage).into_rc_ptr();
+        let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+        for (key, value) in overrides {
+            storage.borrow_mut().set_value(*key, *value);
+        }
 
         // init vm
 
         let (batch_env, _) = inner.create_l1_batch_env(&self.time, storage.clone());
-        let system_env = inner.create_system_env(base_contracts, execution_mode);
-
-        let mut vm: Vm<_, HistoryDisabled> = Vm::new(batch_env, system_env, storage.clone());
+        let system_env = inner.create_system_env(base_contracts, TxExecutionMode::EthCall);
+        // Don't block block sealing while the call is executed
+        drop(inner);
 
-        // We must inject *some* signature (otherwise bootloader code fails to generate hash).
-        if l2_tx.common_data.signature.is_empty() {
-            l2_tx.common_data.signature = PackedEthSignature::default().serialize_packed().into();
-        }
+        let mut vm: Vm<_, HistoryDisabled> = Vm::new(batch_env, system_env, storage);
+        Ok(Self::inspect_l2_call(&mut vm, l2_tx))
+    }
 
-        let tx: Transaction = l2_tx.into();
-        vm.push_transaction(tx.clone());
+    /// Runs `l2_tx` in `vm` with the call tracers.
+    fn inspect_l2_call<W: WriteStorage>(
+        vm: &mut Vm<W, HistoryDisabled>,
+        l2_tx: L2Tx,
+    ) -> L2CallResult {
+        vm.push_transaction(l2_tx.into());
 
         let call_tracer_result = Arc::new(OnceCell::default());
 
@@ -1392,7 +2357,30 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             .take()
             .unwrap_or_default();
 
-        if inner.config.show_tx_summary {
+        L2CallResult {
+            result: tx_result,
+            call_traces,
+        }
+    }
+
+    /// Runs L2 'eth call' method - that doesn't commit to a block.
+    pub fn run_l2_call(
+        &self,
+        l2_tx: L2Tx,
+        base_contracts: BaseSystemContracts,
+    ) -> anyhow::Result<ExecutionResult> {
+        let tx: Transaction = l2_tx.clone().into();
+        let L2CallResult {
+            result: tx_result,
+            call_traces,
+        } = self.execute_l2_call(l2_tx, base_contracts, &HashMap::new())?;
+
+        let inner = self.read_inner()?;
+        let config = inner.config.clone();
+        let console_log_handler = inner.console_log_handler.clone();
+        drop(inner);
+
+        if config.show_tx_summary {
             tracing::info!("");
             match &tx_result.result {
                 ExecutionResult::Success { output } => {
@@ -1409,13 +2397,11 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             };
         }
 
-        if !inner.config.disable_console_log {
-            inner
-                .console_log_handler
-                .handle_calls_recursive(&call_traces);
+        if !config.disable_console_log {
+            console_log_handler.handle_calls_recursive(&call_traces);
         }
 
-        if inner.config.show_calls != ShowCalls::None {
+        if config.show_calls != ShowCalls::None {
             tracing::info!("");
             tracing::info!(
                 "[Transaction Execution] ({} calls)",
@@ -1430,9 +2416,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
                     tx.execute.contract_address,
                     call,
                     is_last_sibling,
-                    &inner.config.show_calls,
-                    inner.config.show_outputs,
-                    inner.config.resolve_hashes,
+                    &config.show_calls,
+                    config.show_outputs,
+                    config.resolve_hashes,
                 );
             }
         }
@@ -1476,12 +2462,12 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             anyhow::bail!("exceeds block gas limit");
         }
 
-        let l2_gas_price = self
-            .inner
-            .read()
-            .expect("failed acquiring reader")
-            .fee_input_provider
-            .gas_price();
+        let l2_gas_price = {
+            let reader = self.inner.read().expect("failed acquiring reader");
+            reader
+                .fee_input_provider
+                .gas_price(reader.protocol_version())
+        };
         if tx.common_data.fee.max_fee_per_gas < l2_gas_price.into() {
             tracing::info!(
                 "Submitted Tx is Unexecutable {:?} because of MaxFeePerGasTooLow {}",
@@ -1528,14 +2514,21 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
         &self,
         l2_tx: L2Tx,
         vm: &mut Vm<W, H>,
+    ) -> anyhow::Result<TxExecutionOutput> {
+        self.run_tx_raw(l2_tx.into(), vm)
+    }
+
+    /// Executes the given transaction of any kind, see [Self::run_l2_tx_raw].
+    fn run_tx_raw<W: WriteStorage, H: HistoryMode>(
+        &self,
+        tx: Transaction,
+        vm: &mut Vm<W, H>,
     ) -> anyhow::Result<TxExecutionOutput> {
         let inner = self
             .inner
             .read()
             .map_err(|_| anyhow::anyhow!("Failed to acquire read lock"))?;
 
-        let tx: Transaction = l2_tx.into();
-
         let call_tracer_result = Arc::new(OnceCell::default());
         let bootloader_debug_result = Arc::new(OnceCell::default());
 
@@ -1551,7 +2544,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             .push_transaction(tx.clone())
             .compressed_bytecodes
             .into_owned();
+        let started_at = Instant::now();
         let tx_result = vm.inspect(&mut tracers.into(), InspectExecutionMode::OneTx);
+        METRICS.tx_execution_time.observe(started_at.elapsed());
 
         let call_traces = call_tracer_result.get().unwrap();
 
@@ -1654,8 +2649,25 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
         block_ctx: &BlockContext,
         batch_env: &L1BatchEnv,
         vm: &mut Vm<W, H>,
+    ) -> anyhow::Result<()> {
+        let mut capacity = BatchCapacity::new(SealCriteria::unlimited());
+        self.run_l2_tx_in_batch(l2_tx, block_ctx, batch_env, vm, &mut capacity, None)
+    }
+
+    /// Runs L2 transaction and commits it to a new block if it fits into the `capacity` left in the batch. Otherwise
+    /// fails with a [SealResolution] and the transaction has to be rolled back. In gasless mode, the `prepayment`
+    /// credited to the payer is withdrawn once the transaction is executed.
+    fn run_l2_tx_in_batch<W: WriteStorage, H: HistoryMode>(
+        &self,
+        l2_tx: L2Tx,
+        block_ctx: &BlockContext,
+        batch_env: &L1BatchEnv,
+        vm: &mut Vm<W, H>,
+        capacity: &mut BatchCapacity,
+        prepayment: Option<&GaslessPrepayment<W>>,
     ) -> anyhow::Result<()> {
         let tx_hash = l2_tx.hash();
+        let encoding_size = Transaction::from(l2_tx.clone()).encoding_len() as u64;
         let transaction_type = l2_tx.common_data.transaction_type;
 
         let show_tx_summary = self
@@ -1694,6 +2706,16 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             // In such case, we should not persist the VM data, and we should pretend that transaction never existed.
             anyhow::bail!("Transaction HALT: {reason}");
         }
+        if let Some(prepayment) = prepayment {
+            prepayment.withdraw()?;
+        }
+        capacity.include(&BatchUsage {
+            transactions: 1,
+            gas: result.statistics.gas_used,
+            pubdata: result.statistics.pubdata_published as u64,
+            circuits: result.statistics.circuit_statistic,
+            encoding_size,
+        })?;
 
         // Write all the factory deps.
         let mut inner = self
@@ -1713,27 +2735,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             )
         }
 
-        let logs = result
-            .logs
-            .events
-            .iter()
-            .enumerate()
-            .map(|(log_idx, log)| Log {
-                address: log.address,
-                topics: log.indexed_topics.clone(),
-                data: Bytes(log.value.clone()),
-                block_hash: Some(block_ctx.hash),
-                block_number: Some(block_ctx.miniblock.into()),
-                l1_batch_number: Some(U64::from(batch_env.number.0)),
-                transaction_hash: Some(tx_hash),
-                transaction_index: Some(U64::zero()),
-                log_index: Some(U256::from(log_idx)),
-                transaction_log_index: Some(U256::from(log_idx)),
-                log_type: None,
-                removed: Some(false),
-                block_timestamp: Some(block_ctx.timestamp.into()),
-            })
-            .collect();
+        let logs = logs_from_tx_result(&result, tx_hash, block_ctx, batch_env);
         for log in &logs {
             inner
                 .filters
@@ -1758,10 +2760,28 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             } else {
                 U64::from(1)
             },
-            effective_gas_price: Some(inner.fee_input_provider.gas_price().into()),
+            effective_gas_price: Some(
+                inner
+                    .fee_input_provider
+                    .gas_price(inner.protocol_version())
+                    .into(),
+            ),
             transaction_type: Some((transaction_type as u32).into()),
             logs_bloom: Default::default(),
         };
+        if let Some(json_log) = &self.json_log {
+            let console_logs = inner
+                .console_log_handler
+                .collect_messages_recursive(&call_traces);
+            json_log.emit_transaction(
+                &l2_tx.clone().into(),
+                block_ctx.miniblock.into(),
+                batch_env.number.0,
+                &result,
+                &call_traces,
+                console_logs,
+            );
+        }
         let debug = create_debug_output(&l2_tx, &result, call_traces).expect("create debug output"); // OK to unwrap here as Halt is handled above
         inner.tx_results.insert(
             tx_hash,
@@ -1779,6 +2799,70 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
         Ok(())
     }
 
+    /// Runs an L1 priority transaction, e.g. of a replayed upstream block, if it fits into the `capacity` left in
+    /// the batch. Priority transactions are not indexed by the node, so their receipt is returned instead.
+    fn run_l1_tx_in_batch<W: WriteStorage, H: HistoryMode>(
+        &self,
+        l1_tx: L1Tx,
+        block_ctx: &BlockContext,
+        batch_env: &L1BatchEnv,
+        vm: &mut Vm<W, H>,
+        capacity: &mut BatchCapacity,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let tx_hash = l1_tx.hash();
+        let tx = Transaction::from(l1_tx.clone());
+        let encoding_size = tx.encoding_len() as u64;
+
+        let TxExecutionOutput { result, .. } = self.run_tx_raw(tx, vm)?;
+        if let ExecutionResult::Halt { reason } = result.result {
+            anyhow::bail!("Priority transaction HALT: {reason}");
+        }
+        capacity.include(&BatchUsage {
+            transactions: 1,
+            gas: result.statistics.gas_used,
+            pubdata: result.statistics.pubdata_published as u64,
+            circuits: result.statistics.circuit_statistic,
+            encoding_size,
+        })?;
+
+        // Bytecodes of priority transactions are published on L1, so they are not among the compressed ones.
+        let mut inner = self
+            .inner
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?;
+        for bytecode in &l1_tx.execute.factory_deps {
+            inner
+                .fork_storage
+                .store_factory_dep(hash_bytecode(bytecode), bytecode.clone());
+        }
+
+        let logs = logs_from_tx_result(&result, tx_hash, block_ctx, batch_env);
+        for log in &logs {
+            inner
+                .filters
+                .notify_new_log(log, block_ctx.miniblock.into());
+        }
+        Ok(TransactionReceipt {
+            transaction_hash: tx_hash,
+            block_hash: block_ctx.hash,
+            block_number: block_ctx.miniblock.into(),
+            l1_batch_number: Some(U64::from(batch_env.number.0)),
+            from: l1_tx.common_data.sender,
+            to: l1_tx.execute.contract_address,
+            gas_used: Some(l1_tx.common_data.gas_limit - result.refunds.gas_refunded),
+            contract_address: contract_address_from_tx_result(&result),
+            logs,
+            status: if result.result.is_failed() {
+                U64::from(0)
+            } else {
+                U64::from(1)
+            },
+            effective_gas_price: Some(l1_tx.common_data.max_fee_per_gas),
+            transaction_type: Some((TransactionType::PriorityOpTransaction as u32).into()),
+            ..Default::default()
+        })
+    }
+
     // Requirement for `TimeExclusive` ensures that we have exclusive writeable access to time
     // manager. Meaning we can construct blocks and apply them without worrying about TOCTOU with
     // timestamps.
@@ -1788,50 +2872,162 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
         txs: Vec<L2Tx>,
         system_contracts: BaseSystemContracts,
     ) -> anyhow::Result<L2BlockNumber> {
+        let (block_number, excluded_txs) =
+            self.seal_block_with_excluded(time, txs, system_contracts)?;
+        // Transactions that did not fit into the batch are included into the next one
+        self.pool.requeue(excluded_txs);
+        Ok(block_number)
+    }
+
+    /// Seals a block like [Self::seal_block], but returns the transactions that did not fit into the batch instead
+    /// of putting them back into the pool.
+    fn seal_block_with_excluded<T: AdvanceTime>(
+        &self,
+        time: &mut T,
+        txs: Vec<L2Tx>,
+        system_contracts: BaseSystemContracts,
+    ) -> anyhow::Result<(L2BlockNumber, Vec<L2Tx>)> {
+        // Other writers of the state wait until the block is applied, only readers are served meanwhile
+        let _seal = self.lock_sealing()?;
+        let result = self.seal_block_impl(time, vec![], txs, system_contracts);
+        self.committed.clear();
+        let (block_number, excluded_txs, _) = result?;
+        Ok((block_number, excluded_txs))
+    }
+
+    /// Seals a block that executes the L1 `priority_txs` before `txs`, like the upstream blocks replayed on a fork.
+    /// Returns the receipts of the executed priority transactions, which are not indexed by the node, and the
+    /// transactions that did not fit into the batch.
+    pub fn seal_block_with_priority_txs<T: AdvanceTime>(
+        &self,
+        time: &mut T,
+        priority_txs: Vec<L1Tx>,
+        txs: Vec<L2Tx>,
+        system_contracts: BaseSystemContracts,
+    ) -> anyhow::Result<(L2BlockNumber, Vec<L2Tx>, Vec<TransactionReceipt>)> {
+        let _seal = self.lock_sealing()?;
+        let result = self.seal_block_impl(time, priority_txs, txs, system_contracts);
+        self.committed.clear();
+        result
+    }
+
+    /// Must be called with [InMemoryNode::seal_lock] held.
+    fn seal_block_impl<T: AdvanceTime>(
+        &self,
+        time: &mut T,
+        priority_txs: Vec<L1Tx>,
+        txs: Vec<L2Tx>,
+        system_contracts: BaseSystemContracts,
+    ) -> anyhow::Result<(L2BlockNumber, Vec<L2Tx>, Vec<TransactionReceipt>)> {
         // Prepare a new block context and a new batch env
-        let inner = self
+        let mut inner = self
             .inner
-            .read()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire read lock"))?;
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?;
         let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+        // Calls are executed on top of the last sealed block, before the scheduled protocol upgrades take effect
+        let call_system_env = inner.create_system_env(
+            inner.system_contracts.contracts_for_l2_call().clone(),
+            TxExecutionMode::EthCall,
+        );
+        let system_contracts = inner.take_pending_protocol_upgrades(&storage, system_contracts)?;
         let system_env = inner.create_system_env(system_contracts, TxExecutionMode::VerifyExecute);
         let (batch_env, mut block_ctx) = inner.create_l1_batch_env(time, storage.clone());
+        let seal_criteria = inner.seal_criteria();
+        let gasless = inner.fee_input_provider.is_gasless();
+        // Publish the state as of the last sealed block, so that reads can be served while the new one is built
+        let log_count =
+            self.committed
+                .sync_logs(inner.current_miniblock_hash, inner.tx_results.len(), || {
+                    inner.collect_logs()
+                });
+        let committed = Arc::new(CommittedState::new(
+            inner.current_miniblock,
+            inner.current_miniblock_hash,
+            log_count,
+            batch_env.clone(),
+            call_system_env,
+            inner.fork_storage.clone(),
+        ));
+        self.committed.store(committed.clone());
         drop(inner);
 
         let mut vm: Vm<_, HistoryEnabled> = Vm::new(batch_env.clone(), system_env, storage.clone());
 
         // Compute block hash. Note that the computed block hash here will be different than that in production.
-        let tx_hashes = txs.iter().map(|t| t.hash()).collect::<Vec<_>>();
+        let tx_hashes = priority_txs
+            .iter()
+            .map(|tx| tx.hash())
+            .chain(txs.iter().map(|tx| tx.hash()))
+            .collect::<Vec<_>>();
         let hash = compute_hash(block_ctx.miniblock, &tx_hashes);
         block_ctx.hash = hash;
 
         // Execute transactions and bootloader
         let mut executed_tx_hashes = Vec::with_capacity(tx_hashes.len());
+        let mut capacity = BatchCapacity::new(seal_criteria);
+        let mut excluded_txs = Vec::new();
+        let mut priority_receipts = Vec::with_capacity(priority_txs.len());
+        for tx in priority_txs {
+            vm.pop_snapshot_no_rollback();
+            vm.make_snapshot();
+            let hash = tx.hash();
+            match self.run_l1_tx_in_batch(tx, &block_ctx, &batch_env, &mut vm, &mut capacity) {
+                Ok(receipt) => priority_receipts.push(receipt),
+                Err(e) => {
+                    vm.rollback_to_the_latest_snapshot();
+                    tracing::error!("Error while executing priority transaction {:?}: {e}", hash);
+                }
+            }
+        }
         for tx in txs {
+            // Once the batch is full, the remaining transactions are moved to the next one in their order.
+            if !excluded_txs.is_empty() {
+                excluded_txs.push(tx);
+                continue;
+            }
             // Executing a next transaction means that a previous transaction was either rolled back (in which case its snapshot
             // was already removed), or that we build on top of it (in which case, it can be removed now).
             vm.pop_snapshot_no_rollback();
             // Save pre-execution VM snapshot.
             vm.make_snapshot();
             let hash = tx.hash();
-            if let Err(e) = self.run_l2_tx(tx, &block_ctx, &batch_env, &mut vm) {
-                tracing::error!("Error while executing transaction: {e}");
-                vm.rollback_to_the_latest_snapshot();
-            } else {
-                executed_tx_hashes.push(hash);
+            let prepayment = gasless.then(|| GaslessPrepayment::credit(&storage, &tx));
+            match self.run_l2_tx_in_batch(
+                tx.clone(),
+                &block_ctx,
+                &batch_env,
+                &mut vm,
+                &mut capacity,
+                prepayment.as_ref(),
+            ) {
+                Ok(()) => executed_tx_hashes.push(hash),
+                Err(e) => {
+                    vm.rollback_to_the_latest_snapshot();
+                    if let Some(prepayment) = &prepayment {
+                        prepayment.restore();
+                    }
+                    match e.downcast_ref::<SealResolution>() {
+                        Some(SealResolution::ExcludeAndSeal(limit)) => {
+                            tracing::info!(
+                                "Transaction {:?} excluded because the batch is full ({} limit reached), moving it to the next batch",
+                                hash,
+                                limit
+                            );
+                            excluded_txs.push(tx);
+                        }
+                        _ => tracing::error!("Error while executing transaction: {e}"),
+                    }
+                }
             }
         }
         vm.execute(InspectExecutionMode::Bootloader);
+        committed.retain_values(storage.borrow().modified_storage_keys().keys());
 
-        // Write all the mutated keys (storage slots).
         let mut inner = self
             .inner
             .write()
             .map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?;
-        for (key, value) in storage.borrow().modified_storage_keys() {
-            inner.fork_storage.set_value(*key, *value);
-        }
-
         let mut transactions = Vec::new();
         let mut tx_receipts = Vec::new();
         let mut debug_calls = Vec::new();
@@ -1871,6 +3067,10 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
                     .chain([BloomInput::Raw(event.address.as_bytes())])
             });
         let logs_bloom = build_bloom(iter);
+        let block_logs = tx_receipts
+            .iter()
+            .flat_map(|r| r.logs.iter().cloned())
+            .collect::<Vec<_>>();
 
         // Calculate how much gas was used across all txs
         let gas_used = debug_calls
@@ -1884,8 +3084,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             .get(&(block_ctx.miniblock - 1))
             .cloned()
             .unwrap_or_default();
-        let block = create_block(
+        let mut block = create_block(
             &batch_env,
+            inner.protocol_version(),
             hash,
             parent_block_hash,
             block_ctx.miniblock,
@@ -1894,13 +3095,28 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             gas_used,
             logs_bloom,
         );
+        block.gas_limit = U256::from(seal_criteria.max_gas);
         inner.current_batch = inner.current_batch.saturating_add(1);
+        let next_batch = inner.current_batch.saturating_add(1);
+        inner.fee_input_provider.apply_fee_schedule(next_batch);
+        METRICS.blocks_sealed.inc();
+        METRICS.txs_sealed.inc_by(block.transactions.len() as u64);
+        if let Some(json_log) = &self.json_log {
+            json_log.emit_block(&block);
+        }
         inner.apply_block(time, block, 0);
 
+        // Write all the mutated keys (storage slots) only after `apply_block` archived the state of
+        // the previous block, so that `previous_states` (and thus historical `eth_getStorageAt`)
+        // holds the state as of the end of each block rather than including the next block's writes.
+        for (key, value) in storage.borrow().modified_storage_keys() {
+            inner.fork_storage.set_value(*key, *value);
+        }
+
         // Hack to ensure we don't mine two empty blocks in the same batch. Otherwise this creates
         // weird side effect on the VM side wrt virtual block logic.
         // TODO: Remove once we separate batch sealing from block sealing
-        if !executed_tx_hashes.is_empty() {
+        if !executed_tx_hashes.is_empty() || !priority_receipts.is_empty() {
             // With the introduction of 'l2 blocks' (and virtual blocks),
             // we are adding one l2 block at the end of each batch (to handle things like remaining events etc).
             // You can look at insert_fictive_l2_block function in VM to see how this fake block is inserted.
@@ -1908,8 +3124,9 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             let block_ctx = block_ctx.new_block(time);
             let hash = compute_hash(block_ctx.miniblock, []);
 
-            let virtual_block = create_block(
+            let mut virtual_block = create_block(
                 &batch_env,
+                inner.protocol_version(),
                 hash,
                 parent_block_hash,
                 block_ctx.miniblock,
@@ -1918,30 +3135,35 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
                 U256::zero(),
                 Bloom::zero(),
             );
+            virtual_block.gas_limit = U256::from(seal_criteria.max_gas);
+            METRICS.blocks_sealed.inc();
+            if let Some(json_log) = &self.json_log {
+                json_log.emit_block(&virtual_block);
+            }
             inner.apply_block(time, virtual_block, 1);
         }
+        self.committed.append_logs(
+            inner.current_miniblock_hash,
+            inner.tx_results.len(),
+            block_logs.into_iter(),
+        );
 
-        Ok(L2BlockNumber(block_ctx.miniblock as u32))
+        Ok((
+            L2BlockNumber(block_ctx.miniblock as u32),
+            excluded_txs,
+            priority_receipts,
+        ))
     }
 
     // Forcefully stores the given bytecode at a given account.
     pub fn override_bytecode(&self, address: &Address, bytecode: &[u8]) -> Result<(), String> {
         let mut inner = self
-            .inner
-            .write()
+            .write_inner()
             .map_err(|e| format!("Failed to acquire write lock: {}", e))?;
 
-        let code_key = get_code_key(address);
-
-        let bytecode_hash = hash_bytecode(bytecode);
-
         inner
-            .fork_storage
-            .store_factory_dep(bytecode_hash, bytecode.to_owned());
-
-        inner.fork_storage.set_value(code_key, bytecode_hash);
-
-        Ok(())
+            .set_code(*address, bytecode.to_owned(), CodeKind::EraVm)
+            .map_err(|e| format!("{:#}", e))
     }
 
     pub fn dump_state(&self, preserve_historical_states: bool) -> anyhow::Result<Bytes> {
@@ -1950,38 +3172,57 @@ impl<S: ForkSource + std::fmt::Debug + Clone> InMemoryNode<S> {
             .read()
             .map_err(|_| anyhow::anyhow!("Failed to acquire read lock"))?
             .dump_state(preserve_historical_states)?;
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&serde_json::to_vec(&state)?)?;
-        Ok(encoder.finish()?.into())
+        Ok(encode_state(&state)?.into())
+    }
+
+    /// Serializes the state captured by `snapshot` in the same format as [InMemoryNode::dump_state].
+    pub fn dump_snapshot_state(
+        &self,
+        snapshot: &Snapshot,
+        preserve_historical_states: bool,
+    ) -> anyhow::Result<Bytes> {
+        let state = self
+            .inner
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire read lock"))?
+            .dump_snapshot_state(snapshot, preserve_historical_states)?;
+        Ok(encode_state(&state)?.into())
     }
 
     pub fn load_state(&self, buf: Bytes) -> Result<bool, LoadStateError> {
-        let orig_buf = &buf.0[..];
-        let mut decoder = GzDecoder::new(orig_buf);
-        let mut decoded_data = Vec::new();
-
-        // Support both compressed and non-compressed state format
-        let decoded = if decoder.header().is_some() {
-            tracing::trace!(bytes = buf.0.len(), "decompressing state");
-            decoder
-                .read_to_end(decoded_data.as_mut())
-                .map_err(LoadStateError::FailedDecompress)?;
-            &decoded_data
-        } else {
-            &buf.0
-        };
-        tracing::trace!(bytes = decoded.len(), "deserializing state");
-        let state: VersionedState =
-            serde_json::from_slice(decoded).map_err(LoadStateError::FailedDeserialize)?;
+        let state = decode_state(&buf.0)?;
 
         let time = self.time.lock();
-        self.inner
-            .write()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire write lock"))?
-            .load_state(time, state)
+        self.write_inner()?.load_state(time, state)
     }
 }
 
+/// Serializes the state into a gzip-compressed JSON buffer.
+fn encode_state(state: &VersionedState) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec(state)?)?;
+    Ok(encoder.finish()?)
+}
+
+/// Deserializes the state from either a gzip-compressed or a plain JSON buffer.
+fn decode_state(buf: &[u8]) -> Result<VersionedState, LoadStateError> {
+    let mut decoder = GzDecoder::new(buf);
+    let mut decoded_data = Vec::new();
+
+    // Support both compressed and non-compressed state format
+    let decoded = if decoder.header().is_some() {
+        tracing::trace!(bytes = buf.len(), "decompressing state");
+        decoder
+            .read_to_end(decoded_data.as_mut())
+            .map_err(LoadStateError::FailedDecompress)?;
+        decoded_data.as_slice()
+    } else {
+        buf
+    };
+    tracing::trace!(bytes = decoded.len(), "deserializing state");
+    serde_json::from_slice(decoded).map_err(LoadStateError::FailedDeserialize)
+}
+
 /// Keeps track of a block's batch number, miniblock number and timestamp.
 /// Useful for keeping track of the current context when creating multiple blocks.
 #[derive(Debug, Clone, Default)]
@@ -2133,7 +3374,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_genesis_creates_block_with_hash_and_zero_parent_hash() {
-        let first_block = create_genesis::<TransactionVariant>(Some(1000));
+        let first_block =
+            create_genesis::<TransactionVariant>(Some(1000), ProtocolVersionId::latest());
 
         assert_eq!(first_block.hash, compute_hash(0, []));
         assert_eq!(first_block.parent_hash, H256::zero());
@@ -2158,11 +3400,13 @@ mod tests {
         // Execute next transaction using a fresh in-memory node and the external fork storage
         let mock_db = testing::ExternalStorage {
             raw_storage: external_storage.inner.read().unwrap().raw_storage.clone(),
+            read_delay: Default::default(),
+            transactions: Default::default(),
         };
         let impersonation = ImpersonationManager::default();
         let node: InMemoryNode<testing::ExternalStorage> = InMemoryNode::new(
             Some(ForkDetails {
-                fork_source: Box::new(mock_db),
+                fork_source: Arc::new(mock_db),
                 chain_id: TEST_NODE_NETWORK_ID.into(),
                 l1_block: L1BatchNumber(1),
                 l2_block: Block::default(),
@@ -2260,4 +3504,51 @@ mod tests {
             _ => panic!("invalid result {:?}", result.result),
         }
     }
+
+    #[tokio::test]
+    async fn test_reads_use_committed_state_while_sealing() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let key = storage_key_for_eth_balance(&H160::repeat_byte(0x1));
+        node.inner
+            .write()
+            .unwrap()
+            .fork_storage
+            .set_value(key, u256_to_h256(U256::from(1)));
+
+        let committed = {
+            let inner = node.inner.read().unwrap();
+            let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+            let (batch_env, _) = inner.create_l1_batch_env(&node.time, storage);
+            let system_env = inner.create_system_env(
+                inner.system_contracts.contracts_for_l2_call().clone(),
+                TxExecutionMode::EthCall,
+            );
+            Arc::new(CommittedState::new(
+                inner.current_miniblock,
+                inner.current_miniblock_hash,
+                0,
+                batch_env,
+                system_env,
+                inner.fork_storage.clone(),
+            ))
+        };
+        node.committed.store(committed.clone());
+        committed.retain_values([key].iter());
+        node.inner
+            .write()
+            .unwrap()
+            .fork_storage
+            .set_value(key, u256_to_h256(U256::from(2)));
+
+        assert_eq!(
+            u256_to_h256(U256::from(1)),
+            node.read_latest_value(&key).unwrap()
+        );
+
+        node.committed.clear();
+        assert_eq!(
+            u256_to_h256(U256::from(2)),
+            node.read_latest_value(&key).unwrap()
+        );
+    }
 }
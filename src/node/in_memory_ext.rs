@@ -1,31 +1,68 @@
-use crate::namespaces::DetailedTransaction;
+use crate::config::Genesis;
+use crate::deps::storage_view::StorageView;
+use crate::metrics::METRICS;
+use crate::namespaces::{
+    BatchLimitsUpdate, DetailedTransaction, FeeParamsUpdate, ForkInfo, ForkedNetwork,
+    HardhatMetadata, NamedSnapshot, ProtocolUpgrade, StateDiff, StateDiffPoint,
+};
+use crate::node::call_error_tracer::CallErrorTracer;
+use crate::node::error::LoadStateError;
+use crate::node::in_memory::{coinbase_key, is_system_address, prev_randao_key, CodeKind};
 use crate::node::pool::TxBatch;
 use crate::node::sealer::BlockSealerMode;
+use crate::node::state_diff::diff_states;
+use crate::node::time::{AdvanceTime, ReadTime, TimestampManager};
+use crate::node::{
+    ImpersonationManager, InMemoryNodeInner, StashedFork, MAX_PREVIOUS_STATES, MAX_TX_SIZE,
+};
 use crate::utils::Numeric;
 use crate::{
     fork::{ForkDetails, ForkSource},
     namespaces::ResetRequest,
     node::InMemoryNode,
-    utils::bytecode_to_factory_dep,
+    utils::{create_debug_output, to_real_block_number},
 };
 use anyhow::{anyhow, Context};
-use std::convert::TryInto;
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
 use std::time::Duration;
-use zksync_multivm::interface::TxExecutionMode;
-use zksync_types::api::{Block, TransactionVariant};
+use zksync_contracts::BaseSystemContracts;
+use zksync_multivm::interface::{
+    ExecutionResult, InspectExecutionMode, TxExecutionMode, VmFactory, VmInterface,
+};
+use zksync_multivm::tracers::CallTracer;
+use zksync_multivm::vm_latest::constants::ETH_CALL_GAS_LIMIT;
+use zksync_multivm::vm_latest::{HistoryDisabled, ToTracerPointer, Vm};
+use zksync_types::api::{Block, DebugCall, TransactionVariant};
+use zksync_types::l2::L2Tx;
+use zksync_types::transaction_request::CallRequestBuilder;
+use zksync_types::web3::Bytes;
 use zksync_types::{
-    get_code_key, get_nonce_key,
-    utils::{nonces_to_full_nonce, storage_key_for_eth_balance},
-    L2BlockNumber, StorageKey,
+    get_nonce_key,
+    utils::{decompose_full_nonce, nonces_to_full_nonce, storage_key_for_eth_balance},
+    L2BlockNumber, Nonce, StorageKey, StorageLogKind, StorageValue,
 };
 use zksync_types::{AccountTreeId, Address, H256, U256, U64};
-use zksync_utils::u256_to_h256;
+use zksync_utils::{address_to_h256, h256_to_u256, u256_to_h256};
 
 type Result<T> = anyhow::Result<T>;
 
-/// The maximum number of [Snapshot]s to store. Each snapshot represents the node state
-/// and can be used to revert the node to an earlier point in time.
-const MAX_SNAPSHOTS: u8 = 100;
+/// Selector of ERC20 `balanceOf(address)`.
+const ERC20_BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+/// Selector of ERC20 `allowance(address,address)`.
+const ERC20_ALLOWANCE_SELECTOR: [u8; 4] = [0xdd, 0x62, 0xed, 0x3e];
+
+/// ABI-encodes a call of `selector` with address arguments.
+fn erc20_calldata(selector: [u8; 4], args: &[Address]) -> Vec<u8> {
+    let mut calldata = selector.to_vec();
+    for arg in args {
+        calldata.extend_from_slice(address_to_h256(arg).as_bytes());
+    }
+    calldata
+}
 
 impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNode<S> {
     /// Increase the current timestamp for the node
@@ -84,10 +121,8 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
                 impersonating: false,
                 txs: Vec::new(),
             });
-        let base_system_contracts = self
-            .system_contracts
-            .contracts(TxExecutionMode::VerifyExecute, impersonating)
-            .clone();
+        let base_system_contracts =
+            self.base_system_contracts(TxExecutionMode::VerifyExecute, impersonating)?;
 
         let block_number = self.seal_block(&mut self.time.lock(), txs, base_system_contracts)?;
         tracing::info!("👷 Mined block #{}", block_number);
@@ -136,28 +171,21 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
     /// The `U64` identifier for this snapshot.
     pub fn snapshot(&self) -> Result<U64> {
         let snapshots = self.snapshots.clone();
-        self.read_inner().and_then(|writer| {
-            // validate max snapshots
-            snapshots
-                .read()
-                .map_err(|err| anyhow!("failed acquiring read lock for snapshot: {:?}", err))
-                .and_then(|snapshots| {
-                    if snapshots.len() >= MAX_SNAPSHOTS as usize {
-                        return Err(anyhow!(
-                            "maximum number of '{}' snapshots exceeded",
-                            MAX_SNAPSHOTS
-                        ));
-                    }
-
-                    Ok(())
-                })?;
-
+        let named_snapshots = self.named_snapshots.clone();
+        self.write_inner().and_then(|mut writer| {
             // snapshot the node
             let snapshot = writer.snapshot().map_err(|err| anyhow!("{}", err))?;
+            let named_snapshots_count = named_snapshots
+                .read()
+                .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))?
+                .len();
             snapshots
                 .write()
                 .map(|mut snapshots| {
                     snapshots.push(snapshot);
+                    METRICS
+                        .snapshots
+                        .set(snapshots.len() + named_snapshots_count);
                     tracing::info!("Created snapshot '{}'", snapshots.len());
                     snapshots.len()
                 })
@@ -177,10 +205,14 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
     /// `true` if a snapshot was reverted, otherwise `false`.
     pub fn revert_snapshot(&self, snapshot_id: U64) -> Result<bool> {
         let snapshots = self.snapshots.clone();
+        let named_snapshots = self.named_snapshots.clone();
         self.write_inner().and_then(|mut writer| {
             let mut snapshots = snapshots
                 .write()
                 .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))?;
+            let mut named_snapshots = named_snapshots
+                .write()
+                .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
             let snapshot_id_index = snapshot_id.as_usize().saturating_sub(1);
             if snapshot_id_index >= snapshots.len() {
                 return Err(anyhow!("no snapshot exists for the id '{}'", snapshot_id));
@@ -191,6 +223,15 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
                 .drain(snapshot_id_index..)
                 .next()
                 .expect("unexpected failure, value must exist");
+            // named snapshots taken after the selected one are invalidated as well
+            named_snapshots.retain(|_, snapshot| {
+                snapshot
+                    .journal_depths
+                    .precedes(&selected_snapshot.journal_depths)
+            });
+            METRICS
+                .snapshots
+                .set(snapshots.len() + named_snapshots.len());
 
             tracing::info!("Reverting node to snapshot '{snapshot_id:?}'");
             writer
@@ -203,6 +244,271 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         })
     }
 
+    /// Snapshot the state of the blockchain at the current block under the given name. Unlike numbered
+    /// snapshots, a named snapshot can be reverted to any number of times until it is deleted.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the snapshot. Must not be used by another snapshot.
+    ///
+    /// # Returns
+    /// `true` if a snapshot was created.
+    pub fn create_named_snapshot(&self, name: String) -> Result<bool> {
+        let snapshots = self.snapshots.clone();
+        let named_snapshots = self.named_snapshots.clone();
+        self.write_inner().and_then(|mut writer| {
+            let snapshots_count = snapshots
+                .read()
+                .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))?
+                .len();
+            let mut named_snapshots = named_snapshots
+                .write()
+                .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+            if named_snapshots.contains_key(&name) {
+                return Err(anyhow!("snapshot '{}' already exists", name));
+            }
+
+            let snapshot = writer.snapshot().map_err(|err| anyhow!("{}", err))?;
+            named_snapshots.insert(name.clone(), snapshot);
+            METRICS
+                .snapshots
+                .set(snapshots_count + named_snapshots.len());
+            tracing::info!("Created snapshot '{}'", name);
+            Ok(true)
+        })
+    }
+
+    /// Lists all named snapshots in the order they were created.
+    pub fn list_named_snapshots(&self) -> Result<Vec<NamedSnapshot>> {
+        self.named_snapshots
+            .read()
+            .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))
+            .map(|named_snapshots| {
+                named_snapshots
+                    .iter()
+                    .map(|(name, snapshot)| NamedSnapshot {
+                        name: name.clone(),
+                        block_number: U64::from(snapshot.current_miniblock),
+                        block_hash: snapshot.current_miniblock_hash,
+                    })
+                    .collect()
+            })
+    }
+
+    /// Deletes a named snapshot. The node state is not affected.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the snapshot to delete.
+    ///
+    /// # Returns
+    /// `true` if a snapshot was deleted, `false` if it did not exist.
+    pub fn delete_named_snapshot(&self, name: String) -> Result<bool> {
+        let snapshots_count = self
+            .snapshots
+            .read()
+            .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))?
+            .len();
+        let mut named_snapshots = self
+            .named_snapshots
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+        let deleted = named_snapshots.shift_remove(&name).is_some();
+        METRICS
+            .snapshots
+            .set(snapshots_count + named_snapshots.len());
+        if deleted {
+            tracing::info!("Deleted snapshot '{}'", name);
+        }
+        Ok(deleted)
+    }
+
+    /// Revert the state of the blockchain to a named snapshot. The snapshot itself is kept, but all snapshots
+    /// (named or numbered) taken after it are deleted.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the snapshot to revert to.
+    ///
+    /// # Returns
+    /// `true` if a snapshot was reverted.
+    pub fn revert_to_named_snapshot(&self, name: String) -> Result<bool> {
+        let snapshots = self.snapshots.clone();
+        let named_snapshots = self.named_snapshots.clone();
+        self.write_inner().and_then(|mut writer| {
+            let mut snapshots = snapshots
+                .write()
+                .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+            let mut named_snapshots = named_snapshots
+                .write()
+                .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+            let selected_snapshot = named_snapshots
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no snapshot exists for the name '{}'", name))?;
+
+            tracing::info!("Reverting node to snapshot '{}'", name);
+            writer
+                .restore_snapshot(selected_snapshot)
+                .map_err(|err| anyhow!("{}", err))?;
+            // re-open the reverted checkpoint so that the snapshot can be reverted to again
+            let snapshot = writer.snapshot().map_err(|err| anyhow!("{}", err))?;
+            snapshots.retain(|other| other.journal_depths.precedes(&snapshot.journal_depths));
+            named_snapshots
+                .retain(|_, other| other.journal_depths.precedes(&snapshot.journal_depths));
+            named_snapshots.insert(name, snapshot);
+            METRICS
+                .snapshots
+                .set(snapshots.len() + named_snapshots.len());
+            Ok(true)
+        })
+    }
+
+    /// Serializes the state captured by a named snapshot, so that it can be loaded into another node
+    /// with [InMemoryNode::load_named_snapshot]. Uses the same format as `anvil_dumpState`.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the snapshot to save.
+    ///
+    /// # Returns
+    /// The serialized state of the snapshot.
+    pub fn save_named_snapshot(&self, name: String) -> Result<Bytes> {
+        let snapshot = self
+            .named_snapshots
+            .read()
+            .map_err(|err| anyhow!("failed acquiring read lock for snapshots: {:?}", err))?
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("no snapshot exists for the name '{}'", name))?;
+        let state = self.dump_snapshot_state(&snapshot, true)?;
+        tracing::info!("Saved snapshot '{}'", name);
+        Ok(state)
+    }
+
+    /// Loads a state serialized by [InMemoryNode::save_named_snapshot] (or `anvil_dumpState`) into a node
+    /// without existing state, and creates a named snapshot of it.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the snapshot to create.
+    /// - `bytes`: The serialized state.
+    ///
+    /// # Returns
+    /// `true` if the snapshot was loaded.
+    pub fn load_named_snapshot(
+        &self,
+        name: String,
+        bytes: Bytes,
+    ) -> std::result::Result<bool, LoadStateError> {
+        self.load_state(bytes)?;
+        tracing::info!("Loaded snapshot '{}'", name);
+        Ok(self.create_named_snapshot(name)?)
+    }
+
+    /// Computes all storage slots that differ between two points in the node history.
+    ///
+    /// # Parameters
+    /// - `from`: The block or named snapshot to diff from.
+    /// - `to`: The block or named snapshot to diff to.
+    ///
+    /// # Returns
+    /// The changed storage slots, decoded where possible.
+    pub fn state_diff(&self, from: StateDiffPoint, to: StateDiffPoint) -> Result<StateDiff> {
+        let inner = self.read_inner()?;
+        let (from_block, before) = self.local_state_at(&inner, from)?;
+        let (to_block, after) = self.local_state_at(&inner, to)?;
+
+        // accounts involved in transactions between the two points, used to decode balance and nonce slots
+        let mut accounts = inner.rich_accounts.iter().copied().collect::<Vec<_>>();
+        for number in from_block.min(to_block) + 1..=from_block.max(to_block) {
+            let Some(block) = inner
+                .block_hashes
+                .get(&number)
+                .and_then(|hash| inner.blocks.get(hash))
+            else {
+                continue;
+            };
+            for tx in &block.transactions {
+                let hash = match tx {
+                    TransactionVariant::Full(tx) => tx.hash,
+                    TransactionVariant::Hash(hash) => *hash,
+                };
+                if let Some(tx_result) = inner.tx_results.get(&hash) {
+                    let receipt = &tx_result.receipt;
+                    accounts.push(receipt.from);
+                    accounts.extend(receipt.to);
+                    accounts.extend(receipt.contract_address);
+                }
+            }
+        }
+
+        let changes = diff_states(
+            &before,
+            &after,
+            |key| {
+                inner
+                    .fork_storage
+                    .read_fork_value_internal(key)
+                    .map_err(|err| anyhow!("failed reading fork storage: {:?}", err))
+            },
+            accounts,
+        )?;
+        Ok(StateDiff {
+            from_block: U64::from(from_block),
+            to_block: U64::from(to_block),
+            changes,
+        })
+    }
+
+    /// Returns the block number and the local storage at the given point in the node history.
+    fn local_state_at(
+        &self,
+        inner: &InMemoryNodeInner<S>,
+        point: StateDiffPoint,
+    ) -> Result<(u64, Arc<HashMap<StorageKey, StorageValue>>)> {
+        match point {
+            StateDiffPoint::Block(block_number) => {
+                let number =
+                    to_real_block_number(block_number, U64::from(inner.current_miniblock)).as_u64();
+                if number == inner.current_miniblock {
+                    let state = inner
+                        .fork_storage
+                        .inner
+                        .read()
+                        .map_err(|err| {
+                            anyhow!("failed acquiring read lock on fork storage: {:?}", err)
+                        })?
+                        .raw_storage
+                        .state
+                        .clone();
+                    return Ok((number, Arc::new(state)));
+                }
+                let state = inner
+                    .block_hashes
+                    .get(&number)
+                    .ok_or_else(|| anyhow!("block #{} does not exist", number))
+                    .and_then(|hash| {
+                        inner.previous_states.get(hash).cloned().ok_or_else(|| {
+                            anyhow!(
+                                "state for block #{} is not available, only the last {} blocks are kept",
+                                number,
+                                MAX_PREVIOUS_STATES
+                            )
+                        })
+                    })?;
+                Ok((number, state))
+            }
+            StateDiffPoint::Snapshot { snapshot: name } => {
+                let named_snapshots = self.named_snapshots.read().map_err(|err| {
+                    anyhow!("failed acquiring read lock for snapshots: {:?}", err)
+                })?;
+                let snapshot = named_snapshots
+                    .get(&name)
+                    .ok_or_else(|| anyhow!("no snapshot exists for the name '{}'", name))?;
+                let state = inner
+                    .fork_storage
+                    .local_state_at(&snapshot.journal_depths.fork_storage);
+                Ok((snapshot.current_miniblock, Arc::new(state)))
+            }
+        }
+    }
+
     pub fn set_balance(&self, address: Address, balance: U256) -> Result<bool> {
         self.write_inner().map(|mut writer| {
             let balance_key = storage_key_for_eth_balance(&address);
@@ -256,10 +562,8 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
                     impersonating: false,
                     txs: Vec::new(),
                 });
-            let base_system_contracts = self
-                .system_contracts
-                .contracts(TxExecutionMode::VerifyExecute, impersonating)
-                .clone();
+            let base_system_contracts =
+                self.base_system_contracts(TxExecutionMode::VerifyExecute, impersonating)?;
             self.seal_block(&mut time, txs, base_system_contracts)?;
         }
         tracing::info!("👷 Mined {} blocks", num_blocks);
@@ -323,6 +627,178 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         }
     }
 
+    /// Creates a new named fork next to the existing ones. The active fork is not changed unless `select` is set.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the fork, must be unique.
+    /// - `url`: The URL to fork from. A fresh local chain is created if not set.
+    /// - `block_number`: The block to fork at, defaults to the latest one.
+    /// - `select`: Whether to make the new fork active.
+    pub fn create_fork(
+        &self,
+        name: String,
+        url: Option<String>,
+        block_number: Option<U64>,
+        select: bool,
+    ) -> Result<bool> {
+        {
+            let forks = self
+                .forks
+                .read()
+                .map_err(|err| anyhow!("failed acquiring read lock for forks: {:?}", err))?;
+            if forks.active == name || forks.inactive.contains_key(&name) {
+                return Err(anyhow!("fork '{}' already exists", name));
+            }
+        }
+
+        let fork_details = match url {
+            Some(url) => {
+                let cache_config = self.get_cache_config().map_err(|err| anyhow!(err))?;
+                Some(
+                    ForkDetails::from_url(url, block_number.map(|n| n.as_u64()), cache_config)
+                        .map_err(|err| anyhow!(err.to_string()))?,
+                )
+            }
+            None => None,
+        };
+        let config = self.read_inner()?.config.clone();
+        // the fork gets its own clock and impersonation state so that the active fork's ones are left untouched
+        let time = TimestampManager::default();
+        let impersonation = ImpersonationManager::default();
+        let mut inner = InMemoryNodeInner::new(
+            fork_details,
+            &config,
+            &time,
+            impersonation.clone(),
+            self.system_contracts.clone(),
+        );
+        // once selected, the fork uses the node's manager with its own state swapped in
+        inner.impersonation = self.impersonation.clone();
+        inner.load_system_contracts_dir()?;
+        inner.fund_rich_wallets();
+        inner.apply_genesis_alloc()?;
+        // the fork's time continues from its latest block
+        let timestamp = inner.blocks.get(&inner.current_miniblock_hash).map_or_else(
+            || time.current_timestamp(),
+            |block| block.timestamp.as_u64(),
+        );
+
+        self.forks
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for forks: {:?}", err))?
+            .inactive
+            .insert(
+                name.clone(),
+                StashedFork {
+                    inner,
+                    snapshots: Default::default(),
+                    named_snapshots: Default::default(),
+                    timestamp,
+                    impersonation: impersonation.state(),
+                    pool: Vec::new(),
+                },
+            );
+        tracing::info!("👷 Created fork '{}'", name);
+
+        if select {
+            self.select_fork(name)?;
+        }
+        Ok(true)
+    }
+
+    /// Makes the given fork active. The previously active fork keeps its state, including snapshots, time,
+    /// impersonated accounts and pending transactions, until it is selected again.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the fork to select.
+    pub fn select_fork(&self, name: String) -> Result<bool> {
+        let mut forks = self
+            .forks
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for forks: {:?}", err))?;
+        if forks.active == name {
+            return Ok(true);
+        }
+        let selected = forks
+            .inactive
+            .shift_remove(&name)
+            .ok_or_else(|| anyhow!("no fork exists for the name '{}'", name))?;
+
+        let mut time = self.time.lock();
+        let mut inner = self.write_inner()?;
+        let mut snapshots = self
+            .snapshots
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+        let mut named_snapshots = self
+            .named_snapshots
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for snapshots: {:?}", err))?;
+
+        let deselected = StashedFork {
+            inner: std::mem::replace(&mut *inner, selected.inner),
+            snapshots: std::mem::replace(&mut *snapshots, selected.snapshots),
+            named_snapshots: std::mem::replace(&mut *named_snapshots, selected.named_snapshots),
+            timestamp: time.current_timestamp(),
+            impersonation: self.impersonation.state(),
+            pool: self.pool.take_all(),
+        };
+        time.reset_to(selected.timestamp);
+        self.impersonation.set_state(selected.impersonation);
+        self.pool.add_txs(selected.pool);
+        self.committed.reset();
+        if let Some(json_log) = &self.json_log {
+            json_log.emit_fork_selected(&name, inner.current_miniblock);
+        }
+        METRICS
+            .snapshots
+            .set(snapshots.len() + named_snapshots.len());
+
+        let deselected_name = std::mem::replace(&mut forks.active, name.clone());
+        forks.inactive.insert(deselected_name, deselected);
+        tracing::info!("👷 Selected fork '{}'", name);
+        Ok(true)
+    }
+
+    /// Lists all forks held by the node, starting with the active one.
+    pub fn list_forks(&self) -> Result<Vec<ForkInfo>> {
+        let forks = self
+            .forks
+            .read()
+            .map_err(|err| anyhow!("failed acquiring read lock for forks: {:?}", err))?;
+        let inner = self.read_inner()?;
+        let mut infos = vec![fork_info(&forks.active, true, &inner)];
+        infos.extend(
+            forks
+                .inactive
+                .iter()
+                .map(|(name, fork)| fork_info(name, false, &fork.inner)),
+        );
+        Ok(infos)
+    }
+
+    /// Removes an inactive fork along with all its state.
+    ///
+    /// # Parameters
+    /// - `name`: The name of the fork to remove.
+    ///
+    /// # Returns
+    /// `true` if a fork was removed, `false` if it did not exist.
+    pub fn remove_fork(&self, name: String) -> Result<bool> {
+        let mut forks = self
+            .forks
+            .write()
+            .map_err(|err| anyhow!("failed acquiring write lock for forks: {:?}", err))?;
+        if forks.active == name {
+            return Err(anyhow!("cannot remove the active fork '{}'", name));
+        }
+        let removed = forks.inactive.shift_remove(&name).is_some();
+        if removed {
+            tracing::info!("👷 Removed fork '{}'", name);
+        }
+        Ok(removed)
+    }
+
     pub fn auto_impersonate_account(&self, enabled: bool) {
         self.impersonation.set_auto_impersonation(enabled);
     }
@@ -350,28 +826,21 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         }
     }
 
+    /// Sets the code of `address`, which is EVM runtime bytecode when the EVM emulator is enabled and EraVM
+    /// bytecode otherwise.
     pub fn set_code(&self, address: Address, code: String) -> Result<()> {
         self.write_inner().and_then(|mut writer| {
-            let code_key = get_code_key(&address);
             tracing::info!("set code for address {address:#x}");
             let code_slice = code
                 .strip_prefix("0x")
                 .ok_or_else(|| anyhow!("code must be 0x-prefixed"))?;
             let code_bytes = hex::decode(code_slice)?;
-            let hashcode = bytecode_to_factory_dep(code_bytes)?;
-            let hash = u256_to_h256(hashcode.0);
-            let code = hashcode
-                .1
-                .iter()
-                .flat_map(|entry| {
-                    let mut bytes = vec![0u8; 32];
-                    entry.to_big_endian(&mut bytes);
-                    bytes.to_vec()
-                })
-                .collect();
-            writer.fork_storage.store_factory_dep(hash, code);
-            writer.fork_storage.set_value(code_key, hash);
-            Ok(())
+            let kind = if writer.config.use_evm_emulator {
+                CodeKind::Evm
+            } else {
+                CodeKind::EraVm
+            };
+            writer.set_code(address, code_bytes, kind)
         })
     }
 
@@ -383,6 +852,122 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         })
     }
 
+    /// Sets the ERC20 balance of `account` in `token` to `amount`.
+    pub fn deal_erc20(&self, token: Address, account: Address, amount: U256) -> Result<bool> {
+        let calldata = erc20_calldata(ERC20_BALANCE_OF_SELECTOR, &[account]);
+        let key = self
+            .set_erc20_value(token, calldata, amount)
+            .with_context(|| format!("failed setting the balance of {:?}", account))?;
+        tracing::info!(
+            "👷 ERC20 balance of {:?} in token {:?} has been set to {} (slot {:?})",
+            account,
+            token,
+            amount,
+            key.key()
+        );
+        Ok(true)
+    }
+
+    /// Sets the ERC20 allowance given by `owner` to `spender` in `token` to `amount`.
+    pub fn set_erc20_allowance(
+        &self,
+        token: Address,
+        owner: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<bool> {
+        let calldata = erc20_calldata(ERC20_ALLOWANCE_SELECTOR, &[owner, spender]);
+        let key = self
+            .set_erc20_value(token, calldata, amount)
+            .with_context(|| format!("failed setting the allowance of {:?}", spender))?;
+        tracing::info!(
+            "👷 ERC20 allowance of {:?} for {:?} in token {:?} has been set to {} (slot {:?})",
+            owner,
+            spender,
+            token,
+            amount,
+            key.key()
+        );
+        Ok(true)
+    }
+
+    /// Finds the storage slot holding the `uint256` returned by calling `token` with `calldata` and sets it to
+    /// `value`.
+    ///
+    /// The candidates are the slots read by the call, those of `token` first so that proxies are supported. Each
+    /// candidate is probed by overriding it with a random value in the call's own view of the storage, so the
+    /// node state is only written once, for the slot whose value the call returns exactly.
+    fn set_erc20_value(
+        &self,
+        token: Address,
+        calldata: Vec<u8>,
+        value: U256,
+    ) -> Result<StorageKey> {
+        let system_contracts = self.base_system_contracts(TxExecutionMode::EthCall, false)?;
+        let allow_no_target = system_contracts.evm_emulator.is_some();
+        let request = CallRequestBuilder::default()
+            .to(Some(token))
+            .data(calldata.into())
+            .build();
+        let mut tx = L2Tx::from_request(request.into(), MAX_TX_SIZE, allow_no_target)
+            .map_err(|err| anyhow!("failed creating the call: {:?}", err))?;
+        tx.common_data.fee.gas_limit = ETH_CALL_GAS_LIMIT.into();
+
+        let (_, reads) = self.call_erc20(&tx, &system_contracts, &HashMap::new())?;
+        let (mut candidates, elsewhere): (Vec<_>, Vec<_>) = reads
+            .into_iter()
+            .rev()
+            .unique()
+            .filter(|key| !is_system_address(key.address()))
+            .partition(|key| *key.address() == token);
+        candidates.extend(elsewhere);
+
+        let returns = |key: StorageKey, expected: U256| {
+            let overrides = HashMap::from([(key, u256_to_h256(expected))]);
+            self.call_erc20(&tx, &system_contracts, &overrides)
+                .map_or(false, |(output, _)| output == expected)
+        };
+        let key = candidates
+            .into_iter()
+            .find(|key| returns(*key, h256_to_u256(H256::random())) && returns(*key, value))
+            .ok_or_else(|| anyhow!("no storage slot read by token {:?} holds the value", token))?;
+
+        self.write_inner()?
+            .fork_storage
+            .set_value(key, u256_to_h256(value));
+        Ok(key)
+    }
+
+    /// Runs an ERC20 view call with the given storage `overrides`, returning the `uint256` it returns along with the
+    /// storage slots it read.
+    fn call_erc20(
+        &self,
+        tx: &L2Tx,
+        system_contracts: &BaseSystemContracts,
+        overrides: &HashMap<StorageKey, StorageValue>,
+    ) -> Result<(U256, Vec<StorageKey>)> {
+        let call = self.execute_l2_call(tx.clone(), system_contracts.clone(), overrides)?;
+        let output = match call.result.result {
+            ExecutionResult::Success { output } if output.len() == 32 => {
+                U256::from_big_endian(&output)
+            }
+            ExecutionResult::Success { .. } => {
+                anyhow::bail!("the token did not return a uint256")
+            }
+            ExecutionResult::Revert { output } => anyhow::bail!("the call reverted: {}", output),
+            ExecutionResult::Halt { reason } => anyhow::bail!("the call halted: {}", reason),
+        };
+        let reads = call
+            .result
+            .logs
+            .storage_logs
+            .iter()
+            .filter(|log| log.log.kind == StorageLogKind::Read)
+            .map(|log| log.log.key)
+            .collect();
+        Ok((output, reads))
+    }
+
     pub fn set_logging_enabled(&self, enable: bool) -> Result<()> {
         let Some(observability) = &self.observability else {
             anyhow::bail!("Node's logging is not set up");
@@ -436,6 +1021,68 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         Ok(self.pool.drop_transaction(hash).map(|tx| tx.hash()))
     }
 
+    /// Removes the latest blocks from the chain, rewinding blocks, transactions and storage to the state as of
+    /// the new latest block. Log filters receive the removed logs flagged with `removed: true`.
+    ///
+    /// # Parameters
+    /// - `depth`: The number of blocks to remove, defaults to `1`.
+    /// - `requeue`: Whether to put the removed transactions back into the pool instead of discarding them.
+    pub fn rollback(&self, depth: Option<U64>, requeue: Option<bool>) -> Result<()> {
+        let depth = depth.map_or(1, |depth| depth.as_u64());
+        let mut time = self.time.lock();
+        let removed_txs = self.write_inner()?.rollback(&mut time, depth)?;
+        tracing::info!(
+            "👷 Rolled back {} blocks, {} transactions were {}",
+            depth,
+            removed_txs.len(),
+            if requeue.unwrap_or(false) {
+                "put back into the pool"
+            } else {
+                "discarded"
+            }
+        );
+        if requeue.unwrap_or(false) {
+            self.pool.add_txs(removed_txs);
+        }
+        Ok(())
+    }
+
+    /// Replaces the latest blocks of the chain with new ones. The removed transactions are discarded and the
+    /// alternative transactions are mined instead, followed by empty blocks until the chain reaches its
+    /// previous height again. The replacement blocks are built from the given transactions only, transactions
+    /// waiting in the pool are left for later blocks.
+    ///
+    /// # Parameters
+    /// - `depth`: The number of blocks to replace.
+    /// - `txs`: Raw signed transactions to mine in the replacement blocks.
+    pub fn reorg(&self, depth: U64, txs: Vec<Bytes>) -> Result<()> {
+        let txs = txs
+            .into_iter()
+            .map(|tx| self.decode_raw_transaction(tx))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| anyhow!("invalid replacement transaction: {}", err))?;
+        let max_transactions = self.read_inner()?.config.max_transactions;
+
+        // Keep time locked for the whole reorg so that the block sealer cannot mine in between.
+        let mut time = self.time.lock();
+        let previous_height = self.read_inner()?.current_miniblock;
+        let removed_txs = self.write_inner()?.rollback(&mut time, depth.as_u64())?;
+        tracing::info!(
+            "👷 Rolled back {} blocks, {} transactions were discarded",
+            depth,
+            removed_txs.len()
+        );
+        self.apply_txs_with_time(&mut time, txs, max_transactions)
+            .context("failed mining replacement transactions")?;
+        while self.read_inner()?.current_miniblock < previous_height {
+            let system_contracts =
+                self.base_system_contracts(TxExecutionMode::VerifyExecute, false)?;
+            self.seal_block(&mut time, vec![], system_contracts)?;
+        }
+        tracing::info!("👷 Reorganized the last {} blocks", depth);
+        Ok(())
+    }
+
     pub fn drop_all_transactions(&self) -> Result<()> {
         self.pool.clear();
         Ok(())
@@ -455,71 +1102,371 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> InMemoryNo
         Ok(())
     }
 
-    pub fn set_rpc_url(&self, url: String) -> Result<()> {
-        let inner = self
-            .inner
-            .read()
-            .map_err(|err| anyhow!("failed acquiring lock: {:?}", err))?;
-        let mut fork_storage = inner
-            .fork_storage
-            .inner
-            .write()
-            .map_err(|err| anyhow!("failed acquiring lock: {:?}", err))?;
-        if let Some(fork) = &mut fork_storage.fork {
-            let old_url = fork.fork_source.get_fork_url().map_err(|e| {
-                anyhow::anyhow!(
-                    "failed to resolve current fork's RPC URL: {}",
-                    e.to_string()
-                )
-            })?;
-            fork.set_rpc_url(url.clone());
-            tracing::info!("Updated fork rpc from \"{}\" to \"{}\"", old_url, url);
-        } else {
-            tracing::info!("Non-forking node tried to switch RPC URL to '{url}'. Call `anvil_reset` instead if you wish to switch to forking mode");
-        }
-        Ok(())
+    /// Sets the L1 gas price of the next batches.
+    pub fn set_l1_gas_price(&self, price: U64) -> Result<bool> {
+        self.set_fee_params(FeeParamsUpdate {
+            l1_gas_price: Some(price),
+            ..Default::default()
+        })
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::fork::ForkStorage;
-    use crate::namespaces::EthNamespaceT;
-    use crate::node::time::{ReadTime, TimestampManager};
-    use crate::node::{BlockSealer, ImpersonationManager, InMemoryNodeInner, Snapshot, TxPool};
-    use crate::{http_fork_source::HttpForkSource, node::InMemoryNode};
-    use std::str::FromStr;
-    use std::sync::{Arc, RwLock};
-    use zksync_multivm::interface::storage::ReadStorage;
-    use zksync_types::{api::BlockNumber, fee::Fee, l2::L2Tx, PackedEthSignature};
-    use zksync_types::{Nonce, H256};
-    use zksync_utils::h256_to_u256;
 
-    #[tokio::test]
-    async fn test_set_balance() {
-        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
-        let node = InMemoryNode::<HttpForkSource>::default();
+    /// Sets the L1 pubdata price of the next batches.
+    pub fn set_fair_pubdata_price(&self, price: U64) -> Result<bool> {
+        self.set_fee_params(FeeParamsUpdate {
+            fair_pubdata_price: Some(price),
+            ..Default::default()
+        })
+    }
 
-        let balance_before = node.get_balance(address, None).await.unwrap();
+    /// Sets the minimal L2 gas price of the next batches.
+    pub fn set_minimal_l2_gas_price(&self, price: U64) -> Result<bool> {
+        self.set_fee_params(FeeParamsUpdate {
+            minimal_l2_gas_price: Some(price),
+            ..Default::default()
+        })
+    }
 
-        let result = node.set_balance(address, U256::from(1337)).unwrap();
-        assert!(result);
+    /// Changes the fee params of the next batches, fields that are not set are kept.
+    pub fn set_fee_params(&self, update: FeeParamsUpdate) -> Result<bool> {
+        self.write_inner()?
+            .fee_input_provider
+            .update_fee_params(&update);
+        tracing::info!("💸 Updated fee params: {:?}", update);
+        Ok(true)
+    }
 
-        let balance_after = node.get_balance(address, None).await.unwrap();
-        assert_eq!(balance_after, U256::from(1337));
-        assert_ne!(balance_before, balance_after);
+    /// Schedules a change of the fee params for when `batch` is reached.
+    pub fn schedule_fee_params(&self, batch: U64, update: FeeParamsUpdate) -> Result<bool> {
+        let batch = u32::try_from(batch.as_u64())
+            .map_err(|_| anyhow!("batch number {} is out of range", batch))?;
+        let mut inner = self.write_inner()?;
+        if batch <= inner.current_batch {
+            return Err(anyhow!(
+                "batch {} has already been sealed, the current batch is {}",
+                batch,
+                inner.current_batch
+            ));
+        }
+        tracing::info!("💸 Scheduled fee params for batch {}: {:?}", batch, update);
+        let next_batch = inner.current_batch.saturating_add(1);
+        inner.fee_input_provider.schedule_fee_params(batch, update);
+        inner.fee_input_provider.apply_fee_schedule(next_batch);
+        Ok(true)
     }
 
-    #[tokio::test]
-    async fn test_set_nonce() {
-        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
-        let node = InMemoryNode::<HttpForkSource>::default();
+    /// Enables or disables the gasless mode, in which all fees are zero and unfunded accounts can send
+    /// transactions. The mode is kept when the node is reset or forked.
+    pub fn set_gasless(&self, enable: bool) -> Result<bool> {
+        let mut inner = self.write_inner()?;
+        inner.config.gasless = enable;
+        inner.fee_input_provider.set_gasless(enable);
+        tracing::info!(
+            "💸 Gasless mode {}",
+            if enable { "enabled" } else { "disabled" }
+        );
+        Ok(true)
+    }
 
-        let nonce_before = node.get_transaction_count(address, None).await.unwrap();
+    /// Sets the maximum gas the transactions of the next batches may use.
+    pub fn set_block_gas_limit(&self, gas_limit: U64) -> Result<bool> {
+        self.set_batch_limits(BatchLimitsUpdate {
+            gas: Some(gas_limit),
+            ..Default::default()
+        })
+    }
 
-        let result = node.set_nonce(address, U256::from(1337)).unwrap();
-        assert!(result);
+    /// Changes the limits at which the next batches are sealed, limits that are not set are kept.
+    pub fn set_batch_limits(&self, limits: BatchLimitsUpdate) -> Result<bool> {
+        let mut inner = self.write_inner()?;
+        if let Some(max_transactions) = limits.max_transactions {
+            inner.config.max_transactions = usize::try_from(max_transactions.as_u64())
+                .context("The maximum number of transactions is too big")?;
+        }
+        if let Some(gas) = limits.gas {
+            inner.config.batch_gas_limit = Some(gas.as_u64());
+        }
+        if let Some(pubdata) = limits.pubdata {
+            inner.config.batch_pubdata_limit = Some(pubdata.as_u64());
+        }
+        if let Some(circuits) = limits.circuits {
+            inner.config.batch_circuits_limit = Some(circuits.as_u64());
+        }
+        if let Some(encoding_size) = limits.encoding_size {
+            inner.config.batch_encoding_size_limit = Some(encoding_size.as_u64());
+        }
+        tracing::info!("👷 Batches are sealed at {:?}", inner.seal_criteria());
+        Ok(true)
+    }
+
+    /// Sets `block.coinbase` of the next blocks. The coinbase also receives the fees of the following batches, like
+    /// the operator would.
+    pub fn set_coinbase(&self, address: Address) -> Result<bool> {
+        self.write_inner()?
+            .fork_storage
+            .set_value(coinbase_key(), address_to_h256(&address));
+        tracing::info!("👷 Set coinbase to {:#x}", address);
+        Ok(true)
+    }
+
+    /// Sets `block.prevrandao` of the next blocks. `SystemContext` keeps the value until it is set again.
+    pub fn set_prev_randao(&self, prev_randao: H256) -> Result<bool> {
+        self.write_inner()?
+            .fork_storage
+            .set_value(prev_randao_key(), prev_randao);
+        Ok(true)
+    }
+
+    /// Returns the metadata of the active chain, see [HardhatMetadata].
+    pub fn metadata(&self) -> Result<HardhatMetadata> {
+        let inner = self.read_inner()?;
+        let forked_network = inner
+            .fork_storage
+            .inner
+            .read()
+            .map_err(|err| anyhow!("failed acquiring read lock on storage: {:?}", err))?
+            .fork
+            .as_ref()
+            .map(|fork| ForkedNetwork {
+                chain_id: fork.chain_id.as_u64(),
+                fork_block_number: fork.l2_miniblock,
+                fork_block_hash: fork.l2_miniblock_hash,
+            });
+        Ok(HardhatMetadata {
+            client_version: String::from("zkSync/v2.0"),
+            chain_id: inner.fork_storage.chain_id.as_u64(),
+            instance_id: inner.instance_id,
+            latest_block_number: inner.current_miniblock,
+            latest_block_hash: inner.current_miniblock_hash,
+            forked_network,
+        })
+    }
+
+    /// Upgrades the active chain in place from the next sealed batch, see
+    /// [InMemoryNodeInner::schedule_protocol_upgrade].
+    pub fn upgrade_protocol(&self, upgrade: ProtocolUpgrade) -> Result<bool> {
+        let mut writer = self.write_inner()?;
+        writer.schedule_protocol_upgrade(upgrade)?;
+
+        tracing::info!(
+            "⬆️ Scheduled protocol upgrade to version {} for the next batch",
+            writer.next_protocol_version() as u16
+        );
+        Ok(true)
+    }
+
+    /// Reloads the contracts of `--system-contracts-dir` into the active chain, see
+    /// [InMemoryNodeInner::load_system_contracts_dir].
+    pub fn reload_system_contracts(&self) -> Result<bool> {
+        let mut writer = self.write_inner()?;
+        let dir = writer
+            .load_system_contracts_dir()?
+            .context("no --system-contracts-dir is configured")?;
+
+        tracing::info!("🔄 Reloaded system contracts from {}", dir);
+        Ok(true)
+    }
+
+    /// Exports the state of the active chain as a genesis file, see [InMemoryNodeInner::export_genesis].
+    pub fn export_genesis(&self) -> Result<Genesis> {
+        self.read_inner()?.export_genesis()
+    }
+
+    /// Re-executes an upstream transaction on top of the current local state, without committing it.
+    ///
+    /// The transaction is fetched from the fork and runs with the impersonating bootloader, so its signature is
+    /// not checked. Its nonce is replaced by the initiator's current local nonce.
+    pub fn replay_transaction(&self, hash: H256) -> Result<DebugCall> {
+        let inner = self.read_inner()?;
+        let mut l2_tx = {
+            let fork_storage = inner
+                .fork_storage
+                .inner
+                .read()
+                .map_err(|err| anyhow!("failed acquiring lock: {:?}", err))?;
+            let fork = fork_storage
+                .fork
+                .as_ref()
+                .context("replaying upstream transactions requires a forked node")?;
+            let tx = fork
+                .fork_source
+                .get_transaction_by_hash(hash)
+                .map_err(|err| anyhow!("failed fetching transaction {:#x}: {:?}", hash, err))?
+                .with_context(|| format!("transaction {:#x} was not found upstream", hash))?;
+            let block_number = tx
+                .block_number
+                .with_context(|| format!("transaction {:#x} is not mined upstream", hash))?;
+            let raw_tx = fork
+                .fork_source
+                .get_raw_block_transactions(L2BlockNumber(block_number.as_u32()))
+                .map_err(|err| {
+                    anyhow!(
+                        "failed fetching transactions of block {}: {:?}",
+                        block_number,
+                        err
+                    )
+                })?
+                .into_iter()
+                .find(|tx| tx.hash() == hash)
+                .with_context(|| {
+                    format!(
+                        "transaction {:#x} was not found in block {}",
+                        hash, block_number
+                    )
+                })?;
+            L2Tx::try_from(raw_tx)
+                .map_err(|_| anyhow!("transaction {:#x} is not an L2 transaction", hash))?
+        };
+
+        let nonce_key = get_nonce_key(&l2_tx.initiator_account());
+        let full_nonce = inner
+            .fork_storage
+            .read_value_internal(&nonce_key)
+            .map_err(|err| anyhow!("failed reading nonce: {:?}", err))?;
+        let (tx_nonce, _) = decompose_full_nonce(h256_to_u256(full_nonce));
+        l2_tx.common_data.nonce = Nonce(tx_nonce.as_u32());
+
+        let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+        let (mut batch_env, _) = inner.create_l1_batch_env(&self.time, storage.clone());
+        // The upstream fee may be lower than the local base fee.
+        let max_fee_per_gas: u64 =
+            l2_tx
+                .common_data
+                .fee
+                .max_fee_per_gas
+                .try_into()
+                .map_err(|_| {
+                    anyhow!(
+                        "max fee per gas {} of transaction {:#x} does not fit into u64",
+                        l2_tx.common_data.fee.max_fee_per_gas,
+                        hash
+                    )
+                })?;
+        batch_env.enforced_base_fee = Some(max_fee_per_gas);
+        let system_contracts = inner
+            .system_contracts
+            .contracts(TxExecutionMode::VerifyExecute, true)
+            .clone();
+        let system_env = inner.create_system_env(system_contracts, TxExecutionMode::VerifyExecute);
+        drop(inner);
+
+        let mut vm: Vm<_, HistoryDisabled> = Vm::new(batch_env, system_env, storage);
+        vm.push_transaction(l2_tx.clone().into());
+
+        let call_tracer_result = Arc::new(OnceCell::default());
+        let tracers = vec![
+            CallErrorTracer::new().into_tracer_pointer(),
+            CallTracer::new(call_tracer_result.clone()).into_tracer_pointer(),
+        ];
+        let tx_result = vm.inspect(&mut tracers.into(), InspectExecutionMode::OneTx);
+        let call_traces = Arc::try_unwrap(call_tracer_result)
+            .unwrap()
+            .take()
+            .unwrap_or_default();
+
+        create_debug_output(&l2_tx, &tx_result, call_traces)
+            .map_err(|err| anyhow!("transaction {:#x} halted: {:?}", hash, err))
+    }
+
+    pub fn set_rpc_url(&self, url: String) -> Result<()> {
+        let inner = self
+            .inner
+            .read()
+            .map_err(|err| anyhow!("failed acquiring lock: {:?}", err))?;
+        let mut fork_storage = inner
+            .fork_storage
+            .inner
+            .write()
+            .map_err(|err| anyhow!("failed acquiring lock: {:?}", err))?;
+        if let Some(fork) = &mut fork_storage.fork {
+            let old_url = fork.fork_source.get_fork_url().map_err(|e| {
+                anyhow::anyhow!(
+                    "failed to resolve current fork's RPC URL: {}",
+                    e.to_string()
+                )
+            })?;
+            fork.set_rpc_url(url.clone());
+            tracing::info!("Updated fork rpc from \"{}\" to \"{}\"", old_url, url);
+        } else {
+            tracing::info!("Non-forking node tried to switch RPC URL to '{url}'. Call `anvil_reset` instead if you wish to switch to forking mode");
+        }
+        Ok(())
+    }
+}
+
+fn fork_info<S>(name: &str, active: bool, inner: &InMemoryNodeInner<S>) -> ForkInfo {
+    let (url, fork_block_number) = inner
+        .fork_storage
+        .inner
+        .read()
+        .ok()
+        .and_then(|storage| {
+            storage.fork.as_ref().map(|fork| {
+                (
+                    fork.fork_source.get_fork_url().ok(),
+                    Some(U64::from(fork.l2_miniblock)),
+                )
+            })
+        })
+        .unwrap_or_default();
+    ForkInfo {
+        name: name.to_string(),
+        active,
+        url,
+        fork_block_number,
+        block_number: U64::from(inner.current_miniblock),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::cache::CacheConfig;
+    use crate::config::constants::{
+        DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR, DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+        DEFAULT_FAIR_PUBDATA_PRICE, DEFAULT_L2_GAS_PRICE, TEST_NODE_NETWORK_ID,
+    };
+    use crate::fork::ForkStorage;
+    use crate::namespaces::{
+        DecodedStorageChange, EthNamespaceT, StorageChange, SystemContractUpgrade,
+    };
+    use crate::node::time::{ReadTime, TimestampManager};
+    use crate::node::{
+        BlockSealer, ImpersonationManager, InMemoryNodeInner, Snapshot, TxPool, DEFAULT_FORK_NAME,
+    };
+    use crate::testing;
+    use crate::{http_fork_source::HttpForkSource, node::InMemoryNode};
+    use std::str::FromStr;
+    use std::sync::{Arc, RwLock};
+    use zksync_multivm::interface::storage::ReadStorage;
+    use zksync_types::{api::BlockNumber, fee::Fee, get_code_key, l2::L2Tx, PackedEthSignature};
+    use zksync_types::{K256PrivateKey, L2ChainId, Nonce, ProtocolVersionId, H256};
+    use zksync_utils::bytecode::hash_bytecode;
+    use zksync_utils::h256_to_u256;
+
+    #[tokio::test]
+    async fn test_set_balance() {
+        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        let balance_before = node.get_balance(address, None).await.unwrap();
+
+        let result = node.set_balance(address, U256::from(1337)).unwrap();
+        assert!(result);
+
+        let balance_after = node.get_balance(address, None).await.unwrap();
+        assert_eq!(balance_after, U256::from(1337));
+        assert_ne!(balance_before, balance_after);
+    }
+
+    #[tokio::test]
+    async fn test_set_nonce() {
+        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        let nonce_before = node.get_transaction_count(address, None).await.unwrap();
+
+        let result = node.set_nonce(address, U256::from(1337)).unwrap();
+        assert!(result);
 
         let nonce_after = node.get_transaction_count(address, None).await.unwrap();
         assert_eq!(nonce_after, U256::from(1337));
@@ -615,12 +1562,16 @@ mod tests {
             impersonation: impersonation.clone(),
             rich_accounts: Default::default(),
             previous_states: Default::default(),
+            instance_id: H256::random(),
+            pending_protocol_upgrades: Vec::new(),
         };
         let pool = TxPool::new(impersonation.clone());
 
         let node = InMemoryNode::<HttpForkSource> {
             inner: Arc::new(RwLock::new(old_inner)),
             snapshots: old_snapshots,
+            named_snapshots: Default::default(),
+            forks: Default::default(),
             system_contracts_options: old_system_contracts_options,
             time,
             impersonation,
@@ -628,6 +1579,9 @@ mod tests {
             pool,
             sealer: BlockSealer::default(),
             system_contracts: Default::default(),
+            json_log: None,
+            committed: Default::default(),
+            seal_lock: Default::default(),
         };
 
         let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
@@ -744,6 +1698,72 @@ mod tests {
         assert_eq!(new_code, code_after);
     }
 
+    #[tokio::test]
+    async fn test_set_evm_code() {
+        let address = Address::repeat_byte(0x1);
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let evm_code = vec![0x60u8, 0x80, 0x60, 0x40, 0x52];
+        let evm_code_hex = format!("0x{}", hex::encode(&evm_code));
+
+        assert!(node.set_code(address, evm_code_hex.clone()).is_err());
+
+        node.write_inner().unwrap().config.use_evm_emulator = true;
+        node.set_code(address, evm_code_hex)
+            .expect("failed setting code");
+
+        let code_hash = node
+            .write_inner()
+            .unwrap()
+            .fork_storage
+            .read_value(&get_code_key(&address));
+        assert!(crate::utils::is_evm_bytecode_hash(code_hash));
+        let code = node
+            .get_code(address, None)
+            .await
+            .expect("failed getting code")
+            .0;
+        assert_eq!(evm_code, code);
+
+        // Word-aligned EVM bytecode is also valid EraVM bytecode, but is still deployed to the emulator
+        for words in [1, 3] {
+            let evm_code = vec![0x5bu8; 32 * words];
+            node.set_code(address, format!("0x{}", hex::encode(&evm_code)))
+                .expect("failed setting code");
+            let code_hash = node
+                .write_inner()
+                .unwrap()
+                .fork_storage
+                .read_value(&get_code_key(&address));
+            assert!(crate::utils::is_evm_bytecode_hash(code_hash));
+            let code = node
+                .get_code(address, None)
+                .await
+                .expect("failed getting code")
+                .0;
+            assert_eq!(evm_code, code);
+        }
+
+        // EraVM bytecode can still be deployed explicitly
+        let era_code = vec![0x1u8; 32];
+        node.write_inner()
+            .unwrap()
+            .set_code(address, era_code.clone(), CodeKind::EraVm)
+            .expect("failed setting code");
+        let code_hash = node
+            .write_inner()
+            .unwrap()
+            .fork_storage
+            .read_value(&get_code_key(&address));
+        assert!(!crate::utils::is_evm_bytecode_hash(code_hash));
+        assert_eq!(
+            era_code,
+            node.get_code(address, None)
+                .await
+                .expect("failed getting code")
+                .0
+        );
+    }
+
     #[tokio::test]
     async fn test_set_storage_at() {
         let node = InMemoryNode::<HttpForkSource>::default();
@@ -764,6 +1784,105 @@ mod tests {
         assert_eq!(value, h256_to_u256(value_after));
     }
 
+    #[test]
+    fn test_erc20_calldata() {
+        let owner = Address::repeat_byte(0x1);
+        let spender = Address::repeat_byte(0x2);
+
+        let calldata = erc20_calldata(ERC20_ALLOWANCE_SELECTOR, &[owner, spender]);
+
+        assert_eq!(calldata.len(), 4 + 2 * 32);
+        assert_eq!(calldata[..4], ERC20_ALLOWANCE_SELECTOR);
+        assert_eq!(calldata[4..36], *address_to_h256(&owner).as_bytes());
+        assert_eq!(calldata[36..], *address_to_h256(&spender).as_bytes());
+    }
+
+    /// Deploys the ERC20 token of the `example_override` directory at `address`.
+    fn deploy_erc20(node: &InMemoryNode<HttpForkSource>, address: Address) {
+        let contract: serde_json::Value = serde_json::from_str(include_str!(
+            "../../example_override/0x8340BF5CE80Ba4ED8C3A18E03567C40d04B05358.json"
+        ))
+        .unwrap();
+        let bytecode = hex::decode(contract["bytecode"]["object"].as_str().unwrap()).unwrap();
+        node.override_bytecode(&address, &bytecode)
+            .expect("failed deploying token");
+    }
+
+    async fn call_erc20_view(
+        node: &InMemoryNode<HttpForkSource>,
+        token: Address,
+        calldata: Vec<u8>,
+    ) -> U256 {
+        let request = CallRequestBuilder::default()
+            .to(Some(token))
+            .data(calldata.into())
+            .build();
+        let output = node
+            .call(request, None)
+            .await
+            .expect("failed calling token");
+        U256::from_big_endian(&output.0)
+    }
+
+    #[tokio::test]
+    async fn test_deal_erc20_and_set_allowance() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let token = Address::repeat_byte(0x2);
+        let owner = Address::repeat_byte(0x3);
+        let spender = Address::repeat_byte(0x4);
+        deploy_erc20(&node, token);
+        let local_keys = |node: &InMemoryNode<HttpForkSource>| {
+            node.read_inner()
+                .unwrap()
+                .fork_storage
+                .inner
+                .read()
+                .unwrap()
+                .raw_storage
+                .state
+                .keys()
+                .cloned()
+                .collect::<std::collections::HashSet<_>>()
+        };
+        let keys_before = local_keys(&node);
+
+        node.deal_erc20(token, owner, U256::from(100))
+            .expect("failed dealing tokens");
+        node.set_erc20_allowance(token, owner, spender, U256::from(50))
+            .expect("failed setting allowance");
+
+        let balance_of = |account| erc20_calldata(ERC20_BALANCE_OF_SELECTOR, &[account]);
+        assert_eq!(
+            U256::from(100),
+            call_erc20_view(&node, token, balance_of(owner)).await
+        );
+        assert_eq!(
+            U256::zero(),
+            call_erc20_view(&node, token, balance_of(spender)).await
+        );
+        assert_eq!(
+            U256::from(50),
+            call_erc20_view(
+                &node,
+                token,
+                erc20_calldata(ERC20_ALLOWANCE_SELECTOR, &[owner, spender])
+            )
+            .await
+        );
+        // Probing does not leave any written slots behind, only the balance and the allowance are set
+        assert_eq!(2, local_keys(&node).difference(&keys_before).count());
+    }
+
+    #[tokio::test]
+    async fn test_deal_erc20_fails_without_a_token() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let account = Address::repeat_byte(0x1);
+
+        let result = node.deal_erc20(Address::repeat_byte(0x2), account, U256::from(100));
+
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_increase_time_zero_value() {
         let node = InMemoryNode::<HttpForkSource>::default();
@@ -1067,4 +2186,923 @@ mod tests {
         let result = node.revert_snapshot(U64::from(100));
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_revert_named_snapshot_keeps_the_snapshot() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        let initial_block = node
+            .get_block_number()
+            .await
+            .expect("failed fetching block number");
+        node.create_named_snapshot("fixture".to_string())
+            .expect("failed creating snapshot");
+
+        for _ in 0..2 {
+            node.mine_block().expect("mine_block");
+            node.mine_block().expect("mine_block");
+            let reverted = node
+                .revert_to_named_snapshot("fixture".to_string())
+                .expect("failed reverting snapshot");
+            assert!(reverted);
+
+            let restored_block = node
+                .get_block_number()
+                .await
+                .expect("failed fetching block number");
+            assert_eq!(restored_block, initial_block);
+        }
+
+        let snapshots = node
+            .list_named_snapshots()
+            .expect("failed listing snapshots");
+        assert_eq!(1, snapshots.len());
+        assert_eq!("fixture", snapshots[0].name);
+        assert_eq!(initial_block, snapshots[0].block_number);
+    }
+
+    #[tokio::test]
+    async fn test_revert_named_snapshot_removes_snapshots_taken_after_it() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        node.create_named_snapshot("first".to_string())
+            .expect("failed creating snapshot");
+        node.mine_block().expect("mine_block");
+        node.snapshot().expect("failed creating snapshot");
+        node.create_named_snapshot("second".to_string())
+            .expect("failed creating snapshot");
+
+        node.revert_to_named_snapshot("first".to_string())
+            .expect("failed reverting snapshot");
+
+        let names = node
+            .list_named_snapshots()
+            .expect("failed listing snapshots")
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["first".to_string()], names);
+        assert!(node.snapshots.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_named_snapshot_errors() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        node.create_named_snapshot("fixture".to_string())
+            .expect("failed creating snapshot");
+        assert!(node.create_named_snapshot("fixture".to_string()).is_err());
+        assert!(node
+            .revert_to_named_snapshot("unknown".to_string())
+            .is_err());
+
+        assert!(node.delete_named_snapshot("fixture".to_string()).unwrap());
+        assert!(!node.delete_named_snapshot("fixture".to_string()).unwrap());
+        assert!(node
+            .revert_to_named_snapshot("fixture".to_string())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_named_snapshot() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
+        node.set_balance(address, U256::from(1337)).unwrap();
+        node.mine_block().expect("mine_block");
+        let snapshot_block = node
+            .get_block_number()
+            .await
+            .expect("failed fetching block number");
+        node.create_named_snapshot("fixture".to_string())
+            .expect("failed creating snapshot");
+
+        // changes made after the snapshot must not end up in the saved state
+        node.set_balance(address, U256::from(42)).unwrap();
+        node.mine_block().expect("mine_block");
+
+        let bytes = node
+            .save_named_snapshot("fixture".to_string())
+            .expect("failed saving snapshot");
+
+        let new_node = InMemoryNode::<HttpForkSource>::default();
+        new_node
+            .load_named_snapshot("fixture".to_string(), bytes)
+            .expect("failed loading snapshot");
+
+        assert_eq!(
+            snapshot_block,
+            new_node
+                .get_block_number()
+                .await
+                .expect("failed fetching block number")
+        );
+        assert_eq!(
+            U256::from(1337),
+            new_node.get_balance(address, None).await.unwrap()
+        );
+
+        new_node.set_balance(address, U256::from(42)).unwrap();
+        new_node
+            .revert_to_named_snapshot("fixture".to_string())
+            .expect("failed reverting snapshot");
+        assert_eq!(
+            U256::from(1337),
+            new_node.get_balance(address, None).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_state_diff_between_blocks_and_snapshots() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        node.create_named_snapshot("before".to_string())
+            .expect("failed creating snapshot");
+
+        let (_, block_number, tx) = testing::apply_tx(&node, H256::repeat_byte(0x1));
+        let initiator = tx.common_data.initiator_address;
+        let is_initiator_nonce_change = |change: &StorageChange| {
+            matches!(
+                change.decoded,
+                Some(DecodedStorageChange::Nonce {
+                    account,
+                    tx_nonce_before,
+                    tx_nonce_after,
+                    ..
+                }) if account == initiator
+                    && tx_nonce_before == U256::zero()
+                    && tx_nonce_after == U256::one()
+            )
+        };
+
+        let diff = node
+            .state_diff(
+                StateDiffPoint::Block(BlockNumber::Number(block_number - 1)),
+                StateDiffPoint::Block(BlockNumber::Latest),
+            )
+            .expect("failed computing state diff");
+        assert_eq!(block_number - 1, diff.from_block);
+        assert!(diff.to_block >= block_number);
+        assert!(diff.changes.iter().any(is_initiator_nonce_change));
+        assert!(diff.changes.iter().any(|change| matches!(
+            change.decoded,
+            Some(DecodedStorageChange::BaseTokenBalance {
+                account,
+                before,
+                after,
+            }) if account == initiator && after < before
+        )));
+
+        let snapshot_diff = node
+            .state_diff(
+                StateDiffPoint::Snapshot {
+                    snapshot: "before".to_string(),
+                },
+                StateDiffPoint::Block(BlockNumber::Latest),
+            )
+            .expect("failed computing state diff");
+        assert_eq!(U64::from(0), snapshot_diff.from_block);
+        assert!(snapshot_diff.changes.iter().any(is_initiator_nonce_change));
+
+        let empty_diff = node
+            .state_diff(
+                StateDiffPoint::Block(BlockNumber::Latest),
+                StateDiffPoint::Block(BlockNumber::Latest),
+            )
+            .expect("failed computing state diff");
+        assert!(empty_diff.changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_rewinds_blocks_transactions_and_storage() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let (_, block_number, tx) = testing::apply_tx(&node, H256::repeat_byte(0x1));
+        let initiator = tx.common_data.initiator_address;
+        assert_eq!(
+            U256::one(),
+            node.get_transaction_count(initiator, None).await.unwrap()
+        );
+
+        let latest_block = node.get_block_number().await.unwrap();
+        node.rollback(Some(latest_block - block_number + 1), None)
+            .expect("failed rolling back");
+
+        assert_eq!(block_number - 1, node.get_block_number().await.unwrap());
+        assert!(node
+            .get_transaction_receipt(tx.hash())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            U256::zero(),
+            node.get_transaction_count(initiator, None).await.unwrap()
+        );
+        assert!(node.pool.take_uniform(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_requeues_transactions() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let (_, block_number, tx) = testing::apply_tx(&node, H256::repeat_byte(0x1));
+
+        let latest_block = node.get_block_number().await.unwrap();
+        node.rollback(Some(latest_block - block_number + 1), Some(true))
+            .expect("failed rolling back");
+
+        let batch = node.pool.take_uniform(1).expect("pool must not be empty");
+        assert_eq!(
+            vec![tx.hash()],
+            batch.txs.iter().map(|tx| tx.hash()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rollback_fails_past_genesis() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        node.mine_block().expect("mine_block");
+
+        assert!(node.rollback(Some(U64::from(2)), None).is_err());
+        assert_eq!(U64::from(1), node.get_block_number().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reorg_replaces_blocks() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let (_, block_number, tx) = testing::apply_tx(&node, H256::repeat_byte(0x1));
+        let latest_block = node.get_block_number().await.unwrap();
+
+        node.reorg(latest_block - block_number + 1, vec![])
+            .expect("failed reorganizing");
+
+        assert!(node.get_block_number().await.unwrap() >= latest_block);
+        assert!(node
+            .get_transaction_receipt(tx.hash())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reorg_mines_only_replacement_txs() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let private_key = K256PrivateKey::from_bytes(H256::repeat_byte(0xef)).unwrap();
+        node.set_rich_account(private_key.address(), U256::from(10u128.pow(20)));
+        let (_, block_number, _) = testing::apply_tx(&node, H256::repeat_byte(0x1));
+        let latest_block = node.get_block_number().await.unwrap();
+
+        let pending_tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x2))
+            .build();
+        node.pool.add_tx(pending_tx.clone());
+
+        let replacement_tx = L2Tx::new_signed(
+            Some(Address::random()),
+            vec![],
+            Nonce(0),
+            Fee {
+                gas_limit: U256::from(4_000_000),
+                max_fee_per_gas: U256::from(50_000_000),
+                max_priority_fee_per_gas: U256::from(50_000_000),
+                gas_per_pubdata_limit: U256::from(50000),
+            },
+            U256::from(1),
+            L2ChainId::from(260),
+            &private_key,
+            vec![],
+            Default::default(),
+        )
+        .unwrap();
+        let raw_tx = replacement_tx.common_data.input_data().unwrap().to_vec();
+
+        node.reorg(latest_block - block_number + 1, vec![Bytes(raw_tx)])
+            .expect("failed reorganizing");
+
+        assert_eq!(latest_block, node.get_block_number().await.unwrap());
+        assert!(node
+            .get_transaction_receipt(replacement_tx.hash())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(node
+            .get_transaction_receipt(pending_tx.hash())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(node.pool.drop_transaction(pending_tx.hash()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_select_fork_keeps_state_of_other_forks() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let address = Address::from_str("0x36615Cf349d7F6344891B1e7CA7C72883F5dc049").unwrap();
+        node.set_balance(address, U256::from(1)).unwrap();
+        node.mine_block().expect("mine_block");
+        let default_timestamp = node.time.current_timestamp();
+
+        node.create_fork("other".to_string(), None, None, true)
+            .expect("failed creating fork");
+        assert_eq!(U64::from(0), node.get_block_number().await.unwrap());
+        node.set_balance(address, U256::from(2)).unwrap();
+        node.mine_block().expect("mine_block");
+        node.mine_block().expect("mine_block");
+
+        node.select_fork(DEFAULT_FORK_NAME.to_string())
+            .expect("failed selecting fork");
+        assert_eq!(U64::from(1), node.get_block_number().await.unwrap());
+        assert_eq!(
+            U256::from(1),
+            node.get_balance(address, None).await.unwrap()
+        );
+        assert_eq!(default_timestamp, node.time.current_timestamp());
+
+        node.select_fork("other".to_string())
+            .expect("failed selecting fork");
+        assert_eq!(U64::from(2), node.get_block_number().await.unwrap());
+        assert_eq!(
+            U256::from(2),
+            node.get_balance(address, None).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_fork_starts_with_own_time_pool_and_impersonation() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let address = Address::random();
+        node.impersonate_account(address).unwrap();
+        let [pending_tx] = node.pool.populate::<1>();
+        let genesis_timestamp = node.time.current_timestamp();
+        node.time
+            .set_current_timestamp_unchecked(genesis_timestamp + 1000);
+
+        node.create_fork("other".to_string(), None, None, true)
+            .expect("failed creating fork");
+        assert_eq!(genesis_timestamp, node.time.current_timestamp());
+        assert!(!node.impersonation.is_impersonating(&address));
+        assert!(node.pool.drop_transaction(pending_tx.hash()).is_none());
+        let [other_pending_tx] = node.pool.populate::<1>();
+
+        node.select_fork(DEFAULT_FORK_NAME.to_string())
+            .expect("failed selecting fork");
+        assert_eq!(genesis_timestamp + 1000, node.time.current_timestamp());
+        assert!(node.impersonation.is_impersonating(&address));
+        assert!(node.pool.drop_transaction(pending_tx.hash()).is_some());
+        assert!(node
+            .pool
+            .drop_transaction(other_pending_tx.hash())
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_remove_forks() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        node.create_fork("other".to_string(), None, None, false)
+            .expect("failed creating fork");
+        assert!(node
+            .create_fork("other".to_string(), None, None, false)
+            .is_err());
+
+        let forks = node.list_forks().expect("failed listing forks");
+        assert_eq!(
+            vec![
+                (DEFAULT_FORK_NAME.to_string(), true),
+                ("other".to_string(), false)
+            ],
+            forks
+                .into_iter()
+                .map(|fork| (fork.name, fork.active))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(node.remove_fork(DEFAULT_FORK_NAME.to_string()).is_err());
+        assert!(node.remove_fork("other".to_string()).unwrap());
+        assert!(!node.remove_fork("other".to_string()).unwrap());
+        assert!(node.select_fork("other".to_string()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_transaction_requires_fork() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        let err = node
+            .replay_transaction(H256::repeat_byte(0x1))
+            .expect_err("replay without fork should fail");
+
+        assert!(err.to_string().contains("requires a forked node"));
+    }
+
+    /// Creates a node forked at block #1 of an upstream chain containing the given transactions, where every
+    /// upstream storage read takes `read_delay`.
+    fn external_fork_node(
+        transactions: Vec<zksync_types::Transaction>,
+        read_delay: Duration,
+    ) -> InMemoryNode<testing::ExternalStorage> {
+        let raw_storage = InMemoryNode::<HttpForkSource>::default()
+            .read_inner()
+            .unwrap()
+            .fork_storage
+            .inner
+            .read()
+            .unwrap()
+            .raw_storage
+            .clone();
+        InMemoryNode::<testing::ExternalStorage>::default_fork(Some(ForkDetails {
+            fork_source: Arc::new(testing::ExternalStorage {
+                raw_storage,
+                read_delay,
+                transactions,
+            }),
+            chain_id: TEST_NODE_NETWORK_ID.into(),
+            l1_block: zksync_types::L1BatchNumber(1),
+            l2_block: Default::default(),
+            l2_miniblock: 1,
+            l2_miniblock_hash: Default::default(),
+            block_timestamp: 1000,
+            overwrite_chain_id: None,
+            l1_gas_price: 1000,
+            l2_fair_gas_price: DEFAULT_L2_GAS_PRICE,
+            fair_pubdata_price: DEFAULT_FAIR_PUBDATA_PRICE,
+            fee_params: None,
+            estimate_gas_price_scale_factor: DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR,
+            estimate_gas_scale_factor: DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+            cache_config: CacheConfig::None,
+        }))
+    }
+
+    /// Starts mining a block on another thread and waits until the node is sealing it.
+    fn start_sealing(
+        node: &InMemoryNode<testing::ExternalStorage>,
+    ) -> std::thread::JoinHandle<Result<L2BlockNumber>> {
+        let sealing = {
+            let node = node.clone();
+            std::thread::spawn(move || node.mine_block())
+        };
+        while node.committed_state().is_none() {
+            assert!(!sealing.is_finished(), "block was sealed too fast");
+            std::thread::yield_now();
+        }
+        sealing
+    }
+
+    #[test]
+    fn test_state_writes_wait_for_block_sealing() {
+        let node = external_fork_node(vec![], Duration::from_millis(5));
+        let address = Address::repeat_byte(0x1);
+
+        let sealing = start_sealing(&node);
+        node.set_balance(address, U256::from(1))
+            .expect("failed setting balance");
+        // The balance is only set once the block has been applied
+        assert!(node.committed_state().is_none());
+        assert_eq!(2, node.read_inner().unwrap().current_miniblock);
+        sealing.join().unwrap().expect("failed sealing block");
+    }
+
+    #[test]
+    fn test_revert_snapshot_waits_for_block_sealing() {
+        let node = external_fork_node(vec![], Duration::from_millis(5));
+        let snapshot = node.snapshot().expect("failed creating snapshot");
+
+        let sealing = start_sealing(&node);
+        assert!(node
+            .revert_snapshot(snapshot)
+            .expect("failed reverting snapshot"));
+        sealing.join().unwrap().expect("failed sealing block");
+
+        // The block sealed meanwhile was reverted as a whole and the chain continues from the snapshot
+        assert_eq!(1, node.read_inner().unwrap().current_miniblock);
+        assert_eq!(
+            L2BlockNumber(2),
+            node.mine_block().expect("failed mining block")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_transaction_traces_upstream_transaction() {
+        let tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x2))
+            .build();
+        let node = external_fork_node(vec![tx.clone().into()], Duration::ZERO);
+        node.set_rich_account(tx.initiator_account(), U256::from(10u128.pow(20)));
+
+        let trace = node
+            .replay_transaction(tx.hash())
+            .expect("failed replaying transaction");
+
+        assert_eq!(tx.initiator_account(), trace.from);
+        assert_eq!(tx.recipient_account().unwrap(), trace.to);
+        assert_eq!(U256::from(1), trace.value);
+        assert!(trace.error.is_none());
+        assert!(trace.revert_reason.is_none());
+        assert!(trace.gas_used > U256::zero());
+        assert!(!trace.calls.is_empty());
+        // The replayed transaction is not committed
+        assert_eq!(U64::from(1), node.get_block_number().await.unwrap());
+        assert!(node
+            .get_transaction_receipt(tx.hash())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_transaction_rejects_max_fee_above_u64() {
+        let tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x2))
+            .set_max_fee_per_gas(U256::from(u64::MAX) + 1)
+            .build();
+        let node = external_fork_node(vec![tx.clone().into()], Duration::ZERO);
+
+        let err = node
+            .replay_transaction(tx.hash())
+            .expect_err("replay with an oversized fee should fail");
+
+        assert!(err.to_string().contains("does not fit into u64"));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_protocol() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let address = Address::from_low_u64_be(0x8099);
+        let contract_code = vec![0x1u8; 32];
+        let default_account = vec![0x2u8; 32];
+        let initial_version = node.read_inner().unwrap().protocol_version();
+        let initial_hashes = node
+            .read_inner()
+            .unwrap()
+            .system_contracts
+            .base_system_contracts_hashes();
+        let upgrade = ProtocolUpgrade {
+            protocol_version: Some(ProtocolVersionId::Version26 as u16),
+            default_account: Some(Bytes(default_account.clone())),
+            system_contracts: vec![SystemContractUpgrade {
+                address,
+                bytecode: Bytes(contract_code.clone()),
+            }],
+            ..Default::default()
+        };
+
+        // a reverted snapshot drops the scheduled upgrade
+        let snapshot_id = node.snapshot().expect("failed taking snapshot");
+        node.upgrade_protocol(upgrade.clone())
+            .expect("failed upgrading protocol");
+        node.mine_block().expect("mine_block");
+        assert!(node
+            .revert_snapshot(snapshot_id)
+            .expect("failed reverting snapshot"));
+        {
+            let inner = node.read_inner().unwrap();
+            assert_eq!(initial_version, inner.protocol_version());
+            assert_eq!(
+                initial_hashes,
+                inner.system_contracts.base_system_contracts_hashes()
+            );
+        }
+
+        node.upgrade_protocol(upgrade)
+            .expect("failed upgrading protocol");
+        // calls keep running with the contracts of the last sealed batch until the next one is sealed
+        assert_ne!(
+            contract_code,
+            node.get_code(address, None)
+                .await
+                .expect("failed getting code")
+                .0
+        );
+        assert_eq!(
+            initial_version,
+            node.read_inner().unwrap().protocol_version()
+        );
+
+        node.mine_block().expect("mine_block");
+        let code = node
+            .get_code(address, None)
+            .await
+            .expect("failed getting code")
+            .0;
+        assert_eq!(contract_code, code);
+        {
+            let inner = node.read_inner().unwrap();
+            assert_eq!(ProtocolVersionId::Version26, inner.protocol_version());
+            assert_eq!(
+                hash_bytecode(&default_account),
+                inner
+                    .system_contracts
+                    .base_system_contracts_hashes()
+                    .default_aa
+            );
+        }
+
+        assert!(node
+            .upgrade_protocol(ProtocolUpgrade {
+                protocol_version: Some(ProtocolVersionId::Version25 as u16),
+                ..Default::default()
+            })
+            .is_err());
+        assert!(node
+            .upgrade_protocol(ProtocolUpgrade {
+                default_account: Some(Bytes(vec![0x2u8; 31])),
+                ..Default::default()
+            })
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reload_system_contracts() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        assert!(node.reload_system_contracts().is_err());
+
+        let dir =
+            tempdir::TempDir::new("system-contracts-test").expect("failed creating temporary dir");
+        let default_account = vec![0x2u8; 32];
+        std::fs::write(
+            dir.path().join("DefaultAccount.json"),
+            format!(r#"{{"bytecode":"0x{}"}}"#, hex::encode(&default_account)),
+        )
+        .unwrap();
+        node.write_inner().unwrap().config.system_contracts_dir =
+            Some(dir.path().display().to_string());
+
+        assert!(node
+            .reload_system_contracts()
+            .expect("failed reloading system contracts"));
+        assert_eq!(
+            hash_bytecode(&default_account),
+            node.read_inner()
+                .unwrap()
+                .system_contracts
+                .base_system_contracts_hashes()
+                .default_aa
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_genesis_round_trip() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let account = Address::repeat_byte(0x1);
+        let contract = Address::repeat_byte(0x2);
+        let contract_code = vec![0x1u8; 32];
+        let slot = U256::from(7);
+        node.set_balance(account, U256::from(1337)).unwrap();
+        node.set_nonce(account, U256::from(3)).unwrap();
+        node.set_code(contract, format!("0x{}", hex::encode(&contract_code)))
+            .unwrap();
+        node.set_storage_at(contract, slot, U256::from(42)).unwrap();
+        // advances the block number, timestamp and hashes kept by the system context
+        node.mine_blocks(Some(U64::from(3)), None).unwrap();
+
+        let genesis = node.export_genesis().expect("failed exporting genesis");
+        let alloc = genesis.alloc.clone().expect("missing alloc");
+        assert_eq!(Some(U256::from(1337)), alloc[&account].balance);
+        assert_eq!(Some(U256::from(3)), alloc[&account].nonce);
+        assert_eq!(Some(Bytes(contract_code.clone())), alloc[&contract].code);
+
+        let other = InMemoryNode::<HttpForkSource>::default();
+        other.write_inner().unwrap().config.genesis = Some(genesis);
+        other
+            .write_inner()
+            .unwrap()
+            .apply_genesis_alloc()
+            .expect("failed applying genesis alloc");
+
+        assert_eq!(
+            U256::from(1337),
+            other.get_balance(account, None).await.unwrap()
+        );
+        assert_eq!(
+            U256::from(3),
+            other.get_transaction_count(account, None).await.unwrap()
+        );
+        assert_eq!(
+            contract_code,
+            other.get_code(contract, None).await.unwrap().0
+        );
+        let key = StorageKey::new(AccountTreeId::new(contract), u256_to_h256(slot));
+        assert_eq!(
+            u256_to_h256(U256::from(42)),
+            other.write_inner().unwrap().fork_storage.read_value(&key)
+        );
+
+        // the imported chain starts from its own first block
+        other
+            .mine_block()
+            .expect("failed mining block after import");
+        assert_eq!(
+            U64::from(1),
+            other
+                .get_block_number()
+                .await
+                .expect("failed fetching block number")
+        );
+        assert_eq!(
+            U256::from(1337),
+            other.get_balance(account, None).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_coinbase_and_prev_randao() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let coinbase = Address::repeat_byte(0x5);
+        let prev_randao = H256::repeat_byte(0x6);
+
+        assert!(node.set_coinbase(coinbase).unwrap());
+        assert!(node.set_prev_randao(prev_randao).unwrap());
+
+        let inner = node.read_inner().unwrap();
+        let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+        let (batch_env, _) = inner.create_l1_batch_env(&node.time, storage);
+        assert_eq!(coinbase, batch_env.fee_account);
+        assert_eq!(
+            prev_randao,
+            inner
+                .fork_storage
+                .read_value_internal(&prev_randao_key())
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schedule_fee_params() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let fair_pubdata_price = |node: &InMemoryNode<HttpForkSource>| {
+            let inner = node.read_inner().unwrap();
+            let storage = StorageView::new(inner.fork_storage.clone()).into_rc_ptr();
+            let (batch_env, _) = inner.create_l1_batch_env(&node.time, storage);
+            batch_env.fee_input.fair_pubdata_price()
+        };
+        let initial = fair_pubdata_price(&node);
+        let current_batch = node.read_inner().unwrap().current_batch;
+
+        node.schedule_fee_params(
+            U64::from(current_batch + 2),
+            FeeParamsUpdate {
+                fair_pubdata_price: Some(U64::from(1_000_000_000_000u64)),
+                ..Default::default()
+            },
+        )
+        .expect("failed scheduling fee params");
+        node.schedule_fee_params(U64::from(current_batch), FeeParamsUpdate::default())
+            .expect_err("scheduled fee params for a sealed batch");
+        assert_eq!(initial, fair_pubdata_price(&node));
+
+        node.mine_block().expect("failed mining block");
+        assert!(fair_pubdata_price(&node) > 1_000_000_000_000);
+
+        node.set_fair_pubdata_price(U64::from(DEFAULT_FAIR_PUBDATA_PRICE))
+            .expect("failed setting fair pubdata price");
+        assert_eq!(initial, fair_pubdata_price(&node));
+    }
+
+    #[tokio::test]
+    async fn test_gasless_accepts_unfunded_accounts() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x1))
+            .build();
+        let from = tx.initiator_account();
+        // Only the transferred value is funded.
+        node.set_balance(from, U256::one())
+            .expect("failed setting balance");
+        assert!(node.apply_txs(vec![tx.clone()], 1).is_err());
+
+        assert!(node
+            .set_gasless(true)
+            .expect("failed enabling gasless mode"));
+        let inner = node.read_inner().unwrap();
+        assert_eq!(
+            0,
+            inner.fee_input_provider.gas_price(inner.protocol_version())
+        );
+        drop(inner);
+        node.apply_txs(vec![tx], 1)
+            .expect("failed applying gasless transaction");
+        assert_eq!(U256::zero(), node.get_balance(from, None).await.unwrap());
+
+        node.reset_network(None).unwrap();
+        assert!(node.read_inner().unwrap().fee_input_provider.is_gasless());
+    }
+
+    #[tokio::test]
+    async fn test_gasless_credits_fees_per_transaction() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        node.set_gasless(true)
+            .expect("failed enabling gasless mode");
+        let mut builder = testing::TransactionBuilder::new();
+        let first = builder.set_hash(H256::repeat_byte(0x1)).build();
+        let second = builder
+            .set_hash(H256::repeat_byte(0x2))
+            .set_nonce(1)
+            .build();
+        let from = first.initiator_account();
+        // Only the value of one transfer is funded, the fee credited for the second transaction
+        // must not pay for the value of the first one.
+        node.set_balance(from, U256::one())
+            .expect("failed setting balance");
+
+        assert!(node
+            .apply_txs(vec![first.clone(), second.clone()], 2)
+            .is_err());
+
+        let inner = node.read_inner().unwrap();
+        assert!(inner.tx_results.contains_key(&first.hash()));
+        assert!(!inner.tx_results.contains_key(&second.hash()));
+        drop(inner);
+        assert_eq!(U256::zero(), node.get_balance(from, None).await.unwrap());
+        assert_eq!(
+            U256::one(),
+            node.get_balance(first.recipient_account().unwrap(), None)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            U256::zero(),
+            node.get_balance(second.recipient_account().unwrap(), None)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_limits_move_transactions_to_the_next_batch() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let txs = [H256::repeat_byte(0x1), H256::repeat_byte(0x2)].map(|hash| {
+            let tx = testing::TransactionBuilder::new().set_hash(hash).build();
+            node.set_rich_account(tx.initiator_account(), U256::from(10u128.pow(20)));
+            tx
+        });
+
+        assert!(node
+            .set_batch_limits(BatchLimitsUpdate {
+                max_transactions: Some(U64::from(1)),
+                ..Default::default()
+            })
+            .expect("failed setting batch limits"));
+        node.apply_txs(txs.to_vec(), 2)
+            .expect("failed applying transactions");
+
+        let inner = node.read_inner().unwrap();
+        let batches = txs
+            .iter()
+            .map(|tx| inner.tx_results.get(&tx.hash()).unwrap().info.batch_number)
+            .collect::<Vec<_>>();
+        assert_eq!(batches[0] + 1, batches[1]);
+        drop(inner);
+
+        // A transaction that exceeds the gas limit on its own is rejected
+        assert!(node
+            .set_block_gas_limit(U64::from(1))
+            .expect("failed setting block gas limit"));
+        let tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x3))
+            .build();
+        node.set_rich_account(tx.initiator_account(), U256::from(10u128.pow(20)));
+        assert!(node.apply_txs(vec![tx], 1).is_err());
+        let block = node
+            .get_block_by_number(BlockNumber::Latest, false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(U256::one(), block.gas_limit);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_respects_batch_limits() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let request = CallRequestBuilder::default()
+            .from(
+                "0xa61464658afeaf65cccaafd3a512b69a83b77618"
+                    .parse()
+                    .unwrap(),
+            )
+            .to(Some(
+                "0x36615cf349d7f6344891b1e7ca7c72883f5dc049"
+                    .parse()
+                    .unwrap(),
+            ))
+            .data(vec![0, 0].into())
+            .build();
+
+        let inner = node.read_inner().unwrap();
+        assert!(inner.estimate_gas_impl(&node.time, request.clone()).is_ok());
+        drop(inner);
+
+        assert!(node
+            .set_batch_limits(BatchLimitsUpdate {
+                encoding_size: Some(U64::from(1)),
+                ..Default::default()
+            })
+            .expect("failed setting batch limits"));
+        let inner = node.read_inner().unwrap();
+        assert!(inner.estimate_gas_impl(&node.time, request).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metadata() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+
+        let metadata = node.metadata().expect("failed getting metadata");
+        assert_eq!(0, metadata.latest_block_number);
+        assert_eq!(None, metadata.forked_network);
+
+        node.reset_network(None).unwrap();
+        assert_ne!(metadata.instance_id, node.metadata().unwrap().instance_id);
+    }
 }
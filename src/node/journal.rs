@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Deref;
+
+/// Undo log for a [HashMap], used to implement cheap snapshots.
+///
+/// Every [Journal::checkpoint] opens a new layer. While at least one layer is open, the first modification
+/// of each key within the top layer records the previous value of the key, so that reverting to a checkpoint
+/// is O(changes since the checkpoint) instead of requiring a full copy of the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal<K, V> {
+    layers: Vec<HashMap<K, Option<V>>>,
+}
+
+impl<K, V> Default for Journal<K, V> {
+    fn default() -> Self {
+        Self { layers: Vec::new() }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Journal<K, V> {
+    /// Opens a new layer and returns the depth to pass to [Journal::revert] to undo all changes made after it.
+    pub fn checkpoint(&mut self) -> usize {
+        self.layers.push(HashMap::new());
+        self.layers.len() - 1
+    }
+
+    /// Returns the number of open layers.
+    pub fn depth(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Records the value `key` had before being modified. Does nothing if no layer is open.
+    pub fn record(&mut self, key: &K, previous: Option<&V>) {
+        if let Some(layer) = self.layers.last_mut() {
+            if !layer.contains_key(key) {
+                layer.insert(key.clone(), previous.cloned());
+            }
+        }
+    }
+
+    /// Undoes all changes recorded since the checkpoint at `depth` and closes the corresponding layers.
+    pub fn revert(&mut self, depth: usize, map: &mut HashMap<K, V>) {
+        while self.layers.len() > depth {
+            let layer = self.layers.pop().expect("layer must exist");
+            for (key, previous) in layer {
+                match previous {
+                    Some(value) => map.insert(key, value),
+                    None => map.remove(&key),
+                };
+            }
+        }
+    }
+
+    /// Returns a copy of `map` as it was at the checkpoint at `depth`, leaving the journal untouched.
+    pub fn values_at(&self, depth: usize, map: &HashMap<K, V>) -> HashMap<K, V> {
+        let mut values = map.clone();
+        for layer in self.layers.iter().skip(depth).rev() {
+            for (key, previous) in layer {
+                match previous {
+                    Some(value) => values.insert(key.clone(), value.clone()),
+                    None => values.remove(key),
+                };
+            }
+        }
+        values
+    }
+}
+
+/// A [HashMap] whose modifications are recorded in a [Journal].
+///
+/// Reads go through [Deref]; modifications are only possible through [JournaledMap::insert].
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournaledMap<K, V> {
+    map: HashMap<K, V>,
+    journal: Journal<K, V>,
+}
+
+impl<K, V> Default for JournaledMap<K, V> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            journal: Journal::default(),
+        }
+    }
+}
+
+impl<K, V> From<HashMap<K, V>> for JournaledMap<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self {
+            map,
+            journal: Journal::default(),
+        }
+    }
+}
+
+impl<K, V> Deref for JournaledMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> JournaledMap<K, V> {
+    /// Inserts a key-value pair into the map, recording the previous value in the journal.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.journal.record(&key, self.map.get(&key));
+        self.map.insert(key, value)
+    }
+
+    /// Removes a key from the map, recording the previous value in the journal.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.journal.record(key, self.map.get(key));
+        self.map.remove(key)
+    }
+
+    /// See [Journal::checkpoint].
+    pub fn checkpoint(&mut self) -> usize {
+        self.journal.checkpoint()
+    }
+
+    /// See [Journal::revert].
+    pub fn revert(&mut self, depth: usize) {
+        self.journal.revert(depth, &mut self.map);
+    }
+
+    /// See [Journal::values_at].
+    pub fn values_at(&self, depth: usize) -> HashMap<K, V> {
+        self.journal.values_at(depth, &self.map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_without_checkpoint_is_not_recorded() {
+        let mut map = JournaledMap::default();
+        map.insert(1, "a");
+
+        assert_eq!(0, map.journal.depth());
+        assert_eq!(Some(&"a"), map.get(&1));
+    }
+
+    #[test]
+    fn test_revert_restores_previous_values() {
+        let mut map = JournaledMap::default();
+        map.insert(1, "a");
+
+        let depth = map.checkpoint();
+        map.insert(1, "b");
+        map.insert(1, "c");
+        map.insert(2, "d");
+        map.revert(depth);
+
+        assert_eq!(HashMap::from([(1, "a")]), *map);
+        assert_eq!(0, map.journal.depth());
+    }
+
+    #[test]
+    fn test_revert_nested_checkpoints() {
+        let mut map = JournaledMap::default();
+        let outer = map.checkpoint();
+        map.insert(1, "a");
+        let inner = map.checkpoint();
+        map.insert(1, "b");
+        map.insert(2, "c");
+
+        map.revert(inner);
+        assert_eq!(HashMap::from([(1, "a")]), *map);
+
+        map.revert(outer);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_revert_restores_removed_values() {
+        let mut map = JournaledMap::default();
+        map.insert(1, "a");
+
+        let depth = map.checkpoint();
+        assert_eq!(Some("a"), map.remove(&1));
+        assert_eq!(None, map.remove(&2));
+        map.revert(depth);
+
+        assert_eq!(HashMap::from([(1, "a")]), *map);
+    }
+
+    #[test]
+    fn test_values_at_does_not_modify_map() {
+        let mut map = JournaledMap::default();
+        map.insert(1, "a");
+        let outer = map.checkpoint();
+        map.insert(1, "b");
+        let inner = map.checkpoint();
+        map.insert(2, "c");
+
+        assert_eq!(HashMap::from([(1, "a")]), map.values_at(outer));
+        assert_eq!(HashMap::from([(1, "b")]), map.values_at(inner));
+        assert_eq!(HashMap::from([(1, "b"), (2, "c")]), *map);
+        assert_eq!(2, map.journal.depth());
+    }
+}
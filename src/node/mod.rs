@@ -3,6 +3,7 @@
 mod anvil;
 mod block_producer;
 mod call_error_tracer;
+mod committed;
 mod config_api;
 mod debug;
 mod error;
@@ -13,17 +14,26 @@ mod hardhat;
 mod impersonate;
 mod in_memory;
 mod in_memory_ext;
+mod journal;
 mod net;
 mod pool;
 mod sealer;
 mod state;
+mod state_diff;
 mod storage_logs;
 mod time;
 mod web3;
 mod zks;
 
 pub use self::{
-    block_producer::BlockProducer, impersonate::ImpersonationManager, pool::TxPool,
-    sealer::BlockSealer, sealer::BlockSealerMode, time::TimestampManager,
+    block_producer::BlockProducer,
+    committed::CommittedState,
+    impersonate::ImpersonationManager,
+    journal::{Journal, JournaledMap},
+    pool::TxPool,
+    sealer::BlockSealer,
+    sealer::BlockSealerMode,
+    sealer::SealCriteria,
+    time::TimestampManager,
 };
 pub use in_memory::*;
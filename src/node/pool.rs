@@ -1,3 +1,4 @@
+use crate::metrics::METRICS;
 use crate::node::impersonate::ImpersonationManager;
 use itertools::Itertools;
 use std::sync::{Arc, RwLock};
@@ -21,18 +22,22 @@ impl TxPool {
     pub fn add_tx(&self, tx: L2Tx) {
         let mut guard = self.inner.write().expect("TxPool lock is poisoned");
         guard.push(tx);
+        METRICS.pool_size.set(guard.len());
     }
 
     pub fn add_txs(&self, txs: impl IntoIterator<Item = L2Tx>) {
         let mut guard = self.inner.write().expect("TxPool lock is poisoned");
         guard.extend(txs);
+        METRICS.pool_size.set(guard.len());
     }
 
     /// Removes a single transaction from the pool
     pub fn drop_transaction(&self, hash: H256) -> Option<L2Tx> {
         let mut guard = self.inner.write().expect("TxPool lock is poisoned");
         let (position, _) = guard.iter_mut().find_position(|tx| tx.hash() == hash)?;
-        Some(guard.remove(position))
+        let tx = guard.remove(position);
+        METRICS.pool_size.set(guard.len());
+        Some(tx)
     }
 
     /// Remove transactions by sender
@@ -43,13 +48,32 @@ impl TxPool {
             .into_iter()
             .partition(|tx| tx.common_data.initiator_address == sender);
         *guard = other_txs;
+        METRICS.pool_size.set(guard.len());
         sender_txs
     }
 
+    /// Puts transactions back to the front of the pool, e.g. when they did not fit into a batch
+    pub fn requeue(&self, txs: Vec<L2Tx>) {
+        if txs.is_empty() {
+            return;
+        }
+        let mut guard = self.inner.write().expect("TxPool lock is poisoned");
+        guard.splice(0..0, txs);
+        METRICS.pool_size.set(guard.len());
+    }
+
+    /// Removes all transactions from the pool and returns them in their original order
+    pub fn take_all(&self) -> Vec<L2Tx> {
+        let mut guard = self.inner.write().expect("TxPool lock is poisoned");
+        METRICS.pool_size.set(0);
+        std::mem::take(&mut *guard)
+    }
+
     /// Removes all transactions from the pool
     pub fn clear(&self) {
         let mut guard = self.inner.write().expect("TxPool lock is poisoned");
         guard.clear();
+        METRICS.pool_size.set(0);
     }
 
     /// Take up to `n` continuous transactions from the pool that are all uniform in impersonation
@@ -80,6 +104,7 @@ impl TxPool {
         });
 
         let txs = guard.drain(0..tx_count).collect();
+        METRICS.pool_size.set(guard.len());
         Some(TxBatch { impersonating, txs })
     }
 }
@@ -375,4 +400,21 @@ mod tests {
         // a change in impersonation state partway through iterating the transactions.
         assert_eq!(tx_batch.txs.len(), 4096);
     }
+
+    #[test]
+    fn requeued_txs_are_taken_first() {
+        let impersonation = ImpersonationManager::default();
+        let pool = TxPool::new(impersonation);
+
+        let [tx0, tx1, tx2] = pool.populate::<3>();
+        let taken = pool.take_uniform(2).expect("failed taking transactions");
+        pool.requeue(taken.txs);
+        assert_eq!(
+            pool.take_uniform(3),
+            Some(TxBatch {
+                impersonating: false,
+                txs: vec![tx0, tx1, tx2]
+            })
+        );
+    }
 }
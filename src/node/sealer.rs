@@ -4,6 +4,7 @@ use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::time::{Interval, MissedTickBehavior};
+use zksync_multivm::interface::CircuitStatistic;
 
 #[derive(Clone, Debug)]
 pub struct BlockSealer {
@@ -134,14 +135,121 @@ impl FixedTimeBlockSealer {
     }
 }
 
+/// Limits at which a batch is sealed. A transaction that does not fit into a batch anymore is moved to the next
+/// one, while a transaction that exceeds a limit on its own can never be included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SealCriteria {
+    pub max_transactions: usize,
+    pub max_gas: u64,
+    pub max_pubdata: u64,
+    pub max_circuits: u64,
+    /// Space the transactions take in the bootloader memory, in words.
+    pub max_encoding_size: u64,
+}
+
+impl SealCriteria {
+    pub fn unlimited() -> Self {
+        Self {
+            max_transactions: usize::MAX,
+            max_gas: u64::MAX,
+            max_pubdata: u64::MAX,
+            max_circuits: u64::MAX,
+            max_encoding_size: u64::MAX,
+        }
+    }
+
+    /// Returns the name of the first limit `usage` exceeds.
+    fn exceeded_limit(&self, usage: &BatchUsage) -> Option<&'static str> {
+        if usage.transactions > self.max_transactions {
+            Some("transaction count")
+        } else if usage.gas > self.max_gas {
+            Some("gas")
+        } else if usage.pubdata > self.max_pubdata {
+            Some("pubdata")
+        } else if usage.circuits.total() as u64 > self.max_circuits {
+            Some("circuits")
+        } else if usage.encoding_size > self.max_encoding_size {
+            Some("encoding size")
+        } else {
+            None
+        }
+    }
+}
+
+/// Resources used by a single transaction or by all transactions of a batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BatchUsage {
+    pub transactions: usize,
+    pub gas: u64,
+    pub pubdata: u64,
+    /// Circuits by type, not rounded, so that they are only rounded up once for the whole batch.
+    pub circuits: CircuitStatistic,
+    pub encoding_size: u64,
+}
+
+impl BatchUsage {
+    fn saturating_add(&self, other: &Self) -> Self {
+        Self {
+            transactions: self.transactions.saturating_add(other.transactions),
+            gas: self.gas.saturating_add(other.gas),
+            pubdata: self.pubdata.saturating_add(other.pubdata),
+            circuits: self.circuits + other.circuits,
+            encoding_size: self.encoding_size.saturating_add(other.encoding_size),
+        }
+    }
+}
+
+/// Reason a transaction was not included into a batch.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SealResolution {
+    /// The batch is full, the transaction is to be included into the next one.
+    #[error("batch is full, {0} limit reached")]
+    ExcludeAndSeal(&'static str),
+    /// The transaction exceeds a limit on its own.
+    #[error("transaction exceeds the {0} limit of a batch")]
+    Unexecutable(&'static str),
+}
+
+/// Keeps track of the resources the transactions of a batch use against the seal criteria.
+#[derive(Clone, Debug)]
+pub struct BatchCapacity {
+    criteria: SealCriteria,
+    used: BatchUsage,
+}
+
+impl BatchCapacity {
+    pub fn new(criteria: SealCriteria) -> Self {
+        Self {
+            criteria,
+            used: BatchUsage::default(),
+        }
+    }
+
+    /// Adds the usage of a transaction to the batch if it still fits.
+    pub fn include(&mut self, tx_usage: &BatchUsage) -> Result<(), SealResolution> {
+        if let Some(limit) = self.criteria.exceeded_limit(tx_usage) {
+            return Err(SealResolution::Unexecutable(limit));
+        }
+        let used = self.used.saturating_add(tx_usage);
+        if let Some(limit) = self.criteria.exceeded_limit(&used) {
+            return Err(SealResolution::ExcludeAndSeal(limit));
+        }
+        self.used = used;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::node::pool::TxBatch;
-    use crate::node::sealer::BlockSealerMode;
+    use crate::node::sealer::{
+        BatchCapacity, BatchUsage, BlockSealerMode, SealCriteria, SealResolution,
+    };
     use crate::node::{BlockSealer, ImpersonationManager, TxPool};
     use std::ptr;
     use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
     use std::time::Duration;
+    use zksync_multivm::interface::CircuitStatistic;
 
     const NOOP: RawWaker = {
         const VTABLE: RawWakerVTable = RawWakerVTable::new(
@@ -360,4 +468,87 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn batch_capacity_excludes_transactions_once_full() {
+        let mut capacity = BatchCapacity::new(SealCriteria {
+            max_gas: 100,
+            ..SealCriteria::unlimited()
+        });
+        let tx_usage = BatchUsage {
+            transactions: 1,
+            gas: 60,
+            ..Default::default()
+        };
+
+        assert_eq!(capacity.include(&tx_usage), Ok(()));
+        assert_eq!(
+            capacity.include(&tx_usage),
+            Err(SealResolution::ExcludeAndSeal("gas"))
+        );
+        assert_eq!(
+            capacity.include(&BatchUsage {
+                transactions: 1,
+                gas: 40,
+                ..Default::default()
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn batch_capacity_rejects_transactions_exceeding_a_limit() {
+        let mut capacity = BatchCapacity::new(SealCriteria {
+            max_transactions: 1,
+            max_pubdata: 10,
+            ..SealCriteria::unlimited()
+        });
+
+        assert_eq!(
+            capacity.include(&BatchUsage {
+                transactions: 1,
+                pubdata: 11,
+                ..Default::default()
+            }),
+            Err(SealResolution::Unexecutable("pubdata"))
+        );
+        assert_eq!(
+            capacity.include(&BatchUsage {
+                transactions: 1,
+                ..Default::default()
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            capacity.include(&BatchUsage {
+                transactions: 1,
+                ..Default::default()
+            }),
+            Err(SealResolution::ExcludeAndSeal("transaction count"))
+        );
+    }
+
+    #[test]
+    fn batch_capacity_rounds_circuits_once_per_batch() {
+        let mut capacity = BatchCapacity::new(SealCriteria {
+            max_circuits: 1,
+            ..SealCriteria::unlimited()
+        });
+        let tx_usage = BatchUsage {
+            transactions: 1,
+            circuits: CircuitStatistic {
+                main_vm: 0.4,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Each transaction alone would need a whole circuit, together they still fit into one.
+        assert_eq!(capacity.include(&tx_usage), Ok(()));
+        assert_eq!(capacity.include(&tx_usage), Ok(()));
+        assert_eq!(
+            capacity.include(&tx_usage),
+            Err(SealResolution::ExcludeAndSeal("circuits"))
+        );
+    }
 }
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+
+use zksync_types::{
+    get_nonce_key,
+    utils::{decompose_full_nonce, storage_key_for_eth_balance},
+    Address, StorageKey, StorageValue, ACCOUNT_CODE_STORAGE_ADDRESS,
+};
+use zksync_utils::{h256_to_account_address, h256_to_u256};
+
+use crate::namespaces::{DecodedStorageChange, StorageChange};
+
+/// Slot of a well-known system contract whose key is hashed and can only be recognized for known accounts.
+#[derive(Debug, Clone, Copy)]
+enum AccountSlot {
+    BaseTokenBalance(Address),
+    Nonce(Address),
+}
+
+/// Computes all storage changes between two local states.
+///
+/// Keys missing from a state (or set to zero) are read with `read_base_value`, the same way the node falls back
+/// to the fork when reading storage. `accounts` are used to recognize balance and nonce slots, which are keyed
+/// by a hash of the account address; the owners of changed contract storage and deployed code are always included.
+pub fn diff_states<E>(
+    before: &HashMap<StorageKey, StorageValue>,
+    after: &HashMap<StorageKey, StorageValue>,
+    mut read_base_value: impl FnMut(&StorageKey) -> Result<StorageValue, E>,
+    accounts: impl IntoIterator<Item = Address>,
+) -> Result<Vec<StorageChange>, E> {
+    let mut keys = before
+        .keys()
+        .chain(after.keys())
+        .copied()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    keys.sort_by_key(|key| (*key.address(), *key.key()));
+
+    let mut accounts = accounts.into_iter().collect::<HashSet<_>>();
+    for key in &keys {
+        accounts.insert(*key.address());
+        if key.address() == &ACCOUNT_CODE_STORAGE_ADDRESS {
+            accounts.insert(h256_to_account_address(key.key()));
+        }
+    }
+    let account_slots = accounts
+        .into_iter()
+        .flat_map(|account| {
+            [
+                (
+                    storage_key_for_eth_balance(&account),
+                    AccountSlot::BaseTokenBalance(account),
+                ),
+                (get_nonce_key(&account), AccountSlot::Nonce(account)),
+            ]
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut changes = Vec::new();
+    for key in keys {
+        let mut base_value = None;
+        let mut value_in = |state: &HashMap<StorageKey, StorageValue>| -> Result<StorageValue, E> {
+            match state.get(&key) {
+                Some(value) if !value.is_zero() => Ok(*value),
+                _ => match base_value {
+                    Some(value) => Ok(value),
+                    None => {
+                        let value = read_base_value(&key)?;
+                        base_value = Some(value);
+                        Ok(value)
+                    }
+                },
+            }
+        };
+        let value_before = value_in(before)?;
+        let value_after = value_in(after)?;
+        if value_before == value_after {
+            continue;
+        }
+
+        changes.push(StorageChange {
+            address: *key.address(),
+            key: *key.key(),
+            before: value_before,
+            after: value_after,
+            decoded: decode_change(&key, value_before, value_after, &account_slots),
+        });
+    }
+    Ok(changes)
+}
+
+fn decode_change(
+    key: &StorageKey,
+    before: StorageValue,
+    after: StorageValue,
+    account_slots: &HashMap<StorageKey, AccountSlot>,
+) -> Option<DecodedStorageChange> {
+    if key.address() == &ACCOUNT_CODE_STORAGE_ADDRESS {
+        return Some(DecodedStorageChange::CodeHash {
+            account: h256_to_account_address(key.key()),
+            before,
+            after,
+        });
+    }
+
+    match account_slots.get(key)? {
+        AccountSlot::BaseTokenBalance(account) => Some(DecodedStorageChange::BaseTokenBalance {
+            account: *account,
+            before: h256_to_u256(before),
+            after: h256_to_u256(after),
+        }),
+        AccountSlot::Nonce(account) => {
+            let (tx_nonce_before, deployment_nonce_before) =
+                decompose_full_nonce(h256_to_u256(before));
+            let (tx_nonce_after, deployment_nonce_after) =
+                decompose_full_nonce(h256_to_u256(after));
+            Some(DecodedStorageChange::Nonce {
+                account: *account,
+                tx_nonce_before,
+                tx_nonce_after,
+                deployment_nonce_before,
+                deployment_nonce_after,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use zksync_types::{get_code_key, utils::nonces_to_full_nonce, AccountTreeId, H256, U256};
+    use zksync_utils::u256_to_h256;
+
+    #[test]
+    fn test_diff_states_decodes_system_slots() {
+        let account = Address::repeat_byte(0x1);
+        let contract = Address::repeat_byte(0x2);
+        let balance_key = storage_key_for_eth_balance(&account);
+        let nonce_key = get_nonce_key(&account);
+        let code_key = get_code_key(&contract);
+        let raw_key = StorageKey::new(AccountTreeId::new(contract), H256::repeat_byte(0x3));
+        let unchanged_key = StorageKey::new(AccountTreeId::new(contract), H256::repeat_byte(0x4));
+
+        let before = HashMap::from([
+            (balance_key, u256_to_h256(U256::from(100))),
+            (unchanged_key, H256::repeat_byte(0x5)),
+        ]);
+        let after = HashMap::from([
+            (balance_key, u256_to_h256(U256::from(50))),
+            (
+                nonce_key,
+                u256_to_h256(nonces_to_full_nonce(U256::from(1), U256::from(2))),
+            ),
+            (code_key, H256::repeat_byte(0x6)),
+            (raw_key, H256::repeat_byte(0x7)),
+            (unchanged_key, H256::repeat_byte(0x5)),
+        ]);
+
+        let changes = diff_states(
+            &before,
+            &after,
+            |_| Ok::<_, Infallible>(H256::zero()),
+            [account],
+        )
+        .unwrap();
+
+        assert_eq!(4, changes.len());
+        let decoded = |key: &StorageKey| {
+            changes
+                .iter()
+                .find(|change| change.address == *key.address() && change.key == *key.key())
+                .expect("change must exist")
+                .decoded
+                .clone()
+        };
+        assert_eq!(
+            Some(DecodedStorageChange::BaseTokenBalance {
+                account,
+                before: U256::from(100),
+                after: U256::from(50),
+            }),
+            decoded(&balance_key)
+        );
+        assert_eq!(
+            Some(DecodedStorageChange::Nonce {
+                account,
+                tx_nonce_before: U256::zero(),
+                tx_nonce_after: U256::from(1),
+                deployment_nonce_before: U256::zero(),
+                deployment_nonce_after: U256::from(2),
+            }),
+            decoded(&nonce_key)
+        );
+        assert_eq!(
+            Some(DecodedStorageChange::CodeHash {
+                account: contract,
+                before: H256::zero(),
+                after: H256::repeat_byte(0x6),
+            }),
+            decoded(&code_key)
+        );
+        assert_eq!(None, decoded(&raw_key));
+    }
+
+    #[test]
+    fn test_diff_states_falls_back_to_base_value() {
+        let key = StorageKey::new(AccountTreeId::new(Address::repeat_byte(0x1)), H256::zero());
+        let before = HashMap::new();
+        let after = HashMap::from([(key, H256::repeat_byte(0x2))]);
+
+        let mut reads = 0;
+        let changes = diff_states(
+            &before,
+            &after,
+            |_| {
+                reads += 1;
+                Ok::<_, Infallible>(H256::repeat_byte(0x2))
+            },
+            [],
+        )
+        .unwrap();
+
+        assert!(changes.is_empty());
+        assert_eq!(1, reads);
+    }
+}
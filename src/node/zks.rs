@@ -10,8 +10,8 @@ use zksync_types::{
     },
     fee::Fee,
     utils::storage_key_for_standard_token_balance,
-    AccountTreeId, Address, ExecuteTransactionCommon, L1BatchNumber, L2BlockNumber,
-    ProtocolVersionId, Transaction, H160, H256, L2_BASE_TOKEN_ADDRESS, U256,
+    AccountTreeId, Address, ExecuteTransactionCommon, L1BatchNumber, L2BlockNumber, Transaction,
+    H160, H256, L2_BASE_TOKEN_ADDRESS, U256,
 };
 use zksync_utils::h256_to_u256;
 use zksync_web3_decl::error::Web3Error;
@@ -22,7 +22,7 @@ use crate::{
     node::{InMemoryNode, TransactionResult},
     utils::{
         internal_error, into_jsrpc_error, not_implemented, report_into_jsrpc_error,
-        utc_datetime_from_epoch_ms, IntoBoxedFuture,
+        spawn_blocking_rpc, utc_datetime_from_epoch_ms, IntoBoxedFuture,
     },
 };
 
@@ -39,16 +39,19 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
     ///
     /// A `BoxFuture` containing a `Result` with a `Fee` representing the estimated gas data required.
     fn estimate_fee(&self, req: zksync_types::transaction_request::CallRequest) -> RpcResult<Fee> {
-        self.get_inner()
-            .read()
-            .map_err(|err| {
-                tracing::error!("failed acquiring lock: {:?}", err);
-                into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(
-                    "Failed to acquire read lock for inner node state.",
-                )))
-            })
-            .and_then(|reader| reader.estimate_gas_impl(&self.time, req))
-            .into_boxed_future()
+        let inner = self.get_inner().clone();
+        let time = self.time.clone();
+        spawn_blocking_rpc(move || {
+            inner
+                .read()
+                .map_err(|err| {
+                    tracing::error!("failed acquiring lock: {:?}", err);
+                    into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(
+                        "Failed to acquire read lock for inner node state.",
+                    )))
+                })
+                .and_then(|reader| reader.estimate_gas_impl(&time, req))
+        })
     }
 
     /// Returns data of transactions in a block.
@@ -295,7 +298,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
                     })?;
 
                     let balances = {
-                        let writer = inner.write().map_err(|_e| {
+                        let reader = inner.read().map_err(|_e| {
                             let error_message = "Failed to acquire lock. Please ensure the lock is not being held by another process or thread.".to_string();
                             into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(error_message)))
                         })?;
@@ -305,7 +308,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
                                 AccountTreeId::new(token.l2_address),
                                 &address,
                             );
-                            let balance = match writer.fork_storage.read_value_internal(&balance_key) {
+                            let balance = match reader.fork_storage.read_value_internal(&balance_key) {
                                 Ok(balance) => balance,
                                 Err(error) => {
                                     return Err(report_into_jsrpc_error(error));
@@ -359,13 +362,14 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
         block_number: zksync_types::L2BlockNumber,
     ) -> RpcResult<Option<zksync_types::api::BlockDetails>> {
         let inner = self.get_inner().clone();
-        let base_system_contracts_hashes = self.system_contracts.base_system_contracts_hashes();
         Box::pin(async move {
             let reader = inner.read().map_err(|_e| {
                 let error_message = "Failed to acquire lock. Please ensure the lock is not being held by another process or thread.".to_string();
                 into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(error_message)))
             })?;
 
+            let base_system_contracts_hashes =
+                reader.system_contracts.base_system_contracts_hashes();
             let maybe_block = reader
                 .block_hashes
                 .get(&(block_number.0 as u64))
@@ -388,12 +392,14 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
                         execute_tx_hash: None,
                         executed_at: None,
                         l1_gas_price: 0,
-                        l2_fair_gas_price: reader.fee_input_provider.gas_price(),
+                        l2_fair_gas_price: reader
+                            .fee_input_provider
+                            .gas_price(reader.protocol_version()),
                         fair_pubdata_price: Some(reader.fee_input_provider.fair_pubdata_price()),
                         base_system_contracts_hashes,
                     },
                     operator_address: Address::zero(),
-                    protocol_version: Some(ProtocolVersionId::latest()),
+                    protocol_version: Some(reader.protocol_version()),
                 })
                 .or_else(|| {
                     reader
@@ -516,13 +522,13 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
     fn get_bytecode_by_hash(&self, hash: zksync_types::H256) -> RpcResult<Option<Vec<u8>>> {
         let inner = self.get_inner().clone();
         Box::pin(async move {
-            let writer = inner.write().map_err(|_e| {
+            let reader = inner.read().map_err(|_e| {
                 into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(
-                    "Failed to acquire write lock for bytecode retrieval.",
+                    "Failed to acquire read lock for bytecode retrieval.",
                 )))
             })?;
 
-            let maybe_bytecode = match writer.fork_storage.load_factory_dep_internal(hash) {
+            let maybe_bytecode = match reader.fork_storage.load_factory_dep_internal(hash) {
                 Ok(maybe_bytecode) => maybe_bytecode,
                 Err(error) => {
                     return Err(report_into_jsrpc_error(error));
@@ -533,7 +539,7 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
                 return Ok(maybe_bytecode);
             }
 
-            let maybe_fork_details = &writer
+            let maybe_fork_details = &reader
                 .fork_storage
                 .inner
                 .read()
@@ -558,8 +564,39 @@ impl<S: ForkSource + std::fmt::Debug + Clone + Send + Sync + 'static> ZksNamespa
         not_implemented("zks_getL1GasPrice")
     }
 
-    fn get_protocol_version(&self, _version_id: Option<u16>) -> RpcResult<Option<ProtocolVersion>> {
-        not_implemented("zks_getProtocolVersion")
+    /// Returns the protocol version the node runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `version_id` - Optional minor version to look up, defaults to the version of the node
+    ///
+    /// # Returns
+    ///
+    /// A `BoxFuture` containing a `Result` with the `ProtocolVersion` of the node, or `None` if another version was requested.
+    fn get_protocol_version(&self, version_id: Option<u16>) -> RpcResult<Option<ProtocolVersion>> {
+        let inner = self.get_inner().clone();
+        Box::pin(async move {
+            let reader = inner.read().map_err(|_e| {
+                let error_message = "Failed to acquire lock. Please ensure the lock is not being held by another process or thread.".to_string();
+                into_jsrpc_error(Web3Error::InternalError(anyhow::Error::msg(error_message)))
+            })?;
+
+            let protocol_version = reader.protocol_version() as u16;
+            if version_id.map_or(false, |version_id| version_id != protocol_version) {
+                return Ok(None);
+            }
+
+            let base_system_contracts_hashes =
+                reader.system_contracts.base_system_contracts_hashes();
+            Ok(Some(ProtocolVersion::new(
+                protocol_version,
+                reader.config.get_genesis_timestamp(),
+                base_system_contracts_hashes.bootloader,
+                base_system_contracts_hashes.default_aa,
+                base_system_contracts_hashes.evm_emulator,
+                None,
+            )))
+        })
     }
 
     /// Retrieves the L1 base token address.
@@ -741,7 +778,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), None, &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
@@ -779,6 +816,33 @@ mod tests {
         assert_eq!(result.base.timestamp, 0);
     }
 
+    #[tokio::test]
+    async fn test_get_protocol_version() {
+        let node = InMemoryNode::<HttpForkSource>::default();
+        let latest = zksync_types::ProtocolVersionId::latest() as u16;
+
+        let version = node
+            .get_protocol_version(None)
+            .await
+            .expect("get protocol version")
+            .expect("protocol version");
+        assert_eq!(version.minor_version, Some(latest));
+        assert_eq!(
+            version.bootloader_code_hash,
+            Some(
+                node.system_contracts
+                    .base_system_contracts_hashes()
+                    .bootloader
+            )
+        );
+
+        assert!(node
+            .get_protocol_version(Some(latest - 1))
+            .await
+            .expect("get protocol version")
+            .is_none());
+    }
+
     #[tokio::test]
     async fn test_get_block_details_fork() {
         let mock_server = MockServer::run_with_config(ForkBlockConfig {
@@ -827,7 +891,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), None, &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
@@ -905,7 +969,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), None, &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
@@ -968,7 +1032,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), None, &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
@@ -1089,7 +1153,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), None, &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], None, &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
@@ -1268,7 +1332,7 @@ mod tests {
         );
 
         let node = InMemoryNode::<HttpForkSource>::default_fork(Some(
-            ForkDetails::from_network(&mock_server.url(), Some(1), &CacheConfig::None)
+            ForkDetails::from_network(&mock_server.url(), &[], Some(1), &CacheConfig::None)
                 .await
                 .unwrap(),
         ));
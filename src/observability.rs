@@ -4,7 +4,8 @@ use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
 use std::{fs::File, sync::Mutex};
 use tracing_subscriber::{
-    filter::LevelFilter, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+    filter::LevelFilter, fmt::writer::BoxMakeWriter, layer::SubscriberExt, reload,
+    util::SubscriberInitExt, EnvFilter, Registry,
 };
 
 /// Log filter level for the node.
@@ -59,6 +60,7 @@ impl Observability {
         log_level_filter: LevelFilter,
         log_file: File,
         disabled: bool,
+        console_to_stderr: bool,
     ) -> Result<Self, anyhow::Error> {
         let directives = binary_names
             .iter()
@@ -76,16 +78,24 @@ impl Observability {
             time::format_description::parse("[hour]:[minute]:[second]").expect("Cataplum");
         let time_offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
         let timer = tracing_subscriber::fmt::time::OffsetTime::new(time_offset, timer_format);
+        // Leaves stdout to other machine-readable output, e.g. the JSON log
+        let console_writer = if console_to_stderr {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        };
 
         tracing_subscriber::registry()
             .with(filter)
             .with(
-                tracing_subscriber::fmt::layer().event_format(
-                    tracing_subscriber::fmt::format()
-                        .compact()
-                        .with_timer(timer.clone())
-                        .with_target(false),
-                ),
+                tracing_subscriber::fmt::layer()
+                    .event_format(
+                        tracing_subscriber::fmt::format()
+                            .compact()
+                            .with_timer(timer.clone())
+                            .with_target(false),
+                    )
+                    .with_writer(console_writer),
             )
             .with(
                 tracing_subscriber::fmt::layer()
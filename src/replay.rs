@@ -0,0 +1,588 @@
+//! Replays a range of upstream blocks on top of a fork and compares the local receipts with the upstream ones.
+//!
+//! This is meant to validate changes to the bootloader or system contracts against real network traffic: any
+//! transaction whose status, gas used or logs differ from the upstream execution ends up in a [ReplayReport].
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use serde_json::{json, Value};
+use zksync_multivm::interface::TxExecutionMode;
+use zksync_types::api::{BlockDetails, Log, TransactionReceipt};
+use zksync_types::l1::L1Tx;
+use zksync_types::l2::L2Tx;
+use zksync_types::{ExecuteTransactionCommon, L2BlockNumber, H256};
+use zksync_web3_decl::client::{Client, L2};
+use zksync_web3_decl::namespaces::{EthNamespaceClient, ZksNamespaceClient};
+
+use crate::fork::ForkSource;
+use crate::node::{InMemoryNode, TimestampManager};
+
+/// A receipt field that differs between the local and the upstream execution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReceiptDivergence {
+    pub field: &'static str,
+    pub local: Value,
+    pub upstream: Value,
+}
+
+/// Outcome of replaying a single upstream transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase")]
+pub enum ReplayOutcome {
+    Matched,
+    Diverged { divergences: Vec<ReceiptDivergence> },
+    Failed { error: String },
+    Skipped { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReplay {
+    pub block_number: u64,
+    pub hash: H256,
+    #[serde(flatten)]
+    pub outcome: ReplayOutcome,
+}
+
+/// Divergence report of a replayed block range.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    pub fork_url: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub transactions: Vec<TransactionReplay>,
+}
+
+impl ReplayReport {
+    /// Logs every transaction that did not match upstream, followed by a summary.
+    pub fn log_summary(&self) {
+        let (mut matched, mut diverged, mut failed, mut skipped) = (0, 0, 0, 0);
+        for tx in &self.transactions {
+            match &tx.outcome {
+                ReplayOutcome::Matched => matched += 1,
+                ReplayOutcome::Diverged { divergences } => {
+                    diverged += 1;
+                    for divergence in divergences {
+                        tracing::warn!(
+                            "block {} tx {:#x}: {} differs, local {} vs upstream {}",
+                            tx.block_number,
+                            tx.hash,
+                            divergence.field,
+                            divergence.local,
+                            divergence.upstream
+                        );
+                    }
+                }
+                ReplayOutcome::Failed { error } => {
+                    failed += 1;
+                    tracing::warn!("block {} tx {:#x}: {}", tx.block_number, tx.hash, error);
+                }
+                ReplayOutcome::Skipped { reason } => {
+                    skipped += 1;
+                    tracing::info!(
+                        "block {} tx {:#x}: skipped, {}",
+                        tx.block_number,
+                        tx.hash,
+                        reason
+                    );
+                }
+            }
+        }
+        tracing::info!(
+            "Replayed blocks {}..={} from {}: {} matched, {} diverged, {} failed, {} skipped",
+            self.from_block,
+            self.to_block,
+            self.fork_url,
+            matched,
+            diverged,
+            failed,
+            skipped
+        );
+    }
+
+    /// Writes the report as JSON to `path`.
+    pub fn write_to_file(&self, path: &str) -> anyhow::Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("failed creating replay report {}", path))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("failed writing replay report {}", path))
+    }
+}
+
+/// Compares the fields of two receipts that don't depend on the block the transaction was included in.
+pub fn compare_receipts(
+    local: &TransactionReceipt,
+    upstream: &TransactionReceipt,
+) -> Vec<ReceiptDivergence> {
+    let mut divergences = Vec::new();
+    if local.status != upstream.status {
+        divergences.push(ReceiptDivergence {
+            field: "status",
+            local: json!(local.status),
+            upstream: json!(upstream.status),
+        });
+    }
+    if local.gas_used != upstream.gas_used {
+        divergences.push(ReceiptDivergence {
+            field: "gasUsed",
+            local: json!(local.gas_used),
+            upstream: json!(upstream.gas_used),
+        });
+    }
+    let local_logs = log_contents(&local.logs);
+    let upstream_logs = log_contents(&upstream.logs);
+    if local_logs != upstream_logs {
+        divergences.push(ReceiptDivergence {
+            field: "logs",
+            local: Value::Array(local_logs),
+            upstream: Value::Array(upstream_logs),
+        });
+    }
+    divergences
+}
+
+/// Returns the emitter, topics and data of each log, leaving out block and index information.
+fn log_contents(logs: &[Log]) -> Vec<Value> {
+    logs.iter()
+        .map(|log| {
+            json!({
+                "address": log.address,
+                "topics": log.topics,
+                "data": log.data,
+            })
+        })
+        .collect()
+}
+
+/// Makes the next batch use the fee input of the upstream block, as forks mirror the fees of the forked network.
+fn mirror_block_fees<S: ForkSource + std::fmt::Debug + Clone>(
+    node: &InMemoryNode<S>,
+    block_details: &BlockDetails,
+) -> anyhow::Result<()> {
+    let inner = node.read_inner()?;
+    if inner.fee_input_provider.is_customized() {
+        tracing::warn!(
+            "fee params were customized, block {} is not replayed with its upstream fees",
+            block_details.number
+        );
+    }
+    let mut storage = inner
+        .fork_storage
+        .inner
+        .write()
+        .map_err(|_| anyhow!("failed acquiring the fork storage lock"))?;
+    let fork = storage
+        .fork
+        .as_mut()
+        .context("replaying blocks requires a fork")?;
+    fork.l1_gas_price = block_details.base.l1_gas_price;
+    fork.l2_fair_gas_price = block_details.base.l2_fair_gas_price;
+    if let Some(fair_pubdata_price) = block_details.base.fair_pubdata_price {
+        fork.fair_pubdata_price = fair_pubdata_price;
+    }
+    Ok(())
+}
+
+/// Re-executes every transaction of the upstream blocks `from..=to` on `node`, which must be forked at block
+/// `from - 1`. Each upstream block is sealed as a single local block with the upstream timestamp and fee input,
+/// executing its L1 priority transactions before the L2 ones.
+pub async fn replay_blocks<S: ForkSource + std::fmt::Debug + Clone>(
+    node: &InMemoryNode<S>,
+    time: &TimestampManager,
+    client: &Client<L2>,
+    fork_url: &str,
+    from: u64,
+    to: u64,
+) -> anyhow::Result<ReplayReport> {
+    let mut report = ReplayReport {
+        fork_url: fork_url.to_string(),
+        from_block: from,
+        to_block: to,
+        transactions: Vec::new(),
+    };
+
+    for block_number in from..=to {
+        let miniblock = L2BlockNumber(block_number as u32);
+        let block_details = client
+            .get_block_details(miniblock)
+            .await?
+            .ok_or_else(|| anyhow!("could not find block {} in {}", block_number, fork_url))?;
+        let raw_transactions = client.get_raw_block_transactions(miniblock).await?;
+
+        let mut priority_txs = Vec::new();
+        let mut transactions = Vec::new();
+        for tx in raw_transactions {
+            let hash = tx.hash();
+            let skip_reason = match tx.common_data {
+                ExecuteTransactionCommon::L1(_) => match L1Tx::try_from(tx) {
+                    Ok(tx) => {
+                        priority_txs.push(tx);
+                        continue;
+                    }
+                    Err(err) => format!("cannot convert L1 transaction: {}", err),
+                },
+                ExecuteTransactionCommon::L2(_) => match L2Tx::try_from(tx) {
+                    Ok(tx) => {
+                        transactions.push(tx);
+                        continue;
+                    }
+                    Err(err) => format!("cannot convert L2 transaction: {}", err),
+                },
+                ExecuteTransactionCommon::ProtocolUpgrade(_) => {
+                    String::from("protocol upgrade transactions cannot be replayed")
+                }
+            };
+            report.transactions.push(TransactionReplay {
+                block_number,
+                hash,
+                outcome: ReplayOutcome::Skipped {
+                    reason: skip_reason,
+                },
+            });
+        }
+        if priority_txs.is_empty() && transactions.is_empty() {
+            continue;
+        }
+
+        if let Err(err) = time.enforce_next_timestamp(block_details.base.timestamp) {
+            tracing::warn!(
+                "cannot replay block {} at its upstream timestamp: {:?}",
+                block_number,
+                err
+            );
+        }
+        mirror_block_fees(node, &block_details)?;
+        let hashes = priority_txs
+            .iter()
+            .map(|tx| tx.hash())
+            .chain(transactions.iter().map(|tx| tx.hash()))
+            .collect::<Vec<_>>();
+        let system_contracts = node.base_system_contracts(TxExecutionMode::VerifyExecute, false)?;
+        let (excluded_hashes, mut priority_receipts) = match node.seal_block_with_priority_txs(
+            &mut time.lock(),
+            priority_txs,
+            transactions,
+            system_contracts,
+        ) {
+            Ok((_, excluded_txs, priority_receipts)) => {
+                if !excluded_txs.is_empty() {
+                    tracing::warn!(
+                        "{} transactions of block {} did not fit into a single batch",
+                        excluded_txs.len(),
+                        block_number
+                    );
+                }
+                let priority_receipts = priority_receipts
+                    .into_iter()
+                    .map(|receipt| (receipt.transaction_hash, receipt))
+                    .collect::<HashMap<_, _>>();
+                (
+                    excluded_txs
+                        .iter()
+                        .map(|tx| tx.hash())
+                        .collect::<HashSet<_>>(),
+                    priority_receipts,
+                )
+            }
+            Err(err) => {
+                tracing::warn!("failed replaying block {}: {:?}", block_number, err);
+                (HashSet::new(), HashMap::new())
+            }
+        };
+
+        for hash in hashes {
+            if excluded_hashes.contains(&hash) {
+                report.transactions.push(TransactionReplay {
+                    block_number,
+                    hash,
+                    outcome: ReplayOutcome::Skipped {
+                        reason: String::from("batch full"),
+                    },
+                });
+                continue;
+            }
+            let local_receipt = match priority_receipts.remove(&hash) {
+                Some(receipt) => Some(receipt),
+                None => node
+                    .read_inner()?
+                    .tx_results
+                    .get(&hash)
+                    .map(|tx_result| tx_result.receipt.clone()),
+            };
+            let upstream_receipt = client.get_transaction_receipt(hash).await?;
+            let outcome = match (local_receipt, upstream_receipt) {
+                (Some(local), Some(upstream)) => {
+                    let divergences = compare_receipts(&local, &upstream);
+                    if divergences.is_empty() {
+                        ReplayOutcome::Matched
+                    } else {
+                        ReplayOutcome::Diverged { divergences }
+                    }
+                }
+                (None, _) => ReplayOutcome::Failed {
+                    error: String::from("transaction was rejected or halted locally"),
+                },
+                (Some(_), None) => ReplayOutcome::Failed {
+                    error: String::from("upstream receipt is missing"),
+                },
+            };
+            report.transactions.push(TransactionReplay {
+                block_number,
+                hash,
+                outcome,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::cache::CacheConfig;
+    use crate::config::constants::{
+        DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR, DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+        DEFAULT_FAIR_PUBDATA_PRICE, DEFAULT_L2_GAS_PRICE, TEST_NODE_NETWORK_ID,
+    };
+    use crate::fork::ForkDetails;
+    use crate::http_fork_source::HttpForkSource;
+    use crate::namespaces::EthNamespaceT;
+    use crate::testing::{self, LogBuilder, MockServer};
+    use std::sync::Arc;
+    use zksync_types::api::{BlockDetailsBase, BlockNumber, BlockStatus};
+    use zksync_types::l1::L1TxCommonData;
+    use zksync_types::{
+        Address, Execute, L1BatchNumber, PriorityOpId, Transaction, H160, U256, U64,
+    };
+
+    fn receipt(status: u64, gas_used: u64, logs: Vec<Log>) -> TransactionReceipt {
+        TransactionReceipt {
+            status: U64::from(status),
+            gas_used: Some(U256::from(gas_used)),
+            logs,
+            ..Default::default()
+        }
+    }
+
+    fn log(topic: u8, block_number: u64) -> Log {
+        LogBuilder::new()
+            .set_address(Address::repeat_byte(0x1))
+            .set_topics(vec![H256::repeat_byte(topic)])
+            .set_block(U64::from(block_number))
+            .build()
+    }
+
+    #[test]
+    fn test_compare_receipts_ignores_block_information() {
+        let local = receipt(1, 100, vec![log(0x3, 10)]);
+        let upstream = receipt(1, 100, vec![log(0x3, 20)]);
+
+        assert!(compare_receipts(&local, &upstream).is_empty());
+    }
+
+    #[test]
+    fn test_compare_receipts_reports_diverging_fields() {
+        let local = receipt(0, 100, vec![]);
+        let upstream = receipt(1, 120, vec![log(0x3, 20)]);
+
+        let divergences = compare_receipts(&local, &upstream);
+
+        assert_eq!(
+            vec!["status", "gasUsed", "logs"],
+            divergences
+                .iter()
+                .map(|divergence| divergence.field)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(json!(U64::from(0)), divergences[0].local);
+        assert_eq!(json!(Some(U256::from(120))), divergences[1].upstream);
+        assert_eq!(Value::Array(vec![]), divergences[2].local);
+    }
+
+    #[test]
+    fn test_replay_report_serializes_outcomes() {
+        let report = ReplayReport {
+            fork_url: String::from("http://localhost"),
+            from_block: 1,
+            to_block: 2,
+            transactions: vec![TransactionReplay {
+                block_number: 1,
+                hash: H256::repeat_byte(0x1),
+                outcome: ReplayOutcome::Failed {
+                    error: String::from("boom"),
+                },
+            }],
+        };
+
+        let value = serde_json::to_value(&report).expect("failed serializing report");
+
+        assert_eq!(json!("failed"), value["transactions"][0]["outcome"]);
+        assert_eq!(json!("boom"), value["transactions"][0]["error"]);
+        assert_eq!(json!(1), value["fromBlock"]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_blocks_executes_priority_txs_with_upstream_fees() {
+        // The L2 transaction is paid for with the ETH minted by the priority transaction before it
+        let l2_tx = testing::TransactionBuilder::new()
+            .set_hash(H256::repeat_byte(0x2))
+            .build();
+        let l1_tx = L1Tx {
+            execute: Execute {
+                contract_address: Some(Address::repeat_byte(0x3)),
+                calldata: vec![],
+                value: U256::zero(),
+                factory_deps: vec![],
+            },
+            common_data: L1TxCommonData {
+                sender: l2_tx.initiator_account(),
+                serial_id: PriorityOpId(0),
+                gas_limit: U256::from(10_000_000),
+                max_fee_per_gas: U256::from(1_000_000_000),
+                gas_per_pubdata_limit: U256::from(800),
+                to_mint: U256::from(10u128.pow(18)),
+                refund_recipient: l2_tx.initiator_account(),
+                canonical_tx_hash: H256::repeat_byte(0x1),
+                ..Default::default()
+            },
+            received_timestamp_ms: 0,
+        };
+
+        let raw_storage = InMemoryNode::<HttpForkSource>::default()
+            .read_inner()
+            .unwrap()
+            .fork_storage
+            .inner
+            .read()
+            .unwrap()
+            .raw_storage
+            .clone();
+        let node = InMemoryNode::<testing::ExternalStorage>::default_fork(Some(ForkDetails {
+            fork_source: Arc::new(testing::ExternalStorage {
+                raw_storage,
+                read_delay: Default::default(),
+                transactions: Default::default(),
+            }),
+            chain_id: TEST_NODE_NETWORK_ID.into(),
+            l1_block: L1BatchNumber(1),
+            l2_block: Default::default(),
+            l2_miniblock: 1,
+            l2_miniblock_hash: Default::default(),
+            block_timestamp: 1000,
+            overwrite_chain_id: None,
+            l1_gas_price: 1000,
+            l2_fair_gas_price: DEFAULT_L2_GAS_PRICE,
+            fair_pubdata_price: DEFAULT_FAIR_PUBDATA_PRICE,
+            fee_params: None,
+            estimate_gas_price_scale_factor: DEFAULT_ESTIMATE_GAS_PRICE_SCALE_FACTOR,
+            estimate_gas_scale_factor: DEFAULT_ESTIMATE_GAS_SCALE_FACTOR,
+            cache_config: CacheConfig::None,
+        }));
+
+        let upstream_fair_l2_gas_price = 40_000_000;
+        let block_details = BlockDetails {
+            number: L2BlockNumber(2),
+            l1_batch_number: L1BatchNumber(2),
+            base: BlockDetailsBase {
+                timestamp: 1100,
+                l1_tx_count: 1,
+                l2_tx_count: 1,
+                root_hash: None,
+                status: BlockStatus::Sealed,
+                commit_tx_hash: None,
+                committed_at: None,
+                prove_tx_hash: None,
+                proven_at: None,
+                execute_tx_hash: None,
+                executed_at: None,
+                l1_gas_price: 1000,
+                l2_fair_gas_price: upstream_fair_l2_gas_price,
+                fair_pubdata_price: Some(1000),
+                base_system_contracts_hashes: Default::default(),
+            },
+            operator_address: H160::zero(),
+            protocol_version: None,
+        };
+        let mock_server = MockServer::run();
+        mock_server.expect(
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "zks_getBlockDetails",
+                "params": [2],
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "result": block_details,
+            }),
+        );
+        mock_server.expect(
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "zks_getRawBlockTransactions",
+                "params": [2],
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": [Transaction::from(l1_tx.clone()), Transaction::from(l2_tx.clone())],
+            }),
+        );
+        for (id, hash) in [(2, l1_tx.hash()), (3, l2_tx.hash())] {
+            mock_server.expect(
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "eth_getTransactionReceipt",
+                    "params": [hash],
+                }),
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": receipt(1, 1, vec![]),
+                }),
+            );
+        }
+
+        let (_, client) = ForkDetails::fork_network_and_client(&mock_server.url()).unwrap();
+        let report = replay_blocks(&node, &node.time, &client, &mock_server.url(), 2, 2)
+            .await
+            .expect("failed replaying blocks");
+
+        assert_eq!(
+            vec![l1_tx.hash(), l2_tx.hash()],
+            report
+                .transactions
+                .iter()
+                .map(|tx| tx.hash)
+                .collect::<Vec<_>>()
+        );
+        for tx in &report.transactions {
+            // Both transactions were executed successfully, only the gas used and the logs differ from the mock
+            match &tx.outcome {
+                ReplayOutcome::Diverged { divergences } => assert!(divergences
+                    .iter()
+                    .all(|divergence| divergence.field != "status")),
+                outcome => panic!("unexpected outcome of {:#x}: {:?}", tx.hash, outcome),
+            }
+        }
+        let block = node
+            .get_block_by_number(BlockNumber::Number(U64::from(2)), false)
+            .await
+            .unwrap()
+            .expect("replayed block is missing");
+        assert_eq!(
+            U256::from(upstream_fair_l2_gas_price),
+            block.base_fee_per_gas
+        );
+    }
+}
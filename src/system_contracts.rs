@@ -1,3 +1,6 @@
+use std::path::Path;
+
+use anyhow::Context;
 use clap::ValueEnum;
 use serde::Deserialize;
 use zksync_contracts::{
@@ -5,9 +8,14 @@ use zksync_contracts::{
     BaseSystemContractsHashes, ContractLanguage, SystemContractCode,
 };
 use zksync_multivm::interface::TxExecutionMode;
+use zksync_types::web3::Bytes;
+use zksync_types::ProtocolVersionId;
 use zksync_utils::{bytecode::hash_bytecode, bytes_to_be_words};
 
-use crate::deps::system_contracts::bytecode_from_slice;
+use crate::deps::system_contracts::{
+    bytecode_from_slice, read_artifact_from_dir, system_contracts_from_dir,
+};
+use crate::namespaces::{BootloaderUpgrade, ProtocolUpgrade, SystemContractUpgrade};
 
 #[derive(Deserialize, Default, Debug, Copy, Clone, PartialEq, ValueEnum)]
 pub enum Options {
@@ -20,6 +28,72 @@ pub enum Options {
     BuiltInWithoutSecurity,
 }
 
+/// Protocol versions a new chain can be started with.
+///
+/// These are the versions whose multivm VM is `vm_latest`, so the node's tracers work with all of them. `vm_latest`
+/// runs the VM subversion matching the protocol version of the system environment, as a `VmInstance` would.
+const NEW_CHAIN_PROTOCOL_VERSIONS: &[ProtocolVersionId] = &[
+    ProtocolVersionId::Version23,
+    ProtocolVersionId::Version24,
+    ProtocolVersionId::Version25,
+    ProtocolVersionId::Version26,
+];
+
+pub fn supported_new_chain_protocol_versions(version: ProtocolVersionId) -> bool {
+    NEW_CHAIN_PROTOCOL_VERSIONS.contains(&version)
+}
+
+pub fn new_chain_protocol_versions_to_string() -> String {
+    let versions: Vec<String> = NEW_CHAIN_PROTOCOL_VERSIONS
+        .iter()
+        .map(|v| (*v as u16).to_string())
+        .collect();
+    versions.join(", ")
+}
+
+/// Protocol version the compiled-in bootloader and system contracts were built for. The contracts of other versions
+/// have to be loaded, either with [Options::Local] or from a `--system-contracts-dir`.
+pub fn built_in_protocol_version() -> ProtocolVersionId {
+    ProtocolVersionId::latest()
+}
+
+/// Reads compiled system contracts from `dir` into a [ProtocolUpgrade] that replaces them on a running chain.
+///
+/// The bootloaders are read from `<name>.yul.zbin`, the default account and system contracts from their
+/// artifacts (see [read_artifact_from_dir]). Contracts missing from the directory are left unchanged.
+pub fn upgrade_from_dir(dir: &Path, options: &Options) -> anyhow::Result<ProtocolUpgrade> {
+    anyhow::ensure!(dir.is_dir(), "{} is not a directory", dir.display());
+    let read = |name: &str| -> anyhow::Result<Option<Bytes>> {
+        Ok(read_artifact_from_dir(dir, name)?.map(Bytes))
+    };
+
+    let bootloader = BootloaderUpgrade {
+        proved_batch: read("proved_batch")?,
+        proved_batch_impersonating: read("proved_batch_impersonating")?,
+        playground_batch: read("playground_batch")?,
+        fee_estimate: read("fee_estimate")?,
+        fee_estimate_impersonating: read("fee_estimate_impersonating")?,
+    };
+    let default_account = match options {
+        Options::BuiltInWithoutSecurity => read("DefaultAccountNoSecurity")?,
+        Options::BuiltIn | Options::Local => read("DefaultAccount")?,
+    };
+    let system_contracts = system_contracts_from_dir(dir)?
+        .into_iter()
+        .map(|contract| SystemContractUpgrade {
+            address: *contract.account_id.address(),
+            bytecode: Bytes(contract.bytecode),
+        })
+        .collect();
+
+    Ok(ProtocolUpgrade {
+        protocol_version: None,
+        bootloader,
+        default_account,
+        system_contracts,
+    })
+}
+
 /// Holds the system contracts (and bootloader) that are used by the in-memory node.
 #[derive(Debug, Clone)]
 pub struct SystemContracts {
@@ -89,6 +163,56 @@ impl SystemContracts {
     pub fn base_system_contracts_hashes(&self) -> BaseSystemContractsHashes {
         self.baseline_contracts.hashes()
     }
+
+    fn contracts_mut(
+        &mut self,
+        execution_mode: TxExecutionMode,
+        impersonating: bool,
+    ) -> &mut BaseSystemContracts {
+        match (execution_mode, impersonating) {
+            (TxExecutionMode::VerifyExecute, false) => &mut self.baseline_contracts,
+            (TxExecutionMode::EstimateFee, false) => &mut self.fee_estimate_contracts,
+            (TxExecutionMode::EthCall, false) => &mut self.playground_contracts,
+            (TxExecutionMode::VerifyExecute, true) => &mut self.baseline_impersonating_contracts,
+            (TxExecutionMode::EstimateFee, true) => &mut self.fee_estimate_impersonating_contracts,
+            (TxExecutionMode::EthCall, true) => {
+                panic!("Account impersonating with eth_call is not supported")
+            }
+        }
+    }
+
+    /// Replaces the bootloader used for the given execution mode, as done by a protocol upgrade.
+    pub fn replace_bootloader(
+        &mut self,
+        execution_mode: TxExecutionMode,
+        impersonating: bool,
+        bootloader_bytecode: Vec<u8>,
+    ) {
+        self.contracts_mut(execution_mode, impersonating).bootloader =
+            system_contract_code(bootloader_bytecode);
+    }
+
+    /// Replaces the default account of every execution mode, as done by a protocol upgrade.
+    pub fn replace_default_account(&mut self, aa_bytecode: Vec<u8>) {
+        let default_aa = system_contract_code(aa_bytecode);
+        for contracts in [
+            &mut self.baseline_contracts,
+            &mut self.playground_contracts,
+            &mut self.fee_estimate_contracts,
+            &mut self.baseline_impersonating_contracts,
+            &mut self.fee_estimate_impersonating_contracts,
+        ] {
+            contracts.default_aa = default_aa.clone();
+        }
+    }
+}
+
+fn system_contract_code(bytecode: Vec<u8>) -> SystemContractCode {
+    let hash = hash_bytecode(&bytecode);
+    SystemContractCode {
+        code: bytes_to_be_words(bytecode),
+        hash,
+    }
 }
 
 /// Creates BaseSystemContracts object with a specific bootloader.
@@ -97,12 +221,7 @@ fn bsc_load_with_bootloader(
     options: &Options,
     use_evm_emulator: bool,
 ) -> BaseSystemContracts {
-    let hash = hash_bytecode(&bootloader_bytecode);
-
-    let bootloader = SystemContractCode {
-        code: bytes_to_be_words(bootloader_bytecode),
-        hash,
-    };
+    let bootloader = system_contract_code(bootloader_bytecode);
 
     let aa_bytecode = match options {
         Options::BuiltIn => bytecode_from_slice(
@@ -116,11 +235,7 @@ fn bsc_load_with_bootloader(
         ),
     };
 
-    let aa_hash = hash_bytecode(&aa_bytecode);
-    let default_aa = SystemContractCode {
-        code: bytes_to_be_words(aa_bytecode),
-        hash: aa_hash,
-    };
+    let default_aa = system_contract_code(aa_bytecode);
 
     let evm_emulator = if use_evm_emulator {
         let evm_emulator_bytecode = match options {
@@ -129,11 +244,7 @@ fn bsc_load_with_bootloader(
                 panic!("no built-in EVM emulator yet")
             }
         };
-        let evm_emulator_hash = hash_bytecode(&evm_emulator_bytecode);
-        Some(SystemContractCode {
-            code: bytes_to_be_words(evm_emulator_bytecode),
-            hash: evm_emulator_hash,
-        })
+        Some(system_contract_code(evm_emulator_bytecode))
     } else {
         None
     };
@@ -212,3 +323,68 @@ fn baseline_impersonating_contracts(
     };
     bsc_load_with_bootloader(bootloader_bytecode, options, use_evm_emulator)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+    use zksync_multivm::VmVersion;
+    use zksync_types::NONCE_HOLDER_ADDRESS;
+
+    #[test]
+    fn test_new_chain_protocol_versions_run_on_vm_latest() {
+        for version in NEW_CHAIN_PROTOCOL_VERSIONS {
+            assert!(
+                matches!(
+                    VmVersion::from(*version),
+                    VmVersion::Vm1_5_0SmallBootloaderMemory
+                        | VmVersion::Vm1_5_0IncreasedBootloaderMemory
+                ),
+                "protocol version {:?} is not executed by vm_latest",
+                version
+            );
+        }
+        assert!(supported_new_chain_protocol_versions(
+            built_in_protocol_version()
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_from_dir() {
+        let dir = TempDir::new("system-contracts-test").expect("failed creating temporary dir");
+        let bootloader = vec![0x1u8; 64];
+        let default_account = vec![0x2u8; 32];
+        let nonce_holder = vec![0x3u8; 32];
+        fs::write(dir.path().join("proved_batch.yul.zbin"), &bootloader).unwrap();
+        fs::write(
+            dir.path().join("DefaultAccount.json"),
+            format!(r#"{{"bytecode":"0x{}"}}"#, hex::encode(&default_account)),
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("NonceHolder.sol")).unwrap();
+        fs::write(
+            dir.path().join("NonceHolder.sol").join("NonceHolder.json"),
+            format!(r#"{{"bytecode":"0x{}"}}"#, hex::encode(&nonce_holder)),
+        )
+        .unwrap();
+
+        let upgrade = upgrade_from_dir(dir.path(), &Options::BuiltIn).expect("failed reading dir");
+
+        assert_eq!(Some(Bytes(bootloader)), upgrade.bootloader.proved_batch);
+        assert_eq!(None, upgrade.bootloader.playground_batch);
+        assert_eq!(Some(Bytes(default_account)), upgrade.default_account);
+        assert_eq!(1, upgrade.system_contracts.len());
+        assert_eq!(NONCE_HOLDER_ADDRESS, upgrade.system_contracts[0].address);
+        assert_eq!(Bytes(nonce_holder), upgrade.system_contracts[0].bytecode);
+    }
+
+    #[test]
+    fn test_upgrade_from_dir_rejects_invalid_artifact() {
+        let dir = TempDir::new("system-contracts-test").expect("failed creating temporary dir");
+        fs::write(dir.path().join("DefaultAccount.json"), "{}").unwrap();
+
+        assert!(upgrade_from_dir(dir.path(), &Options::BuiltIn).is_err());
+        assert!(upgrade_from_dir(&dir.path().join("missing"), &Options::BuiltIn).is_err());
+    }
+}
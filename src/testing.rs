@@ -19,6 +19,7 @@ use httptest::{
 };
 use itertools::Itertools;
 use std::str::FromStr;
+use std::time::Duration;
 use zksync_types::api::{
     BlockDetailsBase, BlockIdVariant, BlockStatus, BridgeAddresses, DebugCall, DebugCallType, Log,
 };
@@ -404,6 +405,7 @@ impl RawTransactionsResponseBuilder {
 pub struct TransactionBuilder {
     tx_hash: H256,
     from_account_private_key: K256PrivateKey,
+    nonce: Nonce,
     gas_limit: U256,
     max_fee_per_gas: U256,
     max_priority_fee_per_gas: U256,
@@ -414,6 +416,7 @@ impl Default for TransactionBuilder {
         Self {
             tx_hash: H256::repeat_byte(0x01),
             from_account_private_key: K256PrivateKey::from_bytes(H256::random()).unwrap(),
+            nonce: Nonce(0),
             gas_limit: U256::from(4_000_000),
             max_fee_per_gas: U256::from(50_000_000),
             max_priority_fee_per_gas: U256::from(50_000_000),
@@ -431,6 +434,11 @@ impl TransactionBuilder {
         self
     }
 
+    pub fn set_nonce(&mut self, nonce: u32) -> &mut Self {
+        self.nonce = Nonce(nonce);
+        self
+    }
+
     pub fn set_gas_limit(&mut self, gas_limit: U256) -> &mut Self {
         self.gas_limit = gas_limit;
         self
@@ -450,7 +458,7 @@ impl TransactionBuilder {
         let mut tx = L2Tx::new_signed(
             Some(Address::random()),
             vec![],
-            Nonce(0),
+            self.nonce,
             Fee {
                 gas_limit: self.gas_limit,
                 max_fee_per_gas: self.max_fee_per_gas,
@@ -746,6 +754,10 @@ pub fn assert_bridge_addresses_eq(
 #[derive(Debug, Clone)]
 pub struct ExternalStorage {
     pub raw_storage: InMemoryStorage,
+    /// Delay of every storage read, simulating a slow upstream network.
+    pub read_delay: Duration,
+    /// Transactions served as the contents of upstream block #1.
+    pub transactions: Vec<zksync_types::Transaction>,
 }
 
 impl ForkSource for ExternalStorage {
@@ -759,6 +771,7 @@ impl ForkSource for ExternalStorage {
         idx: U256,
         _block: Option<BlockIdVariant>,
     ) -> eyre::Result<H256> {
+        std::thread::sleep(self.read_delay);
         let key = StorageKey::new(AccountTreeId::new(address), u256_to_h256(idx));
         Ok(self
             .raw_storage
@@ -770,9 +783,13 @@ impl ForkSource for ExternalStorage {
 
     fn get_raw_block_transactions(
         &self,
-        _block_number: L2BlockNumber,
+        block_number: L2BlockNumber,
     ) -> eyre::Result<Vec<zksync_types::Transaction>> {
-        todo!()
+        if block_number.0 == 1 {
+            Ok(self.transactions.clone())
+        } else {
+            Ok(vec![])
+        }
     }
 
     fn get_bytecode_by_hash(&self, hash: H256) -> eyre::Result<Option<Vec<u8>>> {
@@ -781,9 +798,18 @@ impl ForkSource for ExternalStorage {
 
     fn get_transaction_by_hash(
         &self,
-        _hash: H256,
+        hash: H256,
     ) -> eyre::Result<Option<zksync_types::api::Transaction>> {
-        todo!()
+        Ok(self
+            .transactions
+            .iter()
+            .position(|tx| tx.hash() == hash)
+            .map(|index| zksync_types::api::Transaction {
+                hash,
+                block_number: Some(U64::from(1)),
+                transaction_index: Some(U64::from(index)),
+                ..Default::default()
+            }))
     }
 
     fn get_transaction_details(
@@ -1061,6 +1087,8 @@ mod test {
                     H256::repeat_byte(0x1) => input_bytecode.clone(),
                 },
             },
+            read_delay: Default::default(),
+            transactions: Default::default(),
         };
 
         let actual_batch = storage
@@ -14,6 +14,7 @@ use zksync_types::{
     web3::Bytes,
     CONTRACT_DEPLOYER_ADDRESS, H256, U256, U64,
 };
+use zksync_utils::bytecode::{hash_evm_bytecode, BytecodeMarker};
 use zksync_utils::bytes_to_be_words;
 use zksync_web3_decl::error::Web3Error;
 
@@ -30,6 +31,24 @@ where
 {
 }
 
+/// Runs `f` on the blocking thread pool and resolves to its result, so that VM execution does not stall the
+/// event loop serving the other requests.
+pub(crate) fn spawn_blocking_rpc<T, F>(
+    f: F,
+) -> Pin<Box<dyn Future<Output = jsonrpc_core::Result<T>> + Send>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> jsonrpc_core::Result<T> + Send + 'static,
+{
+    Box::pin(async move {
+        tokio::task::spawn_blocking(f).await.unwrap_or_else(|err| {
+            Err(into_jsrpc_error(Web3Error::InternalError(
+                anyhow::Error::from(err).context("VM execution task failed"),
+            )))
+        })
+    })
+}
+
 /// Parses the genesis file from the given path.
 pub fn parse_genesis_file(path: &str) -> Result<Genesis, String> {
     let file_content =
@@ -66,6 +85,50 @@ pub fn bytecode_to_factory_dep(bytecode: Vec<u8>) -> Result<(U256, Vec<U256>), a
     Ok((bytecode_hash, bytecode_words))
 }
 
+/// Converts EVM runtime bytecode into the preimage the EVM emulator executes and its versioned hash.
+///
+/// The preimage is the bytecode length as a 32-byte word, followed by the bytecode zero-padded to an odd number
+/// of words, as required for every code stored in `KnownCodesStorage`.
+pub fn evm_bytecode_to_factory_dep(bytecode: &[u8]) -> anyhow::Result<(H256, Vec<u8>)> {
+    anyhow::ensure!(
+        bytecode.len() <= u16::MAX as usize,
+        "EVM bytecode is too long: {} bytes",
+        bytecode.len()
+    );
+    let mut padded = vec![0u8; 32];
+    U256::from(bytecode.len()).to_big_endian(&mut padded);
+    padded.extend_from_slice(bytecode);
+    let mut words = (padded.len() + 31) / 32;
+    if words % 2 == 0 {
+        words += 1;
+    }
+    padded.resize(words * 32, 0);
+
+    Ok((hash_evm_bytecode(&padded), padded))
+}
+
+/// Returns the EVM runtime bytecode stored in a preimage created by [evm_bytecode_to_factory_dep].
+pub fn trim_padded_evm_bytecode(padded: &[u8]) -> anyhow::Result<&[u8]> {
+    anyhow::ensure!(
+        padded.len() >= 32 && padded.len() % 32 == 0,
+        "misaligned EVM bytecode of {} bytes",
+        padded.len()
+    );
+    let (len, bytecode) = padded.split_at(32);
+    let len = U256::from_big_endian(len);
+    anyhow::ensure!(
+        len <= U256::from(bytecode.len()),
+        "EVM bytecode length {} exceeds its preimage",
+        len
+    );
+    Ok(&bytecode[..len.as_usize()])
+}
+
+/// Returns whether `bytecode_hash` is the versioned hash of an EVM bytecode.
+pub fn is_evm_bytecode_hash(bytecode_hash: H256) -> bool {
+    BytecodeMarker::new(bytecode_hash) == Some(BytecodeMarker::Evm)
+}
+
 /// Returns the actual [U64] block number from [BlockNumber].
 ///
 /// # Arguments
@@ -391,4 +454,27 @@ mod tests {
         let actual = to_real_block_number(BlockNumber::Number(U64::from(5)), U64::from(10));
         assert_eq!(U64::from(5), actual);
     }
+
+    #[test]
+    fn test_evm_bytecode_round_trip() {
+        let bytecode = vec![0x60u8; 40];
+
+        let (hash, padded) = evm_bytecode_to_factory_dep(&bytecode).unwrap();
+
+        assert!(is_evm_bytecode_hash(hash));
+        assert_eq!(3 * 32, padded.len());
+        assert_eq!(bytecode, trim_padded_evm_bytecode(&padded).unwrap());
+        assert!(!is_evm_bytecode_hash(
+            zksync_utils::bytecode::hash_bytecode(&[0u8; 32])
+        ));
+    }
+
+    #[test]
+    fn test_trim_padded_evm_bytecode_rejects_invalid_preimage() {
+        assert!(trim_padded_evm_bytecode(&[0u8; 31]).is_err());
+
+        let mut padded = vec![0u8; 64];
+        padded[31] = 33;
+        assert!(trim_padded_evm_bytecode(&padded).is_err());
+    }
 }